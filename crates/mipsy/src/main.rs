@@ -1,17 +1,20 @@
-use std::{fmt::{Debug, Display}, fs, process, rc::Rc, str::FromStr};
+mod test;
+
+use std::{collections::BTreeSet, fmt::{Debug, Display}, fs, path::PathBuf, process, rc::Rc, str::FromStr};
 use std::io::Write;
 
 use colored::Colorize;
-use mipsy_lib::{Binary, InstSet, MipsyError, MipsyResult, MpProgram, Runtime, Safe, compile::get_kernel, error::runtime::ErrorContext};
-use mipsy_interactive::prompt;
+use mipsy_lib::{Binary, InstSet, MipsyError, MipsyResult, MpProgram, Runtime, Safe, compile::get_kernel, elf, error::runtime::ErrorContext, runtime::Coverage, snapshot};
+use mipsy_interactive::{FileTable, prompt};
 use clap::{Clap, AppSettings};
 use mipsy_parser::TaggedFile;
-use mipsy_utils::{MipsyConfig, MipsyConfigError, config_path, read_config};
+use mipsy_utils::{Limits, MipsyConfig, MipsyConfigError, config_path, read_config};
 use text_io::try_read;
 
 #[derive(Clap, Debug)]
 #[clap(version = VERSION, author = "Zac K. <zac.kologlu@gmail.com>")]
 #[clap(setting = AppSettings::ColoredHelp)]
+#[clap(after_help = "To run a program against a directory of test cases, see `mipsy test --help`")]
 struct Opts {
     #[clap(long, about("Just output compilation errors, if any"))]
     check: bool,
@@ -23,13 +26,99 @@ struct Opts {
     hex: bool,
     #[clap(long, about("Implies --hex: pad to 8 hex digits with zeroes"))]
     hex_pad_zero: bool,
+    #[clap(long, about("Just compile program and write it to this file as an ELF executable"))]
+    elf: Option<PathBuf>,
+    #[clap(long, about("Just compile program and save it to this file, to be run later without its source"))]
+    save: Option<PathBuf>,
     #[clap(long, about("Enable some SPIM compatibility options"))]
     spim: bool,
+    #[clap(long, about("Accept GNU assembler syntax, such as the output of gcc -S"))]
+    gnu: bool,
+    #[clap(long, about("Error when a function returns without restoring $s0-$s7, $sp, $fp or $gp"))]
+    check_calling_convention: bool,
+    #[clap(long, about("Jump to the program's exception handler (in .ktext 0x80000180) on runtime errors and traps"))]
+    exceptions: bool,
+    #[clap(long, about("Once the program finishes, show where its instructions were executed"))]
+    profile: bool,
+    #[clap(long, default_value = ".", about("Directory that the program's file syscalls are sandboxed to"))]
+    file_root: PathBuf,
+    #[clap(flatten)]
+    limits: LimitOpts,
+    #[clap(flatten)]
+    coverage: CoverageOpts,
     files: Vec<String>,
     #[clap(last = true)]
     args:  Vec<String>,
 }
 
+#[derive(Clap, Debug)]
+struct LimitOpts {
+    #[clap(long, about("Stop the program once it has run this many instructions"))]
+    max_steps: Option<u64>,
+    #[clap(long, about("Stop the program once it has printed this many bytes"))]
+    max_output: Option<u64>,
+    #[clap(long, about("The most bytes the program can grow the heap to"))]
+    max_heap: Option<u32>,
+    #[clap(long, about("The most bytes the program can grow the stack to"))]
+    max_stack: Option<u32>,
+    #[clap(long, about("Stop the program once it has run for this many seconds"))]
+    timeout: Option<f64>,
+}
+
+#[derive(Clap, Debug)]
+struct CoverageOpts {
+    #[clap(long, about("Once the program finishes, show which lines and branches were executed"))]
+    coverage: bool,
+    #[clap(long, about("Write which lines and branches were executed to this file, in lcov format"))]
+    lcov: Option<PathBuf>,
+}
+
+impl CoverageOpts {
+    fn enabled(&self) -> bool {
+        self.coverage || self.lcov.is_some()
+    }
+
+    /// Shows and writes the coverage, as asked for on the command line.
+    fn report(&self, source_code: &[(Rc<str>, Rc<str>)], coverage: &Coverage) {
+        if self.coverage {
+            eprintln!("\n{}", "[coverage]".green().bold());
+            eprint!("{}", coverage.annotate(source_code));
+        }
+
+        if let Some(path) = &self.lcov {
+            if let Err(err) = fs::write(path, coverage.lcov()) {
+                prompt::error_nl(format!("failed to write `{}`: {}", path.display().to_string().bold(), err.to_string().bright_red()));
+                process::exit(1);
+            }
+        }
+    }
+}
+
+impl LimitOpts {
+    /// Overrides the limits from the config with those given on the command line.
+    fn apply(&self, limits: &mut Limits) {
+        if let Some(max_steps) = self.max_steps {
+            limits.max_steps = Some(max_steps);
+        }
+
+        if let Some(max_output) = self.max_output {
+            limits.max_output = Some(max_output);
+        }
+
+        if let Some(max_heap) = self.max_heap {
+            limits.max_heap = Some(max_heap);
+        }
+
+        if let Some(max_stack) = self.max_stack {
+            limits.max_stack = Some(max_stack);
+        }
+
+        if let Some(timeout) = self.timeout {
+            limits.max_time_ms = Some((timeout * 1000.0) as u64);
+        }
+    }
+}
+
 fn get_input<T>(name: &str, line: bool) -> T
 where
     T: FromStr + Display,
@@ -112,7 +201,16 @@ fn get_input_int(name: &str) -> Option<i32> {
 }
 
 fn main() {
-    let opts: Opts = Opts::parse();
+    // `test` is handled separately, so that it can't be mistaken for a file name
+    let test_opts = match std::env::args().nth(1).as_deref() {
+        Some("test") => Some(test::TestOpts::parse_from(std::iter::once(String::from("mipsy test")).chain(std::env::args().skip(2)))),
+        _ => None,
+    };
+
+    let opts: Opts = match test_opts {
+        Some(_) => Opts::parse_from(["mipsy"]),
+        None => Opts::parse(),
+    };
 
     let mut config = match read_config() {
         Ok(config) => config,
@@ -138,84 +236,80 @@ fn main() {
         config.spim = true;
     }
 
-    if opts.files.is_empty() {
-        // launch() returns !
-        mipsy_interactive::launch(config);
+    if opts.gnu {
+        config.gnu = true;
     }
 
-    let files = opts.files.into_iter()
-            .map(|name| {
-                let file_contents = match fs::read_to_string(&name) {
-                    Ok(contents) => contents,
-                    Err(err) => {
-                        prompt::error_nl(format!("failed to read file `{}`: {}", name.bold(), err.to_string().bright_red()));
-            
-                        process::exit(1);
-                    },
-                };
-
-                (name, file_contents)
-            })
-            .collect::<Vec<_>>();
-    
-    let args = opts.args.iter()
-            .map(|arg| &**arg)
-            .collect::<Vec<_>>();
+    if opts.check_calling_convention {
+        config.check_calling_convention = true;
+    }
 
-    let compiled = if opts.check_no_main {
-        compile_with_kernel(&config, &files, &args, &mut MpProgram::new(vec![], vec![]))
-    } else {
-        compile(&config, &files, &args)
-    };
+    if opts.exceptions {
+        config.exceptions = true;
+    }
 
-    let (iset, binary, mut runtime) = match compiled {
-        Ok((iset, binary, runtime)) => (iset, binary, runtime),
+    opts.limits.apply(&mut config.limits);
 
-        Err(MipsyError::Parser(error)) => {
-            prompt::error(format!("failed to parse `{}`", error.file_tag()));
+    if let Some(test_opts) = test_opts {
+        // run() returns !
+        test::run(config, test_opts);
+    }
 
-            let file_tag = error.file_tag();
+    if opts.files.is_empty() {
+        // launch() returns !
+        mipsy_interactive::launch(config, opts.file_root);
+    }
 
-            let file = files
-                .iter()
-                .filter(|(tag, _)| &**tag == &*file_tag)
-                .next()
-                .map(|(_, str)| Rc::from(&**str))
-                .expect("for file to throw a parser error, it should probably exist");
+    let args = opts.args.iter()
+            .map(|arg| &**arg)
+            .collect::<Vec<_>>();
 
-            error.show_error(&config, file);
+    let (files, compiled) = match read_binary(&opts.files) {
+        Some(binary) => {
+            let files = read_source_files(&binary);
+            let runtime = new_runtime(&config, &binary, &args);
 
-            process::exit(1);
+            (files, Ok((mipsy_instructions::inst_set(), binary, runtime)))
         }
+        None => {
+            let files = read_files(&config, opts.files);
 
-        Err(MipsyError::Compiler(error)) => {
-            let compile_tag = if error.file_tag().is_empty() {
-                String::new()
+            let compiled = if opts.check_no_main {
+                compile_with_kernel(&config, &files, &args, &mut MpProgram::new(vec![], vec![]))
             } else {
-                format!(" `{}`", error.file_tag())
+                compile(&config, &files, &args)
             };
 
-            prompt::error(format!("failed to compile{}", compile_tag));
+            (files, compiled)
+        }
+    };
 
-            let file_tag = error.file_tag();
+    let (iset, binary, mut runtime) = match compiled {
+        Ok((iset, binary, runtime)) => (iset, binary, runtime),
+        Err(error) => show_compile_error(&config, &files, error),
+    };
 
-            let file = files
-                .iter()
-                .filter(|(tag, _)| &**tag == &*file_tag)
-                .next()
-                .map(|(_, str)| Rc::from(&**str))
-                .unwrap_or_else(|| Rc::from(""));
+    show_warnings(&config, &files, &binary);
 
-            error.show_error(&config, file);
+    if opts.check || opts.check_no_main {
+        return;
+    }
 
+    if let Some(path) = &opts.elf {
+        if let Err(err) = fs::write(path, binary.to_elf()) {
+            prompt::error_nl(format!("failed to write `{}`: {}", path.display().to_string().bold(), err.to_string().bright_red()));
             process::exit(1);
         }
 
-        // unreachable: a bit tricky to get a runtime error at compile-time
-        Err(MipsyError::Runtime(_)) => unreachable!(),
-    };
+        return;
+    }
+
+    if let Some(path) = &opts.save {
+        if let Err(err) = fs::write(path, binary.save()) {
+            prompt::error_nl(format!("failed to write `{}`: {}", path.display().to_string().bold(), err.to_string().bright_red()));
+            process::exit(1);
+        }
 
-    if opts.check || opts.check_no_main {
         return;
     }
 
@@ -242,6 +336,10 @@ fn main() {
         return;
     }
 
+    runtime.set_profiling(opts.profile || opts.coverage.enabled());
+
+    let mut file_table = FileTable::new(opts.file_root);
+
     loop {
         match runtime.step() {
             Ok(stepped_runtime) => {
@@ -296,8 +394,9 @@ fn main() {
                             Sbrk(_args, new_runtime) => {
                                 runtime = new_runtime;
                             }
-                            Exit(_new_runtime) => {
-                                std::process::exit(0);
+                            Exit(mut new_runtime) => {
+                                show_reports(&files, &binary, &mut new_runtime, opts.profile, &opts.coverage);
+                                exit(binary.expected_exit_code, 0);
                             }
                             PrintChar(args, new_runtime) => {
                                 print!("{}", args.value as char);
@@ -309,12 +408,25 @@ fn main() {
                                 let number: char = get_input_eof("character").unwrap_or('\0');
                                 runtime = guard(number as u8);
                             }
-                            Open(_, _) => todo!(),
-                            Read(_, _) => todo!(),
-                            Write(_, _) => todo!(),
-                            Close(_, _) => todo!(),
-                            ExitStatus(args, _new_runtime) => {
-                                std::process::exit(args.exit_code);
+                            Open(args, guard) => {
+                                let fd = file_table.open(args);
+                                runtime = guard(fd);
+                            }
+                            Read(args, guard) => {
+                                let result = file_table.read(args);
+                                runtime = guard(result);
+                            }
+                            Write(args, guard) => {
+                                let written = file_table.write(args);
+                                runtime = guard(written);
+                            }
+                            Close(args, guard) => {
+                                let status = file_table.close(args);
+                                runtime = guard(status);
+                            }
+                            ExitStatus(args, mut new_runtime) => {
+                                show_reports(&files, &binary, &mut new_runtime, opts.profile, &opts.coverage);
+                                exit(binary.expected_exit_code, args.exit_code);
                             }
                             Breakpoint(new_runtime) => {
                                 runtime = new_runtime;
@@ -345,6 +457,7 @@ fn main() {
                     &runtime
                 );
 
+                show_reports(&files, &binary, &mut runtime, opts.profile, &opts.coverage);
                 process::exit(1);
             }
             Err((_, MipsyError::Parser(_) | MipsyError::Compiler(_))) => {
@@ -354,6 +467,184 @@ fn main() {
     }
 }
 
+/// Loads the program, if it's a single ELF executable
+/// or saved binary rather than source files.
+fn read_binary(names: &[String]) -> Option<Binary> {
+    let name = match names {
+        [name] => name,
+        _ => return None,
+    };
+
+    let bytes = fs::read(name).ok()?;
+
+    let binary = if elf::is_elf(&bytes) {
+        Binary::from_elf(&bytes).map_err(|err| err.to_string())
+    } else if snapshot::is_saved_binary(&bytes) {
+        Binary::load(&bytes).map_err(|err| err.to_string())
+    } else {
+        return None;
+    };
+
+    match binary {
+        Ok(binary) => Some(binary),
+        Err(err) => {
+            prompt::error_nl(format!("failed to load `{}`: {}", name.bold(), err.bright_red()));
+            process::exit(1);
+        }
+    }
+}
+
+/// Reads whichever of the files a binary was compiled from can still be found,
+/// so that they can be shown alongside errors.
+fn read_source_files(binary: &Binary) -> Vec<(String, String)> {
+    let names = binary.line_numbers.values()
+        .map(|(file_tag, _)| file_tag.to_string())
+        .collect::<BTreeSet<_>>();
+
+    names.into_iter()
+        .filter_map(|name| fs::read_to_string(&name).ok().map(|contents| (name, contents)))
+        .collect()
+}
+
+/// Reads each of the named files, and the files they include, exiting if any can't be read.
+fn read_files(config: &MipsyConfig, names: Vec<String>) -> Vec<(String, String)> {
+    let mut files = names.into_iter()
+        .map(|name| {
+            let file_contents = match fs::read_to_string(&name) {
+                Ok(contents) => contents,
+                Err(err) => {
+                    prompt::error_nl(format!("failed to read file `{}`: {}", name.bold(), err.to_string().bright_red()));
+        
+                    process::exit(1);
+                },
+            };
+
+            (name, file_contents)
+        })
+        .collect();
+
+    if let Err(error) = mipsy_lib::include_files(&mut files, config, |path| fs::read_to_string(path)) {
+        show_compile_error(config, &files, error);
+    }
+
+    files
+}
+
+fn show_compile_error(config: &MipsyConfig, files: &[(String, String)], error: MipsyError) -> ! {
+    match error {
+        MipsyError::Parser(error) => {
+            prompt::error(format!("failed to parse `{}`", error.file_tag()));
+
+            let file_tag = error.file_tag();
+
+            let file = files
+                .iter()
+                .filter(|(tag, _)| &**tag == &*file_tag)
+                .next()
+                .map(|(_, str)| Rc::from(&**str))
+                .expect("for file to throw a parser error, it should probably exist");
+
+            error.show_error(config, file);
+
+            process::exit(1);
+        }
+
+        MipsyError::Compiler(error) => {
+            let compile_tag = if error.file_tag().is_empty() {
+                String::new()
+            } else {
+                format!(" `{}`", error.file_tag())
+            };
+
+            prompt::error(format!("failed to compile{}", compile_tag));
+
+            let file_tag = error.file_tag();
+
+            let file = files
+                .iter()
+                .filter(|(tag, _)| &**tag == &*file_tag)
+                .next()
+                .map(|(_, str)| Rc::from(&**str))
+                .unwrap_or_else(|| Rc::from(""));
+
+            error.show_error(config, file);
+
+            process::exit(1);
+        }
+
+        // unreachable: a bit tricky to get a runtime error at compile-time
+        MipsyError::Runtime(_) => unreachable!(),
+    }
+}
+
+fn show_warnings(config: &MipsyConfig, files: &[(String, String)], binary: &Binary) {
+    for warning in binary.warnings.iter() {
+        let compile_tag = if warning.file_tag().is_empty() {
+            String::new()
+        } else {
+            format!(" `{}`", warning.file_tag())
+        };
+
+        prompt::warning(format!("while compiling{}", compile_tag));
+
+        let file_tag = warning.file_tag();
+
+        let file = files
+            .iter()
+            .find(|(tag, _)| **tag == *file_tag)
+            .map(|(_, str)| Rc::from(&**str))
+            .unwrap_or_else(|| Rc::from(""));
+
+        warning.show_warning(config, file);
+        eprintln!();
+    }
+}
+
+/// The number of lines shown by `--profile`.
+const PROFILE_LINES: usize = 10;
+
+/// Shows the program's profile and coverage, if either was asked for.
+fn show_reports(files: &[(String, String)], binary: &Binary, runtime: &mut Runtime, show_profile: bool, coverage_opts: &CoverageOpts) {
+    let profile = match runtime.profile() {
+        Some(profile) => profile,
+        None => return,
+    };
+
+    let source_code = files.iter()
+        .map(|(tag, content)| (Rc::from(&**tag), Rc::from(&**content)))
+        .collect::<Vec<_>>();
+
+    if show_profile {
+        eprintln!("\n{}", "[profile]".green().bold());
+        eprint!("{}", profile.report(binary, &source_code, PROFILE_LINES));
+    }
+
+    if coverage_opts.enabled() {
+        let mut coverage = Coverage::new(binary);
+        coverage.add(profile);
+
+        coverage_opts.report(&source_code, &coverage);
+    }
+}
+
+/// Exits with the program's exit code - unless the program
+/// declared a different `#![expected_exit_code(...)]`.
+fn exit(expected_exit_code: Option<i32>, exit_code: i32) -> ! {
+    if let Some(expected_exit_code) = expected_exit_code {
+        if exit_code != expected_exit_code {
+            prompt::error(format!(
+                "program exited with code {}, but was expected to exit with code {}",
+                exit_code.to_string().bold(),
+                expected_exit_code.to_string().bold(),
+            ));
+
+            process::exit(1);
+        }
+    }
+
+    process::exit(exit_code);
+}
+
 fn read_string(_max_len: u32) -> String {
     loop {
         let input: String = get_input("string", true);
@@ -389,9 +680,19 @@ fn compile_with_kernel(config: &MipsyConfig, files: &[(String, String)], args: &
 
     let iset    = mipsy_instructions::inst_set();
     let binary  = mipsy_lib::compile_with_kernel(&iset, files, kernel, &config)?;
-    let runtime = mipsy_lib::runtime(&binary, args);
+    let runtime = new_runtime(config, &binary, args);
 
     Ok((iset, binary, runtime))
 }
 
+fn new_runtime(config: &MipsyConfig, binary: &Binary, args: &[&str]) -> Runtime {
+    let mut runtime = mipsy_lib::runtime(binary, args);
+    runtime.timeline_mut().set_history_policy(config.history);
+    runtime.set_check_calling_convention(config.check_calling_convention);
+    runtime.set_exceptions(config.exceptions);
+    runtime.set_limits(config.limits);
+
+    runtime
+}
+
 pub const VERSION: &str = concat!(env!("VERGEN_COMMIT_DATE"), " ", env!("VERGEN_SHA_SHORT"));
@@ -0,0 +1,661 @@
+use std::{collections::BTreeMap, fs, path::{Path, PathBuf}, process, rc::Rc};
+
+use clap::{AppSettings, Clap};
+use colored::Colorize;
+use mipsy_interactive::{FileTable, prompt};
+use mipsy_lib::{Binary, InstSet, MipsyError, Runtime, error::runtime::{Error, ErrorContext, Limit}, function_test::{FunctionTest, FunctionTests}, runtime::{Coverage, RuntimeSyscallGuard}};
+use mipsy_utils::{HistoryPolicy, MipsyConfig};
+use serde::Serialize;
+
+use crate::{CoverageOpts, LimitOpts};
+
+/// Test cases can't run forever, even without a `--max-steps`.
+const DEFAULT_MAX_STEPS: u64 = 10_000_000;
+
+const CASES_HELP: &str = "\
+Each test case is a set of files in <cases> that share a name:
+    <name>.in      the program's standard input (default: empty)
+    <name>.out     the expected standard output (default: not checked)
+    <name>.err     the expected standard error (default: not checked)
+    <name>.exit    the expected exit status (default: #![expected_exit_code], or 0)
+    <name>.args    the program's arguments, split like a shell would (default: none)
+
+If <cases> is a .yaml file, it instead holds unit tests for single functions:
+    function: sum_array
+    tests:
+      - name: sums three numbers
+        args: [{ words: [1, 2, 3] }, 3]
+        expect: { v0: 6 }
+Each test can also set `memory` and `stdin` before the call, and expect
+`v1`, `memory`, `stdout` and `stderr` afterwards. Functions must restore $s0-$s7,
+$sp, $fp and $gp unless `check_calling_convention: false` is given.
+
+Programs are stopped after 10000000 instructions, unless --max-steps is given.";
+
+/// Run a program against a directory of test cases
+#[derive(Clap, Debug)]
+#[clap(name = "mipsy test", setting = AppSettings::ColoredHelp, after_help = CASES_HELP)]
+pub struct TestOpts {
+    #[clap(about("Directory containing the test cases, or a YAML file of function tests"))]
+    cases: PathBuf,
+    #[clap(required = true, about("The program to test"))]
+    files: Vec<String>,
+    #[clap(flatten)]
+    limits: LimitOpts,
+    #[clap(flatten)]
+    coverage: CoverageOpts,
+    #[clap(long, about("Write a JSON report of the results to this file"))]
+    json: Option<PathBuf>,
+    #[clap(long, about("Error when a function returns without restoring $s0-$s7, $sp, $fp or $gp"))]
+    check_calling_convention: bool,
+    #[clap(long, about("Jump to the program's exception handler (in .ktext 0x80000180) on runtime errors and traps"))]
+    exceptions: bool,
+    #[clap(long, about("Enable some SPIM compatibility options"))]
+    spim: bool,
+    #[clap(long, about("Accept GNU assembler syntax, such as the output of gcc -S"))]
+    gnu: bool,
+}
+
+#[derive(Debug, Default)]
+struct TestCase {
+    name: String,
+    input: Option<PathBuf>,
+    output: Option<PathBuf>,
+    error_output: Option<PathBuf>,
+    exit: Option<PathBuf>,
+    args: Option<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    passed: usize,
+    failed: usize,
+    cases: Vec<CaseReport>,
+}
+
+#[derive(Debug, Serialize)]
+struct CaseReport {
+    name: String,
+    passed: bool,
+    steps: u64,
+    /// Why the test case failed, if it did.
+    failures: Vec<String>,
+    exit_code: Option<i32>,
+    /// Function tests aren't expected to exit.
+    expected_exit_code: Option<i32>,
+    output: String,
+    expected_output: Option<String>,
+    error_output: String,
+    expected_error_output: Option<String>,
+}
+
+/// How a test case stopped running.
+enum Outcome {
+    Exited(i32),
+    /// A function test's function returned.
+    Returned,
+    Error(String),
+    StepLimit,
+}
+
+pub fn run(mut config: MipsyConfig, opts: TestOpts) -> ! {
+    if opts.spim {
+        config.spim = true;
+    }
+
+    if opts.gnu {
+        config.gnu = true;
+    }
+
+    if opts.check_calling_convention {
+        config.check_calling_convention = true;
+    }
+
+    if opts.exceptions {
+        config.exceptions = true;
+    }
+
+    opts.limits.apply(&mut config.limits);
+    config.limits.max_steps.get_or_insert(DEFAULT_MAX_STEPS);
+
+    let files = crate::read_files(&config, opts.files.clone());
+
+    let (iset, binary, _) = match crate::compile(&config, &files, &[]) {
+        Ok(compiled) => compiled,
+        Err(error) => crate::show_compile_error(&config, &files, error),
+    };
+
+    crate::show_warnings(&config, &files, &binary);
+
+    let source_code = files.iter()
+        .map(|(tag, content)| (Rc::from(&**tag), Rc::from(&**content)))
+        .collect::<Vec<_>>();
+
+    let is_yaml = opts.cases.extension()
+        .map_or(false, |extension| extension == "yaml" || extension == "yml");
+
+    // every test case's coverage is added together
+    let mut coverage = Coverage::new(&binary);
+
+    let reports = if is_yaml {
+        run_function_tests(&config, &iset, &binary, &source_code, &opts, &mut coverage)
+    } else {
+        run_cases(&config, &iset, &binary, &source_code, &opts, &mut coverage)
+    };
+
+    let passed = reports.iter().filter(|report| report.passed).count();
+    let failed = reports.len() - passed;
+
+    println!();
+    println!(
+        "test result: {}. {} passed; {} failed",
+        if failed == 0 { "ok".green().bold() } else { "FAILED".red().bold() },
+        passed,
+        failed,
+    );
+
+    opts.coverage.report(&source_code, &coverage);
+
+    if let Some(path) = &opts.json {
+        let report = Report { passed, failed, cases: reports };
+        let json = serde_json::to_string_pretty(&report)
+            .expect("cannot fail to serialise test report");
+
+        if let Err(err) = fs::write(path, json) {
+            prompt::error_nl(format!("failed to write `{}`: {}", path.display().to_string().bold(), err.to_string().bright_red()));
+            process::exit(1);
+        }
+    }
+
+    process::exit(if failed == 0 { 0 } else { 1 });
+}
+
+fn run_cases(config: &MipsyConfig, iset: &InstSet, binary: &Binary, source_code: &[(Rc<str>, Rc<str>)], opts: &TestOpts, coverage: &mut Coverage) -> Vec<CaseReport> {
+    let cases = match find_cases(&opts.cases) {
+        Ok(cases) if !cases.is_empty() => cases,
+        Ok(_) => {
+            prompt::error_nl(format!("no test cases found in `{}`", opts.cases.display().to_string().bold()));
+            process::exit(1);
+        }
+        Err(err) => {
+            prompt::error_nl(format!("failed to read `{}`: {}", opts.cases.display().to_string().bold(), err.to_string().bright_red()));
+            process::exit(1);
+        }
+    };
+
+    let mut reports = vec![];
+
+    for case in cases {
+        let report = match run_case(config, iset, binary, source_code, &case, opts, coverage) {
+            Ok(report) => report,
+            Err(err) => {
+                prompt::error_nl(format!("failed to read test case `{}`: {}", case.name.bold(), err.to_string().bright_red()));
+                process::exit(1);
+            }
+        };
+
+        show_case(&report);
+        reports.push(report);
+    }
+
+    reports
+}
+
+fn run_function_tests(config: &MipsyConfig, iset: &InstSet, binary: &Binary, source_code: &[(Rc<str>, Rc<str>)], opts: &TestOpts, coverage: &mut Coverage) -> Vec<CaseReport> {
+    let tests = match fs::read_to_string(&opts.cases) {
+        Ok(yaml) => yaml,
+        Err(err) => {
+            prompt::error_nl(format!("failed to read `{}`: {}", opts.cases.display().to_string().bold(), err.to_string().bright_red()));
+            process::exit(1);
+        }
+    };
+
+    let tests = match FunctionTests::from_yaml(&tests) {
+        Ok(tests) if !tests.tests.is_empty() => tests,
+        Ok(_) => {
+            prompt::error_nl(format!("no tests found in `{}`", opts.cases.display().to_string().bold()));
+            process::exit(1);
+        }
+        Err(err) => {
+            prompt::error_nl(format!("failed to parse `{}`: {}", opts.cases.display().to_string().bold(), err.to_string().bright_red()));
+            process::exit(1);
+        }
+    };
+
+    let mut reports = vec![];
+
+    for test in tests.tests.iter() {
+        let report = match run_function_test(config, iset, binary, source_code, &tests, test, opts, coverage) {
+            Ok(report) => report,
+            Err(err) => {
+                prompt::error_nl(format!("failed to set up test `{}`: {}", test.name.bold(), err.bright_red()));
+                process::exit(1);
+            }
+        };
+
+        show_case(&report);
+        reports.push(report);
+    }
+
+    reports
+}
+
+/// Groups the files in `dir` into test cases by name, in alphabetical order.
+fn find_cases(dir: &Path) -> std::io::Result<Vec<TestCase>> {
+    let mut cases: BTreeMap<String, TestCase> = BTreeMap::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        let (name, extension) = match (path.file_stem(), path.extension()) {
+            (Some(name), Some(extension)) => (name.to_string_lossy().to_string(), extension.to_string_lossy().to_string()),
+            _ => continue,
+        };
+
+        let case = cases.entry(name.clone())
+            .or_insert_with(|| TestCase { name, ..Default::default() });
+
+        match &*extension {
+            "in"   => case.input  = Some(path),
+            "out"  => case.output       = Some(path),
+            "err"  => case.error_output = Some(path),
+            "exit" => case.exit         = Some(path),
+            "args" => case.args         = Some(path),
+            _ => {}
+        }
+    }
+
+    Ok(
+        cases.into_values()
+            .filter(|case| case.input.is_some() || case.output.is_some() || case.error_output.is_some() || case.exit.is_some() || case.args.is_some())
+            .collect()
+    )
+}
+
+fn run_case(config: &MipsyConfig, iset: &InstSet, binary: &Binary, source_code: &[(Rc<str>, Rc<str>)], case: &TestCase, opts: &TestOpts, coverage: &mut Coverage) -> std::io::Result<CaseReport> {
+    let input = match &case.input {
+        Some(path) => fs::read(path)?,
+        None => vec![],
+    };
+
+    let expected_output = match &case.output {
+        Some(path) => Some(fs::read_to_string(path)?),
+        None => None,
+    };
+
+    let expected_error_output = match &case.error_output {
+        Some(path) => Some(fs::read_to_string(path)?),
+        None => None,
+    };
+
+    let expected_exit_code = match &case.exit {
+        Some(path) => {
+            let contents = fs::read_to_string(path)?;
+
+            contents.trim().parse::<i32>()
+                .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("`{}` is not an exit status", contents.trim())))?
+        }
+        None => binary.expected_exit_code.unwrap_or(0),
+    };
+    let expected_exit_code = Some(expected_exit_code);
+
+    let args = match &case.args {
+        Some(path) => shlex::split(&fs::read_to_string(path)?)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "arguments are not correctly quoted"))?,
+        None => vec![],
+    };
+    let args = args.iter().map(|arg| &**arg).collect::<Vec<_>>();
+
+    let mut runtime = mipsy_lib::runtime(binary, &args);
+    // nobody will be stepping backwards
+    runtime.timeline_mut().set_history_policy(HistoryPolicy::Disabled);
+    runtime.set_check_calling_convention(config.check_calling_convention);
+    runtime.set_exceptions(config.exceptions);
+    runtime.set_limits(config.limits);
+    runtime.set_profiling(opts.coverage.enabled());
+
+    let root = opts.cases.clone();
+    let mut io = CaseIo::new(input, root);
+    let (outcome, mut runtime) = run_headless(runtime, iset, binary, source_code, &mut io);
+    let steps = runtime.steps();
+    if let Some(profile) = runtime.profile() {
+        coverage.add(profile);
+    }
+    let output = String::from_utf8_lossy(&io.output).to_string();
+    let error_output = String::from_utf8_lossy(&io.error_output).to_string();
+
+    let mut failures = vec![];
+
+    let exit_code = match outcome {
+        Outcome::Exited(exit_code) => Some(exit_code),
+        Outcome::Returned => unreachable!("only function tests return"),
+        Outcome::Error(message) => {
+            failures.push(format!("runtime error: {}", message));
+            None
+        }
+        Outcome::StepLimit => {
+            failures.push(format!("did not finish within {} instructions", steps));
+            None
+        }
+    };
+
+    if let (Some(exit_code), Some(expected_exit_code)) = (exit_code, expected_exit_code) {
+        if exit_code != expected_exit_code {
+            failures.push(format!("exited with code {}, but was expected to exit with code {}", exit_code, expected_exit_code));
+        }
+    }
+
+    if let Some(expected_output) = &expected_output {
+        if let Some(difference) = first_difference("output", expected_output, &output) {
+            failures.push(difference);
+        }
+    }
+
+    if let Some(expected_error_output) = &expected_error_output {
+        if let Some(difference) = first_difference("error output", expected_error_output, &error_output) {
+            failures.push(difference);
+        }
+    }
+
+    Ok(CaseReport {
+        name: case.name.clone(),
+        passed: failures.is_empty(),
+        steps,
+        failures,
+        exit_code,
+        expected_exit_code,
+        output,
+        expected_output,
+        error_output,
+        expected_error_output,
+    })
+}
+
+fn run_function_test(config: &MipsyConfig, iset: &InstSet, binary: &Binary, source_code: &[(Rc<str>, Rc<str>)], tests: &FunctionTests, test: &FunctionTest, opts: &TestOpts, coverage: &mut Coverage) -> Result<CaseReport, String> {
+    let (mut runtime, args) = test.runtime(tests, binary)
+        .map_err(|err| err.to_string())?;
+    runtime.timeline_mut().set_history_policy(HistoryPolicy::Disabled);
+    runtime.set_exceptions(config.exceptions);
+    runtime.set_limits(config.limits);
+    runtime.set_profiling(opts.coverage.enabled());
+
+    let root = opts.cases.parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let mut io = CaseIo::new(test.stdin.clone().into_bytes(), root);
+    let (outcome, mut runtime) = run_headless(runtime, iset, binary, source_code, &mut io);
+    let steps = runtime.steps();
+    if let Some(profile) = runtime.profile() {
+        coverage.add(profile);
+    }
+    let output = String::from_utf8_lossy(&io.output).to_string();
+    let error_output = String::from_utf8_lossy(&io.error_output).to_string();
+
+    let mut failures = vec![];
+    let mut exit_code = None;
+
+    match outcome {
+        Outcome::Returned => {
+            failures.extend(test.check(binary, runtime.timeline().state(), &args));
+        }
+        Outcome::Exited(code) => {
+            failures.push(format!("exited with code {} instead of returning", code));
+            exit_code = Some(code);
+        }
+        Outcome::Error(message) => {
+            failures.push(format!("runtime error: {}", message));
+        }
+        Outcome::StepLimit => {
+            failures.push(format!("did not return within {} instructions", steps));
+        }
+    }
+
+    if let Some(expected_output) = &test.expect.stdout {
+        if let Some(difference) = first_difference("output", expected_output, &output) {
+            failures.push(difference);
+        }
+    }
+
+    if let Some(expected_error_output) = &test.expect.stderr {
+        if let Some(difference) = first_difference("error output", expected_error_output, &error_output) {
+            failures.push(difference);
+        }
+    }
+
+    Ok(CaseReport {
+        name: test.name.clone(),
+        passed: failures.is_empty(),
+        steps,
+        failures,
+        exit_code,
+        expected_exit_code: None,
+        output,
+        expected_output: test.expect.stdout.clone(),
+        error_output,
+        expected_error_output: test.expect.stderr.clone(),
+    })
+}
+
+/// Runs a program to completion, without any user interaction.
+/// Returns how it stopped, and the runtime as it was when it stopped.
+fn run_headless(mut runtime: Runtime, iset: &InstSet, binary: &Binary, source_code: &[(Rc<str>, Rc<str>)], io: &mut CaseIo) -> (Outcome, Runtime) {
+    use RuntimeSyscallGuard::*;
+
+    loop {
+        if runtime.call_returned() {
+            return (Outcome::Returned, runtime);
+        }
+
+        let guard = match runtime.step() {
+            Ok(Ok(new_runtime)) => {
+                runtime = new_runtime;
+                continue;
+            }
+            Ok(Err(guard)) => guard,
+            Err((old_runtime, MipsyError::Runtime(err))) => {
+                if let Error::LimitExceeded { limit: Limit::Steps(_) } = err.error() {
+                    return (Outcome::StepLimit, old_runtime);
+                }
+
+                let message = err.error().message(ErrorContext::Binary, source_code.to_vec(), iset, binary, &old_runtime);
+                let message = strip_ansi_escapes::strip(message.lines().next().unwrap_or_default())
+                    .map(|message| String::from_utf8_lossy(&message).to_string())
+                    .unwrap_or_default();
+
+                return (Outcome::Error(message), old_runtime);
+            }
+            Err((_, MipsyError::Parser(_) | MipsyError::Compiler(_))) => {
+                unreachable!("the only possible error at runtime is a MipsyError::Runtime");
+            }
+        };
+
+        runtime = match guard {
+            PrintInt(args, new_runtime) => {
+                io.output.extend(args.value.to_string().bytes());
+                new_runtime
+            }
+            PrintFloat(args, new_runtime) => {
+                io.output.extend(args.value.to_string().bytes());
+                new_runtime
+            }
+            PrintDouble(args, new_runtime) => {
+                io.output.extend(args.value.to_string().bytes());
+                new_runtime
+            }
+            PrintString(args, new_runtime) => {
+                io.output.extend(args.value);
+                new_runtime
+            }
+            PrintChar(args, new_runtime) => {
+                io.output.push(args.value);
+                new_runtime
+            }
+            ReadInt(guard) => {
+                let number = io.read_token().and_then(|token| token.parse::<i64>().ok()).unwrap_or(0);
+                guard(number as i32)
+            }
+            ReadFloat(guard) => {
+                let number = io.read_token().and_then(|token| token.parse().ok()).unwrap_or(0.0);
+                guard(number)
+            }
+            ReadDouble(guard) => {
+                let number = io.read_token().and_then(|token| token.parse().ok()).unwrap_or(0.0);
+                guard(number)
+            }
+            ReadString(_args, guard) => {
+                let line = io.read_line();
+                guard(line)
+            }
+            ReadChar(guard) => {
+                let char = io.read_bytes(1).first().copied().unwrap_or(0);
+                guard(char)
+            }
+            Sbrk(_args, new_runtime) => new_runtime,
+            Exit(new_runtime) => return (Outcome::Exited(0), new_runtime),
+            ExitStatus(args, new_runtime) => return (Outcome::Exited(args.exit_code), new_runtime),
+            Open(args, guard) => {
+                let fd = io.files.open(args);
+                guard(fd)
+            }
+            Read(args, guard) => {
+                let result = match args.fd {
+                    0 => {
+                        let bytes = io.read_bytes(args.len as usize);
+                        (bytes.len() as i32, bytes)
+                    }
+                    _ => io.files.read(args),
+                };
+
+                guard(result)
+            }
+            Write(args, guard) => {
+                let written = match args.fd {
+                    1 => {
+                        io.output.extend(&args.buf);
+                        args.buf.len() as i32
+                    }
+                    2 => {
+                        io.error_output.extend(&args.buf);
+                        args.buf.len() as i32
+                    }
+                    _ => io.files.write(args),
+                };
+
+                guard(written)
+            }
+            Close(args, guard) => {
+                let status = io.files.close(args);
+                guard(status)
+            }
+            Breakpoint(new_runtime) => new_runtime,
+            Trap(new_runtime) => new_runtime,
+            UnknownSyscall(args, new_runtime) => {
+                return (Outcome::Error(format!("unknown syscall: {}", args.syscall_number)), new_runtime);
+            }
+        };
+    }
+}
+
+/// The input and output of a single test case.
+struct CaseIo {
+    input: Vec<u8>,
+    position: usize,
+    output: Vec<u8>,
+    error_output: Vec<u8>,
+    files: FileTable,
+}
+
+impl CaseIo {
+    fn new(input: Vec<u8>, root: PathBuf) -> Self {
+        Self {
+            input,
+            position: 0,
+            output: vec![],
+            error_output: vec![],
+            files: FileTable::new(root),
+        }
+    }
+
+    /// Reads the next whitespace-separated token, if there is one.
+    fn read_token(&mut self) -> Option<String> {
+        while self.input.get(self.position).map_or(false, u8::is_ascii_whitespace) {
+            self.position += 1;
+        }
+
+        let start = self.position;
+
+        while self.input.get(self.position).map_or(false, |byte| !byte.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+
+        if start == self.position {
+            return None;
+        }
+
+        Some(String::from_utf8_lossy(&self.input[start..self.position]).to_string())
+    }
+
+    /// Reads up to and including the next newline.
+    fn read_line(&mut self) -> Vec<u8> {
+        let end = self.input[self.position..].iter()
+            .position(|&byte| byte == b'\n')
+            .map(|newline| self.position + newline + 1)
+            .unwrap_or(self.input.len());
+
+        let line = self.input[self.position..end].to_vec();
+        self.position = end;
+
+        line
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Vec<u8> {
+        let end = usize::min(self.position + len, self.input.len());
+
+        let bytes = self.input[self.position..end].to_vec();
+        self.position = end;
+
+        bytes
+    }
+}
+
+/// Describes the first line where `output`, which is the program's `stream`
+/// (such as "output"), differs from `expected`, if any.
+fn first_difference(stream: &str, expected: &str, output: &str) -> Option<String> {
+    if expected == output {
+        return None;
+    }
+
+    let mut expected_lines = expected.split_inclusive('\n');
+    let mut output_lines = output.split_inclusive('\n');
+
+    for line_num in 1.. {
+        match (expected_lines.next(), output_lines.next()) {
+            (Some(expected), Some(output)) if expected == output => continue,
+            (Some(expected), Some(output)) => {
+                return Some(format!("{} differs at line {}: expected {:?}, got {:?}", stream, line_num, expected, output));
+            }
+            (Some(expected), None) => {
+                return Some(format!("{} ended early at line {}: expected {:?}", stream, line_num, expected));
+            }
+            (None, Some(output)) => {
+                return Some(format!("{} continued past the end at line {}: got {:?}", stream, line_num, output));
+            }
+            (None, None) => break,
+        }
+    }
+
+    None
+}
+
+fn show_case(report: &CaseReport) {
+    if report.passed {
+        println!("test {} ... {}", report.name.bold(), "ok".green().bold());
+        return;
+    }
+
+    println!("test {} ... {}", report.name.bold(), "FAILED".red().bold());
+
+    for failure in report.failures.iter() {
+        println!("    {}", failure);
+    }
+}
@@ -70,6 +70,10 @@ pub enum ArgumentType {
     OffRt,
     F32,
     F64,
+    Fd,
+    Fs,
+    Ft,
+    Cd,
 
     // pseudo
     Rx,
@@ -85,4 +89,6 @@ pub enum ReadsRegisterType {
     Rt,
     OffRs,
     OffRt,
+    Fs,
+    Ft,
 }
@@ -251,6 +251,10 @@ fn quote_instruction(instruction: InstructionYaml) -> proc_macro2::TokenStream {
                 ArgumentType::OffRt   => quote! { OffRt },
                 ArgumentType::F32     => quote! { F32 },
                 ArgumentType::F64     => quote! { F64 },
+                ArgumentType::Fd      => quote! { Fd },
+                ArgumentType::Fs      => quote! { Fs },
+                ArgumentType::Ft      => quote! { Ft },
+                ArgumentType::Cd      => quote! { Cd },
                 ArgumentType::I32     => quote! { I32 },
                 ArgumentType::U32     => quote! { U32 },
                 ArgumentType::Off32Rs => quote! { Off32Rs },
@@ -300,12 +304,17 @@ fn quote_instruction(instruction: InstructionYaml) -> proc_macro2::TokenStream {
                 let opcode = instruction.runtime.opcode
                         .expect(&format!("invalid mips.yaml: missing opcode for {}", instruction.name));
 
+                let rs = match instruction.runtime.rs {
+                    Some(rs) => quote! { ::std::option::Option::Some(#rs) },
+                    None     => quote! { ::std::option::Option::None },
+                };
+
                 let rt = match instruction.runtime.rt {
                     Some(rt) => quote! { ::std::option::Option::Some(#rt) },
                     None     => quote! { ::std::option::Option::None },
                 };
 
-                quote! { I { opcode: #opcode, rt: #rt } }
+                quote! { I { opcode: #opcode, rs: #rs, rt: #rt } }
             }
             InstructionType::J => {
                 let opcode = instruction.runtime.opcode
@@ -330,6 +339,8 @@ fn quote_instruction(instruction: InstructionYaml) -> proc_macro2::TokenStream {
             ReadsRegisterType::Rt      => quote! { Rt },
             ReadsRegisterType::OffRs   => quote! { OffRs },
             ReadsRegisterType::OffRt   => quote! { OffRt },
+            ReadsRegisterType::Fs      => quote! { Fs },
+            ReadsRegisterType::Ft      => quote! { Ft },
         };
 
         quote! {
@@ -394,6 +405,10 @@ fn quote_pseudo_instruction(instruction: PseudoInstructionYaml) -> proc_macro2::
                 ArgumentType::OffRt   => quote! { OffRt },
                 ArgumentType::F32     => quote! { F32 },
                 ArgumentType::F64     => quote! { F64 },
+                ArgumentType::Fd      => quote! { Fd },
+                ArgumentType::Fs      => quote! { Fs },
+                ArgumentType::Ft      => quote! { Ft },
+                ArgumentType::Cd      => quote! { Cd },
                 ArgumentType::I32     => quote! { I32 },
                 ArgumentType::U32     => quote! { U32 },
                 ArgumentType::Off32Rs => quote! { Off32Rs },
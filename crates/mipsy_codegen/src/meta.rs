@@ -103,6 +103,10 @@ pub enum ArgumentType {
     OffRt,
     F32,
     F64,
+    Fd,
+    Fs,
+    Ft,
+    Cd,
 
     // pseudo
     Rx,
@@ -118,6 +122,8 @@ pub enum ReadsRegisterType {
     Rt,
     OffRs,
     OffRt,
+    Fs,
+    Ft,
 }
 
 impl Display for ArgumentType {
@@ -150,6 +156,10 @@ impl Into<super::base::ArgumentType> for ArgumentType {
             ArgumentType::OffRt   => super::base::ArgumentType::OffRt,
             ArgumentType::F32     => super::base::ArgumentType::F32,
             ArgumentType::F64     => super::base::ArgumentType::F64,
+            ArgumentType::Fd      => super::base::ArgumentType::Fd,
+            ArgumentType::Fs      => super::base::ArgumentType::Fs,
+            ArgumentType::Ft      => super::base::ArgumentType::Ft,
+            ArgumentType::Cd      => super::base::ArgumentType::Cd,
             ArgumentType::I32     => super::base::ArgumentType::I32,
             ArgumentType::U32     => super::base::ArgumentType::U32,
             ArgumentType::Off32Rs => super::base::ArgumentType::Off32Rs,
@@ -175,6 +185,8 @@ impl Into<super::base::ReadsRegisterType> for ReadsRegisterType {
             ReadsRegisterType::Rt => super::base::ReadsRegisterType::Rt,
             ReadsRegisterType::OffRs => super::base::ReadsRegisterType::OffRs,
             ReadsRegisterType::OffRt => super::base::ReadsRegisterType::OffRt,
+            ReadsRegisterType::Fs => super::base::ReadsRegisterType::Fs,
+            ReadsRegisterType::Ft => super::base::ReadsRegisterType::Ft,
         }
     }
 }
@@ -0,0 +1,206 @@
+use std::{fs::{File, OpenOptions}, io::{self, Read, Write}, path::{Component, Path, PathBuf}};
+
+use mipsy_lib::runtime::{CloseArgs, OpenArgs, ReadArgs, WriteArgs};
+
+// open(2) flags, as defined by the MIPS linux ABI
+const O_ACCMODE: u32 = 0x0003;
+const O_RDONLY:  u32 = 0x0000;
+const O_WRONLY:  u32 = 0x0001;
+const O_RDWR:    u32 = 0x0002;
+const O_APPEND:  u32 = 0x0008;
+const O_CREAT:   u32 = 0x0100;
+const O_TRUNC:   u32 = 0x0200;
+const O_EXCL:    u32 = 0x0400;
+
+/// The most bytes read from standard input at once. Like read(2),
+/// a read can give back fewer bytes than were asked for.
+const MAX_STDIN_READ: usize = 64 * 1024;
+
+enum FileEntry {
+    Stdin,
+    Stdout,
+    Stderr,
+    File(File),
+}
+
+/// # The file descriptors available to a running program.
+///
+/// Descriptors `0`, `1` and `2` are the program's standard input, output
+/// and error. Files opened by the program are allocated the lowest free
+/// descriptor, and are only ever looked up inside `root` - paths that
+/// are absolute, or that escape `root` with `..` or through a symlink,
+/// fail to open.
+pub struct FileTable {
+    root: PathBuf,
+    entries: Vec<Option<FileEntry>>,
+}
+
+impl FileTable {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            entries: vec![
+                Some(FileEntry::Stdin),
+                Some(FileEntry::Stdout),
+                Some(FileEntry::Stderr),
+            ],
+        }
+    }
+
+    /// Opens the file at `args.path`, returning the new file descriptor,
+    /// or `-1` if the file could not be opened.
+    ///
+    /// For compatibility with MARS, a plain write-only open (optionally
+    /// with `O_APPEND`) will also create the file if it doesn't exist.
+    pub fn open(&mut self, args: OpenArgs) -> i32 {
+        let create = args.flags & O_CREAT != 0
+            || args.flags & !O_APPEND == O_WRONLY;
+
+        let path = match self.resolve(&args.path, create) {
+            Some(path) => path,
+            None => return -1,
+        };
+
+        let mut options = OpenOptions::new();
+
+        match args.flags & O_ACCMODE {
+            O_RDONLY => { options.read(true); }
+            O_WRONLY => { options.write(true); }
+            O_RDWR   => { options.read(true).write(true); }
+            _ => return -1,
+        }
+
+        options
+            .append(args.flags & O_APPEND != 0)
+            .truncate(args.flags & O_TRUNC != 0 || args.flags == O_WRONLY);
+
+        if create && args.flags & O_EXCL != 0 {
+            options.create_new(true);
+        } else {
+            options.create(create);
+        }
+
+        let file = match options.open(path) {
+            Ok(file) => file,
+            Err(_) => return -1,
+        };
+
+        let entry = Some(FileEntry::File(file));
+
+        let fd = match self.entries.iter().position(Option::is_none) {
+            Some(fd) => {
+                self.entries[fd] = entry;
+                fd
+            }
+            None => {
+                self.entries.push(entry);
+                self.entries.len() - 1
+            }
+        };
+
+        fd as i32
+    }
+
+    /// Reads up to `args.len` bytes from `args.fd`, returning the number
+    /// of bytes read (or `-1` on failure) along with the bytes themselves.
+    pub fn read(&mut self, args: ReadArgs) -> (i32, Vec<u8>) {
+        // `args.len` comes straight from the program, so the buffer
+        // only grows as far as there are bytes to fill it with
+        let mut buf = vec![];
+
+        let result = match self.entry(args.fd) {
+            Some(FileEntry::Stdin) => {
+                buf.resize((args.len as usize).min(MAX_STDIN_READ), 0);
+                io::stdin().read(&mut buf)
+            }
+            Some(FileEntry::File(file)) => Read::take(file, args.len as u64).read_to_end(&mut buf),
+            _ => return (-1, vec![]),
+        };
+
+        match result {
+            Ok(n_bytes) => {
+                buf.truncate(n_bytes);
+                (n_bytes as i32, buf)
+            }
+            Err(_) => (-1, vec![]),
+        }
+    }
+
+    /// Writes `args.buf` to `args.fd`, returning the number of bytes
+    /// written, or `-1` on failure.
+    pub fn write(&mut self, args: WriteArgs) -> i32 {
+        let result = match self.entry(args.fd) {
+            Some(FileEntry::Stdout) => {
+                let mut stdout = io::stdout();
+                stdout.write_all(&args.buf).and_then(|_| stdout.flush())
+            }
+            Some(FileEntry::Stderr) => io::stderr().write_all(&args.buf),
+            Some(FileEntry::File(file)) => file.write_all(&args.buf),
+            _ => return -1,
+        };
+
+        match result {
+            Ok(()) => args.buf.len() as i32,
+            Err(_) => -1,
+        }
+    }
+
+    /// Closes `args.fd`, returning `0` on success or `-1` if it wasn't open.
+    pub fn close(&mut self, args: CloseArgs) -> i32 {
+        match self.entries.get_mut(args.fd as usize) {
+            Some(entry @ Some(_)) => {
+                *entry = None;
+                0
+            }
+            _ => -1,
+        }
+    }
+
+    /// Closes every file the program opened, leaving just the standard streams.
+    pub fn reset(&mut self) {
+        self.entries.truncate(3);
+    }
+
+    fn entry(&mut self, fd: u32) -> Option<&mut FileEntry> {
+        self.entries.get_mut(fd as usize)?.as_mut()
+    }
+
+    /// The real path of `path` inside `root`. A file that doesn't exist yet
+    /// can only be resolved if it's about to be `create`d.
+    fn resolve(&self, path: &[u8], create: bool) -> Option<PathBuf> {
+        let path = std::str::from_utf8(path).ok()?;
+        let path = Path::new(path);
+
+        let mut resolved = self.root.clone();
+        let mut depth = 0usize;
+
+        for component in path.components() {
+            match component {
+                Component::Normal(part) => {
+                    resolved.push(part);
+                    depth += 1;
+                }
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    depth = depth.checked_sub(1)?;
+                    resolved.pop();
+                }
+                Component::RootDir | Component::Prefix(_) => return None,
+            }
+        }
+
+        // symlinks inside the root can still point outside of it
+        let root = self.root.canonicalize().ok()?;
+        let real = match resolved.canonicalize() {
+            Ok(real) => real,
+            // only the directory it's created in exists so far
+            Err(_) if create && resolved.symlink_metadata().is_err() => {
+                let name = resolved.file_name()?;
+                resolved.parent()?.canonicalize().ok()?.join(name)
+            }
+            Err(_) => return None,
+        };
+
+        real.starts_with(root).then_some(real)
+    }
+}
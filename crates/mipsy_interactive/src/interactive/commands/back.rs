@@ -1,4 +1,4 @@
-use crate::interactive::{error::CommandError, prompt};
+use crate::interactive::error::CommandError;
 
 use super::*;
 use colored::*;
@@ -32,6 +32,8 @@ pub(crate) fn back_command() -> Command {
                 None => Ok(1),
             }?;
 
+            util::expect_history(state)?;
+
             let mut backs = 0;
             for _ in 0..times {
                 let runtime = state.runtime.as_mut().ok_or(CommandError::MustLoadFile)?;
@@ -39,27 +41,13 @@ pub(crate) fn back_command() -> Command {
                 if runtime.timeline_mut().pop_last_state() {
                     backs += 1;
                     state.exited = false;
+                    state.debugger.discard_pending();
                 } else if backs == 0 {
                     return Err(CommandError::CannotStepFurtherBack);
                 }
             }
 
-            let binary  = state.binary.as_ref().ok_or(CommandError::MustLoadFile)?;
-            let runtime = state.runtime.as_ref().ok_or(CommandError::MustLoadFile)?;
-
-            let pluralise = if backs != 1 { "s" } else { "" };
-
-            let mut text = format!("stepped back {} instruction{}", backs.to_string().magenta(), pluralise);
-            if backs < times {
-                text.push_str(" (reached start of program)");
-            }
-            text.push_str(", next instruction will be:");
-
-            prompt::success(text);
-            if let Ok(inst) = runtime.next_inst() {
-                util::print_inst(&state.iset, binary, inst, runtime.timeline().state().pc(), state.program.as_deref());
-            }
-            println!();
+            util::print_rewound(state, "stepped back", backs as usize, backs < times)?;
 
             Ok(())
         }
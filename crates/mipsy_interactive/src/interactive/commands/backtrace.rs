@@ -0,0 +1,57 @@
+use std::rc::Rc;
+
+use crate::interactive::{error::CommandError, prompt};
+
+use super::*;
+use colored::*;
+use mipsy_lib::error::runtime;
+
+pub(crate) fn backtrace_command() -> Command {
+    command(
+        "backtrace",
+        vec!["bt", "where"],
+        vec![],
+        vec![],
+        "prints the functions that are currently being executed",
+        &format!(
+            "Prints the call stack - the functions that have been called, but haven't yet returned.\n\
+             The current function is shown first, followed by the function that called it, and so on.\n\
+             Each function is shown with the label it was called at, where execution currently is in it,\n\
+         \x20 and the value of `{}{}` when it was called.\n\
+             Functions are called with `{}` or `{}`, and return with `{}`.",
+            "$".yellow(),
+            "sp".bold(),
+            "jal".bold(),
+            "jalr".bold(),
+            "jr".bold(),
+        ),
+        |state, _label, _args| {
+            if state.exited {
+                return Err(CommandError::ProgramExited);
+            }
+
+            let program = state.program.as_ref().ok_or(CommandError::MustLoadFile)?;
+            let binary  = state.binary.as_ref().ok_or(CommandError::MustLoadFile)?;
+            let runtime = state.runtime.as_ref().ok_or(CommandError::MustLoadFile)?;
+
+            let source_code = program.iter()
+                .map(|(tag, content)| (Rc::from(&**tag), Rc::from(&**content)))
+                .collect::<Vec<_>>();
+
+            let lines = runtime::backtrace(binary, runtime.timeline().state(), &source_code);
+
+            if lines.is_empty() {
+                prompt::error_nl("no functions have been called yet");
+                return Ok(());
+            }
+
+            println!("\n{}", "[backtrace]".green().bold());
+            for line in lines {
+                println!("{}", line);
+            }
+            println!();
+
+            Ok(())
+        }
+    )
+}
@@ -59,7 +59,7 @@ pub(crate) fn breakpoint_command() -> Command {
             let arg = mipsy_parser::parse_argument(&args[1], state.config.tab_size)
                     .map_err(|_| get_error())?;
 
-            let binary = state.binary.as_mut().ok_or(CommandError::MustLoadFile)?;
+            let binary = state.binary.as_ref().ok_or(CommandError::MustLoadFile)?;
 
             match arg {
                 MpArgument::Number(MpNumber::Immediate(ref imm)) => {
@@ -83,6 +83,12 @@ pub(crate) fn breakpoint_command() -> Command {
                                 true
                             )
                         }
+                        MpImmediate::Relocation(relocation, value) => {
+                            (relocation.apply(util::evaluate(binary, &value.0, "<addr>")?) as u32, false)
+                        }
+                        MpImmediate::Expression(value) => {
+                            (util::evaluate(binary, &value.0, "<addr>")?, false)
+                        }
                     };
 
                     if addr % 4 != 0 {
@@ -91,7 +97,7 @@ pub(crate) fn breakpoint_command() -> Command {
                     }
 
                     if remove {
-                        if !binary.breakpoints.contains(&addr) {
+                        if state.debugger.breakpoint(addr).is_none() {
                             prompt::error_nl(format!(
                                 "breakpoint at {} doesn't exist", 
                                 if is_label {
@@ -103,7 +109,7 @@ pub(crate) fn breakpoint_command() -> Command {
 
                             return Ok(());
                         }
-                    } else if binary.breakpoints.contains(&addr) {
+                    } else if state.debugger.breakpoint(addr).is_some() {
                         prompt::error_nl(format!(
                             "breakpoint at {} already exists", 
                             if is_label {
@@ -118,10 +124,10 @@ pub(crate) fn breakpoint_command() -> Command {
 
                     let action = 
                         if remove {
-                            binary.breakpoints.retain(|&x| addr != x);
+                            state.debugger.remove_breakpoint(addr);
                             "removed"
                         } else {
-                            binary.breakpoints.push(addr);
+                            state.debugger.insert_breakpoint(addr);
                             "inserted"
                         };
 
@@ -1,4 +1,5 @@
 use crate::interactive::{error::CommandError, prompt};
+use mipsy_parser::label_display_name;
 
 use super::*;
 use colored::*;
@@ -23,20 +24,22 @@ pub(crate) fn breakpoints_command() -> Command {
              "break".bold(),
         ),
         |state, _label, _args| {
-            let binary = state.binary.as_mut().ok_or(CommandError::MustLoadFile)?;
+            let binary = state.binary.as_ref().ok_or(CommandError::MustLoadFile)?;
 
-            if binary.breakpoints.is_empty() {
+            if state.debugger.breakpoints().next().is_none() {
                 prompt::error_nl("no breakpoints set");
                 return Ok(());
             }
 
-            let mut breakpoints = binary.breakpoints.iter()
-                    .map(|&addr| {
+            let mut breakpoints = state.debugger.breakpoints()
+                    .map(|breakpoint| breakpoint.addr)
+                    .map(|addr| {
                         (
                             addr,
                             binary.labels.iter()
                                 .find(|(_, &val)| val == addr)
-                                .map(|(name, _)| (
+                                .map(|(name, _)| label_display_name(name))
+                                .map(|name| (
                                     format!("{}", name.yellow().bold()),
                                     name.len()
                                 ))
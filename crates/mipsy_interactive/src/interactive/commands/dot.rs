@@ -33,12 +33,13 @@ pub(crate) fn dot_command() -> Command {
                 vec![],
             );
 
-            compile::check_pre(&program)
+            // warnings aren't useful for a single line, so only errors are reported
+            compile::check_pre(&program, &state.config, &state.iset)
                     .map_err(|error| CommandError::CannotCompileLine { line: line.to_string(), error })?;
 
             let binary  = state.binary.as_ref().ok_or(CommandError::MustLoadFile)?;
 
-            compile::check_post_data_label(&program, binary)
+            compile::check_post_data_label(&program, &MpProgram::new(vec![], vec![]), &state.config, binary)
                     .map_err(|error| CommandError::CannotCompileLine { line: line.to_string(), error })?;
 
             let opcodes = mipsy_lib::compile1(binary, &state.iset, &inst)
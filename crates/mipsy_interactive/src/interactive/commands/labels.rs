@@ -1,5 +1,6 @@
 use crate::interactive::{error::CommandError};
 use mipsy_lib::DATA_BOT;
+use mipsy_parser::label_display_name;
 
 use super::*;
 use colored::*;
@@ -17,12 +18,12 @@ pub(crate) fn labels_command() -> Command {
 
             let max_len = binary.labels.keys()
                 .filter(|label| !label.starts_with("kernel__"))
-                .map(|label| label.len())
+                .map(|label| label_display_name(label).len())
                 .max()
                 .unwrap_or(0);
             
             let mut entries: Vec<(String, u32)> = binary.labels.iter()
-                    .map(|(key, &val)| (key.to_string(), val))
+                    .map(|(key, &val)| (label_display_name(key).to_string(), val))
                     .filter(|(key, _)| !key.starts_with("kernel__"))
                     .collect();
 
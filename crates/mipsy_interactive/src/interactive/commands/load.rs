@@ -1,8 +1,12 @@
+use std::rc::Rc;
+
 use crate::interactive::{error::CommandError, prompt};
 
 use super::*;
 use colored::*;
 use mipsy_parser::TaggedFile;
+use mipsy_lib::runtime::Debugger;
+use mipsy_utils::Limits;
 
 pub(crate) fn load_command() -> Command {
     command_varargs(
@@ -30,7 +34,7 @@ pub(crate) fn load_command() -> Command {
                 }
             };
 
-            let program: Vec<_> = files.iter()
+            let mut program: Vec<_> = files.iter()
                     .map(|path| {
                         match std::fs::read_to_string(path) {
                             Ok(content) => Ok((path.to_string(), content)),
@@ -39,9 +43,13 @@ pub(crate) fn load_command() -> Command {
                     })
                     .collect::<Result<_, _>>()?;
 
+            let included = mipsy_lib::include_files(&mut program, &state.config, |path| std::fs::read_to_string(path));
+
             state.program = Some(program);
             let program = state.program.as_ref().unwrap();
 
+            included.map_err(|err| CommandError::CannotCompile { mipsy_error: err })?;
+
             let binary_files = program.iter()
                     .map(|(path, file)| TaggedFile::new(Some(path), file))
                     .collect::<Vec<_>>();
@@ -49,11 +57,35 @@ pub(crate) fn load_command() -> Command {
             let binary = mipsy_lib::compile(&state.iset, binary_files, &state.config)
                 .map_err(|err| CommandError::CannotCompile { mipsy_error: err })?;
 
-            let runtime = mipsy_lib::runtime(&binary, &arguments.iter().map(|x| &**x).collect::<Vec<_>>());
+            for warning in binary.warnings.iter() {
+                prompt::warning(format!("while compiling `{}`", warning.file_tag()));
+
+                let file_tag = warning.file_tag();
+
+                let file = program
+                    .iter()
+                    .find(|(tag, _)| **tag == *file_tag)
+                    .map(|(_, str)| Rc::from(&**str))
+                    .unwrap_or_else(|| Rc::from(""));
+
+                warning.show_warning(&state.config, file);
+                eprintln!();
+            }
+
+            let mut runtime = mipsy_lib::runtime(&binary, &arguments.iter().map(|x| &**x).collect::<Vec<_>>());
+            runtime.timeline_mut().set_history_policy(state.config.history);
+            runtime.set_check_calling_convention(state.config.check_calling_convention);
+            runtime.set_exceptions(state.config.exceptions);
+            // the program spends most of its time paused in interactive mode,
+            // so there's no sensible time to measure against
+            runtime.set_limits(Limits { max_time_ms: None, ..state.config.limits });
+            runtime.set_profiling(true);
 
-            state.binary  = Some(binary);
-            state.runtime = Some(runtime);
-            state.exited  = false;
+            state.binary   = Some(binary);
+            state.runtime  = Some(runtime);
+            state.debugger = Debugger::new();
+            state.exited   = false;
+            state.files.reset();
 
             let loaded = if program.len() == 1 {
                 "file loaded"
@@ -1,4 +1,5 @@
 mod back;
+mod backtrace;
 mod breakpoint;
 mod breakpoints;
 mod context;
@@ -10,14 +11,23 @@ mod load;
 mod label;
 mod labels;
 mod print;
+mod profile;
+mod rcontinue;
 mod reset;
+mod rewind;
+mod restore;
+mod rstep_out;
 mod run;
+mod save;
 mod step;
 mod step2input;
 mod step2syscall;
+mod watch;
+mod watchpoints;
 pub(crate) mod util;
 
 pub(crate) use back::back_command;
+pub(crate) use backtrace::backtrace_command;
 pub(crate) use breakpoint::breakpoint_command;
 pub(crate) use breakpoints::breakpoints_command;
 pub(crate) use context::context_command;
@@ -29,11 +39,19 @@ pub(crate) use load::load_command;
 pub(crate) use label::label_command;
 pub(crate) use labels::labels_command;
 pub(crate) use print::print_command;
+pub(crate) use profile::profile_command;
+pub(crate) use rcontinue::rcontinue_command;
 pub(crate) use reset::reset_command;
+pub(crate) use rewind::rewind_command;
+pub(crate) use restore::restore_command;
+pub(crate) use rstep_out::rstep_out_command;
 pub(crate) use run::run_command;
+pub(crate) use save::save_command;
 pub(crate) use step::step_command;
 pub(crate) use step2input::step2input_command;
 pub(crate) use step2syscall::step2syscall_command;
+pub(crate) use watch::{watch_command, rwatch_command, awatch_command};
+pub(crate) use watchpoints::watchpoints_command;
 
 use super::{error::CommandResult, State};
 
@@ -4,7 +4,7 @@ use crate::interactive::{error::CommandError, prompt};
 
 use super::*;
 use colored::*;
-use mipsy_lib::Register;
+use mipsy_lib::{Register, inst::register};
 use mipsy_parser::*;
 
 pub(crate) fn print_command() -> Command {
@@ -17,8 +17,9 @@ pub(crate) fn print_command() -> Command {
         &format!(
             "Prints the current value of an {0} in the loaded program.\n\
              {0} can be one of:\n\
-        \x20- a {1}: named (`{2}{3}`) or numbered (`{2}{4}`),\n\
+        \x20- a {1}: named (`{2}{3}`), numbered (`{2}{4}`) or floating point (`{2}{22}`),\n\
         \x20- a {5} {1}: `{2}{6}`, `{2}{7}`, `{2}{8}`,\n\
+        \x20- a {23} {1}: `{2}{24}`, `{2}{25}`, `{2}{26}`, `{2}{27}`,\n\
         \x20- an {9}: decimal (`4194304`), hex (`{10}400000`), labelled (`{11}`),\n\
         \x20- {12}: `{2}{13}` - prints all currently initialised registers.\n\
              {14} can optionally be specified (default: `{15}`) to specify how the value\n\
@@ -46,6 +47,12 @@ pub(crate) fn print_command() -> Command {
             "he".bold(),
             format!("{}{}", "c".yellow().bold(), "har".bold()),
             format!("{}{}", "s".yellow().bold(), "tring".bold()),
+            "f4".bold(),
+            "coprocessor 0".yellow().bold(),
+            "status".bold(),
+            "cause".bold(),
+            "epc".bold(),
+            "badvaddr".bold(),
         ),
         |state, _label, args| {
             let get_error = || CommandError::WithTip { 
@@ -98,6 +105,22 @@ pub(crate) fn print_command() -> Command {
                         }
 
                         println!(" {:4} = {}", "pc".bold(), format_simple_print(runtime.timeline().state().pc() as i32, print_type));
+
+                        // only interesting when exceptions can set them
+                        if runtime.exceptions() {
+                            for (reg_num, name) in register::COP0_REGISTERS {
+                                if let Ok(val) = runtime.timeline().state().read_cop0_register(reg_num) {
+                                    println!(" {:4} = {}", name.bold(), format_simple_print(val as i32, print_type));
+                                }
+                            }
+                        }
+
+                        for reg_num in 0..register::FLOAT_REGISTERS {
+                            if let Ok(val) = runtime.timeline().state().read_fp_register(reg_num) {
+                                let out = format_simple_print(val as i32, print_type);
+                                println!("{}{:4} = {}", "$".yellow(), format!("f{}", reg_num).bold(), out);
+                            }
+                        }
                     } else {
                         let (val, reg_name) = 
                         {
@@ -106,20 +129,26 @@ pub(crate) fn print_command() -> Command {
                                     let name = name.to_ascii_lowercase();
 
                                     if name == "pc" {
-                                        Ok((Ok(runtime.timeline().state().pc() as i32), "pc"))
+                                        Ok((Ok(runtime.timeline().state().pc() as i32), "pc".to_string()))
                                     } else if name == "hi" {
-                                        Ok((runtime.timeline().state().read_hi(), "hi"))
+                                        Ok((runtime.timeline().state().read_hi(), "hi".to_string()))
                                     } else if name == "lo" {
-                                        Ok((runtime.timeline().state().read_lo(), "lo"))
+                                        Ok((runtime.timeline().state().read_lo(), "lo".to_string()))
+                                    } else if let Some(&(reg_num, _)) = register::COP0_REGISTERS.iter().find(|&&(_, cop0_name)| cop0_name == name) {
+                                        Ok((runtime.timeline().state().read_cop0_register(reg_num).map(|val| val as i32), name.clone()))
+                                    } else if register::is_float_register_name(&name) {
+                                        register::float_register_from_str(&name)
+                                            .map(|reg_num| (runtime.timeline().state().read_fp_register(reg_num).map(|val| val as i32), name.clone()))
+                                            .map_err(|_| CommandError::UnknownRegister { register: name })
                                     } else {
                                         Register::from_str(&name)
-                                            .map(|reg| (runtime.timeline().state().read_register(reg.to_u32()), reg.to_lower_str()))
+                                            .map(|reg| (runtime.timeline().state().read_register(reg.to_u32()), reg.to_lower_str().to_string()))
                                             .map_err(|_| CommandError::UnknownRegister { register: name })
                                     }
                                 },
                                 MpRegisterIdentifier::Numbered(num) => {
                                     Register::from_number(num as i32)
-                                        .map(|reg| (runtime.timeline().state().read_register(reg.to_u32()), reg.to_lower_str()))
+                                        .map(|reg| (runtime.timeline().state().read_register(reg.to_u32()), reg.to_lower_str().to_string()))
                                         .map_err(|_| CommandError::UnknownRegister { register: num.to_string() })
                                 }
                             }?;
@@ -157,6 +186,12 @@ pub(crate) fn print_command() -> Command {
                             binary.get_label(&label)
                                     .map_err(|_| CommandError::UnknownLabel { label: label.to_string() })?
                         }
+                        MpImmediate::Relocation(relocation, value) => {
+                            relocation.apply(util::evaluate(binary, &value.0, "<item>")?) as u32
+                        }
+                        MpImmediate::Expression(value) => {
+                            util::evaluate(binary, &value.0, "<item>")?
+                        }
                     };
 
                     let map_err = |_err| CommandError::UninitialisedPrint { addr: imm };
@@ -0,0 +1,65 @@
+use std::rc::Rc;
+
+use crate::interactive::{error::CommandError, prompt};
+
+use super::*;
+use colored::*;
+use util::expect_u32;
+
+pub(crate) fn profile_command() -> Command {
+    command(
+        "profile",
+        vec!["prof"],
+        vec![],
+        vec!["lines"],
+        &format!(
+            "prints how many instructions each function and the top 10 (or {}) lines executed",
+            "[lines]".magenta(),
+        ),
+        &format!(
+            "Prints how many instructions the program has executed - in total, in each function,\n\
+         \x20 and on the 10 (or {0}) lines that executed the most instructions.\n\
+             Loads, stores, taken branches and syscalls are also counted.\n\
+             A function's {1} count is just the instructions in that function,\n\
+         \x20 while its {2} count also includes the functions it called.\n\
+             Stepping backwards doesn't uncount instructions - use `{3}` to start counting again.",
+            "[lines]".magenta(),
+            "exclusive".bold(),
+            "inclusive".bold(),
+            "reset".bold(),
+        ),
+        |state, label, args| {
+            let lines = match args.first() {
+                Some(arg) => expect_u32(
+                    label,
+                    &"[lines]".bright_magenta().to_string(),
+                    arg,
+                    None::<fn(i32) -> String>,
+                ),
+                None => Ok(10),
+            }?;
+
+            let program = state.program.as_ref().ok_or(CommandError::MustLoadFile)?;
+            let binary  = state.binary.as_ref().ok_or(CommandError::MustLoadFile)?;
+            let runtime = state.runtime.as_mut().ok_or(CommandError::MustLoadFile)?;
+
+            let profile = match runtime.profile() {
+                Some(profile) if profile.total().executed > 0 => profile,
+                _ => {
+                    prompt::error_nl("no instructions have been executed yet");
+                    return Ok(());
+                }
+            };
+
+            let source_code = program.iter()
+                .map(|(tag, content)| (Rc::from(&**tag), Rc::from(&**content)))
+                .collect::<Vec<_>>();
+
+            println!("\n{}", "[profile]".green().bold());
+            print!("{}", profile.report(binary, &source_code, lines as usize));
+            println!();
+
+            Ok(())
+        }
+    )
+}
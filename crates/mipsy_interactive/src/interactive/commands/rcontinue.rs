@@ -0,0 +1,50 @@
+use crate::interactive::error::CommandError;
+
+use super::*;
+use colored::*;
+use mipsy_lib::runtime::Rewound;
+
+pub(crate) fn rcontinue_command() -> Command {
+    command(
+        "rcontinue",
+        vec!["rc", "rcont"],
+        vec![],
+        vec![],
+        "run backwards until the previous breakpoint or watchpoint",
+        &format!(
+            "Runs the currently loaded program backwards, until it reaches the last point\n\
+         \x20 that a breakpoint or watchpoint would have stopped it at.\n\
+             If nothing was hit, it will stop at the start of the program.\n\
+             Breakpoints and watchpoints are not counted as hit when running backwards.\n\
+             To run forwards again, use `{}` or `{}`.",
+            "run".bold(),
+            "step".bold(),
+        ),
+        |state, _label, _args| {
+            util::expect_history(state)?;
+
+            let runtime = state.runtime.as_mut().ok_or(CommandError::MustLoadFile)?;
+
+            let len = runtime.timeline().timeline_len();
+            let rewound = runtime.rewind_until(&mut state.debugger);
+            let steps = len - runtime.timeline().timeline_len();
+
+            if steps == 0 {
+                return Err(CommandError::CannotStepFurtherBack);
+            }
+
+            state.exited = false;
+
+            let reached_start = match rewound {
+                Rewound::Debugger(reasons) => {
+                    state.show_stop_reasons(&reasons);
+
+                    false
+                }
+                Rewound::HistoryStart => true,
+            };
+
+            util::print_rewound(state, "rewound", steps, reached_start)
+        }
+    )
+}
@@ -0,0 +1,48 @@
+use crate::interactive::{error::CommandError, prompt};
+
+use super::*;
+use colored::*;
+use mipsy_lib::{runtime::Debugger, snapshot::Snapshot};
+use mipsy_utils::Limits;
+
+pub(crate) fn restore_command() -> Command {
+    command(
+        "restore",
+        vec![],
+        vec!["file"],
+        vec![],
+        "restore a program saved with `save`",
+        &format!(
+            "Restores a program saved with `{} {}`, overwriting whatever is currently loaded.\n\
+             The program is paused exactly where it was saved, but can't step back from there.\n\
+             `{}` still returns to the start of the program.",
+            "save".bold(),
+            "<file>".magenta(),
+            "reset".bold(),
+        ),
+        |state, _label, args| {
+            let path = &args[0];
+
+            let bytes = std::fs::read(path)
+                .map_err(|err| CommandError::CannotReadFile { path: path.clone(), os_error: err.to_string() })?;
+
+            let Snapshot { binary, files, mut runtime } = Snapshot::load(&bytes)
+                .map_err(|err| CommandError::CannotRestore { path: path.clone(), error: err.to_string() })?;
+
+            runtime.timeline_mut().set_history_policy(state.config.history);
+            runtime.set_limits(Limits { max_time_ms: None, ..runtime.limits() });
+            runtime.set_profiling(true);
+
+            state.program  = Some(files);
+            state.binary   = Some(binary);
+            state.runtime  = Some(runtime);
+            state.debugger = Debugger::new();
+            state.exited   = false;
+            state.files.reset();
+
+            prompt::success_nl(format!("program restored from `{}`", path.bold()));
+
+            Ok(())
+        }
+    )
+}
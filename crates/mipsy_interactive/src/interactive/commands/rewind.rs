@@ -0,0 +1,97 @@
+use std::str::FromStr;
+
+use crate::interactive::{error::CommandError, prompt};
+
+use super::*;
+use colored::*;
+use mipsy_lib::{Register, inst::register, runtime::state::{WRITE_MARKER_HI, WRITE_MARKER_LO}};
+use mipsy_parser::*;
+
+pub(crate) fn rewind_command() -> Command {
+    command(
+        "rewind",
+        vec!["rwd"],
+        vec!["register"],
+        vec![],
+        &format!("step backwards to where {} was last changed", "<register>".magenta()),
+        &format!(
+            "Steps backwards to the instruction that last changed {0}.\n\
+             {0} may be named (`{1}{2}`), numbered (`{1}{3}`), floating point (`{1}{4}`),\n\
+        \x20 or one of `{1}{5}`, `{1}{6}`.\n\
+             The next instruction will then be the one that changed {0}.\n\
+             May error if {0} hasn't been changed since the start of the retained history.",
+            "<register>".magenta(),
+            "$".yellow(),
+            "t3".bold(),
+            "12".bold(),
+            "f4".bold(),
+            "hi".bold(),
+            "lo".bold(),
+        ),
+        |state, label, args| {
+            let get_error = || CommandError::WithTip {
+                error: Box::new(CommandError::BadArgument { arg: "<register>".magenta().to_string(), instead: args[0].to_string() }),
+                tip: format!("try `{} {}`", "help".bold(), label.bold()),
+            };
+
+            let ident = match mipsy_parser::parse_argument(&args[0], state.config.tab_size) {
+                Ok(MpArgument::Register(MpRegister::Normal(ident))) => ident,
+                _ => return Err(get_error()),
+            };
+
+            util::expect_history(state)?;
+
+            let runtime = state.runtime.as_mut().ok_or(CommandError::MustLoadFile)?;
+
+            let (last_write, name) = match ident {
+                MpRegisterIdentifier::Named(name) => {
+                    let name = name.to_ascii_lowercase();
+
+                    if name == "hi" {
+                        (runtime.timeline().last_write(WRITE_MARKER_HI), name)
+                    } else if name == "lo" {
+                        (runtime.timeline().last_write(WRITE_MARKER_LO), name)
+                    } else if register::is_float_register_name(&name) {
+                        let reg_num = register::float_register_from_str(&name)
+                            .map_err(|_| CommandError::UnknownRegister { register: name.clone() })?;
+
+                        (runtime.timeline().last_fp_write(reg_num), name)
+                    } else {
+                        let register = Register::from_str(&name)
+                            .map_err(|_| CommandError::UnknownRegister { register: name })?;
+
+                        (runtime.timeline().last_write(register.to_u32()), register.to_lower_str().to_string())
+                    }
+                }
+                MpRegisterIdentifier::Numbered(num) => {
+                    let register = Register::from_number(num as i32)
+                        .map_err(|_| CommandError::UnknownRegister { register: num.to_string() })?;
+
+                    (runtime.timeline().last_write(register.to_u32()), register.to_lower_str().to_string())
+                }
+            };
+
+            // the state before the one in which the register was written,
+            // so that the next instruction is the one that wrote it
+            let target = match last_write {
+                Some(index) if index > runtime.timeline().history_start() => index - 1,
+                _ => {
+                    let since = if runtime.timeline().discarded_states() == 0 {
+                        "the start of the program"
+                    } else {
+                        "the start of the retained history"
+                    };
+
+                    prompt::error_nl(format!("{}{} hasn't been changed since {}", "$".yellow(), name.bold(), since));
+                    return Ok(());
+                }
+            };
+
+            let steps = runtime.timeline_mut().rewind_to(target);
+            state.exited = false;
+            state.debugger.discard_pending();
+
+            util::print_rewound(state, "rewound", steps, false)
+        }
+    )
+}
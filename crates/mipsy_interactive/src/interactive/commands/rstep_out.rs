@@ -0,0 +1,40 @@
+use crate::interactive::{error::CommandError, prompt};
+
+use super::*;
+use colored::*;
+
+pub(crate) fn rstep_out_command() -> Command {
+    command(
+        "rstep-out",
+        vec!["rso", "rfinish"],
+        vec![],
+        vec![],
+        "step backwards to where the current function was called",
+        &format!(
+            "Steps backwards to the `{}` or `{}` instruction that called the current function.\n\
+             Using `{}` will then step into the function again.\n\
+             May error if the call happened before the start of the retained history.",
+            "jal".bold(),
+            "jalr".bold(),
+            "step".bold(),
+        ),
+        |state, _label, _args| {
+            util::expect_history(state)?;
+
+            let runtime = state.runtime.as_mut().ok_or(CommandError::MustLoadFile)?;
+
+            let len = runtime.timeline().timeline_len();
+
+            if !runtime.rewind_to_call() {
+                prompt::error_nl("couldn't find where the current function was called");
+                return Ok(());
+            }
+
+            let steps = len - runtime.timeline().timeline_len();
+            state.exited = false;
+            state.debugger.discard_pending();
+
+            util::print_rewound(state, "rewound", steps, false)
+        }
+    )
+}
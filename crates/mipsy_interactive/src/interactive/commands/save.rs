@@ -0,0 +1,45 @@
+use crate::interactive::{error::CommandError, prompt};
+
+use super::*;
+use colored::*;
+use mipsy_lib::snapshot::Snapshot;
+
+pub(crate) fn save_command() -> Command {
+    command(
+        "save",
+        vec![],
+        vec!["file"],
+        vec![],
+        "save the paused program to a file",
+        &format!(
+            "Saves the currently loaded program to {}, exactly where it is paused -\n\
+             along with the files it was loaded from. It can be picked up again later,\n\
+             by anyone, using `{} {}`.\n\
+             Only the current state is saved, so once restored, the program can't\n\
+             step back past where it was saved.",
+            "<file>".magenta(),
+            "restore".bold(),
+            "<file>".magenta(),
+        ),
+        |state, _label, args| {
+            let path = &args[0];
+
+            let binary  = state.binary.as_ref().ok_or(CommandError::MustLoadFile)?;
+            let runtime = state.runtime.as_ref().ok_or(CommandError::MustLoadFile)?;
+            let program = state.program.as_deref().unwrap_or_default();
+
+            if state.exited {
+                return Err(CommandError::ProgramExited);
+            }
+
+            let snapshot = Snapshot::save(binary, program, runtime);
+
+            std::fs::write(path, snapshot)
+                .map_err(|err| CommandError::CannotWriteFile { path: path.clone(), os_error: err.to_string() })?;
+
+            prompt::success_nl(format!("program saved to `{}`", path.bold()));
+
+            Ok(())
+        }
+    )
+}
@@ -1,7 +1,9 @@
-use mipsy_lib::{KTEXT_BOT, decompile::Uninit};
-use crate::interactive::{error::{CommandError, CommandResult}, prompt};
+use mipsy_lib::{KTEXT_BOT, Safe, decompile::Uninit, runtime::debugger::{WatchKind, WatchTarget, WatchValue}};
+use crate::interactive::{State, error::{CommandError, CommandResult}, prompt};
+use mipsy_utils::HistoryPolicy;
 use colored::*;
-use mipsy_lib::{Binary, decompile::Decompiled, InstSet, decompile::decompile_inst_into_parts};
+use mipsy_lib::{Binary, decompile::Decompiled, InstSet, decompile::decompile_inst_into_parts, error::{InternalError, compiler}};
+use mipsy_parser::{label_display_name, MpConstValue};
 
 pub(crate) fn expect_u32<F>(command: &str, name: &str, arg: &str, neg_tip: Option<F>) -> CommandResult<u32>
 where
@@ -28,6 +30,18 @@ where
     }
 }
 
+/// Evaluates an expression of labels and constants,
+/// given as the argument `arg`, such as `array + 4`.
+pub(crate) fn evaluate(binary: &Binary, value: &MpConstValue, arg: &str) -> CommandResult<u32> {
+    binary.evaluate(value)
+        .map_err(|error| match error {
+            InternalError::Compiler(compiler::Error::UnresolvedLabel { label, .. } | compiler::Error::LocalLabel { label, .. }) => {
+                CommandError::UnknownLabel { label }
+            }
+            _ => CommandError::BadArgument { arg: arg.magenta().to_string(), instead: value.to_string() },
+        })
+}
+
 pub(crate) fn print_inst_parts(binary: &Binary, parts: &Result<Decompiled, Uninit>, files: Option<&[(String, String)]>, highlight: bool) {
     let labels = match parts {
         Ok(ok)      => &ok.labels,
@@ -168,6 +182,43 @@ pub(crate) fn print_inst(iset: &InstSet, binary: &Binary, inst: u32, addr: u32,
     print_inst_parts(binary, &Ok(parts), files, false);
 }
 
+/// Checks that a program is loaded and that it can be stepped backwards.
+pub(crate) fn expect_history(state: &State) -> CommandResult<()> {
+    let runtime = state.runtime.as_ref().ok_or(CommandError::MustLoadFile)?;
+
+    if let HistoryPolicy::Disabled = runtime.timeline().history_policy() {
+        return Err(CommandError::HistoryDisabled);
+    }
+
+    Ok(())
+}
+
+/// Reports that the program was stepped backwards, followed by the next instruction.
+pub(crate) fn print_rewound(state: &State, action: &str, steps: usize, reached_start: bool) -> CommandResult<()> {
+    let binary  = state.binary.as_ref().ok_or(CommandError::MustLoadFile)?;
+    let runtime = state.runtime.as_ref().ok_or(CommandError::MustLoadFile)?;
+
+    let pluralise = if steps != 1 { "s" } else { "" };
+
+    let mut text = format!("{} {} instruction{}", action, steps.to_string().magenta(), pluralise);
+    if reached_start {
+        if runtime.timeline().discarded_states() == 0 {
+            text.push_str(" (reached start of program)");
+        } else {
+            text.push_str(" (reached start of retained history)");
+        }
+    }
+    text.push_str(", next instruction will be:");
+
+    prompt::success(text);
+    if let Ok(inst) = runtime.next_inst() {
+        print_inst(&state.iset, binary, inst, runtime.timeline().state().pc(), state.program.as_deref());
+    }
+    println!();
+
+    Ok(())
+}
+
 pub(crate) fn get_last_line(binary: &Binary, addr: u32) -> u32 {
     let mut last_line = 1;
 
@@ -183,4 +234,80 @@ pub(crate) fn get_last_line(binary: &Binary, addr: u32) -> u32 {
     }
 
     last_line
-}
\ No newline at end of file
+}
+pub(crate) fn watch_kind_name(kind: WatchKind) -> &'static str {
+    match kind {
+        WatchKind::Write  => "write",
+        WatchKind::Read   => "read",
+        WatchKind::Access => "access",
+    }
+}
+
+/// Whether two watchpoints watch the same thing, ignoring the size of watched memory.
+pub(crate) fn same_watch_target(a: &WatchTarget, b: &WatchTarget) -> bool {
+    match (a, b) {
+        (WatchTarget::Memory { addr: addr_a, kind: kind_a, .. }, WatchTarget::Memory { addr: addr_b, kind: kind_b, .. }) => {
+            addr_a == addr_b && kind_a == kind_b
+        }
+        _ => a == b,
+    }
+}
+
+pub(crate) fn describe_watch_target(binary: &Binary, target: &WatchTarget) -> String {
+    match target {
+        WatchTarget::Register(register) => {
+            format!("{}{}", "$".yellow(), register.to_lower_str().bold())
+        }
+        WatchTarget::Memory { addr, len, .. } => {
+            let bytes = if *len == 1 { "byte" } else { "bytes" };
+
+            let label = binary.labels.iter()
+                .find(|(_, &label_addr)| label_addr == *addr)
+                .map(|(name, _)| label_display_name(name));
+
+            match label {
+                Some(label) => format!("{} ({}{:08x}, {} {})", label.yellow().bold(), "0x".yellow(), addr, len, bytes),
+                None => format!("{}{:08x} ({} {})", "0x".yellow(), addr, len, bytes),
+            }
+        }
+        WatchTarget::Condition(_) => {
+            String::from("a condition")
+        }
+    }
+}
+
+pub(crate) fn format_watch_value(value: &WatchValue) -> String {
+    match value {
+        WatchValue::Register(Safe::Valid(value)) => {
+            format!("{} ({}{:08x})", value, "0x".yellow(), value)
+        }
+        WatchValue::Register(Safe::Uninitialised) => {
+            "uninitialised".red().to_string()
+        }
+        WatchValue::Memory(bytes) if bytes.iter().all(|byte| byte.as_option().is_some()) && matches!(bytes.len(), 1 | 2 | 4) => {
+            let value = bytes.iter()
+                .rev()
+                .fold(0u32, |value, byte| (value << 8) | *byte.as_option().unwrap() as u32);
+
+            let signed = match bytes.len() {
+                1 => value as u8  as i8  as i32,
+                2 => value as u16 as i16 as i32,
+                _ => value as i32,
+            };
+
+            format!("{} ({}{:0width$x})", signed, "0x".yellow(), value, width = bytes.len() * 2)
+        }
+        WatchValue::Memory(bytes) => {
+            bytes.iter()
+                .map(|byte| match byte {
+                    Safe::Valid(byte)   => format!("{:02x}", byte),
+                    Safe::Uninitialised => "__".red().to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        }
+        WatchValue::Condition(value) => {
+            value.to_string()
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use std::str::FromStr;
+
+use crate::interactive::{error::CommandError, prompt};
+
+use super::*;
+use colored::*;
+use mipsy_lib::{Register, runtime::debugger::{WatchKind, WatchTarget}};
+use mipsy_parser::*;
+
+pub(crate) fn watch_command() -> Command {
+    command(
+        "watch",
+        vec!["wa", "wat"],
+        vec!["i|d", "item"],
+        vec!["size"],
+        &format!(
+            "{}nserts or {}eletes a watchpoint on a register or {}",
+            "<i>".magenta(),
+            "<d>".magenta(),
+            "<item>".magenta(),
+        ),
+        &format!(
+            "{0}nserts or {1}eletes a watchpoint on the specified {2}.\n\
+             {2} may be: a register (`{3}{4}`), a decimal address (`268500992`),\n\
+        \x20            a hex address (`{5}10010000`), or a label (`{6}`).\n\
+             {7} must be `i`, `in`, `ins`, `insert`, or `add` to insert the watchpoint, or\n\
+        \x20             `d`, `del`, `delete`, or `remove` to remove the watchpoint.\n\
+             For addresses, {8} is how many bytes to watch (default: `{9}`),\n\
+        \x20 and may be a number, or one of `{10}`, `{11}`, `{9}`.\n\
+             When running or stepping through your program, execution will pause whenever\n\
+        \x20 the watched value changes, showing the old value, the new value, and the\n\
+        \x20 instruction that changed it.\n\
+           \n{12} to pause when memory is read, use `{13}` or `{14}` instead",
+            "<i>".magenta(),
+            "<d>".magenta(),
+            "<item>".magenta(),
+            "$".yellow(),
+            "t0".bold(),
+            "0x".yellow(),
+            "my_array".yellow().bold(),
+            "<i|d>".magenta(),
+            "[size]".magenta(),
+            "word".bold(),
+            "byte".bold(),
+            "half".bold(),
+            format!(
+                "{}{}",
+                "tip".yellow().bold(), ":".bold(),
+            ),
+            "rwatch".bold(),
+            "awatch".bold(),
+        ),
+        |state, label, args| watch(state, label, args, WatchKind::Write),
+    )
+}
+
+pub(crate) fn rwatch_command() -> Command {
+    command(
+        "rwatch",
+        vec!["rw", "rwa"],
+        vec!["i|d", "addr"],
+        vec!["size"],
+        &format!(
+            "{}nserts or {}eletes a read watchpoint at an {}ess",
+            "<i>".magenta(),
+            "<d>".magenta(),
+            "<addr>".magenta(),
+        ),
+        &format!(
+            "{0}nserts or {1}eletes a read watchpoint at the specified {2}.\n\
+             {2} may be: a decimal address (`268500992`), a hex address (`{3}10010000`),\n\
+        \x20            or a label (`{4}`).\n\
+             {5} must be `i`, `in`, `ins`, `insert`, or `add` to insert the watchpoint, or\n\
+        \x20             `d`, `del`, `delete`, or `remove` to remove the watchpoint.\n\
+             {6} is how many bytes to watch (default: `{7}`),\n\
+        \x20 and may be a number, or one of `{8}`, `{9}`, `{7}`.\n\
+             When running or stepping through your program, execution will pause whenever\n\
+        \x20 a load instruction reads from the watched memory.",
+            "<i>".magenta(),
+            "<d>".magenta(),
+            "<addr>".magenta(),
+            "0x".yellow(),
+            "my_array".yellow().bold(),
+            "<i|d>".magenta(),
+            "[size]".magenta(),
+            "word".bold(),
+            "byte".bold(),
+            "half".bold(),
+        ),
+        |state, label, args| watch(state, label, args, WatchKind::Read),
+    )
+}
+
+pub(crate) fn awatch_command() -> Command {
+    command(
+        "awatch",
+        vec!["aw", "awa"],
+        vec!["i|d", "addr"],
+        vec!["size"],
+        &format!(
+            "{}nserts or {}eletes an access watchpoint at an {}ess",
+            "<i>".magenta(),
+            "<d>".magenta(),
+            "<addr>".magenta(),
+        ),
+        &format!(
+            "{0}nserts or {1}eletes an access watchpoint at the specified {2}.\n\
+             {2} may be: a decimal address (`268500992`), a hex address (`{3}10010000`),\n\
+        \x20            or a label (`{4}`).\n\
+             {5} must be `i`, `in`, `ins`, `insert`, or `add` to insert the watchpoint, or\n\
+        \x20             `d`, `del`, `delete`, or `remove` to remove the watchpoint.\n\
+             {6} is how many bytes to watch (default: `{7}`),\n\
+        \x20 and may be a number, or one of `{8}`, `{9}`, `{7}`.\n\
+             When running or stepping through your program, execution will pause whenever\n\
+        \x20 the watched memory is read or written, or changed by a syscall.",
+            "<i>".magenta(),
+            "<d>".magenta(),
+            "<addr>".magenta(),
+            "0x".yellow(),
+            "my_array".yellow().bold(),
+            "<i|d>".magenta(),
+            "[size]".magenta(),
+            "word".bold(),
+            "byte".bold(),
+            "half".bold(),
+        ),
+        |state, label, args| watch(state, label, args, WatchKind::Access),
+    )
+}
+
+fn watch(state: &mut State, label: &str, args: &[String], kind: WatchKind) -> CommandResult<()> {
+    let help = format!("try `{} {}`", "help".bold(), label.bold());
+
+    let remove = match &*args[0] {
+        "i" | "in" | "ins" | "insert" | "add" => false,
+        "d" | "del" | "delete" | "remove" => true,
+        _ => return Err(
+            CommandError::WithTip {
+                error: Box::new(CommandError::BadArgument {
+                    arg: "<i|d>".magenta().to_string(),
+                    instead: args[0].to_string()
+                }),
+                tip: help,
+        }),
+    };
+
+    let item_name = if kind == WatchKind::Write { "<item>" } else { "<addr>" };
+
+    let get_error = || CommandError::WithTip {
+        error: Box::new(CommandError::BadArgument { arg: item_name.magenta().to_string(), instead: args[1].to_string() }),
+        tip: help.clone(),
+    };
+
+    let arg = mipsy_parser::parse_argument(&args[1], state.config.tab_size)
+            .map_err(|_| get_error())?;
+
+    let binary = state.binary.as_ref().ok_or(CommandError::MustLoadFile)?;
+
+    let target = match arg {
+        MpArgument::Register(MpRegister::Normal(ident)) => {
+            if kind != WatchKind::Write {
+                prompt::error(format!("{} can only watch memory, not registers", label.bold()));
+                prompt::tip_nl(format!("try `{}` to watch a register for changes", format!("watch i {}", args[1]).bold()));
+                return Ok(());
+            }
+
+            let register = match ident {
+                MpRegisterIdentifier::Named(name) => {
+                    Register::from_str(&name)
+                        .map_err(|_| CommandError::UnknownRegister { register: name })?
+                }
+                MpRegisterIdentifier::Numbered(num) => {
+                    Register::from_number(num as i32)
+                        .map_err(|_| CommandError::UnknownRegister { register: num.to_string() })?
+                }
+            };
+
+            WatchTarget::Register(register)
+        }
+        MpArgument::Number(MpNumber::Immediate(ref imm)) => {
+            let addr = match imm {
+                MpImmediate::I16(imm) => *imm as u32,
+                MpImmediate::U16(imm) => *imm as u32,
+                MpImmediate::I32(imm) => *imm as u32,
+                MpImmediate::U32(imm) => *imm,
+                MpImmediate::LabelReference(label) => {
+                    binary.get_label(label)
+                        .map_err(|_| CommandError::UnknownLabel { label: label.to_string() })?
+                }
+                MpImmediate::Relocation(relocation, value) => {
+                    relocation.apply(util::evaluate(binary, &value.0, "<item>")?) as u32
+                }
+                MpImmediate::Expression(value) => {
+                    util::evaluate(binary, &value.0, "<item>")?
+                }
+            };
+
+            let len = match args.get(2).map(|size| &**size) {
+                None | Some("w") | Some("word") => 4,
+                Some("h") | Some("half") => 2,
+                Some("b") | Some("byte") => 1,
+                Some(size) => match size.parse::<u32>() {
+                    Ok(len) if len > 0 => len,
+                    _ => return Err(CommandError::WithTip {
+                        error: Box::new(CommandError::BadArgument { arg: "[size]".magenta().to_string(), instead: size.to_string() }),
+                        tip: help,
+                    }),
+                }
+            };
+
+            WatchTarget::Memory { addr, len, kind }
+        }
+        _ => return Err(get_error()),
+    };
+
+    let name = util::describe_watch_target(binary, &target);
+
+    let existing = state.debugger.watchpoints()
+        .find(|watchpoint| util::same_watch_target(&watchpoint.target, &target))
+        .map(|watchpoint| watchpoint.id);
+
+    match (remove, existing) {
+        (true, None) => {
+            prompt::error_nl(format!("{} watchpoint on {} doesn't exist", util::watch_kind_name(kind), name));
+        }
+        (false, Some(_)) => {
+            prompt::error_nl(format!("{} watchpoint on {} already exists", util::watch_kind_name(kind), name));
+        }
+        (true, Some(id)) => {
+            if let Some(watchpoint) = state.debugger.remove_watchpoint(id) {
+                let name = util::describe_watch_target(binary, &watchpoint.target);
+                prompt::success_nl(format!("{} watchpoint {} removed on {}", util::watch_kind_name(kind), id, name));
+            }
+        }
+        (false, None) => {
+            let id = state.debugger.insert_watchpoint(target);
+            prompt::success_nl(format!("{} watchpoint {} inserted on {}", util::watch_kind_name(kind), id, name));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use crate::interactive::{error::CommandError, prompt};
+
+use super::*;
+use colored::*;
+use mipsy_lib::runtime::debugger::{WatchKind, WatchTarget};
+
+pub(crate) fn watchpoints_command() -> Command {
+    command(
+        "watchpoints",
+        vec!["ws", "was", "wats", "watches"],
+        vec![],
+        vec![],
+        "lists currently set watchpoints",
+        &format!(
+            "Lists currently set watchpoints, and how many times each has been hit.\n\
+             When running or stepping through your program, a watchpoint will cause execution to\n\
+         \x20 pause temporarily when the watched register or memory is used.\n\
+           \n{} watchpoints are set with `{}`, `{}` and `{}`",
+             format!(
+                 "{}{}",
+                 "tip".yellow().bold(), ":".bold(),
+             ),
+             "watch".bold(),
+             "rwatch".bold(),
+             "awatch".bold(),
+        ),
+        |state, _label, _args| {
+            let binary = state.binary.as_ref().ok_or(CommandError::MustLoadFile)?;
+
+            if state.debugger.watchpoints().next().is_none() {
+                prompt::error_nl("no watchpoints set");
+                return Ok(());
+            }
+
+            println!("\n{}", "[watchpoints]".green().bold());
+            for watchpoint in state.debugger.watchpoints() {
+                let kind = match watchpoint.target {
+                    WatchTarget::Memory { kind, .. } => kind,
+                    _ => WatchKind::Write,
+                };
+
+                let hits = if watchpoint.hits == 1 { "hit" } else { "hits" };
+
+                println!(
+                    "{:>3}: {:6} {} [{} {}]",
+                    watchpoint.id.to_string().bold(),
+                    util::watch_kind_name(kind),
+                    util::describe_watch_target(binary, &watchpoint.target),
+                    watchpoint.hits,
+                    hits,
+                );
+            }
+            println!();
+
+            Ok(())
+        }
+    )
+}
@@ -11,6 +11,8 @@ pub enum CommandError {
     ArgExpectedU32     { arg: String, instead: String, },
     HelpUnknownCommand { command: String },
     CannotReadFile     { path: String, os_error: String, },
+    CannotWriteFile    { path: String, os_error: String, },
+    CannotRestore      { path: String, error: String, },
     CannotCompile      { mipsy_error: MipsyError },
     CannotParseLine    { line: String, error: ErrorLocation },
     CannotCompileLine  { line: String, error: MipsyError },
@@ -23,6 +25,7 @@ pub enum CommandError {
     ProgramExited,
 
     CannotStepFurtherBack,
+    HistoryDisabled,
     RuntimeError { mipsy_error: MipsyError },
     ReplRuntimeError { mipsy_error: MipsyError, line: String },
 
@@ -4,9 +4,9 @@ mod helper;
 mod error;
 mod runtime_handler;
 
-use std::{ops::Deref, rc::Rc};
+use std::{ops::Deref, path::PathBuf, rc::Rc};
 
-use mipsy_lib::{MipsyError, ParserError, error::{parser, runtime::ErrorContext}, runtime::SteppedRuntime};
+use mipsy_lib::{MipsyError, ParserError, error::{parser, runtime::ErrorContext}, runtime::{Debugger, SteppedRuntime, StoppedRuntime, StopReason, debugger::WatchValue}};
 use helper::MyHelper;
 
 use rustyline::{
@@ -32,6 +32,9 @@ use commands::{
 };
 
 use mipsy_utils::MipsyConfig;
+use mipsy_parser::label_display_name;
+
+use crate::FileTable;
 
 use self::error::{CommandError, CommandResult};
 
@@ -42,13 +45,15 @@ pub(crate) struct State {
     pub(crate) program: Option<Vec<(String, String)>>,
     pub(crate) binary:  Option<Binary>,
     pub(crate) runtime: Option<Runtime>,
+    pub(crate) debugger: Debugger,
+    pub(crate) files: FileTable,
     pub(crate) exited: bool,
     pub(crate) prev_command: Option<String>,
     pub(crate) confirm_exit: bool,
 }
 
 impl State {
-    fn new(config: MipsyConfig) -> Self {
+    fn new(config: MipsyConfig, file_root: PathBuf) -> Self {
         Self {
             config,
             iset: mipsy_instructions::inst_set(),
@@ -56,6 +61,8 @@ impl State {
             program: None,
             binary:  None,
             runtime: None,
+            debugger: Debugger::new(),
+            files: FileTable::new(file_root),
             exited: false,
             prev_command: None,
             confirm_exit: false,
@@ -164,6 +171,12 @@ impl State {
             CommandError::CannotReadFile { path, os_error, } => {
                 prompt::error(format!("failed to read file `{}`: {}", path, os_error));
             }
+            CommandError::CannotWriteFile { path, os_error, } => {
+                prompt::error(format!("failed to write file `{}`: {}", path, os_error));
+            }
+            CommandError::CannotRestore { path, error, } => {
+                prompt::error(format!("failed to restore `{}`: {}", path, error));
+            }
             CommandError::CannotCompile  { mipsy_error } => {
                 let file_tag = match mipsy_error {
                     MipsyError::Parser(ref error) => error.file_tag(),
@@ -216,6 +229,10 @@ impl State {
             CommandError::CannotStepFurtherBack => {
                 prompt::error("can't step any further back")
             }
+            CommandError::HistoryDisabled => {
+                prompt::error("stepping backwards is disabled");
+                prompt::tip(format!("set `{}` in your mipsy config to enable it", "history".bold()));
+            }
             CommandError::RuntimeError { mipsy_error, } => {
                 self.mipsy_error(mipsy_error, ErrorContext::Interactive, None);
             }
@@ -353,6 +370,7 @@ impl State {
                         self.exited = true;
                         
                         runtime_handler::sys10_exit(verbose);
+                        self.check_expected_exit_code(0);
                     }
                     PrintChar(args, new_runtime) => {
                         self.runtime = Some(new_runtime);
@@ -363,19 +381,19 @@ impl State {
                         self.runtime = Some(guard(value));
                     }
                     Open(args, guard) => {
-                        let value = runtime_handler::sys13_open(verbose, args);
+                        let value = runtime_handler::sys13_open(verbose, &mut self.files, args);
                         self.runtime = Some(guard(value));
                     }
                     Read(args, guard) => {
-                        let value = runtime_handler::sys14_read(verbose, args);
+                        let value = runtime_handler::sys14_read(verbose, &mut self.files, args);
                         self.runtime = Some(guard(value));
                     }
                     Write(args, guard) => {
-                        let value = runtime_handler::sys15_write(verbose, args);
+                        let value = runtime_handler::sys15_write(verbose, &mut self.files, args);
                         self.runtime = Some(guard(value));
                     }
                     Close(args, guard) => {
-                        let value = runtime_handler::sys16_close(verbose, args);
+                        let value = runtime_handler::sys16_close(verbose, &mut self.files, args);
                         self.runtime = Some(guard(value));
                     }
                     ExitStatus(args, new_runtime) => {
@@ -383,6 +401,7 @@ impl State {
                         self.exited = true;
 
                         runtime_handler::sys17_exit_status(verbose, args.exit_code);
+                        self.check_expected_exit_code(args.exit_code);
                     }
                     Breakpoint(new_runtime) => {
                         self.runtime = Some(new_runtime);
@@ -406,34 +425,105 @@ impl State {
             }
         };
 
-        Ok(
-            if self.exited {
-                true
-            } else {
-                let pc = self.runtime.as_ref().unwrap().timeline().state().pc();
-                let binary = self.binary.as_ref().unwrap();
-    
-                if breakpoint || binary.breakpoints.contains(&pc) {
-                    let label = binary.labels.iter()
-                            .find(|(_, &addr)| addr == pc)
-                            .map(|(name, _)| name.yellow().bold().to_string());
-                    
-                    runtime_handler::breakpoint(label.as_deref(), pc);
-    
-                    true
-                } else if trapped {
-                    true
-                } else {
-                    false
-                }
+        if self.exited {
+            // nothing runs after the exit syscall, so there's nothing for it to trigger
+            self.debugger.discard_pending();
+
+            return Ok(true);
+        }
+
+        let reasons = self.debugger.check_pending(self.runtime.as_ref().unwrap());
+
+        if breakpoint {
+            let pc = self.runtime.as_ref().unwrap().timeline().state().pc();
+            self.show_breakpoint(pc);
+        }
+
+        self.show_stop_reasons(&reasons);
+
+        Ok(breakpoint || trapped || !reasons.is_empty())
+    }
+
+    pub(crate) fn eval_stopped_runtime(&mut self, verbose: bool, result: Result<StoppedRuntime, (Runtime, MipsyError)>) -> CommandResult<bool> {
+        match result {
+            Ok(StoppedRuntime::Guard(guard)) => {
+                self.eval_stepped_runtime(verbose, Ok(Err(guard)))
+            }
+            Ok(StoppedRuntime::Debugger(new_runtime, reasons)) => {
+                self.runtime = Some(new_runtime);
+                self.show_stop_reasons(&reasons);
+
+                Ok(true)
+            }
+            Ok(StoppedRuntime::StepLimit(new_runtime)) => {
+                self.runtime = Some(new_runtime);
+
+                Ok(false)
             }
-        )
+            Err(err) => {
+                self.eval_stepped_runtime(verbose, Err(err))
+            }
+        }
+    }
+
+    pub(crate) fn show_stop_reasons(&self, reasons: &[StopReason]) {
+        for reason in reasons {
+            match *reason {
+                StopReason::Breakpoint { addr, .. } => self.show_breakpoint(addr),
+                StopReason::Watchpoint { id, inst_addr, ref old, ref new } => self.show_watchpoint(id, inst_addr, old, new),
+            }
+        }
+    }
+
+    fn show_watchpoint(&self, id: u32, inst_addr: u32, old: &WatchValue, new: &WatchValue) {
+        let binary = self.binary.as_ref().unwrap();
+
+        // the watchpoint may have been hit by a syscall, after which it was removed
+        let target = self.debugger.watchpoint(id)
+            .map(|watchpoint| commands::util::describe_watch_target(binary, &watchpoint.target));
+
+        let old = commands::util::format_watch_value(old);
+        let new = commands::util::format_watch_value(new);
+
+        runtime_handler::watchpoint(id, target.as_deref(), &old, &new);
+
+        if let Ok(inst) = self.runtime.as_ref().unwrap().timeline().state().read_mem_word(inst_addr) {
+            commands::util::print_inst(&self.iset, binary, inst, inst_addr, self.program.as_deref());
+        }
+
+        println!();
+    }
+
+    fn show_breakpoint(&self, pc: u32) {
+        let binary = self.binary.as_ref().unwrap();
+
+        let label = binary.labels.iter()
+                .find(|(_, &addr)| addr == pc)
+                .map(|(name, _)| label_display_name(name).yellow().bold().to_string());
+
+        runtime_handler::breakpoint(label.as_deref(), pc);
+    }
+
+    fn check_expected_exit_code(&self, exit_code: i32) {
+        let expected_exit_code = self.binary.as_ref()
+            .and_then(|binary| binary.expected_exit_code);
+
+        if let Some(expected_exit_code) = expected_exit_code {
+            if exit_code != expected_exit_code {
+                prompt::warning(format!(
+                    "program exited with code {}, but was expected to exit with code {}",
+                    exit_code.to_string().bold(),
+                    expected_exit_code.to_string().bold(),
+                ));
+            }
+        }
     }
 
     pub(crate) fn step(&mut self, verbose: bool) -> CommandResult<bool> {
         let runtime = self.runtime.take().ok_or(CommandError::MustLoadFile)?;
+        let result = runtime.run_until(&mut self.debugger, Some(1));
 
-        self.eval_stepped_runtime(verbose, runtime.step())
+        self.eval_stopped_runtime(verbose, result)
     }
 
     pub(crate) fn exec_inst(&mut self, opcode: u32, verbose: bool) -> CommandResult<bool> {
@@ -448,7 +538,10 @@ impl State {
         }
 
         loop {
-            if self.step(false)? {
+            let runtime = self.runtime.take().ok_or(CommandError::MustLoadFile)?;
+            let result = runtime.run_until(&mut self.debugger, None);
+
+            if self.eval_stopped_runtime(false, result)? {
                 break;
             }
         }
@@ -458,7 +551,9 @@ impl State {
 
     pub(crate) fn reset(&mut self) -> CommandResult<()> {
         let runtime = self.runtime.as_mut().ok_or(CommandError::MustLoadFile)?;
-        runtime.timeline_mut().reset();
+        runtime.reset();
+        self.debugger.discard_pending();
+        self.files.reset();
         self.exited = false;
 
         Ok(())
@@ -488,20 +583,31 @@ fn editor() -> Editor<MyHelper> {
     rl
 }
 
-fn state(config: MipsyConfig) -> State {
-    let mut state = State::new(config);
+fn state(config: MipsyConfig, file_root: PathBuf) -> State {
+    let mut state = State::new(config, file_root);
 
     state.add_command(commands::load_command());
     state.add_command(commands::run_command());
     state.add_command(commands::step_command());
     state.add_command(commands::back_command());
+    state.add_command(commands::rcontinue_command());
+    state.add_command(commands::rstep_out_command());
+    state.add_command(commands::rewind_command());
     state.add_command(commands::step2syscall_command());
     state.add_command(commands::step2input_command());
     state.add_command(commands::reset_command());
+    state.add_command(commands::save_command());
+    state.add_command(commands::restore_command());
     state.add_command(commands::breakpoint_command());
     state.add_command(commands::breakpoints_command());
+    state.add_command(commands::watch_command());
+    state.add_command(commands::rwatch_command());
+    state.add_command(commands::awatch_command());
+    state.add_command(commands::watchpoints_command());
     state.add_command(commands::disassemble_command());
     state.add_command(commands::context_command());
+    state.add_command(commands::backtrace_command());
+    state.add_command(commands::profile_command());
     state.add_command(commands::label_command());
     state.add_command(commands::labels_command());
     state.add_command(commands::print_command());
@@ -512,9 +618,9 @@ fn state(config: MipsyConfig) -> State {
     state
 }
 
-pub fn launch(config: MipsyConfig) -> ! {
+pub fn launch(config: MipsyConfig, file_root: PathBuf) -> ! {
     let mut rl = editor();
-    let mut state = state(config);
+    let mut state = state(config, file_root);
 
     loop {
         let readline = rl.readline(state.prompt());
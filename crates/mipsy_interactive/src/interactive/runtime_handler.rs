@@ -1,5 +1,6 @@
 use std::{fmt::{Debug, Display}, str::FromStr};
 use super::{prompt};
+use crate::FileTable;
 use colored::*;
 use mipsy_lib::{runtime::{CloseArgs, OpenArgs, ReadArgs, WriteArgs}};
 use text_io::try_read;
@@ -248,20 +249,56 @@ pub(crate) fn sys12_read_char(verbose: bool, ) -> u8 {
         .unwrap_or(0)
 }
 
-pub(crate) fn sys13_open(_verbose: bool, _args: OpenArgs) -> i32 {
-    todo!()
+pub(crate) fn sys13_open(verbose: bool, files: &mut FileTable, args: OpenArgs) -> i32 {
+    let path = String::from_utf8_lossy(&args.path).to_string();
+    let flags = args.flags;
+    let fd = files.open(args);
+
+    if verbose {
+        prompt::syscall_nl(
+            13,
+            format!("open: \"{}\" [flags={:#x}] = {}", path.escape_default().to_string().green(), flags, fd.to_string().green()),
+        );
+    }
+
+    fd
 }
 
-pub(crate) fn sys14_read(_verbose: bool, _args: ReadArgs) -> (i32, Vec<u8>) {
-    todo!()
+pub(crate) fn sys14_read(verbose: bool, files: &mut FileTable, args: ReadArgs) -> (i32, Vec<u8>) {
+    if verbose {
+        prompt::syscall(14, format!("read [fd={}, size={}]: ", args.fd, args.len));
+        std::io::stdout().flush().unwrap();
+    }
+
+    let (n_bytes, bytes) = files.read(args);
+
+    if verbose {
+        println!("{}", n_bytes.to_string().green());
+    }
+
+    (n_bytes, bytes)
 }
 
-pub(crate) fn sys15_write(_verbose: bool, _args: WriteArgs) -> i32 {
-    todo!()
+pub(crate) fn sys15_write(verbose: bool, files: &mut FileTable, args: WriteArgs) -> i32 {
+    let fd = args.fd;
+    let n_bytes = files.write(args);
+
+    if verbose {
+        prompt::syscall_nl(15, format!("write [fd={}]: {}", fd, n_bytes.to_string().green()));
+    }
+
+    n_bytes
 }
 
-pub(crate) fn sys16_close(_verbose: bool, _args: CloseArgs) -> i32 {
-    todo!()
+pub(crate) fn sys16_close(verbose: bool, files: &mut FileTable, args: CloseArgs) -> i32 {
+    let fd = args.fd;
+    let result = files.close(args);
+
+    if verbose {
+        prompt::syscall_nl(16, format!("close [fd={}]: {}", fd, result.to_string().green()));
+    }
+
+    result
 }
 
 pub(crate) fn sys17_exit_status(verbose: bool, val: i32) {
@@ -304,3 +341,17 @@ pub(crate) fn breakpoint(label: Option<&str>, pc: u32) {
         "]".cyan().bold()
     );
 }
+
+pub(crate) fn watchpoint(id: u32, target: Option<&str>, old: &str, new: &str) {
+    match target {
+        Some(target) => println!("{}{}{} {}", "\n[WATCHPOINT ".cyan().bold(), id, "]".cyan().bold(), target),
+        None         => println!("{}{}{}",    "\n[WATCHPOINT ".cyan().bold(), id, "]".cyan().bold()),
+    }
+
+    if old == new {
+        println!("{}: {}", "value".bold(), new);
+    } else {
+        println!("{}: {}", "old value".bold(), old);
+        println!("{}: {}", "new value".bold(), new);
+    }
+}
@@ -1,4 +1,6 @@
 mod interactive;
+mod files;
 
 pub use interactive::launch;
 pub use interactive::prompt;
+pub use files::FileTable;
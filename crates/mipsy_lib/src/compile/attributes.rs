@@ -0,0 +1,161 @@
+use mipsy_parser::Attribute;
+
+/// # The attributes that mipsy understands.
+///
+/// Attributes are written as `#[name(value)]` before an item,
+/// or as `#![name(value)]` at the top of a file to apply to
+/// the whole file. Any other attribute is reported with an
+/// `unknown_attribute` warning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum KnownAttribute {
+    /// `allow(lint, ...)` - silences the given warnings.
+    Allow,
+    /// `warn(lint, ...)` - reports the given warnings, even if disabled by default.
+    Warn,
+    /// `deny(lint, ...)` - turns the given warnings into errors.
+    Deny,
+    /// `tabsize(n)` - how many columns a tab character takes up in the file.
+    TabSize,
+    /// `expected_exit_code(n)` - the exit code a correct run of the program ends with.
+    ExpectedExitCode,
+    /// `no_uninit_check` - reading uninitialised registers or memory gives zero,
+    /// rather than an error. On a label, this covers every instruction until the
+    /// next label.
+    NoUninitCheck,
+}
+
+/// # Where an attribute is allowed to be written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AttributePlacement {
+    /// Only at the top of a file, as `#![...]`.
+    File,
+    /// Only before an item, as `#[...]`.
+    Item,
+    /// Either of the above.
+    Any,
+}
+
+/// # The value an attribute takes, inside its parentheses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AttributeValue {
+    /// No value - eg. `#[no_uninit_check]`.
+    None,
+    /// A (possibly negative) 32-bit integer.
+    Integer,
+    /// An integer greater than zero.
+    PositiveInteger,
+    /// A comma-separated list of lint names.
+    Lints,
+}
+
+impl KnownAttribute {
+    pub const ALL: [KnownAttribute; 6] = [
+        KnownAttribute::Allow,
+        KnownAttribute::Warn,
+        KnownAttribute::Deny,
+        KnownAttribute::TabSize,
+        KnownAttribute::ExpectedExitCode,
+        KnownAttribute::NoUninitCheck,
+    ];
+
+    /// Looks up an attribute by name. Attribute names are case-insensitive.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter()
+            .copied()
+            .find(|attribute| attribute.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            KnownAttribute::Allow            => "allow",
+            KnownAttribute::Warn             => "warn",
+            KnownAttribute::Deny             => "deny",
+            KnownAttribute::TabSize          => "tabsize",
+            KnownAttribute::ExpectedExitCode => "expected_exit_code",
+            KnownAttribute::NoUninitCheck    => "no_uninit_check",
+        }
+    }
+
+    pub fn placement(&self) -> AttributePlacement {
+        match self {
+            KnownAttribute::Allow            => AttributePlacement::Any,
+            KnownAttribute::Warn             => AttributePlacement::Any,
+            KnownAttribute::Deny             => AttributePlacement::Any,
+            KnownAttribute::TabSize          => AttributePlacement::File,
+            KnownAttribute::ExpectedExitCode => AttributePlacement::File,
+            KnownAttribute::NoUninitCheck    => AttributePlacement::Any,
+        }
+    }
+
+    pub fn value(&self) -> AttributeValue {
+        match self {
+            KnownAttribute::Allow            => AttributeValue::Lints,
+            KnownAttribute::Warn             => AttributeValue::Lints,
+            KnownAttribute::Deny             => AttributeValue::Lints,
+            KnownAttribute::TabSize          => AttributeValue::PositiveInteger,
+            KnownAttribute::ExpectedExitCode => AttributeValue::Integer,
+            KnownAttribute::NoUninitCheck    => AttributeValue::None,
+        }
+    }
+
+    /// Whether `attribute` is this attribute.
+    pub fn is(&self, attribute: &Attribute) -> bool {
+        self.name().eq_ignore_ascii_case(attribute.key())
+    }
+}
+
+impl AttributePlacement {
+    pub fn allows(&self, file_level: bool) -> bool {
+        match self {
+            AttributePlacement::File => file_level,
+            AttributePlacement::Item => !file_level,
+            AttributePlacement::Any  => true,
+        }
+    }
+}
+
+impl AttributeValue {
+    /// Whether `value` (the text between the parentheses, if any)
+    /// is well-formed. Lint names are checked separately, as an
+    /// unknown lint is only a warning.
+    pub fn accepts(&self, value: Option<&str>) -> bool {
+        match (self, value.map(str::trim)) {
+            (AttributeValue::None, value) => value.is_none(),
+            (AttributeValue::Integer, Some(value)) => value.parse::<i32>().is_ok(),
+            (AttributeValue::PositiveInteger, Some(value)) => matches!(value.parse::<u32>(), Ok(n) if n > 0),
+            (AttributeValue::Lints, Some(value)) => lint_names(value).all(|name| !name.is_empty()),
+            (_, None) => false,
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            AttributeValue::None            => "no value",
+            AttributeValue::Integer         => "an integer",
+            AttributeValue::PositiveInteger => "a positive integer",
+            AttributeValue::Lints           => "a comma-separated list of warnings",
+        }
+    }
+}
+
+/// The lint names in the value of an `allow`, `warn` or `deny` attribute.
+pub fn lint_names(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(str::trim)
+}
+
+/// The last occurrence of `attribute` in `attributes`, if any.
+pub fn find_attribute(attributes: &[Attribute], attribute: KnownAttribute) -> Option<&Attribute> {
+    attributes.iter()
+        .rev()
+        .find(|attr| attribute.is(attr))
+}
+
+/// The integer value of the last occurrence of `attribute` in `attributes`,
+/// if it's present and valid.
+pub fn integer_attribute(attributes: &[Attribute], attribute: KnownAttribute) -> Option<i32> {
+    find_attribute(attributes, attribute)?
+        .value()?
+        .trim()
+        .parse()
+        .ok()
+}
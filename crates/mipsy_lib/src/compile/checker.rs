@@ -1,48 +1,163 @@
-use std::rc::Rc;
+use std::{collections::HashSet, rc::Rc};
 
-use mipsy_parser::{MpArgument, MpImmediate, MpItem, MpNumber};
+use mipsy_parser::{Attribute, label_display_name, MpArgument, MpConstValue, MpDirective, MpImmediate, MpInstruction, MpItem, MpNumber, MpRegister};
+use mipsy_utils::MipsyConfig;
 
-use crate::{Binary, MpProgram, MipsyResult, error::{ToMipsyResult, compiler}, inst::instruction::ToRegister, HEAP_BOT, DATA_BOT, MipsyError, CompilerError};
+use crate::{Binary, MpProgram, MipsyResult, InstSet, error::{ToMipsyResult, compiler, warning::{Lint, LintLevel, Warning}}, inst::instruction::{SignatureRef, ToRegister}, HEAP_BOT, DATA_BOT, MipsyError, CompilerError, CompilerWarning};
+use super::{Scope, attributes::{AttributeValue, KnownAttribute, lint_names}, text::find_instruction};
 
-pub enum Warning {
+/// Collects warnings, applying the level that the
+/// config and attributes give each one.
+struct Diagnostics<'a> {
+    config: &'a MipsyConfig,
+    warnings: Vec<CompilerWarning>,
+}
+
+impl<'a> Diagnostics<'a> {
+    fn new(config: &'a MipsyConfig) -> Self {
+        Self {
+            config,
+            warnings: vec![],
+        }
+    }
+
+    fn warn(&mut self, warning: CompilerWarning, file_attributes: &[Attribute], item_attributes: &[Attribute]) -> MipsyResult<()> {
+        match warning.warning().lint().level(self.config, file_attributes, item_attributes) {
+            LintLevel::Allow => {}
+            LintLevel::Warn  => self.warnings.push(warning),
+            LintLevel::Deny  => return Err(MipsyError::Compiler(warning.into_error())),
+        }
 
+        Ok(())
+    }
 }
 
-pub fn check_pre(program: &MpProgram) -> MipsyResult<Vec<Warning>> {
-    let warnings = vec![];
+pub fn check_pre(program: &MpProgram, config: &MipsyConfig, iset: &InstSet) -> MipsyResult<Vec<CompilerWarning>> {
+    let mut diagnostics = Diagnostics::new(config);
+    let mut prev_item: Option<(&MpItem, Option<Rc<str>>, u32)> = None;
+
+    for (file_tag, file_attributes) in program.file_attributes() {
+        let file_tag = file_tag.clone().unwrap_or_else(|| Rc::from(""));
+
+        for attribute in file_attributes {
+            check_attribute(&mut diagnostics, attribute, true, file_tag.clone(), file_attributes, &[])?;
+        }
+    }
 
     for attributed_item in program.items() {
         let item = attributed_item.item();
+        let attributes = attributed_item.attributes();
+        let file_attributes = program.attributes_of_file(attributed_item.file_tag().as_deref());
         let line = attributed_item.line_number();
         let file_tag = attributed_item.file_tag()
             .unwrap_or_else(|| Rc::from(""));
 
+        for attribute in attributes {
+            check_attribute(&mut diagnostics, attribute, false, file_tag.clone(), file_attributes, attributes)?;
+        }
+
+        // the fake .text directive inserted between files
+        // doesn't appear in any source, so can't be warned about -
+        // and nor can the layout of a macro's expansion, which is
+        // only written once, in the macro
+        let in_source = line != 0 && attributed_item.expanded_from().is_none();
+
         match item {
             MpItem::Instruction(ref instruction) => {
+                if in_source && instruction.col() == 1 {
+                    diagnostics.warn(
+                        CompilerWarning::new(
+                            Warning::UnindentedInstruction { inst_name: instruction.name().to_string() },
+                            file_tag.clone(), line, instruction.col(), instruction.col_end(),
+                        ),
+                        file_attributes, attributes,
+                    )?;
+                }
+
+                if let Some(native_name) = single_native_expansion(iset, instruction) {
+                    diagnostics.warn(
+                        CompilerWarning::new(
+                            Warning::SingleNativePseudo {
+                                inst_name: instruction.name().to_string(),
+                                native_name,
+                            },
+                            file_tag.clone(), line, instruction.col(), instruction.col_end(),
+                        ),
+                        file_attributes, attributes,
+                    )?;
+                }
+
                 for (argument, col, col_end) in instruction.arguments() {
                     match argument {
                         MpArgument::Register(register) => {
                             let ident = register.get_identifier();
-                            ident.to_register().into_compiler_mipsy_result(file_tag.clone(), line, *col, *col_end)?;
+
+                            if ident.is_float_register() {
+                                ident.to_float_register().into_compiler_mipsy_result(file_tag.clone(), line, *col, *col_end)?;
+                            } else {
+                                ident.to_register().into_compiler_mipsy_result(file_tag.clone(), line, *col, *col_end)?;
+                            }
                         }
                         MpArgument::Number(_) => {}
-                        // MpArgument::LabelPlusConst(..) => {}
                     }
                 }
             }
-            MpItem::Label(_) => {}
-            MpItem::Directive(_) => {}
+            MpItem::Label(label) => {
+                if in_source && label.col() > 1 {
+                    diagnostics.warn(
+                        CompilerWarning::new(
+                            Warning::IndentedLabel { label: label_display_name(&label.label()).to_string() },
+                            file_tag.clone(), line, label.col(), label.col_end(),
+                        ),
+                        file_attributes, attributes,
+                    )?;
+                }
+
+                let follows_instruction = matches!(
+                    prev_item,
+                    Some((MpItem::Instruction(_), ref prev_tag, prev_line))
+                        if *prev_tag == attributed_item.file_tag() && prev_line + 1 == line
+                );
+
+                if follows_instruction {
+                    diagnostics.warn(
+                        CompilerWarning::new(
+                            Warning::NoBlankLineBeforeLabel { label: label_display_name(&label.label()).to_string() },
+                            file_tag.clone(), line, label.col(), label.col_end(),
+                        ),
+                        file_attributes, attributes,
+                    )?;
+                }
+            }
+            MpItem::Directive((directive, position)) => {
+                if directive.is_gnu() && !config.gnu {
+                    return Err(MipsyError::Compiler(CompilerError::new(
+                        compiler::Error::GnuDirective { directive: directive.clone() },
+                        file_tag, line, position.col(), position.col_end(),
+                    )));
+                }
+
+                if in_source && position.col() == 1 {
+                    diagnostics.warn(
+                        CompilerWarning::new(
+                            Warning::UnindentedDirective,
+                            file_tag.clone(), line, position.col(), position.col_end(),
+                        ),
+                        file_attributes, attributes,
+                    )?;
+                }
+            }
             MpItem::Constant(_) => {}
         }
-    }
 
-    // TODO
+        prev_item = Some((item, attributed_item.file_tag(), line));
+    }
 
-    Ok(warnings)
+    Ok(diagnostics.warnings)
 }
 
-pub fn check_post_data_label(program: &MpProgram, binary: &Binary) -> MipsyResult<Vec<Warning>> {
-    let warnings = vec![];
+pub fn check_post_data_label(program: &MpProgram, kernel: &MpProgram, config: &MipsyConfig, binary: &Binary) -> MipsyResult<Vec<CompilerWarning>> {
+    let mut diagnostics = Diagnostics::new(config);
 
     for attributed_item in program.items() {
         let item = attributed_item.item();
@@ -50,6 +165,10 @@ pub fn check_post_data_label(program: &MpProgram, binary: &Binary) -> MipsyResul
         let file_tag = attributed_item.file_tag()
             .unwrap_or_else(|| Rc::from(""));
 
+        // outside of compilation (such as for a line of the debugger),
+        // only the labels that can be used from anywhere can be used
+        let scope = binary.scope.as_ref().map(|_| Scope::File(file_tag.clone()));
+
         match item {
             MpItem::Instruction(ref instruction) => {
                 for (argument, col, col_end) in instruction.arguments() {
@@ -57,22 +176,16 @@ pub fn check_post_data_label(program: &MpProgram, binary: &Binary) -> MipsyResul
                         MpArgument::Register(_) => {}
                         MpArgument::Number(number) => {
                             match number {
-                                MpNumber::Immediate(imm) => check_imm(binary, imm, file_tag.clone(), line, *col, *col_end)?,
+                                MpNumber::Immediate(imm) => check_imm(binary, scope.as_ref(), imm, file_tag.clone(), line, *col, *col_end)?,
                                 MpNumber::BinaryOpImmediate(i1, _, i2) => {
-                                    check_imm(binary, i1, file_tag.clone(), line, *col, *col_end)?;
-                                    check_imm(binary, i2, file_tag.clone(), line, *col, *col_end)?;
+                                    check_imm(binary, scope.as_ref(), i1, file_tag.clone(), line, *col, *col_end)?;
+                                    check_imm(binary, scope.as_ref(), i2, file_tag.clone(), line, *col, *col_end)?;
                                 }
                                 MpNumber::Float32(_) => {}
                                 MpNumber::Float64(_) => {}
                                 MpNumber::Char(_) => {}
                             }
                         }
-                        // MpArgument::LabelPlusConst(label, _const) => {
-                        //     if binary.constants.get(label).is_none() {
-                        //         binary.get_label(label)
-                        //             .into_compiler_mipsy_result(file_tag.clone(), line, *col, *col_end)?;
-                        //     }
-                        // }
                     }
                 }
             }
@@ -96,23 +209,211 @@ pub fn check_post_data_label(program: &MpProgram, binary: &Binary) -> MipsyResul
         );
     }
 
-    // TODO
+    let used_labels = used_labels(program)
+        .union(&used_labels(kernel))
+        .cloned()
+        .collect::<HashSet<_>>();
+
+    for attributed_item in program.items() {
+        if let MpItem::Label(label) = attributed_item.item() {
+            let name = label.label();
+
+            if name.starts_with('_') || used_labels.contains(&name) {
+                continue;
+            }
+
+            diagnostics.warn(
+                CompilerWarning::new(
+                    Warning::UnusedLabel { label: label_display_name(&name).to_string() },
+                    attributed_item.file_tag().unwrap_or_else(|| Rc::from("")),
+                    attributed_item.line_number(),
+                    label.col(),
+                    label.col_end(),
+                ),
+                program.attributes_of_file(attributed_item.file_tag().as_deref()),
+                attributed_item.attributes(),
+            )?;
+        }
+    }
+
+    Ok(diagnostics.warnings)
+}
+
+/// Checks that an attribute is one mipsy understands, that it's
+/// in a place it can be used, and that it has a valid value.
+fn check_attribute(diagnostics: &mut Diagnostics<'_>, attribute: &Attribute, file_level: bool, file_tag: Rc<str>, file_attributes: &[Attribute], item_attributes: &[Attribute]) -> MipsyResult<()> {
+    let line = attribute.line();
+    let col = attribute.col();
+    let col_end = attribute.col_end();
+
+    let known = match KnownAttribute::from_name(attribute.key()) {
+        Some(known) => known,
+        None => {
+            return diagnostics.warn(
+                CompilerWarning::new(
+                    Warning::UnknownAttribute { name: attribute.key().to_string() },
+                    file_tag, line, col, col_end,
+                ),
+                file_attributes, item_attributes,
+            );
+        }
+    };
+
+    if !known.placement().allows(file_level) {
+        return Err(MipsyError::Compiler(CompilerError::new(
+            compiler::Error::MisplacedAttribute { name: known.name().to_string(), file_level },
+            file_tag, line, col, col_end,
+        )));
+    }
+
+    if !known.value().accepts(attribute.value()) {
+        return Err(MipsyError::Compiler(CompilerError::new(
+            compiler::Error::InvalidAttributeValue {
+                name: known.name().to_string(),
+                value: attribute.value().map(str::to_string),
+            },
+            file_tag, line, col, col_end,
+        )));
+    }
+
+    if known.value() == AttributeValue::Lints {
+        let names = attribute.value().unwrap_or("");
+
+        for name in lint_names(names).filter(|name| !Lint::is_known_name(name)) {
+            diagnostics.warn(
+                CompilerWarning::new(
+                    Warning::UnknownLint { name: name.to_string() },
+                    file_tag.clone(), line, col, col_end,
+                ),
+                file_attributes, item_attributes,
+            )?;
+        }
+    }
+
+    Ok(())
+}
 
-    Ok(warnings)
+fn single_native_expansion(iset: &InstSet, instruction: &MpInstruction) -> Option<String> {
+    match find_instruction(iset, instruction) {
+        Ok(SignatureRef::Pseudo(pseudo)) => match pseudo.expansion() {
+            [native] if native.inst() != pseudo.name() => Some(native.inst().to_ascii_lowercase()),
+            _ => None,
+        },
+        _ => None,
+    }
 }
 
-fn check_imm(binary: &Binary, imm: &MpImmediate, file_tag: Rc<str>, line: u32, col: u32, col_end: u32) -> MipsyResult<()> {
-    match imm {
-        MpImmediate::LabelReference(label) => {
-            if binary.constants.get(label).is_none() {
-                binary.get_label(label)
-                    .into_compiler_mipsy_result(file_tag, line, col, col_end)?;
+/// Every label that the program refers to, either from an instruction,
+/// from the value of a data directive, or by declaring it with `.globl`.
+fn used_labels(program: &MpProgram) -> HashSet<String> {
+    let mut used = HashSet::new();
+
+    for attributed_item in program.items() {
+        match attributed_item.item() {
+            MpItem::Instruction(instruction) => {
+                for (argument, _, _) in instruction.arguments() {
+                    let imms = match argument {
+                        MpArgument::Number(MpNumber::Immediate(imm))
+                        | MpArgument::Register(MpRegister::Offset(imm, _)) => vec![imm],
+                        MpArgument::Number(MpNumber::BinaryOpImmediate(i1, _, i2))
+                        | MpArgument::Register(MpRegister::BinaryOpOffset(i1, _, i2, _)) => vec![i1, i2],
+                        _ => continue,
+                    };
+
+                    for imm in imms {
+                        match imm {
+                            MpImmediate::LabelReference(label) => {
+                                used.insert(label.clone());
+                            }
+                            MpImmediate::Relocation(_, value)
+                            | MpImmediate::Expression(value) => const_names(&value.0, &mut used),
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            MpItem::Directive((MpDirective::Globl(label), _)) => {
+                used.insert(label.clone());
+            }
+            MpItem::Directive((MpDirective::Byte(values), _))
+            | MpItem::Directive((MpDirective::Half(values), _))
+            | MpItem::Directive((MpDirective::Word(values), _)) => {
+                for (value, n) in values {
+                    const_names(&value.0, &mut used);
+
+                    if let Some(n) = n {
+                        const_names(&n.0, &mut used);
+                    }
+                }
+            }
+            MpItem::Directive((MpDirective::Space(value), _))
+            | MpItem::Directive((MpDirective::Align(value), _))
+            | MpItem::Directive((MpDirective::P2Align(value), _)) => {
+                const_names(&value.0, &mut used);
             }
+            MpItem::Directive((MpDirective::Comm(_, size, alignment), _)) => {
+                const_names(&size.0, &mut used);
+
+                if let Some(alignment) = alignment {
+                    const_names(&alignment.0, &mut used);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    used
+}
+
+/// Collects the names used in a constant expression.
+fn const_names(value: &MpConstValue, names: &mut HashSet<String>) {
+    match value {
+        MpConstValue::Value(_) => {}
+        MpConstValue::Const(name) => {
+            names.insert(name.clone());
+        }
+        MpConstValue::Minus(value)
+        | MpConstValue::Neg(value) => const_names(&value.0, names),
+        MpConstValue::Sum (v1, v2)
+        | MpConstValue::Sub (v1, v2)
+        | MpConstValue::Div (v1, v2)
+        | MpConstValue::Mult(v1, v2)
+        | MpConstValue::Mod (v1, v2)
+        | MpConstValue::And (v1, v2)
+        | MpConstValue::Or  (v1, v2)
+        | MpConstValue::Xor (v1, v2)
+        | MpConstValue::Shl (v1, v2)
+        | MpConstValue::Shr (v1, v2) => {
+            const_names(&v1.0, names);
+            const_names(&v2.0, names);
+        }
+    }
+}
+
+fn check_imm(binary: &Binary, scope: Option<&Scope>, imm: &MpImmediate, file_tag: Rc<str>, line: u32, col: u32, col_end: u32) -> MipsyResult<()> {
+    let names = match imm {
+        MpImmediate::LabelReference(label) => vec![label.clone()],
+        MpImmediate::Relocation(_, value)
+        | MpImmediate::Expression(value) => {
+            let mut names = HashSet::new();
+            const_names(&value.0, &mut names);
+
+            let mut names = names.into_iter().collect::<Vec<_>>();
+            names.sort();
+
+            names
         }
         MpImmediate::I16(_)
         | MpImmediate::U16(_)
         | MpImmediate::I32(_)
-        | MpImmediate::U32(_) => {}
+        | MpImmediate::U32(_) => vec![],
+    };
+
+    for name in names {
+        if binary.constants.get(&name).is_none() {
+            binary.get_label_in(&name, scope)
+                .into_compiler_mipsy_result(file_tag.clone(), line, col, col_end)?;
+        }
     }
 
     Ok(())
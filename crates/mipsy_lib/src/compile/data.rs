@@ -1,17 +1,19 @@
 use std::rc::Rc;
 
-use crate::{CompilerError, KDATA_BOT, KTEXT_BOT, MipsyError, MipsyResult, MpProgram, error::{ToMipsyResult, compiler::{DirectiveType, Error}}, inst::instruction::InstSet, util::Safe};
+use crate::{CompilerError, KDATA_BOT, KTEXT_BOT, MipsyError, MipsyResult, MpProgram, error::{InternalError, MipsyInternalResult, ToMipsyResult, compiler::{DirectiveType, Error}}, inst::instruction::InstSet, util::Safe};
 use super::{
     TEXT_BOT,
     DATA_BOT,
     Binary,
+    link::{GlobalDeclaration, Scope},
     text::instruction_length,
     bytes::ToBytes
 };
+use linked_hash_map::LinkedHashMap;
 use mipsy_parser::{MpArgument, MpConstValue, MpConstValueLoc, MpDirective, MpImmediate, MpItem, MpNumber};
 use mipsy_utils::MipsyConfig;
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 pub(crate) enum Segment {
     Text,
     Data,
@@ -29,23 +31,52 @@ fn align<T: Clone>(binary: &mut Binary, segment: &Segment, align_to: usize) -> V
 
     let alignment = (align_to - (curr_size % align_to)) % align_to;
     if alignment != 0 {
-        let mut labels = vec![];
+        binary.move_labels(bot + curr_size as u32, bot + (curr_size + alignment) as u32);
+    }
+    
+    vec![Safe::Uninitialised; alignment]
+}
 
-        for (label, &addr) in binary.labels.iter() {
-            if addr as usize == (bot as usize + curr_size) {
-                labels.push(label.to_string());
-            }
+/// # A value in the data segment that refers to a label.
+///
+/// Labels can be used before they're defined, so these values are
+/// left as zero on the first pass, and filled in by [`resolve_data_labels`]
+/// once every label's address is known.
+pub(super) struct DataLabelRef {
+    segment: Segment,
+    offset: usize,
+    size: DataSize,
+    value: MpConstValueLoc,
+    file_tag: Rc<str>,
+    scope: Scope,
+}
+
+#[derive(Clone, Copy)]
+enum DataSize {
+    Byte,
+    Half,
+    Word,
+}
+
+impl DataSize {
+    fn range(&self) -> (i64, i64) {
+        match self {
+            DataSize::Byte => (i8::MIN  as _, u8::MAX  as _),
+            DataSize::Half => (i16::MIN as _, u16::MAX as _),
+            DataSize::Word => (i32::MIN as _, u32::MAX as _),
         }
+    }
 
-        for label in labels {
-            binary.labels.insert(label, bot + (curr_size + alignment) as u32);
+    fn to_bytes(self, value: i64) -> Vec<u8> {
+        match self {
+            DataSize::Byte => vec![value as u8],
+            DataSize::Half => (value as u16).to_bytes(),
+            DataSize::Word => (value as u32).to_bytes(),
         }
     }
-    
-    vec![Safe::Uninitialised; alignment]
 }
 
-pub(super) fn eval_directive(directive: &MpDirective, binary: &mut Binary, config: &MipsyConfig, file_tag: Rc<str>, segment: &mut Segment, first_pass: bool) -> MipsyResult<Vec<Safe<u8>>> {
+pub(super) fn eval_directive(directive: &MpDirective, binary: &mut Binary, config: &MipsyConfig, file_tag: Rc<str>, segment: &mut Segment, first_pass: bool, label_refs: &mut Vec<DataLabelRef>) -> MipsyResult<Vec<Safe<u8>>> {
     let bytes = match directive {
         MpDirective::Text => {
             *segment = Segment::Text;
@@ -55,7 +86,7 @@ pub(super) fn eval_directive(directive: &MpDirective, binary: &mut Binary, confi
             *segment = Segment::Data;
             vec![]
         }
-        MpDirective::KText => {
+        MpDirective::KText(_) => {
             *segment = Segment::KText;
             vec![]
         }
@@ -63,6 +94,14 @@ pub(super) fn eval_directive(directive: &MpDirective, binary: &mut Binary, confi
             *segment = Segment::KData;
             vec![]
         }
+        MpDirective::Section(name) => {
+            *segment = section_segment(name);
+            vec![]
+        }
+        MpDirective::RData => {
+            *segment = Segment::Data;
+            vec![]
+        }
         MpDirective::Ascii(ref string) => {
             let chars: Vec<Safe<u8>> = string.chars().flat_map(|c| c.to_bytes()).map(Safe::Valid).collect();
 
@@ -75,66 +114,20 @@ pub(super) fn eval_directive(directive: &MpDirective, binary: &mut Binary, confi
             chars
         }
         MpDirective::Byte(bytes) => {
-            bytes.into_iter()
-                .map(|(byte, n)| Ok((
-                    eval_constant_in_range(&byte, i8::MIN as _, u8::MAX as _, binary, file_tag.clone())? as u8,
-                    if let Some(n) = n {
-                        eval_constant_in_range(&n, u32::MIN as _, u32::MAX as _, binary, file_tag.clone())? as u32
-                    } else {
-                        1
-                    }
-                )))
-                .collect::<Result<Vec<_>, _>>()?
-                .into_iter()
-                .flat_map(|(byte, n)| (0..n).map(move |_| Safe::Valid(byte)))
-                .collect::<Vec<_>>()
+            eval_values(bytes, DataSize::Byte, vec![], binary, &file_tag, *segment, first_pass, label_refs)?
         }
         MpDirective::Half(halfs) => {
             let alignment = align(binary, segment, 2);
 
-            let halfs = halfs.into_iter()
-                .map(|(half, n)| Ok((
-                    eval_constant_in_range(&half, i16::MIN as _, u16::MAX as _, binary, file_tag.clone())? as u16,
-                    if let Some(n) = n {
-                        eval_constant_in_range(&n, u32::MIN as _, u32::MAX as _, binary, file_tag.clone())? as u32
-                    } else {
-                        1
-                    }
-                )))
-                .collect::<Result<Vec<_>, _>>()?
-                .into_iter()
-                .flat_map(|(half, n)| (0..n).map(move |_| half))
-                .flat_map(|half| half.to_bytes())
-                .map(Safe::Valid);
-
-            alignment.into_iter()
-                .chain(halfs)
-                .collect()
+            eval_values(halfs, DataSize::Half, alignment, binary, &file_tag, *segment, first_pass, label_refs)?
         }
         MpDirective::Word(words) => {
             let alignment = align(binary, segment, 2);
 
-            let words = words.into_iter()
-                .map(|(word, n)| Ok((
-                    eval_constant_in_range(&word, i32::MIN as _, u32::MAX as _, binary, file_tag.clone())? as u32,
-                    if let Some(n) = n {
-                        eval_constant_in_range(&n, u32::MIN as _, u32::MAX as _, binary, file_tag.clone())? as u32
-                    } else {
-                        1
-                    }
-                )))
-                .collect::<Result<Vec<_>, _>>()?
-                .into_iter()
-                .flat_map(|(word, n)| (0..n).map(move |_| word))
-                .flat_map(|word| word.to_bytes())
-                .map(Safe::Valid);
-
-            alignment.into_iter()
-                .chain(words)
-                .collect()
+            eval_values(words, DataSize::Word, alignment, binary, &file_tag, *segment, first_pass, label_refs)?
         }
         MpDirective::Float(floats) => {
-            let alignment = align(binary, segment, 2);
+            let alignment = align(binary, segment, 4);
 
             let floats = floats.into_iter()
                 .map(|(float, n)| Ok((
@@ -156,7 +149,7 @@ pub(super) fn eval_directive(directive: &MpDirective, binary: &mut Binary, confi
                 .collect()
         }
         MpDirective::Double(doubles) => {
-            let alignment = align(binary, segment, 2);
+            let alignment = align(binary, segment, 8);
 
             let doubles = doubles.into_iter()
                 .map(|(double, n)| Ok((
@@ -177,7 +170,7 @@ pub(super) fn eval_directive(directive: &MpDirective, binary: &mut Binary, confi
                 .chain(doubles)
                 .collect()
         }
-        MpDirective::Align(num) => {
+        MpDirective::Align(num) | MpDirective::P2Align(num) => {
             let num = eval_constant_in_range(&num, u32::MIN as _, 31, binary, file_tag)? as u32;
 
             let multiple = 2usize.pow(num);
@@ -191,31 +184,185 @@ pub(super) fn eval_directive(directive: &MpDirective, binary: &mut Binary, confi
             
             vec![space_byte; num as usize]
         }
-        MpDirective::Globl(label) => {
-            if first_pass {
-                binary.globals.push(label.to_string());
+        // declarations are collected by `populate_labels_and_data`
+        MpDirective::Globl(_) => vec![],
+        // included files are compiled as files of their own
+        MpDirective::Include(_) => vec![],
+        // the space is reserved by `populate_labels_and_data`,
+        // in the data segment, whichever segment it's used in
+        MpDirective::Comm(..) => vec![],
+        // replaced with the section it goes back to, by `gnu::apply_compat`
+        MpDirective::Previous => vec![],
+        // `.set noreorder` is dealt with by `gnu::apply_compat`, too
+        MpDirective::Set(_) => vec![],
+        MpDirective::Annotation(_) => vec![],
+    };
+
+    Ok(bytes)
+}
+
+/// The segment a section is put in: the text segment for `.text`
+/// (and `.text.*`), and the data segment for anything else.
+fn section_segment(name: &str) -> Segment {
+    match name {
+        ".ktext" => Segment::KText,
+        ".kdata" => Segment::KData,
+        name if name == ".text" || name.starts_with(".text.") => Segment::Text,
+        _ => Segment::Data,
+    }
+}
+
+/// Evaluates the values of a `.byte`, `.half` or `.word` directive, following `alignment`.
+///
+/// On the first pass, values that refer to a label are written as zero,
+/// and recorded in `label_refs` to be filled in later.
+#[allow(clippy::too_many_arguments)]
+fn eval_values(values: &[(MpConstValueLoc, Option<MpConstValueLoc>)], size: DataSize, alignment: Vec<Safe<u8>>, binary: &Binary, file_tag: &Rc<str>, segment: Segment, first_pass: bool, label_refs: &mut Vec<DataLabelRef>) -> MipsyResult<Vec<Safe<u8>>> {
+    let start = match segment {
+        Segment::Data  => binary.data.len(),
+        Segment::KData => binary.kdata.len(),
+        Segment::Text  => binary.text.len(),
+        Segment::KText => binary.ktext.len(),
+    };
+
+    let mut bytes = alignment;
+    let (range_low, range_high) = size.range();
+
+    for (value, n) in values {
+        let n = if let Some(n) = n {
+            eval_constant_in_range(n, u32::MIN as _, u32::MAX as _, binary, file_tag.clone())? as u32
+        } else {
+            1
+        };
+
+        let deferred = first_pass && refers_to_label(binary, &value.0);
+
+        let evaluated = if deferred {
+            0
+        } else {
+            eval_constant_in_range(value, range_low, range_high, binary, file_tag.clone())?
+        };
+
+        for _ in 0..n {
+            // text segment directives are evaluated again with the
+            // instructions, by which point every label is known
+            if deferred && matches!(segment, Segment::Data | Segment::KData) {
+                label_refs.push(DataLabelRef {
+                    segment,
+                    offset: start + bytes.len(),
+                    size,
+                    value: value.clone(),
+                    file_tag: file_tag.clone(),
+                    scope: binary.scope.clone().expect("data is evaluated in a scope"),
+                });
             }
 
-            vec![]
+            bytes.extend(size.to_bytes(evaluated).into_iter().map(Safe::Valid));
         }
-    };
+    }
 
     Ok(bytes)
 }
 
-pub fn populate_labels_and_data(binary: &mut Binary, config: &MipsyConfig, iset: &InstSet, program: &mut MpProgram) -> MipsyResult<()> {
+/// Whether a constant expression uses a name that isn't a constant,
+/// which must then be a label.
+fn refers_to_label(binary: &Binary, value: &MpConstValue) -> bool {
+    match value {
+        MpConstValue::Value(_) => false,
+        MpConstValue::Const(label) => !binary.constants.contains_key(label),
+        MpConstValue::Minus(value)
+        | MpConstValue::Neg(value) => refers_to_label(binary, &value.0),
+        MpConstValue::Sum (v1, v2)
+        | MpConstValue::Sub (v1, v2)
+        | MpConstValue::Div (v1, v2)
+        | MpConstValue::Mult(v1, v2)
+        | MpConstValue::Mod (v1, v2)
+        | MpConstValue::And (v1, v2)
+        | MpConstValue::Or  (v1, v2)
+        | MpConstValue::Xor (v1, v2)
+        | MpConstValue::Shl (v1, v2)
+        | MpConstValue::Shr (v1, v2) => refers_to_label(binary, &v1.0) || refers_to_label(binary, &v2.0),
+    }
+}
+
+/// Fills in the data values that refer to labels,
+/// now that the address of every label is known.
+pub fn resolve_data_labels(binary: &mut Binary, label_refs: &[DataLabelRef]) -> MipsyResult<()> {
+    for label_ref in label_refs {
+        binary.scope = Some(label_ref.scope.clone());
+
+        let (range_low, range_high) = label_ref.size.range();
+        let value = eval_constant_in_range(&label_ref.value, range_low, range_high, binary, label_ref.file_tag.clone())?;
+
+        let data = match label_ref.segment {
+            Segment::Data  => &mut binary.data,
+            Segment::KData => &mut binary.kdata,
+            Segment::Text | Segment::KText => unreachable!("text segment values are never deferred"),
+        };
+
+        for (i, byte) in label_ref.size.to_bytes(value).into_iter().enumerate() {
+            data[label_ref.offset + i] = Safe::Valid(byte);
+        }
+    }
+
+    Ok(())
+}
+
+/// The number of bytes to skip from `ktext_len` bytes into the kernel text,
+/// to reach the address given to a `.ktext` directive.
+pub(super) fn ktext_padding(address: &MpConstValueLoc, ktext_len: usize, binary: &Binary, file_tag: Rc<str>) -> MipsyResult<usize> {
+    let addr = eval_constant(binary, address, file_tag.clone())?;
+    let next_addr = KTEXT_BOT as i64 + ktext_len as i64;
+
+    if addr < next_addr || addr >= KDATA_BOT as i64 || addr % 4 != 0 {
+        let position = &address.1;
+
+        return Err(
+            MipsyError::Compiler(
+                CompilerError::new(
+                    Error::KTextAddress { addr: addr as u32, next_addr: next_addr as u32 },
+                    file_tag,
+                    position.line(),
+                    position.col(),
+                    position.col_end(),
+                )
+            )
+        );
+    }
+
+    Ok((addr - next_addr) as usize)
+}
+
+/// Defines the labels and data of `program`.
+///
+/// The kernel text is shared between the kernel and the program,
+/// so `ktext_len` carries on from where the kernel's left off.
+#[allow(clippy::too_many_arguments)]
+pub fn populate_labels_and_data(binary: &mut Binary, config: &MipsyConfig, iset: &InstSet, program: &mut MpProgram, in_kernel: bool, ktext_len: &mut usize, label_refs: &mut Vec<DataLabelRef>, declarations: &mut Vec<GlobalDeclaration>) -> MipsyResult<()> {
     let mut text_len = 0;
-    let mut ktext_len = 0;
     let mut segment = Segment::Text;
 
     for attributed_item in program.items_mut() {
         let line = attributed_item.line_number();
         let file_tag = attributed_item.file_tag()
             .unwrap_or_else(|| Rc::from(""));
+        let scope = Scope::of(in_kernel, &file_tag);
+        binary.scope = Some(scope.clone());
+
         let item = attributed_item.item_mut();
         
         match item {
             MpItem::Directive(directive) => {
+                if let (MpDirective::Globl(label), position) = &directive {
+                    declarations.push(GlobalDeclaration {
+                        scope: scope.clone(),
+                        label: label.to_string(),
+                        line: position.line(),
+                        col: position.col(),
+                        col_end: position.col_end(),
+                    });
+                }
+
                 // Only allow .text and .data in a Text segment
                 // if segment == Segment::Text || segment == Segment::KText {
                 //     match &*directive {
@@ -236,7 +383,28 @@ pub fn populate_labels_and_data(binary: &mut Binary, config: &MipsyConfig, iset:
                 //     }
                 // }
 
-                let bytes = eval_directive(&directive.0, binary, config, file_tag, &mut segment, true)?;
+                if let (MpDirective::Comm(label, size, alignment), position) = &directive {
+                    let size = eval_constant_in_range(size, u32::MIN as _, u32::MAX as _, binary, file_tag.clone())? as usize;
+                    let alignment = match alignment {
+                        Some(alignment) => eval_constant_in_range(alignment, 1, u32::MAX as _, binary, file_tag.clone())? as usize,
+                        // aligned as the largest value that fits, up to a doubleword
+                        None => [8, 4, 2, 1].into_iter().find(|&alignment| size >= alignment).unwrap_or(1),
+                    };
+
+                    let mut padding = align(binary, &Segment::Data, alignment);
+                    binary.data.append(&mut padding);
+
+                    define_label(binary, scope.clone(), label, DATA_BOT + binary.data.len() as u32)
+                        .into_compiler_mipsy_result(file_tag.clone(), line, position.col(), position.col_end())?;
+
+                    binary.data.append(&mut vec![Safe::Valid(0); size]);
+                }
+
+                if let MpDirective::KText(Some(address)) = &directive.0 {
+                    *ktext_len += ktext_padding(address, *ktext_len, binary, file_tag.clone())?;
+                }
+
+                let bytes = eval_directive(&directive.0, binary, config, file_tag, &mut segment, true, label_refs)?;
                 insert_safe_data(&segment, binary, &bytes);
 
                 match segment {
@@ -244,7 +412,7 @@ pub fn populate_labels_and_data(binary: &mut Binary, config: &MipsyConfig, iset:
                         text_len += bytes.len();
                     }
                     Segment::KText => {
-                        ktext_len += bytes.len();
+                        *ktext_len += bytes.len();
                     }
                     _ => {}
                 }
@@ -281,7 +449,7 @@ pub fn populate_labels_and_data(binary: &mut Binary, config: &MipsyConfig, iset:
                         (TEXT_BOT, &mut text_len)
                     }
                     Segment::KText => {
-                        (KTEXT_BOT, &mut ktext_len)
+                        (KTEXT_BOT, &mut *ktext_len)
                     }
                     _ => {
                         return Err(
@@ -301,17 +469,7 @@ pub fn populate_labels_and_data(binary: &mut Binary, config: &MipsyConfig, iset:
                 let alignment = (4 - *length % 4) % 4;
 
                 if alignment != 0 {
-                    let mut labels = vec![];
-
-                    for (label, &addr) in binary.labels.iter() {
-                        if addr as usize == (bot as usize + *length) {
-                            labels.push(label.to_string());
-                        }
-                    }
-
-                    for label in labels {
-                        binary.labels.insert(label, bot + (*length + alignment) as u32);
-                    }
+                    binary.move_labels(bot + *length as u32, bot + (*length + alignment) as u32);
                 }
 
                 *length += alignment + inst_length;
@@ -321,29 +479,15 @@ pub fn populate_labels_and_data(binary: &mut Binary, config: &MipsyConfig, iset:
                 let col = mplabel.col();
                 let col_end = mplabel.col_end();
 
-                if binary.labels.contains_key(&*label) {
-                    return Err(
-                        MipsyError::Compiler(
-                            CompilerError::new(
-                                Error::RedefinedLabel { label },
-                                file_tag,
-                                line,
-                                col,
-                                col_end
-                            )
-                        )
-                    );
-                }
+                let addr = match segment {
+                    Segment::Text => TEXT_BOT + text_len as u32,
+                    Segment::Data => DATA_BOT + binary.data.len() as u32,
+                    Segment::KText => KTEXT_BOT + *ktext_len as u32,
+                    Segment::KData => KDATA_BOT + binary.kdata.len() as u32,
+                };
 
-                binary.labels.insert(
-                    label.to_string(),
-                    match segment {
-                        Segment::Text => TEXT_BOT + text_len as u32,
-                        Segment::Data => DATA_BOT + binary.data.len() as u32,
-                        Segment::KText => KTEXT_BOT + ktext_len as u32,
-                        Segment::KData => KDATA_BOT + binary.kdata.len() as u32,
-                    }
-                );
+                define_label(binary, scope, &label, addr)
+                    .into_compiler_mipsy_result(file_tag, line, col, col_end)?;
             }
             MpItem::Constant(constant) => {
                 let label = constant.label();
@@ -371,11 +515,25 @@ pub fn populate_labels_and_data(binary: &mut Binary, config: &MipsyConfig, iset:
     Ok(())
 }
 
+/// Defines a label in `scope`, unless it's already defined there.
+fn define_label(binary: &mut Binary, scope: Scope, label: &str, addr: u32) -> MipsyInternalResult<()> {
+    let labels = binary.scopes.entry(scope).or_insert_with(LinkedHashMap::new);
+
+    if labels.contains_key(label) {
+        return Err(InternalError::Compiler(Error::RedefinedLabel { label: label.to_string() }));
+    }
+
+    labels.insert(label.to_string(), addr);
+
+    Ok(())
+}
+
 fn eval_constant(binary: &Binary, constant: &MpConstValueLoc, file: Rc<str>) -> MipsyResult<i64> {
     Ok(
         match &constant.0 {
             &MpConstValue::Value(value) => value as _,
             MpConstValue::Const(label) => binary.constants.get(label).copied()
+                .or_else(|| binary.find_label(label, binary.scope.as_ref()).map(|addr| addr as i64))
                 .ok_or_else(|| MipsyError::Compiler(
                     CompilerError::new(
                         Error::UnresolvedConstant { label: label.to_string() },
@@ -388,9 +546,9 @@ fn eval_constant(binary: &Binary, constant: &MpConstValueLoc, file: Rc<str>) ->
             MpConstValue::Minus(value) => -eval_constant(binary, value, file)?,
             MpConstValue::Sum (v1, v2) => eval_constant(binary, v1, file.clone())? + eval_constant(binary, v2, file)?,
             MpConstValue::Sub (v1, v2) => eval_constant(binary, v1, file.clone())? - eval_constant(binary, v2, file)?,
-            MpConstValue::Div (v1, v2) => eval_constant(binary, v1, file.clone())? / eval_constant(binary, v2, file)?,
+            MpConstValue::Div (v1, v2) => eval_constant(binary, v1, file.clone())? / eval_divisor(binary, v2, file)?,
             MpConstValue::Mult(v1, v2) => eval_constant(binary, v1, file.clone())? * eval_constant(binary, v2, file)?,
-            MpConstValue::Mod (v1, v2) => eval_constant(binary, v1, file.clone())? % eval_constant(binary, v2, file)?,
+            MpConstValue::Mod (v1, v2) => eval_constant(binary, v1, file.clone())? % eval_divisor(binary, v2, file)?,
             MpConstValue::And (v1, v2) => eval_constant(binary, v1, file.clone())? & eval_constant(binary, v2, file)?,
             MpConstValue::Or  (v1, v2) => eval_constant(binary, v1, file.clone())? | eval_constant(binary, v2, file)?,
             MpConstValue::Xor (v1, v2) => eval_constant(binary, v1, file.clone())? ^ eval_constant(binary, v2, file)?,
@@ -401,6 +559,21 @@ fn eval_constant(binary: &Binary, constant: &MpConstValueLoc, file: Rc<str>) ->
     )
 }
 
+fn eval_divisor(binary: &Binary, constant: &MpConstValueLoc, file: Rc<str>) -> MipsyResult<i64> {
+    match eval_constant(binary, constant, file.clone())? {
+        0 => Err(MipsyError::Compiler(
+            CompilerError::new(
+                Error::DivisionByZero,
+                file,
+                constant.1.line(),
+                constant.1.col(),
+                constant.1.col_end(),
+            )
+        )),
+        value => Ok(value),
+    }
+}
+
 fn eval_constant_in_range(constant: &MpConstValueLoc, range_low: i64, range_high: i64, binary: &Binary, file: Rc<str>) -> MipsyResult<i64> {
     let value = eval_constant(binary, constant, file.clone())?;
 
@@ -0,0 +1,236 @@
+use std::collections::HashSet;
+
+use mipsy_parser::{MpArgument, MpDirective, MpImmediate, MpInstruction, MpItem, MpNumber, MpRegister, MpRegisterIdentifier};
+
+use crate::{CompilerError, InstSet, MipsyError, MipsyResult, MpProgram, error::compiler::Error, inst::{instruction::{ArgumentType, SignatureRef, ToRegister}, register::Register}};
+use super::text::find_instruction;
+
+/// # Makes the assembly GNU tools write fit mipsy.
+///
+/// This is done before anything else is compiled, in GNU compatibility mode.
+pub(super) fn apply_compat(program: &mut MpProgram, iset: &InstSet) -> MipsyResult<()> {
+    resolve_previous(program);
+    resolve_local_labels(program);
+    fill_delay_slots(program, iset)
+}
+
+/// Replaces each `.previous` with the section directive it goes back to.
+fn resolve_previous(program: &mut MpProgram) {
+    let mut current_file = None;
+    let (mut section, mut previous) = (MpDirective::Text, MpDirective::Text);
+
+    for attributed_item in program.items_mut() {
+        if attributed_item.file_tag() != current_file {
+            current_file = attributed_item.file_tag();
+            section = MpDirective::Text;
+            previous = MpDirective::Text;
+        }
+
+        if let MpItem::Directive((directive, _)) = attributed_item.item_mut() {
+            match directive {
+                MpDirective::Previous => {
+                    // going back to the kernel text carries on from where it was
+                    *directive = match &previous {
+                        MpDirective::KText(_) => MpDirective::KText(None),
+                        previous => previous.clone(),
+                    };
+                    std::mem::swap(&mut section, &mut previous);
+                }
+                MpDirective::Text
+                | MpDirective::Data
+                | MpDirective::KText(_)
+                | MpDirective::KData
+                | MpDirective::RData
+                | MpDirective::Section(_) => {
+                    previous = std::mem::replace(&mut section, directive.clone());
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Turns each use of a `$`-prefixed label (eg. `$L2`), which
+/// is parsed as a register, into a reference to the label.
+fn resolve_local_labels(program: &mut MpProgram) {
+    let labels = program.items().iter()
+        .filter_map(|attributed_item| match attributed_item.item() {
+            MpItem::Label(label) if label.label().starts_with('$') => Some(label.label()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+
+    for attributed_item in program.items_mut() {
+        if let MpItem::Instruction(instruction) = attributed_item.item_mut() {
+            for (argument, _, _) in instruction.arguments_mut() {
+                let label = match argument {
+                    MpArgument::Register(MpRegister::Normal(ident @ MpRegisterIdentifier::Named(_)))
+                        if ident.to_register().is_err() && !ident.is_float_register() => format!("${}", ident),
+                    _ => continue,
+                };
+
+                if labels.contains(&label) {
+                    *argument = MpArgument::Number(MpNumber::Immediate(MpImmediate::LabelReference(label)));
+                }
+            }
+        }
+    }
+}
+
+/// # Moves each instruction in a delay slot to just before its branch.
+///
+/// After `.set noreorder`, the instruction after a branch (its delay slot)
+/// is run before the branch is taken. mipsy has no delay slots, so this
+/// puts the instruction where it runs - unless it's a `nop`, which can
+/// stay put.
+fn fill_delay_slots(program: &mut MpProgram, iset: &InstSet) -> MipsyResult<()> {
+    let items = program.items_mut();
+
+    let mut current_file = None;
+    let mut noreorder = false;
+    let mut index = 0;
+
+    while index < items.len() {
+        if items[index].file_tag() != current_file {
+            current_file = items[index].file_tag();
+            noreorder = false;
+        }
+
+        let branch = match items[index].item() {
+            MpItem::Directive((MpDirective::Set(option), _)) => {
+                match option.as_str() {
+                    "noreorder" => noreorder = true,
+                    "reorder"   => noreorder = false,
+                    _ => {}
+                }
+
+                index += 1;
+                continue;
+            }
+            MpItem::Instruction(instruction) if noreorder && is_branch(iset, instruction) => instruction,
+            _ => {
+                index += 1;
+                continue;
+            }
+        };
+
+        // the delay slot is the next instruction, even if it has a label
+        let slot_index = items[index + 1..].iter()
+            .position(|item| !matches!(item.item(), MpItem::Label(_)))
+            .map(|position| index + 1 + position)
+            .filter(|&slot_index| items[slot_index].file_tag() == current_file);
+
+        let (slot_index, slot) = match slot_index.map(|slot_index| (slot_index, items[slot_index].item())) {
+            Some((slot_index, MpItem::Instruction(slot))) => (slot_index, slot),
+            _ => {
+                index += 1;
+                continue;
+            }
+        };
+
+        if is_nop(slot) {
+            index = slot_index + 1;
+            continue;
+        }
+
+        if slot_index != index + 1 || !can_run_before(iset, slot, branch) {
+            return Err(MipsyError::Compiler(CompilerError::new(
+                Error::DelaySlot {
+                    inst_name: slot.name().to_string(),
+                    branch_name: branch.name().to_string(),
+                },
+                items[slot_index].file_tag().unwrap_or_else(|| "".into()),
+                items[slot_index].line_number(),
+                slot.col(),
+                slot.col_end(),
+            )));
+        }
+
+        items.swap(index, slot_index);
+        index = slot_index + 1;
+    }
+
+    Ok(())
+}
+
+fn is_nop(instruction: &MpInstruction) -> bool {
+    instruction.name().eq_ignore_ascii_case("nop") && instruction.arguments().is_empty()
+}
+
+/// Whether an instruction is a branch or a jump, which has a delay slot.
+fn is_branch(iset: &InstSet, instruction: &MpInstruction) -> bool {
+    match find_instruction(iset, instruction) {
+        Ok(signature) => {
+            let compile = signature.compile_sig();
+
+            compile.relative_label()
+                || compile.format().contains(&ArgumentType::J)
+                || matches!(signature.name(), "jr" | "jalr")
+        }
+        Err(_) => false,
+    }
+}
+
+/// Whether `slot` does the same when run before `branch` as it does in its delay slot.
+fn can_run_before(iset: &InstSet, slot: &MpInstruction, branch: &MpInstruction) -> bool {
+    if is_branch(iset, slot) {
+        return false;
+    }
+
+    let branch_registers = registers(branch);
+
+    if written_registers(iset, slot).iter().any(|register| branch_registers.contains(register)) {
+        return false;
+    }
+
+    // the delay slot sees the return address the branch links
+    match link_register(branch) {
+        Some(link) => !registers(slot).contains(&link),
+        None => true,
+    }
+}
+
+/// The general-purpose registers an instruction uses.
+fn registers(instruction: &MpInstruction) -> Vec<Register> {
+    instruction.arguments().iter()
+        .filter_map(|(argument, _, _)| match argument {
+            MpArgument::Register(register) => register.get_identifier().to_register().ok(),
+            MpArgument::Number(_) => None,
+        })
+        .collect()
+}
+
+/// The registers an instruction could write to.
+fn written_registers(iset: &InstSet, instruction: &MpInstruction) -> Vec<Register> {
+    match find_instruction(iset, instruction) {
+        Ok(SignatureRef::Native(native)) => {
+            let reads = native.runtime_metadata().reads();
+
+            native.compile_signature().format().iter()
+                .zip(instruction.arguments())
+                .filter(|(arg_type, _)| matches!(arg_type, ArgumentType::Rd | ArgumentType::Rs | ArgumentType::Rt))
+                .filter(|(arg_type, _)| !reads.iter().any(|read| read.eq_argument_type(arg_type)))
+                .filter_map(|(_, (argument, _, _))| match argument {
+                    MpArgument::Register(register) => register.get_identifier().to_register().ok(),
+                    MpArgument::Number(_) => None,
+                })
+                .collect()
+        }
+        // a pseudo-instruction's first register is usually the one it writes,
+        // but it could use $at along the way
+        _ => registers(instruction).into_iter()
+            .take(1)
+            .chain(Some(Register::At))
+            .collect(),
+    }
+}
+
+/// The register a branch writes its return address to, if it links.
+fn link_register(branch: &MpInstruction) -> Option<Register> {
+    match &*branch.name().to_ascii_lowercase() {
+        "jal" | "bal" | "bgezal" | "bltzal" => Some(Register::Ra),
+        "jalr" if branch.arguments().len() == 2 => registers(branch).first().copied(),
+        "jalr" => Some(Register::Ra),
+        _ => None,
+    }
+}
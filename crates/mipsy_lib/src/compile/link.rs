@@ -0,0 +1,182 @@
+use std::rc::Rc;
+
+use linked_hash_map::LinkedHashMap;
+use serde::{Serialize, Deserialize};
+
+use crate::{CompilerError, MipsyError, MipsyResult, error::compiler::Error};
+use super::Binary;
+
+/// # Where a label is defined, and so where it can be used.
+///
+/// Labels are local to the file they're defined in, unless that file
+/// declares them with `.globl`. The kernel can also use any label
+/// that isn't defined in more than one file - such as `main`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Scope {
+    Kernel,
+    /// A file, by its tag.
+    File(Rc<str>),
+}
+
+impl Scope {
+    pub(super) fn of(in_kernel: bool, file_tag: &Rc<str>) -> Self {
+        if in_kernel {
+            Scope::Kernel
+        } else {
+            Scope::File(file_tag.clone())
+        }
+    }
+
+    fn file_tag(&self) -> Rc<str> {
+        match self {
+            Scope::Kernel => Rc::from(""),
+            Scope::File(file_tag) => file_tag.clone(),
+        }
+    }
+}
+
+/// # A label declared with `.globl`.
+///
+/// If its file defines the label, the label becomes global -
+/// otherwise, it must be defined as global by another file.
+pub(super) struct GlobalDeclaration {
+    pub(super) scope: Scope,
+    pub(super) label: String,
+    pub(super) line: u32,
+    pub(super) col: u32,
+    pub(super) col_end: u32,
+}
+
+impl Binary {
+    /// The address of a label that can be used from `scope`,
+    /// or from anywhere if there's no scope.
+    pub(super) fn find_label(&self, label: &str, scope: Option<&Scope>) -> Option<u32> {
+        let scope = match scope {
+            Some(scope) => scope,
+            None => return self.labels.get(label).copied(),
+        };
+
+        if let Some(&addr) = self.scopes.get(scope).and_then(|labels| labels.get(label)) {
+            return Some(addr);
+        }
+
+        let visible = match scope {
+            Scope::Kernel  => true,
+            Scope::File(_) => self.globals.iter().any(|global| global == label),
+        };
+
+        self.labels.get(label)
+            .filter(|_| visible)
+            .copied()
+    }
+
+    /// The files that define a label locally, for when it can't be used from `scope`.
+    pub(super) fn defined_elsewhere(&self, label: &str, scope: Option<&Scope>) -> Option<Vec<Rc<str>>> {
+        let files = self.scopes.iter()
+            .filter(|&(defined_in, labels)| Some(defined_in) != scope && labels.contains_key(label))
+            .filter_map(|(scope, _)| match scope {
+                Scope::Kernel => None,
+                Scope::File(file_tag) => Some(file_tag.clone()),
+            })
+            .collect::<Vec<_>>();
+
+        if files.is_empty() {
+            None
+        } else {
+            Some(files)
+        }
+    }
+
+    /// Moves every label at `from` to `to`, in every scope.
+    pub(super) fn move_labels(&mut self, from: u32, to: u32) {
+        let labels = self.scopes.iter_mut()
+            .map(|(_, labels)| labels)
+            .chain(std::iter::once(&mut self.labels));
+
+        for labels in labels {
+            for (_, addr) in labels.iter_mut() {
+                if *addr == from {
+                    *addr = to;
+                }
+            }
+        }
+    }
+}
+
+/// # Works out which labels are global, and which can be used from anywhere.
+///
+/// Each global label must be defined by exactly one file,
+/// which must have declared it with `.globl`.
+pub(super) fn link(binary: &mut Binary, declarations: &[GlobalDeclaration]) -> MipsyResult<()> {
+    let mut globals: LinkedHashMap<String, Scope> = LinkedHashMap::new();
+
+    let defines = |binary: &Binary, declaration: &GlobalDeclaration| binary.scopes.get(&declaration.scope)
+        .is_some_and(|labels| labels.contains_key(&declaration.label));
+
+    for declaration in declarations.iter().filter(|&declaration| defines(binary, declaration)) {
+        match globals.get(&declaration.label) {
+            Some(scope) if *scope == declaration.scope => {}
+            Some(scope) => {
+                return Err(link_error(
+                    Error::RedefinedGlobal {
+                        label: declaration.label.clone(),
+                        first_file: scope.file_tag(),
+                        second_file: declaration.scope.file_tag(),
+                    },
+                    declaration,
+                ));
+            }
+            None => {
+                globals.insert(declaration.label.clone(), declaration.scope.clone());
+            }
+        }
+    }
+
+    for declaration in declarations.iter().filter(|&declaration| !defines(binary, declaration)) {
+        if !globals.contains_key(&declaration.label) {
+            return Err(link_error(
+                Error::UndefinedGlobal {
+                    label: declaration.label.clone(),
+                    local_in: binary.scopes.iter()
+                        .filter(|(_, labels)| labels.contains_key(&declaration.label))
+                        .map(|(scope, _)| scope.file_tag())
+                        .collect(),
+                },
+                declaration,
+            ));
+        }
+    }
+
+    let mut labels = LinkedHashMap::new();
+
+    for (scope, scope_labels) in binary.scopes.iter() {
+        for (label, &addr) in scope_labels.iter() {
+            let visible = match globals.get(label) {
+                Some(global_scope) => global_scope == scope,
+                // a local label can be used from anywhere, unless that would be ambiguous
+                None => binary.scopes.values().filter(|labels| labels.contains_key(label)).count() == 1,
+            };
+
+            if visible {
+                labels.insert(label.clone(), addr);
+            }
+        }
+    }
+
+    binary.labels = labels;
+    binary.globals = globals.into_iter().map(|(label, _)| label).collect();
+
+    Ok(())
+}
+
+fn link_error(error: Error, declaration: &GlobalDeclaration) -> MipsyError {
+    MipsyError::Compiler(
+        CompilerError::new(
+            error,
+            declaration.scope.file_tag(),
+            declaration.line,
+            declaration.col,
+            declaration.col_end,
+        )
+    )
+}
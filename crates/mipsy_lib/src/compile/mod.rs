@@ -1,8 +1,18 @@
-use std::{collections::HashMap, rc::Rc};
-use crate::{InstSet, MpProgram, MipsyResult, error::{InternalError, MipsyInternalResult, compiler}, util::Safe};
+use std::{collections::{HashMap, HashSet}, rc::Rc};
+use crate::{CompilerWarning, InstSet, MpProgram, MipsyError, MipsyResult, error::{InternalError, MipsyInternalResult, compiler}, util::Safe};
 
 mod bytes;
 
+mod attributes;
+pub use attributes::{
+    KnownAttribute,
+    AttributePlacement,
+    AttributeValue,
+    lint_names,
+    find_attribute,
+    integer_attribute,
+};
+
 mod checker;
 pub use checker::{
     check_pre,
@@ -10,11 +20,18 @@ pub use checker::{
 };
 
 mod data;
-use data::populate_labels_and_data;
+use data::{populate_labels_and_data, resolve_data_labels};
+
+mod gnu;
+
+mod link;
+pub use link::Scope;
+use link::link;
 
 mod text;
 use linked_hash_map::LinkedHashMap;
-use mipsy_parser::TaggedFile;
+use serde::{Serialize, Deserialize};
+use mipsy_parser::{local_label_number, MpConstValue, MpConstValueLoc, MpMacroExpansion, TaggedFile};
 use mipsy_utils::MipsyConfig;
 use text::populate_text;
 pub use text::compile1;
@@ -33,26 +50,69 @@ pub const STACK_TOP:  u32 = 0x7FFFFFFF;
 pub const KTEXT_BOT:  u32 = 0x80000000;
 pub const KDATA_BOT:  u32 = 0x90000000;
 
+#[derive(Serialize, Deserialize)]
 pub struct Binary {
+    #[serde(with = "crate::util::safe_bytes")]
     pub text:    Vec<Safe<u8>>,
+    #[serde(with = "crate::util::safe_bytes")]
     pub data:    Vec<Safe<u8>>,
+    #[serde(with = "crate::util::safe_bytes")]
     pub ktext:   Vec<Safe<u8>>,
+    #[serde(with = "crate::util::safe_bytes")]
     pub kdata:   Vec<Safe<u8>>,
+    /// Every label that can be used from anywhere: the global labels,
+    /// and the local labels whose name isn't shared with another file.
     pub labels:  LinkedHashMap<String, u32>,
+    /// The labels defined in each file, including those local to it.
+    pub scopes:  LinkedHashMap<Scope, LinkedHashMap<String, u32>>,
     pub constants: HashMap<String, i64>,
-    pub breakpoints:  Vec<u32>,
+    /// The labels declared with `.globl`.
     pub globals: Vec<String>,
     pub line_numbers: HashMap<u32, (Rc<str>, u32)>,
+    pub warnings: Vec<CompilerWarning>,
+    pub expected_exit_code: Option<i32>,
+    pub no_uninit_check: HashSet<u32>,
+    /// Which labels can be used, while the program is being compiled.
+    #[serde(skip)]
+    pub(crate) scope: Option<Scope>,
+    /// Whether the instructions being compiled are in the kernel text segment.
+    #[serde(skip)]
+    pub(crate) in_ktext: bool,
 }
 
 impl Binary {
+    /// The address of the next instruction to be compiled.
+    pub(crate) fn next_inst_addr(&self) -> u32 {
+        if self.in_ktext {
+            KTEXT_BOT + self.ktext.len() as u32
+        } else {
+            TEXT_BOT + self.text.len() as u32
+        }
+    }
+
     pub fn get_label(&self, label: &str) -> MipsyInternalResult<u32> {
-        if let Some(&addr) = self.labels.get(label) {
+        self.get_label_in(label, self.scope.as_ref())
+    }
+
+    /// Finds a label that can be used from `scope`,
+    /// or from anywhere if there's no scope.
+    pub(crate) fn get_label_in(&self, label: &str, scope: Option<&Scope>) -> MipsyInternalResult<u32> {
+        if let Some(addr) = self.find_label(label, scope) {
             Ok(addr)
+        } else if let Some(defined_in) = self.defined_elsewhere(label, scope) {
+            Err(
+                InternalError::Compiler(
+                    compiler::Error::LocalLabel {
+                        label: label.to_string(),
+                        defined_in,
+                    }
+                )
+            )
         } else {
             let label_lower = label.to_ascii_lowercase();
 
             let mut similar = self.labels.keys()
+                    .filter(|label| local_label_number(label).is_none())
                     .map(|label| label.to_ascii_lowercase())
                     .map(|label| (strsim::jaro_winkler(&label, &label_lower), label))
                     .filter(|&(sim, _)| sim >= 0.9)
@@ -75,6 +135,48 @@ impl Binary {
         }
     }
 
+    /// Evaluates an expression in an instruction, in which
+    /// each name is a constant or, failing that, a label.
+    pub fn evaluate(&self, value: &MpConstValue) -> MipsyInternalResult<u32> {
+        self.evaluate_i64(value).map(|value| value as u32)
+    }
+
+    fn evaluate_i64(&self, value: &MpConstValue) -> MipsyInternalResult<i64> {
+        let eval = |value: &MpConstValueLoc| self.evaluate_i64(&value.0);
+
+        Ok(
+            match value {
+                &MpConstValue::Value(value) => value as i64,
+                MpConstValue::Const(name) => match self.constants.get(name) {
+                    Some(&value) => value,
+                    None => self.get_label(name)? as i64,
+                },
+                MpConstValue::Minus(value) => eval(value)?.wrapping_neg(),
+                MpConstValue::Sum (v1, v2) => eval(v1)?.wrapping_add(eval(v2)?),
+                MpConstValue::Sub (v1, v2) => eval(v1)?.wrapping_sub(eval(v2)?),
+                MpConstValue::Mult(v1, v2) => eval(v1)?.wrapping_mul(eval(v2)?),
+                MpConstValue::Div (v1, v2) | MpConstValue::Mod(v1, v2) => {
+                    let (v1, v2) = (eval(v1)?, eval(v2)?);
+
+                    if v2 == 0 {
+                        return Err(InternalError::Compiler(compiler::Error::DivisionByZero));
+                    }
+
+                    match value {
+                        MpConstValue::Div(..) => v1.wrapping_div(v2),
+                        _                     => v1.wrapping_rem(v2),
+                    }
+                }
+                MpConstValue::And (v1, v2) => eval(v1)? & eval(v2)?,
+                MpConstValue::Or  (v1, v2) => eval(v1)? | eval(v2)?,
+                MpConstValue::Xor (v1, v2) => eval(v1)? ^ eval(v2)?,
+                MpConstValue::Neg (value)  => !eval(value)?,
+                MpConstValue::Shl (v1, v2) => eval(v1)?.wrapping_shl(eval(v2)? as u32),
+                MpConstValue::Shr (v1, v2) => eval(v1)?.wrapping_shr(eval(v2)? as u32),
+            }
+        )
+    }
+
     pub fn insert_label(&mut self, label: &str, addr: u32) {
         self.labels.insert(label.to_string(), addr);
     }
@@ -97,35 +199,92 @@ pub fn compile(program: &mut MpProgram, config: &MipsyConfig, iset: &InstSet) ->
 }
 
 pub fn compile_with_kernel(program: &mut MpProgram, kernel: &mut MpProgram, config: &MipsyConfig, iset: &InstSet) -> MipsyResult<Binary> {
-    let warnings = check_pre(program)?;
-    if !warnings.is_empty() {
-        // TODO: Deal with warnings here
+    let mut binary = compile_program(program, kernel, config, iset)
+        .map_err(|error| match error {
+            MipsyError::Compiler(error) => match expansion_at(program, &error.file_tag(), error.line()) {
+                Some(expansion) => MipsyError::Compiler(error.in_expansion(expansion.clone())),
+                None => MipsyError::Compiler(error),
+            },
+            error => error,
+        })?;
+
+    binary.warnings = std::mem::take(&mut binary.warnings).into_iter()
+        .map(|warning| match expansion_at(program, &warning.file_tag(), warning.line()) {
+            Some(expansion) => warning.in_expansion(expansion.clone()),
+            None => warning,
+        })
+        .collect();
+
+    Ok(binary)
+}
+
+/// The expansion of the macro invoked at `line` of a file, if one was.
+///
+/// Everything a macro expands to is given the line it was invoked on, so
+/// anything wrong with the expansion is pointed at the invocation.
+fn expansion_at<'a>(program: &'a MpProgram, file_tag: &str, line: u32) -> Option<&'a MpMacroExpansion> {
+    program.items().iter()
+        .filter(|item| item.file_tag().as_deref().unwrap_or("") == file_tag)
+        .find_map(|item| item.expanded_from().filter(|expansion| expansion.line() == line))
+}
+
+fn compile_program(program: &mut MpProgram, kernel: &mut MpProgram, config: &MipsyConfig, iset: &InstSet) -> MipsyResult<Binary> {
+    if config.gnu {
+        gnu::apply_compat(program, iset)?;
     }
 
+    let mut warnings = check_pre(program, config, iset)?;
+
     let mut binary = Binary {
         text: vec![],
         data: vec![],
         ktext: vec![],
         kdata: vec![],
         labels: LinkedHashMap::new(),
+        scopes: LinkedHashMap::new(),
         constants: HashMap::new(),
-        breakpoints: vec![],
         globals: vec![],
         line_numbers: HashMap::new(),
+        warnings: vec![],
+        expected_exit_code: None,
+        no_uninit_check: HashSet::new(),
+        scope: None,
+        in_ktext: false,
     };
     
-    populate_labels_and_data(&mut binary, config, iset, kernel)?;
+    let mut label_refs = vec![];
+    let mut declarations = vec![];
 
-    populate_labels_and_data(&mut binary, config, iset, program)?;
+    let mut ktext_len = 0;
 
-    let warnings = check_post_data_label(program, &binary)?;
-    if !warnings.is_empty() {
-        // TODO: Deal with warnings here
-    }
+    populate_labels_and_data(&mut binary, config, iset, kernel, true, &mut ktext_len, &mut label_refs, &mut declarations)?;
+
+    populate_labels_and_data(&mut binary, config, iset, program, false, &mut ktext_len, &mut label_refs, &mut declarations)?;
+
+    link(&mut binary, &declarations)?;
+
+    resolve_data_labels(&mut binary, &label_refs)?;
 
-    populate_text           (&mut binary, iset, config, program)?;
+    warnings.append(&mut check_post_data_label(program, kernel, config, &binary)?);
+
+    // the kernel text comes first, as its labels were defined first
+    populate_text           (&mut binary, iset, config, kernel, true)?;
+
+    populate_text           (&mut binary, iset, config, program, false)?;
+
+    binary.scope = None;
+
+    // the checks run as separate passes, so put the warnings back in source order
+    let mut file_order = HashMap::new();
+    for item in program.items() {
+        let next = file_order.len();
+        file_order.entry(item.file_tag().unwrap_or_else(|| "".into())).or_insert(next);
+    }
 
-    populate_text           (&mut binary, iset, config, &kernel)?;
+    warnings.sort_by_key(|warning| (file_order.get(&warning.file_tag()).copied(), warning.line(), warning.col()));
+    binary.warnings = warnings;
+    binary.expected_exit_code = program.file_attributes().iter()
+        .find_map(|(_, attributes)| integer_attribute(attributes, KnownAttribute::ExpectedExitCode));
 
     Ok(binary)
 }
@@ -1,11 +1,11 @@
 use std::rc::Rc;
 
-use crate::{Safe, TEXT_BOT, error::{InternalError, MipsyInternalResult, ToMipsyResult, compiler}};
+use crate::{Safe, TEXT_BOT, KTEXT_BOT, error::{InternalError, MipsyInternalResult, ToMipsyResult, compiler}};
 use crate::inst::instruction::SignatureRef;
 use crate::{MpProgram, MipsyResult};
 use crate::inst::instruction::InstSet;
-use super::{Binary, bytes::ToBytes, data::Segment};
-use mipsy_parser::{MpInstruction, MpItem};
+use super::{Binary, KnownAttribute, Scope, bytes::ToBytes, data::Segment, find_attribute};
+use mipsy_parser::{MpDirective, MpInstruction, MpItem};
 use mipsy_utils::MipsyConfig;
 
 pub fn find_instruction<'a>(iset: &'a InstSet, inst: &MpInstruction) -> MipsyInternalResult<SignatureRef<'a>> {
@@ -77,18 +77,37 @@ pub fn compile1(binary: &Binary, iset: &InstSet, inst: &MpInstruction) -> MipsyI
     find_instruction(iset, inst)?.compile_ops(binary, iset, inst)
 }
 
-pub fn populate_text(binary: &mut Binary, iset: &InstSet, config: &MipsyConfig, program: &MpProgram) -> MipsyResult<()> {
+pub fn populate_text(binary: &mut Binary, iset: &InstSet, config: &MipsyConfig, program: &MpProgram, in_kernel: bool) -> MipsyResult<()> {
     let mut segment = Segment::Text;
 
+    let unchecked = |attributes: &[_]| find_attribute(attributes, KnownAttribute::NoUninitCheck).is_some();
+
+    // whether we're after a label marked #[no_uninit_check],
+    // which lasts until the next label or the end of the file
+    let mut in_unchecked_label = false;
+    let mut current_file = None;
+
     for attributed_item in program.items() {
         let line = attributed_item.line_number();
         let file_tag = attributed_item.file_tag()
             .unwrap_or_else(|| Rc::from(""));
         let item = attributed_item.item();
 
+        binary.scope = Some(Scope::of(in_kernel, &file_tag));
+
+        if attributed_item.file_tag() != current_file {
+            current_file = attributed_item.file_tag();
+            in_unchecked_label = false;
+        }
+
         match item {
             MpItem::Directive(directive) => {
-                let bytes = super::data::eval_directive(&directive.0, binary, config, file_tag.clone(), &mut segment, false)?;
+                if let MpDirective::KText(Some(address)) = &directive.0 {
+                    let padding = super::data::ktext_padding(address, binary.ktext.len(), binary, file_tag.clone())?;
+                    binary.ktext.append(&mut vec![Safe::Uninitialised; padding]);
+                }
+
+                let bytes = super::data::eval_directive(&directive.0, binary, config, file_tag.clone(), &mut segment, false, &mut vec![])?;
                 match segment {
                     Segment::Text  => {
                         binary.text.extend(bytes);
@@ -101,10 +120,12 @@ pub fn populate_text(binary: &mut Binary, iset: &InstSet, config: &MipsyConfig,
                 }
             }
             MpItem::Instruction(ref instruction) => {
+                binary.in_ktext = segment == Segment::KText;
+
                 let compiled = compile1(binary, iset, instruction)
                     .into_compiler_mipsy_result(file_tag.clone(), line, instruction.col(), instruction.col_end())?;
 
-                let text = match segment {
+                let (text, base_addr) = match segment {
                     Segment::Text  => {
                         let alignment = (4 - binary.text.len() % 4) % 4;
                         binary.text.append(&mut vec![Safe::Uninitialised; alignment]);
@@ -113,20 +134,35 @@ pub fn populate_text(binary: &mut Binary, iset: &InstSet, config: &MipsyConfig,
                             binary.line_numbers.insert(TEXT_BOT + (binary.text.len() as u32), (file_tag.clone(), line));
                         }
 
-                        &mut binary.text
+                        (&mut binary.text, TEXT_BOT)
                     }
                     Segment::KText => {
                         let alignment = (4 - binary.ktext.len() % 4) % 4;
                         binary.ktext.append(&mut vec![Safe::Uninitialised; alignment]);
-                        
-                        &mut binary.ktext
+
+                        if !in_kernel && !file_tag.is_empty() {
+                            binary.line_numbers.insert(KTEXT_BOT + (binary.ktext.len() as u32), (file_tag.clone(), line));
+                        }
+
+                        (&mut binary.ktext, KTEXT_BOT)
                     },
                     _              => continue,
                 };
 
+                let addr = base_addr + text.len() as u32;
+                let n_insts = compiled.len() as u32;
+
                 text.append(&mut compiled.into_iter().flat_map(|ref b| ToBytes::to_bytes(b)).map(Safe::Valid).collect());
+
+                let file_attributes = program.attributes_of_file(attributed_item.file_tag().as_deref());
+
+                if in_unchecked_label || unchecked(file_attributes) || unchecked(attributed_item.attributes()) {
+                    binary.no_uninit_check.extend((0..n_insts).map(|n| addr + n * 4));
+                }
+            }
+            MpItem::Label(_) => {
+                in_unchecked_label = unchecked(attributed_item.attributes());
             }
-            MpItem::Label(_) => {}
             MpItem::Constant(_) => {}
         }
     }
@@ -4,6 +4,7 @@ use crate::inst::RuntimeMetadata;
 use crate::{Binary, Safe};
 use crate::inst::instruction::{InstSet, CompileSignature, ArgumentType, RuntimeSignature};
 use crate::inst::register::Register;
+use mipsy_parser::{label_display_name, local_label_number};
 
 pub struct Decompiled<'a> {
     pub opcode: u32,
@@ -85,7 +86,7 @@ pub fn decompile_into_parts<'a>(program: &Binary, iset: &'a InstSet) -> HashMap<
 
             for (label, &addr) in program.labels.iter() {
                 if addr == text_addr {
-                    labels.push(label.to_string());
+                    labels.push(label_display_name(label).to_string());
                 }
             }
 
@@ -116,7 +117,7 @@ pub fn decompile_inst_into_parts<'a>(program: &Binary, iset: &'a InstSet, inst:
 
     for (label, &addr) in program.labels.iter() {
         if addr == text_addr {
-            parts.labels.push(label.to_string());
+            parts.labels.push(label_display_name(label).to_string());
         }
     }
 
@@ -163,10 +164,16 @@ pub fn decompile_inst_into_parts<'a>(program: &Binary, iset: &'a InstSet, inst:
                 }
             }
 
-            &RuntimeSignature::I { opcode: inst_opcode, rt: inst_rt } => {
+            &RuntimeSignature::I { opcode: inst_opcode, rs: inst_rs, rt: inst_rt } => {
                 if inst_opcode as u32 != opcode || inst_rt.is_some() && inst_rt.unwrap() as u32 != rt {
                     continue;
                 }
+
+                if let Some(inst_rs) = inst_rs {
+                    if inst_rs as u32 != rs {
+                        continue;
+                    }
+                }
             }
 
             &RuntimeSignature::J { opcode: inst_opcode, .. } => {
@@ -194,6 +201,10 @@ pub fn decompile_inst_into_parts<'a>(program: &Binary, iset: &'a InstSet, inst:
                     ArgumentType::Rt     => format!("${}", Register::u32_to_str(rt)),
                     ArgumentType::Rs     => format!("${}", Register::u32_to_str(rs)),
                     ArgumentType::Shamt  => format!("{}", shamt),
+                    ArgumentType::Fd     => format!("$f{}", shamt),
+                    ArgumentType::Fs     => format!("$f{}", rd),
+                    ArgumentType::Ft     => format!("$f{}", rt),
+                    ArgumentType::Cd     => format!("${}", rd),
                     ArgumentType::OffRs  => format!("{}(${})", if imm != 0 { imm.to_string() } else { String::new() }, Register::u32_to_str(rs)),
                     ArgumentType::OffRt  => format!("{}(${})", if imm != 0 { imm.to_string() } else { String::new() }, Register::u32_to_str(rt)),
                     ArgumentType::I16    => {
@@ -203,15 +214,15 @@ pub fn decompile_inst_into_parts<'a>(program: &Binary, iset: &'a InstSet, inst:
 
                             for (label, &addr) in program.labels.iter() {
                                 if addr == text_addr.wrapping_add((imm as i32 * 4) as u32) {
-                                    res = Some(label);
+                                    res = Some((label, addr));
                                     break;
                                 }
                             }
 
                         }
                         
-                        if let Some(label) = res {
-                            label.to_string()
+                        if let Some((label, addr)) = res {
+                            label_reference(label, addr, text_addr)
                         } else {
                             imm.to_string()
                         }
@@ -229,7 +240,7 @@ pub fn decompile_inst_into_parts<'a>(program: &Binary, iset: &'a InstSet, inst:
                             }
                         }
 
-                        j_label.map(|label| label.to_string()).unwrap_or(format!("{:08x}", j_addr))
+                        j_label.map(|label| label_reference(label, j_addr, text_addr)).unwrap_or(format!("{:08x}", j_addr))
                     }
                     _ => unreachable!(),
                 }.to_ascii_lowercase())
@@ -239,3 +250,13 @@ pub fn decompile_inst_into_parts<'a>(program: &Binary, iset: &'a InstSet, inst:
 
     parts
 }
+
+/// How an instruction at `addr` refers to a label at `label_addr` -
+/// by its name, or for a numeric local label, as `1b` or `1f`.
+fn label_reference(label: &str, label_addr: u32, addr: u32) -> String {
+    match local_label_number(label) {
+        Some(number) if label_addr <= addr => format!("{}b", number),
+        Some(number) => format!("{}f", number),
+        None => label.to_string(),
+    }
+}
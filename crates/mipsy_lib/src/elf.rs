@@ -0,0 +1,1166 @@
+//! # Reading and writing binaries as ELF executables.
+//!
+//! A [`Binary`] is written as a 32-bit little-endian MIPS ELF executable,
+//! with a loadable segment each for `.text`, `.data`, `.ktext` and `.kdata`,
+//! a symbol table of its labels, and its line numbers as DWARF `.debug_line`
+//! information - so `objdump -d -l` can make sense of it.
+//!
+//! mipsy has no branch delay slots, so its branch offsets count from
+//! the branch itself, rather than from the instruction after it like
+//! MIPS' do. Branches are adjusted to match MIPS when written, and back
+//! when read.
+//!
+//! ELF has no notion of uninitialised memory, so uninitialised bytes are
+//! written as zeroes, and listed in a `.mipsy.uninit` section of
+//! `(address, length)` pairs, which is used when the ELF is read back in.
+//! The section is always written, as it's also how an ELF written by mipsy
+//! is told apart from one that wasn't.
+//!
+//! ELF files that weren't written by mipsy can be read too, so long as
+//! their segments fit in mipsy's memory layout. Without a `.ktext` segment,
+//! a small kernel is provided which calls the ELF's entry point, then exits
+//! with its `$v0`. Only mipsy's syscalls are available, and instructions in
+//! delay slots aren't run before their branch or jump is, so only simple
+//! programs can be read - those with just `nop`s in their delay slots.
+//!
+//! Constants, warnings and attributes such as `#![expected_exit_code]`
+//! aren't kept.
+
+use std::{collections::{HashMap, HashSet}, fmt, rc::Rc};
+
+use linked_hash_map::LinkedHashMap;
+
+use crate::{Binary, DATA_BOT, HEAP_BOT, KDATA_BOT, KTEXT_BOT, Safe, TEXT_BOT, compile::TEXT_TOP, runtime::profile::is_branch};
+
+const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+const ELFCLASS32: u8 = 1;
+const ELFDATA2LSB: u8 = 1;
+const EV_CURRENT: u8 = 1;
+const ET_EXEC: u16 = 2;
+const EM_MIPS: u16 = 8;
+const EF_MIPS_ABI_O32: u32 = 0x0000_1000;
+const EF_MIPS_ARCH_32: u32 = 0x5000_0000;
+
+const EHDR_SIZE: u32 = 52;
+const PHDR_SIZE: u32 = 32;
+const SHDR_SIZE: u32 = 40;
+const SYM_SIZE: u32 = 16;
+
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHF_WRITE: u32 = 1;
+const SHF_ALLOC: u32 = 2;
+const SHF_EXECINSTR: u32 = 4;
+const SHN_UNDEF: u16 = 0;
+const SHN_ABS: u16 = 0xFFF1;
+
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STB_WEAK: u8 = 2;
+const STT_NOTYPE: u8 = 0;
+const STT_SECTION: u8 = 3;
+const STT_FILE: u8 = 4;
+
+const PAGE_SIZE: u32 = 0x1000;
+/// The most memory a single segment can take up.
+const MAX_SEGMENT_SIZE: u32 = 0x0100_0000;
+
+const UNINIT_SECTION: &str = ".mipsy.uninit";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ElfError {
+    NotElf,
+    /// The ELF isn't a 32-bit little-endian MIPS executable.
+    Unsupported { reason: &'static str },
+    /// The ELF ends before the data at `offset` does.
+    Truncated { offset: usize },
+    /// A segment is loaded at an address outside of mipsy's memory layout.
+    BadAddress { addr: u32 },
+    /// A segment is smaller in memory than in the file, or too large to load.
+    BadSize { addr: u32, memsz: u32 },
+    /// An ELF that wasn't written by mipsy has something other
+    /// than a `nop` in the delay slot at `addr`.
+    DelaySlot { addr: u32 },
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ElfError::NotElf => write!(f, "not an ELF file"),
+            ElfError::Unsupported { reason } => write!(f, "unsupported ELF file: {}", reason),
+            ElfError::Truncated { offset } => write!(f, "ELF file is truncated at offset 0x{:x}", offset),
+            ElfError::BadAddress { addr } => write!(f, "cannot load a segment at address 0x{:08x}", addr),
+            ElfError::BadSize { addr, memsz } => write!(f, "cannot load a segment of 0x{:x} bytes at address 0x{:08x}", memsz, addr),
+            ElfError::DelaySlot { addr } => write!(f, "the delay slot at address 0x{:08x} isn't a `nop`, and mipsy doesn't run delay slots", addr),
+        }
+    }
+}
+
+/// Whether `bytes` look like an ELF file.
+pub fn is_elf(bytes: &[u8]) -> bool {
+    bytes.starts_with(&ELF_MAGIC)
+}
+
+/// A section to be written.
+struct Section {
+    name: &'static str,
+    kind: u32,
+    flags: u32,
+    addr: u32,
+    data: Vec<u8>,
+    link: u32,
+    info: u32,
+    align: u32,
+    entsize: u32,
+}
+
+impl Section {
+    fn new(name: &'static str, kind: u32, data: Vec<u8>) -> Self {
+        Self { name, kind, flags: 0, addr: 0, data, link: 0, info: 0, align: 1, entsize: 0 }
+    }
+
+    fn loadable(name: &'static str, flags: u32, addr: u32, bytes: &[Safe<u8>]) -> Self {
+        let mut data = bytes.iter()
+            .map(|byte| byte.into_option().unwrap_or(0))
+            .collect::<Vec<_>>();
+
+        if flags & SHF_EXECINSTR != 0 {
+            for word in data.chunks_exact_mut(4) {
+                let inst = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+                word.copy_from_slice(&adjust_branch(inst, -1).to_le_bytes());
+            }
+        }
+
+        Self {
+            flags: SHF_ALLOC | flags,
+            addr,
+            align: PAGE_SIZE,
+            ..Self::new(name, SHT_PROGBITS, data)
+        }
+    }
+}
+
+impl Binary {
+    /// # Writes the binary as an ELF executable.
+    ///
+    /// The entry point is the start of the kernel.
+    pub fn to_elf(&self) -> Vec<u8> {
+        let segments = [
+            (".text",  SHF_EXECINSTR, TEXT_BOT,  &self.text),
+            (".data",  SHF_WRITE,     DATA_BOT,  &self.data),
+            (".ktext", SHF_EXECINSTR, KTEXT_BOT, &self.ktext),
+            (".kdata", SHF_WRITE,     KDATA_BOT, &self.kdata),
+        ];
+
+        // the null section
+        let mut sections = vec![Section::new("", 0, vec![])];
+
+        for &(name, flags, addr, bytes) in segments.iter() {
+            if !bytes.is_empty() {
+                sections.push(Section::loadable(name, flags, addr, bytes));
+            }
+        }
+
+        let n_loadable = sections.len() - 1;
+
+        if !self.line_numbers.is_empty() {
+            sections.push(Section::new(".debug_line", SHT_PROGBITS, self.debug_line()));
+        }
+
+        let uninit = segments.iter()
+            .flat_map(|&(_, _, addr, bytes)| uninit_ranges(addr, bytes))
+            .flat_map(|(addr, len)| addr.to_le_bytes().into_iter().chain(len.to_le_bytes()))
+            .collect::<Vec<_>>();
+
+        sections.push(Section { align: 4, ..Section::new(UNINIT_SECTION, SHT_PROGBITS, uninit) });
+
+        let (symtab, strtab, n_local) = self.symbols(&sections);
+        let symtab_index = sections.len() as u32;
+        sections.push(Section {
+            link: symtab_index + 1,
+            info: n_local,
+            align: 4,
+            entsize: SYM_SIZE,
+            ..Section::new(".symtab", SHT_SYMTAB, symtab)
+        });
+        sections.push(Section::new(".strtab", SHT_STRTAB, strtab));
+
+        let mut shstrtab = vec![0];
+        let mut name_offsets = vec![];
+        for section in sections.iter() {
+            name_offsets.push(push_str(&mut shstrtab, section.name));
+        }
+        name_offsets.push(push_str(&mut shstrtab, ".shstrtab"));
+        sections.push(Section::new(".shstrtab", SHT_STRTAB, shstrtab));
+
+        // lay the file out: headers, then each section, then the section headers
+        let mut offset = EHDR_SIZE + PHDR_SIZE * n_loadable as u32;
+        let mut offsets = vec![0];
+        for section in sections.iter().skip(1) {
+            offset = align_to(offset, section.align);
+            offsets.push(offset);
+            offset += section.data.len() as u32;
+        }
+        let shoff = align_to(offset, 4);
+
+        let mut elf = vec![];
+
+        elf.extend(ELF_MAGIC);
+        elf.extend([ELFCLASS32, ELFDATA2LSB, EV_CURRENT]);
+        elf.resize(16, 0);
+        push_u16(&mut elf, ET_EXEC);
+        push_u16(&mut elf, EM_MIPS);
+        push_u32(&mut elf, EV_CURRENT as u32);
+        push_u32(&mut elf, KTEXT_BOT);
+        push_u32(&mut elf, EHDR_SIZE);
+        push_u32(&mut elf, shoff);
+        push_u32(&mut elf, EF_MIPS_ARCH_32 | EF_MIPS_ABI_O32);
+        push_u16(&mut elf, EHDR_SIZE as u16);
+        push_u16(&mut elf, PHDR_SIZE as u16);
+        push_u16(&mut elf, n_loadable as u16);
+        push_u16(&mut elf, SHDR_SIZE as u16);
+        push_u16(&mut elf, sections.len() as u16);
+        push_u16(&mut elf, sections.len() as u16 - 1);
+
+        for (section, &offset) in sections.iter().zip(offsets.iter()).skip(1).take(n_loadable) {
+            let flags = match section.flags & SHF_EXECINSTR {
+                0 => PF_R | PF_W,
+                _ => PF_R | PF_X,
+            };
+
+            push_u32(&mut elf, PT_LOAD);
+            push_u32(&mut elf, offset);
+            push_u32(&mut elf, section.addr);
+            push_u32(&mut elf, section.addr);
+            push_u32(&mut elf, section.data.len() as u32);
+            push_u32(&mut elf, section.data.len() as u32);
+            push_u32(&mut elf, flags);
+            push_u32(&mut elf, section.align);
+        }
+
+        for (section, &offset) in sections.iter().zip(offsets.iter()).skip(1) {
+            elf.resize(offset as usize, 0);
+            elf.extend(&section.data);
+        }
+
+        elf.resize(shoff as usize, 0);
+
+        for ((section, &offset), &name) in sections.iter().zip(offsets.iter()).zip(name_offsets.iter()) {
+            push_u32(&mut elf, name);
+            push_u32(&mut elf, section.kind);
+            push_u32(&mut elf, section.flags);
+            push_u32(&mut elf, section.addr);
+            push_u32(&mut elf, offset);
+            push_u32(&mut elf, section.data.len() as u32);
+            push_u32(&mut elf, section.link);
+            push_u32(&mut elf, section.info);
+            push_u32(&mut elf, if section.kind == 0 { 0 } else { section.align });
+            push_u32(&mut elf, section.entsize);
+        }
+
+        elf
+    }
+
+    /// The symbol table and its string table, and the number of local symbols -
+    /// which must all come before the global ones.
+    fn symbols(&self, sections: &[Section]) -> (Vec<u8>, Vec<u8>, u32) {
+        let globals = self.globals.iter().collect::<HashSet<_>>();
+
+        // local labels that share their name with another file's
+        // can't be used from anywhere, but are still worth naming
+        let shared_locals = self.scopes.iter()
+            .flat_map(|(_, labels)| labels.iter())
+            .filter(|&(label, addr)| self.labels.get(label) != Some(addr))
+            .map(|(label, addr)| (label, addr, false));
+
+        let mut labels = self.labels.iter()
+            .map(|(label, addr)| (label, addr, globals.contains(label)))
+            .chain(shared_locals)
+            .collect::<Vec<_>>();
+        // stable, so labels otherwise keep their order
+        labels.sort_by_key(|&(_, _, global)| global);
+
+        let mut symtab = vec![0; SYM_SIZE as usize];
+        let mut strtab = vec![0];
+        let mut n_local = 1;
+
+        for (label, &addr, global) in labels {
+            let bind = if global {
+                STB_GLOBAL
+            } else {
+                n_local += 1;
+                STB_LOCAL
+            };
+
+            // a label can be just past the end of its section
+            let shndx = sections.iter()
+                .position(|section| section.flags & SHF_ALLOC != 0 && addr >= section.addr && addr <= section.addr + section.data.len() as u32)
+                .map_or(SHN_ABS, |index| index as u16);
+
+            push_u32(&mut symtab, push_str(&mut strtab, label));
+            push_u32(&mut symtab, addr);
+            push_u32(&mut symtab, 0);
+            symtab.push(bind << 4 | STT_NOTYPE);
+            symtab.push(0);
+            push_u16(&mut symtab, shndx);
+        }
+
+        (symtab, strtab, n_local)
+    }
+
+    /// The line numbers, as a DWARF version 2 line number program.
+    fn debug_line(&self) -> Vec<u8> {
+        let mut files: Vec<&Rc<str>> = vec![];
+        let mut rows = self.line_numbers.iter()
+            .map(|(&addr, (file_tag, line_num))| {
+                let file = match files.iter().position(|&file| file == file_tag) {
+                    Some(index) => index,
+                    None => {
+                        files.push(file_tag);
+                        files.len() - 1
+                    }
+                };
+
+                (addr, file as u64 + 1, *line_num)
+            })
+            .collect::<Vec<_>>();
+        rows.sort_unstable();
+
+        let mut header = vec![];
+        // minimum_instruction_length, default_is_stmt, line_base, line_range, opcode_base
+        header.extend([4, 1, (-5i8) as u8, 14, 13]);
+        header.extend([0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1]);
+        // no include directories
+        header.push(0);
+        for file in files {
+            push_str(&mut header, file);
+            // directory, modification time, length
+            header.extend([0, 0, 0]);
+        }
+        header.push(0);
+
+        let mut program = vec![];
+        let mut line = 1i64;
+        let mut segment_end = None;
+
+        for (addr, file, line_num) in rows {
+            let end = self.segment_end(addr);
+
+            match segment_end {
+                Some(previous_end) if previous_end != end => {
+                    end_sequence(&mut program, previous_end);
+                    line = 1;
+                }
+                _ => {}
+            }
+            segment_end = Some(end);
+
+            set_address(&mut program, addr);
+            // DW_LNS_set_file
+            program.push(4);
+            push_uleb(&mut program, file);
+            // DW_LNS_advance_line
+            program.push(3);
+            push_sleb(&mut program, line_num as i64 - line);
+            // DW_LNS_copy
+            program.push(1);
+
+            line = line_num as i64;
+        }
+
+        if let Some(segment_end) = segment_end {
+            end_sequence(&mut program, segment_end);
+        }
+
+        let mut debug_line = vec![];
+        push_u32(&mut debug_line, (2 + 4 + header.len() + program.len()) as u32);
+        push_u16(&mut debug_line, 2);
+        push_u32(&mut debug_line, header.len() as u32);
+        debug_line.extend(header);
+        debug_line.extend(program);
+
+        debug_line
+    }
+
+    /// The address just past the end of the text segment `addr` is in.
+    fn segment_end(&self, addr: u32) -> u32 {
+        if addr >= KTEXT_BOT {
+            KTEXT_BOT + self.ktext.len() as u32
+        } else {
+            TEXT_BOT + self.text.len() as u32
+        }
+    }
+
+    /// # Reads a binary from an ELF executable.
+    pub fn from_elf(elf: &[u8]) -> Result<Binary, ElfError> {
+        if !is_elf(elf) {
+            return Err(ElfError::NotElf);
+        }
+
+        let header = Reader::new(elf);
+        if header.u8_at(4)? != ELFCLASS32 || header.u8_at(5)? != ELFDATA2LSB {
+            return Err(ElfError::Unsupported { reason: "not a 32-bit little-endian ELF" });
+        }
+        if header.u16_at(18)? != EM_MIPS {
+            return Err(ElfError::Unsupported { reason: "not a MIPS ELF" });
+        }
+        if header.u16_at(16)? != ET_EXEC {
+            return Err(ElfError::Unsupported { reason: "not an executable" });
+        }
+
+        let entry     = header.u32_at(24)?;
+        let phoff     = header.u32_at(28)? as usize;
+        let shoff     = header.u32_at(32)? as usize;
+        let phentsize = header.u16_at(42)? as usize;
+        let phnum     = header.u16_at(44)? as usize;
+        let shentsize = header.u16_at(46)? as usize;
+        let shnum     = header.u16_at(48)? as usize;
+        let shstrndx  = header.u16_at(50)? as usize;
+
+        let mut binary = Binary {
+            text: vec![],
+            data: vec![],
+            ktext: vec![],
+            kdata: vec![],
+            labels: LinkedHashMap::new(),
+            scopes: LinkedHashMap::new(),
+            constants: HashMap::new(),
+            globals: vec![],
+            line_numbers: HashMap::new(),
+            warnings: vec![],
+            expected_exit_code: None,
+            no_uninit_check: HashSet::new(),
+            scope: None,
+            in_ktext: false,
+        };
+
+        // where the instructions are, by segment and by section
+        let mut executable_segments = vec![];
+        let mut executable_sections = vec![];
+        let mut written_by_mipsy = false;
+
+        for i in 0..phnum {
+            let phdr = phoff + i * phentsize;
+
+            if header.u32_at(phdr)? != PT_LOAD {
+                continue;
+            }
+
+            let offset = header.u32_at(phdr + 4)? as usize;
+            let vaddr  = header.u32_at(phdr + 8)?;
+            let filesz = header.u32_at(phdr + 16)? as usize;
+            let memsz  = header.u32_at(phdr + 20)?;
+            let flags  = header.u32_at(phdr + 24)?;
+
+            let bytes = header.bytes_at(offset, filesz)?;
+            binary.load_segment(vaddr, memsz, bytes)?;
+
+            if flags & PF_X != 0 {
+                executable_segments.push((vaddr, filesz as u32));
+            }
+        }
+
+        let sections = (0..shnum)
+            .map(|i| SectionHeader::read(&header, shoff + i * shentsize))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let shstrtab = match sections.get(shstrndx) {
+            Some(shstrtab) => shstrtab.data(elf)?,
+            None => &[],
+        };
+
+        for section in sections.iter() {
+            let name = Reader::new(shstrtab).str_at(section.name as usize).unwrap_or_default();
+
+            match (section.kind, &*name) {
+                (SHT_SYMTAB, _) => {
+                    let strtab = match sections.get(section.link as usize) {
+                        Some(strtab) => strtab.data(elf)?,
+                        None => &[],
+                    };
+
+                    binary.read_symbols(section.data(elf)?, strtab)?;
+                }
+                (SHT_PROGBITS, _) if section.flags & (SHF_ALLOC | SHF_EXECINSTR) == SHF_ALLOC | SHF_EXECINSTR => {
+                    executable_sections.push((section.addr, section.size));
+                }
+                (SHT_PROGBITS, ".debug_line") => binary.read_debug_line(section.data(elf)?)?,
+                (SHT_PROGBITS, UNINIT_SECTION) => {
+                    written_by_mipsy = true;
+
+                    let ranges = Reader::new(section.data(elf)?);
+
+                    for range in (0..section.size as usize / 8).map(|i| i * 8) {
+                        let addr = ranges.u32_at(range)?;
+                        let len  = ranges.u32_at(range + 4)?;
+
+                        binary.uninitialise(addr, len);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // sections are more precise, as a segment can also hold read-only data
+        let executable = match executable_sections.is_empty() {
+            true  => executable_segments,
+            false => executable_sections,
+        };
+
+        for (addr, len) in executable {
+            if !written_by_mipsy {
+                binary.check_delay_slots(addr, len)?;
+            }
+
+            binary.adjust_branches(addr, len);
+        }
+
+        if binary.ktext.is_empty() {
+            binary.ktext = entry_kernel(entry).iter()
+                .flat_map(|inst| inst.to_le_bytes())
+                .map(Safe::Valid)
+                .collect();
+        }
+
+        Ok(binary)
+    }
+
+    /// The segment holding `addr`, and `addr`'s offset into it.
+    fn segment_mut(&mut self, addr: u32) -> Option<(&mut Vec<Safe<u8>>, usize)> {
+        let bot = segment_bot(addr)?;
+
+        let segment = match bot {
+            TEXT_BOT  => &mut self.text,
+            DATA_BOT  => &mut self.data,
+            KTEXT_BOT => &mut self.ktext,
+            _         => &mut self.kdata,
+        };
+
+        Some((segment, (addr - bot) as usize))
+    }
+
+    /// Loads `bytes` at `vaddr`, followed by zeroes up to `memsz` bytes.
+    fn load_segment(&mut self, vaddr: u32, memsz: u32, bytes: &[u8]) -> Result<(), ElfError> {
+        if (memsz as usize) < bytes.len() || memsz > MAX_SEGMENT_SIZE {
+            return Err(ElfError::BadSize { addr: vaddr, memsz });
+        }
+
+        if memsz == 0 {
+            return Ok(());
+        }
+
+        // the whole segment has to fit into one of mipsy's
+        let last = vaddr.checked_add(memsz - 1);
+        if segment_bot(vaddr).is_none() || last.and_then(segment_bot) != segment_bot(vaddr) {
+            return Err(ElfError::BadAddress { addr: vaddr });
+        }
+
+        let (segment, offset) = self.segment_mut(vaddr).unwrap();
+
+        let len = memsz as usize;
+        if segment.len() < offset + len {
+            segment.resize(offset + len, Safe::Uninitialised);
+        }
+
+        let filled = bytes.len();
+        for (i, &byte) in bytes.iter().enumerate() {
+            segment[offset + i] = Safe::Valid(byte);
+        }
+
+        for byte in segment[offset + filled..offset + len].iter_mut() {
+            *byte = Safe::Valid(0);
+        }
+
+        Ok(())
+    }
+
+    /// Converts the MIPS branches in `len` bytes from `addr` into mipsy's.
+    fn adjust_branches(&mut self, addr: u32, len: u32) {
+        for inst_addr in (addr..addr.saturating_add(len)).step_by(4) {
+            let (segment, offset) = match self.segment_mut(inst_addr) {
+                Some(segment) => segment,
+                None => continue,
+            };
+
+            let bytes = match segment.get(offset..offset + 4) {
+                Some([Safe::Valid(b1), Safe::Valid(b2), Safe::Valid(b3), Safe::Valid(b4)]) => [*b1, *b2, *b3, *b4],
+                _ => continue,
+            };
+
+            let inst = adjust_branch(u32::from_le_bytes(bytes), 1);
+            for (i, byte) in inst.to_le_bytes().into_iter().enumerate() {
+                segment[offset + i] = Safe::Valid(byte);
+            }
+        }
+    }
+
+    /// Checks that every branch or jump in `len` bytes from `addr` has a `nop` in its delay slot.
+    fn check_delay_slots(&mut self, addr: u32, len: u32) -> Result<(), ElfError> {
+        let end = addr.saturating_add(len);
+
+        for inst_addr in (addr..end).step_by(4) {
+            let slot_addr = inst_addr + 4;
+            if slot_addr >= end || !has_delay_slot(self.word_at(inst_addr)) {
+                continue;
+            }
+
+            if self.word_at(slot_addr) != 0 {
+                return Err(ElfError::DelaySlot { addr: slot_addr });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The word at `addr`, with anything uninitialised read as zero.
+    fn word_at(&mut self, addr: u32) -> u32 {
+        let mut bytes = [0; 4];
+
+        if let Some((segment, offset)) = self.segment_mut(addr) {
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                *byte = segment.get(offset + i).and_then(|byte| byte.as_option().copied()).unwrap_or(0);
+            }
+        }
+
+        u32::from_le_bytes(bytes)
+    }
+
+    fn uninitialise(&mut self, addr: u32, len: u32) {
+        for addr in addr..addr.saturating_add(len) {
+            if let Some((segment, offset)) = self.segment_mut(addr) {
+                if let Some(byte) = segment.get_mut(offset) {
+                    *byte = Safe::Uninitialised;
+                }
+            }
+        }
+    }
+
+    fn read_symbols(&mut self, symtab: &[u8], strtab: &[u8]) -> Result<(), ElfError> {
+        let symbols = Reader::new(symtab);
+        let strings = Reader::new(strtab);
+
+        for symbol in (1..symtab.len() / SYM_SIZE as usize).map(|i| i * SYM_SIZE as usize) {
+            let name  = symbols.u32_at(symbol)? as usize;
+            let value = symbols.u32_at(symbol + 4)?;
+            let info  = symbols.u8_at(symbol + 12)?;
+            let shndx = symbols.u16_at(symbol + 14)?;
+
+            let name = strings.str_at(name)?;
+            if name.is_empty() || shndx == SHN_UNDEF || shndx == SHN_ABS || matches!(info & 0xF, STT_SECTION | STT_FILE) {
+                continue;
+            }
+
+            if matches!(info >> 4, STB_GLOBAL | STB_WEAK) {
+                self.globals.push(name.clone());
+            }
+
+            self.labels.insert(name, value);
+        }
+
+        Ok(())
+    }
+
+    /// Reads the line numbers from DWARF (version 2 to 4) `.debug_line` information.
+    fn read_debug_line(&mut self, debug_line: &[u8]) -> Result<(), ElfError> {
+        let reader = Reader::new(debug_line);
+        let mut unit = 0;
+
+        while unit + 4 <= debug_line.len() {
+            let unit_length = reader.u32_at(unit)? as usize;
+            let unit_end = unit + 4 + unit_length;
+
+            let version = reader.u16_at(unit + 4)?;
+            // 64-bit DWARF, or a version we can't read
+            if unit_length >= 0xFFFF_FFF0 || !(2..=4).contains(&version) {
+                return Ok(());
+            }
+
+            let header_length = reader.u32_at(unit + 6)? as usize;
+            let program_start = unit + 10 + header_length;
+
+            let mut cursor = Cursor { reader: &reader, offset: unit + 10 };
+            let min_inst_length = cursor.u8()? as u32;
+            if version >= 4 {
+                // maximum_operations_per_instruction
+                cursor.u8()?;
+            }
+            let _default_is_stmt = cursor.u8()?;
+            let line_base = cursor.u8()? as i8 as i64;
+            let line_range = cursor.u8()?;
+            let opcode_base = cursor.u8()?;
+            let opcode_lengths = (1..opcode_base).map(|_| cursor.u8()).collect::<Result<Vec<_>, _>>()?;
+
+            if line_range == 0 {
+                return Err(ElfError::Unsupported { reason: "invalid line number information" });
+            }
+
+            let mut directories = vec![];
+            loop {
+                let directory = cursor.str()?;
+                if directory.is_empty() {
+                    break;
+                }
+
+                directories.push(directory);
+            }
+
+            let mut files: Vec<Rc<str>> = vec![];
+            loop {
+                let file = cursor.str()?;
+                if file.is_empty() {
+                    break;
+                }
+
+                let directory = cursor.uleb()? as usize;
+                cursor.uleb()?;
+                cursor.uleb()?;
+
+                files.push(match directories.get(directory.wrapping_sub(1)) {
+                    Some(directory) if !file.starts_with('/') => format!("{}/{}", directory, file).into(),
+                    _ => file.into(),
+                });
+            }
+
+            cursor.offset = program_start;
+
+            let mut addr = 0u32;
+            let mut file = 1u64;
+            let mut line = 1i64;
+            let mut last_row = None;
+
+            while cursor.offset < unit_end {
+                let opcode = cursor.u8()?;
+                let mut emit = false;
+
+                if opcode >= opcode_base {
+                    let adjusted = opcode - opcode_base;
+                    addr = addr.wrapping_add((adjusted / line_range) as u32 * min_inst_length);
+                    line += line_base + (adjusted % line_range) as i64;
+                    emit = true;
+                } else {
+                    match opcode {
+                        // extended opcodes
+                        0 => {
+                            let length = cursor.uleb()? as usize;
+                            let next = cursor.offset + length;
+
+                            match cursor.u8()? {
+                                // DW_LNE_end_sequence
+                                1 => {
+                                    addr = 0;
+                                    file = 1;
+                                    line = 1;
+                                    last_row = None;
+                                }
+                                // DW_LNE_set_address
+                                2 => addr = cursor.u32()?,
+                                _ => {}
+                            }
+
+                            cursor.offset = next;
+                        }
+                        // DW_LNS_copy
+                        1 => emit = true,
+                        // DW_LNS_advance_pc
+                        2 => addr = addr.wrapping_add(cursor.uleb()? as u32 * min_inst_length),
+                        // DW_LNS_advance_line
+                        3 => line += cursor.sleb()?,
+                        // DW_LNS_set_file
+                        4 => file = cursor.uleb()?,
+                        // DW_LNS_const_add_pc
+                        8 => addr = addr.wrapping_add(((255 - opcode_base) / line_range) as u32 * min_inst_length),
+                        // DW_LNS_fixed_advance_pc
+                        9 => addr = addr.wrapping_add(cursor.u16()? as u32),
+                        _ => {
+                            for _ in 0..opcode_lengths[opcode as usize - 1] {
+                                cursor.uleb()?;
+                            }
+                        }
+                    }
+                }
+
+                if emit {
+                    let row = (file, line);
+
+                    // only the first instruction of each line is numbered
+                    if last_row != Some(row) {
+                        if let (Some(file_tag), Ok(line_num)) = (files.get(file as usize - 1), u32::try_from(line)) {
+                            self.line_numbers.insert(addr, (file_tag.clone(), line_num));
+                        }
+                    }
+
+                    last_row = Some(row);
+                }
+            }
+
+            unit = unit_end;
+        }
+
+        Ok(())
+    }
+}
+
+/// The start of the segment that `addr` is in, if it's in one.
+fn segment_bot(addr: u32) -> Option<u32> {
+    match addr {
+        _ if (TEXT_BOT..=TEXT_TOP).contains(&addr)  => Some(TEXT_BOT),
+        _ if (DATA_BOT..HEAP_BOT).contains(&addr)   => Some(DATA_BOT),
+        _ if (KTEXT_BOT..KDATA_BOT).contains(&addr) => Some(KTEXT_BOT),
+        _ if addr >= KDATA_BOT                      => Some(KDATA_BOT),
+        _ => None,
+    }
+}
+
+/// The ranges of uninitialised bytes in a segment starting at `addr`.
+fn uninit_ranges(addr: u32, bytes: &[Safe<u8>]) -> Vec<(u32, u32)> {
+    let mut ranges: Vec<(u32, u32)> = vec![];
+
+    for (i, byte) in bytes.iter().enumerate() {
+        if byte.as_option().is_some() {
+            continue;
+        }
+
+        let byte_addr = addr + i as u32;
+
+        match ranges.last_mut() {
+            Some((start, len)) if *start + *len == byte_addr => *len += 1,
+            _ => ranges.push((byte_addr, 1)),
+        }
+    }
+
+    ranges
+}
+
+/// Whether `inst` is a branch or jump, which MIPS would follow with a delay slot.
+fn has_delay_slot(inst: u32) -> bool {
+    let opcode = inst >> 26;
+    let funct  = inst & 0x3F;
+
+    // J, JAL, and JR, JALR
+    is_branch(inst) || matches!(opcode, 0x02 | 0x03) || (opcode == 0 && matches!(funct, 0x08 | 0x09))
+}
+
+/// Moves the offset of `inst`, if it's a branch, by `delta` instructions.
+fn adjust_branch(inst: u32, delta: i16) -> u32 {
+    if !is_branch(inst) {
+        return inst;
+    }
+
+    let offset = (inst as u16 as i16).wrapping_add(delta);
+
+    inst & 0xFFFF_0000 | offset as u16 as u32
+}
+
+/// A kernel that calls `entry`, then exits with its `$v0`.
+fn entry_kernel(entry: u32) -> [u32; 6] {
+    [
+        // lui   $k0, %hi(entry)
+        0x3C1A_0000 | entry >> 16,
+        // ori   $k0, $k0, %lo(entry)
+        0x375A_0000 | entry & 0xFFFF,
+        // jalr  $k0
+        0x0340_F809,
+        // move  $a0, $v0
+        0x0040_2021,
+        // li    $v0, 17
+        0x2402_0011,
+        // syscall
+        0x0000_000C,
+    ]
+}
+
+fn end_sequence(program: &mut Vec<u8>, end: u32) {
+    set_address(program, end);
+    // DW_LNE_end_sequence
+    program.extend([0, 1, 1]);
+}
+
+fn set_address(program: &mut Vec<u8>, addr: u32) {
+    // DW_LNE_set_address
+    program.extend([0, 5, 2]);
+    push_u32(program, addr);
+}
+
+fn align_to(offset: u32, align: u32) -> u32 {
+    offset.div_ceil(align) * align
+}
+
+fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+    bytes.extend(value.to_le_bytes());
+}
+
+fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend(value.to_le_bytes());
+}
+
+/// Appends a NUL-terminated string, returning its offset.
+fn push_str(bytes: &mut Vec<u8>, string: &str) -> u32 {
+    let offset = bytes.len() as u32;
+
+    bytes.extend(string.bytes());
+    bytes.push(0);
+
+    offset
+}
+
+fn push_uleb(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn push_sleb(bytes: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            bytes.push(byte);
+            return;
+        }
+
+        bytes.push(byte | 0x80);
+    }
+}
+
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u32,
+    addr: u32,
+    offset: u32,
+    size: u32,
+    link: u32,
+}
+
+impl SectionHeader {
+    fn read(reader: &Reader, shdr: usize) -> Result<Self, ElfError> {
+        Ok(Self {
+            name:   reader.u32_at(shdr)?,
+            kind:   reader.u32_at(shdr + 4)?,
+            flags:  reader.u32_at(shdr + 8)?,
+            addr:   reader.u32_at(shdr + 12)?,
+            offset: reader.u32_at(shdr + 16)?,
+            size:   reader.u32_at(shdr + 20)?,
+            link:   reader.u32_at(shdr + 24)?,
+        })
+    }
+
+    fn data<'a>(&self, elf: &'a [u8]) -> Result<&'a [u8], ElfError> {
+        Reader::new(elf).bytes_at(self.offset as usize, self.size as usize)
+    }
+}
+
+/// Reads little-endian values at offsets into some bytes.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn bytes_at(&self, offset: usize, len: usize) -> Result<&'a [u8], ElfError> {
+        offset.checked_add(len)
+            .and_then(|end| self.bytes.get(offset..end))
+            .ok_or(ElfError::Truncated { offset })
+    }
+
+    fn u8_at(&self, offset: usize) -> Result<u8, ElfError> {
+        Ok(self.bytes_at(offset, 1)?[0])
+    }
+
+    fn u16_at(&self, offset: usize) -> Result<u16, ElfError> {
+        let bytes = self.bytes_at(offset, 2)?;
+
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32_at(&self, offset: usize) -> Result<u32, ElfError> {
+        let bytes = self.bytes_at(offset, 4)?;
+
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn str_at(&self, offset: usize) -> Result<String, ElfError> {
+        let bytes = self.bytes.get(offset..)
+            .ok_or(ElfError::Truncated { offset })?;
+        let len = bytes.iter()
+            .position(|&byte| byte == 0)
+            .ok_or(ElfError::Truncated { offset })?;
+
+        Ok(String::from_utf8_lossy(&bytes[..len]).to_string())
+    }
+}
+
+/// Reads values one after another.
+struct Cursor<'a, 'b> {
+    reader: &'b Reader<'a>,
+    offset: usize,
+}
+
+impl Cursor<'_, '_> {
+    fn u8(&mut self) -> Result<u8, ElfError> {
+        let value = self.reader.u8_at(self.offset)?;
+        self.offset += 1;
+
+        Ok(value)
+    }
+
+    fn u16(&mut self) -> Result<u16, ElfError> {
+        let value = self.reader.u16_at(self.offset)?;
+        self.offset += 2;
+
+        Ok(value)
+    }
+
+    fn u32(&mut self) -> Result<u32, ElfError> {
+        let value = self.reader.u32_at(self.offset)?;
+        self.offset += 4;
+
+        Ok(value)
+    }
+
+    fn str(&mut self) -> Result<String, ElfError> {
+        let value = self.reader.str_at(self.offset)?;
+        self.offset += value.len() + 1;
+
+        Ok(value)
+    }
+
+    fn uleb(&mut self) -> Result<u64, ElfError> {
+        let mut value = 0;
+        let mut shift = 0;
+
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7F) as u64) << shift;
+            }
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn sleb(&mut self) -> Result<i64, ElfError> {
+        let mut value = 0;
+        let mut shift = 0;
+
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7F) as i64) << shift;
+            }
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+
+                return Ok(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ori $t0, $zero, 1
+    // beq $t0, $zero, exit
+    // ori $v0, $zero, 10
+    // exit:
+    // syscall
+    const TEXT: [u32; 4] = [0x34080001, 0x11000002, 0x3402000A, 0x0000000C];
+
+    fn binary() -> Binary {
+        let mut labels = LinkedHashMap::new();
+        labels.insert("main".to_string(), TEXT_BOT);
+        labels.insert("value".to_string(), DATA_BOT);
+
+        Binary {
+            text: TEXT.iter().flat_map(|inst| inst.to_le_bytes()).map(Safe::Valid).collect(),
+            data: vec![Safe::Valid(42), Safe::Uninitialised, Safe::Valid(7)],
+            ktext: vec![],
+            kdata: vec![],
+            labels,
+            scopes: LinkedHashMap::new(),
+            constants: HashMap::new(),
+            globals: vec!["main".to_string()],
+            line_numbers: HashMap::new(),
+            warnings: vec![],
+            expected_exit_code: None,
+            no_uninit_check: HashSet::new(),
+            scope: None,
+            in_ktext: false,
+        }
+    }
+
+    fn bytes(segment: &[Safe<u8>]) -> Vec<Option<u8>> {
+        segment.iter().map(|byte| byte.into_option()).collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let binary = binary();
+        let read = Binary::from_elf(&binary.to_elf()).unwrap();
+
+        // the instruction after the branch isn't a delay slot, as mipsy wrote it
+        assert_eq!(bytes(&read.text), bytes(&binary.text));
+        assert_eq!(bytes(&read.data), bytes(&binary.data));
+        assert_eq!(read.labels.get("main"), Some(&TEXT_BOT));
+        assert_eq!(read.labels.get("value"), Some(&DATA_BOT));
+        assert_eq!(read.globals, vec!["main".to_string()]);
+    }
+
+    #[test]
+    fn test_foreign_delay_slot() {
+        let mut elf = binary().to_elf();
+
+        // without the section, the ELF no longer looks like mipsy's own
+        let name = elf.windows(UNINIT_SECTION.len())
+            .position(|window| window == UNINIT_SECTION.as_bytes())
+            .unwrap();
+        elf[name + 1] = b'X';
+
+        assert_eq!(Binary::from_elf(&elf).err(), Some(ElfError::DelaySlot { addr: TEXT_BOT + 8 }));
+    }
+
+    #[test]
+    fn test_bad_segment_size() {
+        let elf = binary().to_elf();
+        let memsz = (EHDR_SIZE + 20) as usize;
+
+        let mut smaller = elf.clone();
+        smaller[memsz..memsz + 4].copy_from_slice(&15u32.to_le_bytes());
+        assert_eq!(Binary::from_elf(&smaller).err(), Some(ElfError::BadSize { addr: TEXT_BOT, memsz: 15 }));
+
+        let mut oversized = elf.clone();
+        oversized[memsz..memsz + 4].copy_from_slice(&0x0800_0000u32.to_le_bytes());
+        assert_eq!(Binary::from_elf(&oversized).err(), Some(ElfError::BadSize { addr: TEXT_BOT, memsz: 0x0800_0000 }));
+
+        let truncated = &elf[..EHDR_SIZE as usize + 8];
+        assert!(matches!(Binary::from_elf(truncated).err(), Some(ElfError::Truncated { .. })));
+    }
+}
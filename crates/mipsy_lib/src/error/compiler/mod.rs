@@ -1,12 +1,14 @@
-use std::{fmt::Display, path::MAIN_SEPARATOR, rc::Rc};
+use std::{fmt::Display, rc::Rc};
 use serde::{Serialize, Deserialize};
 
-use colored::Colorize;
-use mipsy_parser::{MpDirective, MpInstruction};
+use colored::{Color, Colorize};
+use mipsy_parser::{MpDirective, MpInstruction, MpMacroExpansion};
 use mipsy_utils::MipsyConfig;
-use crate::{inst::instruction::Signature, DATA_BOT, HEAP_BOT};
+use crate::{compile::KnownAttribute, inst::instruction::Signature, DATA_BOT, HEAP_BOT, KDATA_BOT, KTEXT_BOT};
 
-use super::util::{syntax_highlight_argument, tip_header};
+use super::warning::Warning;
+
+use super::util::{highlight_source_line, note_source_line, syntax_highlight_argument, tip_header};
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub struct CompilerError {
@@ -15,6 +17,8 @@ pub struct CompilerError {
     line:     u32,
     col:      u32,
     col_end:  u32,
+    /// The macro expansion the error is in, if any.
+    expansion: Option<MpMacroExpansion>,
 }
 
 impl CompilerError {
@@ -25,6 +29,17 @@ impl CompilerError {
             line,
             col,
             col_end,
+            expansion: None,
+        }
+    }
+
+    /// Points the error at the invocation of the macro whose expansion it's in.
+    pub(crate) fn in_expansion(self, expansion: MpMacroExpansion) -> Self {
+        Self {
+            col: expansion.col(),
+            col_end: expansion.col_end(),
+            expansion: Some(expansion),
+            ..self
         }
     }
 
@@ -48,9 +63,13 @@ impl CompilerError {
         self.col_end
     }
 
+    pub fn expansion(&self) -> Option<&MpMacroExpansion> {
+        self.expansion.as_ref()
+    }
+
     pub fn show_error(&self, config: &MipsyConfig, file: Rc<str>) {
         if self.error().should_highlight_line() {
-            self.highlight_line(config, file);
+            self.highlight_line(config, file.clone());
         }
 
         println!("{}", self.error.message());
@@ -58,73 +77,18 @@ impl CompilerError {
         for tip in self.error.tips() {
             print!("{} {}", tip_header(), tip);
         }
-    }
-
-    // TODO(zkol): Can't just pull tab_size from the config, since
-    // file may have #![tabsize(...)]
-    fn highlight_line(&self, config: &MipsyConfig, file: Rc<str>) {
-        let line = file.lines()
-            .nth((self.line - 1) as usize)
-            .expect("invalid line position in compiler error");
-
-        let updated_line = {
-            let mut updated_line = String::new();
-            
-            for char in line.chars() {
-                if char != '\t' {
-                    updated_line.push(char);
-                    continue;
-                }
-
-                let spaces_to_insert = config.tab_size - (updated_line.len() as u32 % config.tab_size);
-                updated_line.push_str(&" ".repeat(spaces_to_insert as usize));
-            }
-
-            updated_line
-        };
 
-        // format of the error:
-
-        //   --> ./foo.s:1:2
-        //    |
-        // 22 | mips code here
-        //    |      ^^^^ error: some useless diagnosis
-        //
-
-        let line_num_str = self.line.to_string();
-        let line_num_str_colored = line_num_str.bright_blue().bold();
-        let line_num_width = line_num_str.len();
-        let line_num_blank = " ".repeat(line_num_width);
-        let arrow = "-->".bright_blue().bold();
-        let file_name = {
-            if self.file_tag.is_empty() {
-                String::new()
-            } else {
-                let dot_slash = if !self.file_tag.contains(MAIN_SEPARATOR) {
-                    "./"
-                } else {
-                    ""
-                };
-
-                let line_col = format!(":{}:{}", self.line, self.col);
-
-                format!("{}{}{}", dot_slash.bold(), self.file_tag.bold(), line_col.bold())
-            }
-        };
-        let bar = "|".bright_blue().bold();
-        let line = updated_line;
-        let pre_highlight_space = " ".repeat((self.col - 1) as usize);
-        let highlight = "^".repeat((self.col_end - self.col) as usize).bright_red().bold();
-
-        // and this is where the magic happens...
-
-        if !file_name.is_empty() {
-            println!("{}{} {}", line_num_blank, arrow, file_name);
+        if let Some(expansion) = &self.expansion {
+            let note = format!("in this macro, `{}`, as it was expanded", expansion.name().bold());
+            print!("{}", note_source_line(config, &file, &self.file_tag, expansion.defined_at(), &note));
         }
+    }
 
-        println!("{} {}", line_num_blank, bar);
-        println!("{} {} {}", line_num_str_colored, bar, line);
-        print!  ("{} {} {}{} ", line_num_blank, bar, pre_highlight_space, highlight);
+    fn highlight_line(&self, config: &MipsyConfig, file: Rc<str>) {
+        print!(
+            "{}",
+            highlight_source_line(config, &file, &self.file_tag, self.line, self.col, self.col_end, Color::BrightRed)
+        );
     }
 }
 
@@ -140,16 +104,29 @@ pub enum Error {
 
     RedefinedLabel  { label: String },
     UnresolvedLabel { label: String, similar: Vec<String> },
+    LocalLabel      { label: String, defined_in: Vec<Rc<str>> },
+
+    RedefinedGlobal { label: String, first_file: Rc<str>, second_file: Rc<str> },
+    UndefinedGlobal { label: String, local_in: Vec<Rc<str>> },
 
     RedefinedConstant  { label: String },
     UnresolvedConstant { label: String },
 
     ConstantValueDoesNotFit { directive_type: DirectiveType, value: i64, range_low: i64, range_high: i64 },
+    DivisionByZero,
 
     DataInTextSegment { directive_type: MpDirective },
     InstructionInDataSegment,
 
     TooMuchData { data_size: u32 },
+    KTextAddress { addr: u32, next_addr: u32 },
+
+    GnuDirective { directive: MpDirective },
+    DelaySlot    { inst_name: String, branch_name: String },
+
+    MisplacedAttribute    { name: String, file_level: bool },
+    InvalidAttributeValue { name: String, value: Option<String> },
+    DeniedWarning         { warning: Warning },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
@@ -220,6 +197,31 @@ impl Error {
                 format!("{} `{}` {}", message_1, label, message_2)
             }
 
+            Error::LocalLabel { label, defined_in } => {
+                let message_1 = "the label".bright_red().bold();
+                let message_2 = "is local to".bright_red().bold();
+                let label = label.bold();
+
+                format!("{} `{}` {} {}", message_1, label, message_2, list_files(defined_in))
+            }
+
+            Error::RedefinedGlobal { label, first_file, second_file } => {
+                let message_1 = "the global label".bright_red().bold();
+                let message_2 = "is defined in both".bright_red().bold();
+                let message_3 = "and".bright_red().bold();
+                let label = label.bold();
+
+                format!("{} `{}` {} `{}` {} `{}`", message_1, label, message_2, first_file.bold(), message_3, second_file.bold())
+            }
+
+            Error::UndefinedGlobal { label, .. } => {
+                let message_1 = "the global label".bright_red().bold();
+                let message_2 = "is not defined in any file".bright_red().bold();
+                let label = label.bold();
+
+                format!("{} `{}` {}", message_1, label, message_2)
+            }
+
             Error::RedefinedConstant { label } => {
                 let message_1 = "the constant".bright_red().bold();
                 let message_2 = "is defined multiple times".bright_red().bold();
@@ -229,7 +231,7 @@ impl Error {
             }
             
             Error::UnresolvedConstant { label } => {
-                let message_1 = "cannot find constant".bright_red().bold();
+                let message_1 = "cannot find constant or label".bright_red().bold();
                 let message_2 = "in program".bright_red().bold();
                 let label = label.bold();
                 
@@ -246,6 +248,10 @@ impl Error {
                 format!("{} `{}` {} {} {} {}", message_1, value, message_2, low, message_3, high)
             }
 
+            Error::DivisionByZero => {
+                "cannot divide by zero".bright_red().bold().to_string()
+            }
+
             Error::DataInTextSegment { directive_type } => {
                 let message_1 = "cannot put".bright_red().bold();
                 let message_2 = directive_type.to_string().bold();
@@ -267,6 +273,49 @@ impl Error {
 
                 format!("{} `{}` {}", message_1, message_2, message_3)
             }
+
+            Error::KTextAddress { addr, .. } => {
+                let message_1 = "cannot put kernel text at".bright_red().bold();
+                let zero_x = "0x".yellow();
+
+                format!("{} {}{:08x}", message_1, zero_x, addr)
+            }
+
+            Error::GnuDirective { directive } => {
+                let message = "unsupported directive".bright_red().bold();
+
+                format!("{} `{}{}`", message, ".".bold(), directive.to_string().bold())
+            }
+
+            Error::DelaySlot { inst_name, branch_name } => {
+                let message_1 = "cannot run".bright_red().bold();
+                let message_2 = "in the delay slot of".bright_red().bold();
+
+                format!("{} `{}` {} `{}`", message_1, inst_name.bold(), message_2, branch_name.bold())
+            }
+
+            Error::MisplacedAttribute { name, file_level } => {
+                let message = if *file_level {
+                    "attribute can't be applied to a whole file:"
+                } else {
+                    "attribute can only be applied to a whole file:"
+                }.bright_red().bold();
+
+                format!("{} {}", message, name.bold())
+            }
+
+            Error::InvalidAttributeValue { name, value } => {
+                let message = "invalid value for attribute".bright_red().bold();
+
+                match value {
+                    Some(value) => format!("{} {}: `{}`", message, name.bold(), value.trim().bold()),
+                    None        => format!("{} {}: no value given", message, name.bold()),
+                }
+            }
+
+            Error::DeniedWarning { warning } => {
+                warning.message()
+            }
         }
     }
 
@@ -297,6 +346,7 @@ impl Error {
                     't' => 9,
                     's' => 7,
                     'k' => 1,
+                    'f' => 31,
                     _ => unreachable!(),
                 }.to_string().bold();
     
@@ -423,6 +473,34 @@ impl Error {
                 }
             }
 
+            Error::LocalLabel { label, defined_in } => {
+                let globl = format!(".globl {}", label).bold();
+
+                let tip = match defined_in.as_slice() {
+                    [file] => format!("to use it from other files, declare it with `{}` in `{}`\n", globl, file.bold()),
+                    _      => format!("to use one of them from other files, declare it with `{}` in that file\n", globl),
+                };
+
+                vec![tip]
+            }
+
+            Error::RedefinedGlobal { label, .. } => {
+                let globl = format!(".globl {}", label).bold();
+                let tip = format!("only one file can define a global label - remove `{}` from the other, to make its label local\n", globl);
+
+                vec![tip]
+            }
+
+            Error::UndefinedGlobal { label, local_in } => {
+                let globl = format!(".globl {}", label).bold();
+
+                if local_in.is_empty() {
+                    vec![]
+                } else {
+                    vec![format!("it is defined in {}, but only as a local label - declare it with `{}` there\n", list_files(local_in), globl)]
+                }
+            }
+
             Error::RedefinedConstant { .. } => {
                 // good luck kiddo
                 vec![]
@@ -440,6 +518,10 @@ impl Error {
                 vec![tip]
             }
 
+            Error::DivisionByZero => {
+                vec![]
+            }
+
             Error::DataInTextSegment { directive_type } => {
                 let data = ".data".bold();
                 let tip = format!("you may want to insert a `{}` directive before your `{}{}`\n", data, ".".bold(), directive_type.to_string().bold());
@@ -468,6 +550,79 @@ impl Error {
 
                 vec![tip1, tip2]
             }
+
+            Error::GnuDirective { directive } => {
+                let directive = format!(".{}", directive).bold();
+                let gnu = "--gnu".bold();
+
+                vec![format!("`{}` is a GNU assembler directive, which mipsy only accepts with `{}`\n", directive, gnu)]
+            }
+
+            Error::KTextAddress { addr, next_addr } => {
+                let tip = if *addr < KTEXT_BOT || *addr >= KDATA_BOT {
+                    format!(
+                        "kernel text goes from {}{:08x} up to {}{:08x}\n",
+                        "0x".yellow(), KTEXT_BOT,
+                        "0x".yellow(), KDATA_BOT,
+                    )
+                } else if addr % 4 != 0 {
+                    "the address of kernel text must be a multiple of 4\n".to_string()
+                } else {
+                    format!(
+                        "the kernel text before it already goes up to {}{:08x}\n",
+                        "0x".yellow(), next_addr,
+                    )
+                };
+
+                vec![tip]
+            }
+
+            Error::DelaySlot { .. } => {
+                let noreorder = ".set noreorder".bold();
+
+                vec![
+                    format!("mipsy has no delay slots, so after `{}` it runs the instruction after a branch just before the branch instead\n", noreorder),
+                    "that can't be done when the instruction has a label, or changes a register the branch uses\n".to_string(),
+                ]
+            }
+
+            Error::MisplacedAttribute { name, file_level } => {
+                let usage = if *file_level {
+                    format!("#[{}]", name)
+                } else {
+                    format!("#![{}]", name)
+                };
+
+                let place = if *file_level {
+                    "before the item it applies to"
+                } else {
+                    "at the top of the file, before any other items"
+                };
+
+                vec![
+                    format!("write `{}` {}\n", usage.bold(), place),
+                ]
+            }
+
+            Error::InvalidAttributeValue { name, .. } => {
+                match KnownAttribute::from_name(name) {
+                    Some(attribute) => vec![
+                        format!("`{}` takes {}\n", attribute.name().bold(), attribute.value().describe()),
+                    ],
+                    None => vec![],
+                }
+            }
+
+            Error::DeniedWarning { warning } => {
+                let mut tips = warning.tips();
+
+                tips.push(format!(
+                    "this warning is an error because of `{}`\n",
+                    format!("deny({})", warning.lint().name()).bold(),
+                ));
+
+                tips
+            }
         }
     }
 
@@ -475,6 +630,7 @@ impl Error {
         match self {
             // only highlight the error-ing line if the requested label is not `main`
             Self::UnresolvedLabel { label, .. } => label != "main",
+            Self::LocalLabel { label, .. } => label != "main",
 
             // In theory we could highlight the piece of data that pushed us over the
             // limit, but then it would make it harder to get the total bytes that
@@ -488,6 +644,18 @@ impl Error {
     }
 }
 
+/// Lists files as `a.s`, `a.s` and `b.s`, or `a.s`, `b.s` and `c.s`.
+fn list_files(files: &[Rc<str>]) -> String {
+    let files = files.iter()
+        .map(|file| format!("`{}`", file.bold()))
+        .collect::<Vec<_>>();
+
+    match files.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} {} {}", rest.join(", "), "and".bright_red().bold(), last),
+        _ => files.join(""),
+    }
+}
+
 impl Display for DirectiveType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", match self {
@@ -4,12 +4,14 @@ use serde::{Serialize, Deserialize};
 pub mod parser;
 pub mod compiler;
 pub mod runtime;
+pub mod warning;
 pub mod util;
 
 pub type MipsyResult<T> = Result<T, MipsyError>;
 pub type ParserError    = parser::ParserError;
 pub type CompilerError  = compiler::CompilerError;
 pub type RuntimeError   = runtime::RuntimeError;
+pub type CompilerWarning = warning::CompilerWarning;
 
 pub type MipsyInternalResult<T> = Result<T, InternalError>;
 
@@ -1,14 +1,19 @@
-use colored::Colorize;
+use colored::{Color, Colorize};
+use mipsy_parser::{IncludeError, MacroError, MpMacroExpansion};
 use mipsy_utils::MipsyConfig;
 use serde::{Deserialize, Serialize};
 use std::{path::MAIN_SEPARATOR, rc::Rc};
 
+use super::util::{highlight_source_line, note_header, note_source_line};
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct ParserError {
     error: Error,
     file_tag: Rc<str>,
     line: u32,
     col: u32,
+    /// The macro expansion the error is in, if any.
+    expansion: Option<MpMacroExpansion>,
 }
 
 impl ParserError {
@@ -18,6 +23,14 @@ impl ParserError {
             file_tag,
             line,
             col,
+            expansion: None,
+        }
+    }
+
+    pub fn with_expansion(self, expansion: Option<MpMacroExpansion>) -> Self {
+        Self {
+            expansion,
+            ..self
         }
     }
 
@@ -37,10 +50,12 @@ impl ParserError {
         self.col
     }
 
-    // TODO(zkol): Can't just pull tab_size from the config, since
-    // file may have #![tabsize(...)]
+    pub fn expansion(&self) -> Option<&MpMacroExpansion> {
+        self.expansion.as_ref()
+    }
+
     pub fn show_error(&self, config: &MipsyConfig, file: Rc<str>) {
-        let message = "failed to parse".bright_red().bold();
+        let message = self.error.message();
 
         let line = {
             let target_line = (self.line - 1) as usize;
@@ -62,6 +77,8 @@ impl ParserError {
             line.expect("invalid line position in compiler error")
         };
 
+        let tab_size = mipsy_parser::file_tab_size(&file, config.tab_size);
+
         let updated_line = {
             let mut updated_line = String::new();
 
@@ -71,7 +88,7 @@ impl ParserError {
                     continue;
                 }
 
-                let spaces_to_insert = config.tab_size - (updated_line.len() as u32 % config.tab_size);
+                let spaces_to_insert = tab_size - (updated_line.len() as u32 % tab_size);
                 updated_line.push_str(&" ".repeat(spaces_to_insert as usize));
             }
 
@@ -133,10 +150,115 @@ impl ParserError {
             "{} {} {}{} {}",
             line_num_blank, bar, pre_highlight_space, highlight, message
         );
+
+        self.show_note(config, &file);
+    }
+
+    fn show_note(&self, config: &MipsyConfig, file: &str) {
+        match &self.error {
+            Error::InvalidMacro { error: MacroError::Redefined { name, defined_at } } => {
+                let note = format!("`{}` was first defined here", name.bold());
+                eprint!("{}", note_source_line(config, file, &self.file_tag, *defined_at, &note));
+            }
+            Error::InvalidMacro { error: MacroError::WrongArguments { name, defined_at, .. } } => {
+                let note = format!("`{}` is defined here", name.bold());
+                eprint!("{}", note_source_line(config, file, &self.file_tag, *defined_at, &note));
+            }
+            _ => {
+                if let Some(expansion) = &self.expansion {
+                    eprint!(
+                        "{}",
+                        highlight_source_line(config, file, &self.file_tag, expansion.line(), expansion.col(), expansion.col_end(), Color::BrightBlue)
+                    );
+                    eprintln!("{} in this expansion of `{}`", note_header(), expansion.name().bold());
+                }
+            }
+        }
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum Error {
     ParseFailure,
+    InvalidMacro   { error: MacroError },
+    InvalidInclude { error: IncludeError },
+}
+
+impl Error {
+    pub fn message(&self) -> String {
+        match self {
+            Error::ParseFailure => {
+                "failed to parse".bright_red().bold().to_string()
+            }
+            Error::InvalidMacro { error } => match error {
+                MacroError::MissingName => {
+                    "expected a name for the macro".bright_red().bold().to_string()
+                }
+                MacroError::InvalidParameter { param } => {
+                    let message = "invalid macro parameter".bright_red().bold();
+
+                    format!("{} `{}`", message, param.bold())
+                }
+                MacroError::Unterminated { name } => {
+                    let message_1 = "the macro".bright_red().bold();
+                    let message_2 = "is missing its".bright_red().bold();
+
+                    format!("{} `{}` {} `{}`", message_1, name.bold(), message_2, ".end_macro".bold())
+                }
+                MacroError::UnmatchedEnd => {
+                    let message = "without a matching".bright_red().bold();
+
+                    format!("`{}` {} `{}`", ".end_macro".bold(), message, ".macro".bold())
+                }
+                MacroError::NestedDefinition { name } => {
+                    let message = "cannot define a macro inside the macro".bright_red().bold();
+
+                    format!("{} `{}`", message, name.bold())
+                }
+                MacroError::Redefined { name, .. } => {
+                    let message_1 = "the macro".bright_red().bold();
+                    let message_2 = "is defined multiple times with the same number of parameters".bright_red().bold();
+
+                    format!("{} `{}` {}", message_1, name.bold(), message_2)
+                }
+                MacroError::WrongArguments { name, given, expected, .. } => {
+                    let message_1 = "the macro".bright_red().bold();
+                    let message_2 = format!(
+                        "takes {} argument{}, but {} {} given",
+                        expected,
+                        if *expected == 1 { "" } else { "s" },
+                        given,
+                        if *given == 1 { "was" } else { "were" },
+                    ).bright_red().bold();
+
+                    format!("{} `{}` {}", message_1, name.bold(), message_2)
+                }
+                MacroError::Recursive { name } => {
+                    let message_1 = "the macro".bright_red().bold();
+                    let message_2 = "expands into itself".bright_red().bold();
+
+                    format!("{} `{}` {}", message_1, name.bold(), message_2)
+                }
+            }
+            Error::InvalidInclude { error } => match error {
+                IncludeError::CannotRead { path, reason } => {
+                    let message = "cannot read included file".bright_red().bold();
+
+                    format!("{} `{}`: {}", message, path.bold(), reason)
+                }
+                IncludeError::Cycle { path } => {
+                    let message_1 = "cannot include".bright_red().bold();
+                    let message_2 = "as it includes this file".bright_red().bold();
+
+                    format!("{} `{}`, {}", message_1, path.bold(), message_2)
+                }
+                IncludeError::NotLoaded { path } => {
+                    let message_1 = "the included file".bright_red().bold();
+                    let message_2 = "was not loaded".bright_red().bold();
+
+                    format!("{} `{}` {}", message_1, path.bold(), message_2)
+                }
+            }
+        }
+    }
 }
@@ -1,8 +1,9 @@
 use std::rc::Rc;
 
 use super::util::{inst_parts_to_string, inst_to_string, tip_header};
-use crate::{Binary, InstSet, Register, Runtime, Safe, State, decompile::{self, Decompiled, decompile_inst_into_parts}, inst::ReadsRegisterType, runtime::state::{WRITE_MARKER_HI, WRITE_MARKER_LO}, KDATA_BOT, KTEXT_BOT, DATA_BOT, TEXT_BOT, HEAP_BOT, STACK_BOT, STACK_TOP};
+use crate::{Binary, InstSet, Register, Runtime, Safe, State, decompile::{self, Decompiled, decompile_inst_into_parts}, inst::{ReadsRegisterType, register::COP0_REGISTERS}, runtime::state::{WRITE_MARKER_HI, WRITE_MARKER_LO}, KDATA_BOT, KTEXT_BOT, DATA_BOT, TEXT_BOT, HEAP_BOT, STACK_BOT, STACK_PTR, STACK_TOP};
 use colored::Colorize;
+use mipsy_parser::label_display_name;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
@@ -31,15 +32,95 @@ impl RuntimeError {
             "{}{} {}",
             "error".bright_red().bold(),
             ":".bold(),
-            self.error.message(context, source_code, inst_set, binary, runtime)
+            self.error.message(context, source_code.clone(), inst_set, binary, runtime)
         );
 
         for tip in self.error.tips(inst_set, binary, runtime) {
             println!("{} {}", tip_header(), tip);
         }
+
+        // only worth showing if the error wasn't in main
+        let state = runtime.timeline().state();
+        if state.call_stack().depth() > 1 {
+            if let ErrorContext::Binary | ErrorContext::Interactive = context {
+                println!("\nbacktrace:");
+
+                for line in backtrace(binary, state, &source_code) {
+                    println!("{}", line);
+                }
+            }
+        }
     }
 }
 
+/// # The call stack of a program, one line per function.
+///
+/// The current function is first. Each line shows where execution is in the
+/// function, the label the function was called at, and `$sp` when it was called.
+pub fn backtrace(binary: &Binary, state: &State, source_code: &[(Rc<str>, Rc<str>)]) -> Vec<String> {
+    let frames = state.call_stack().frames().collect::<Vec<_>>();
+
+    let name_width = frames.iter()
+        .map(|frame| function_name(binary, frame.entry).len())
+        .max()
+        .unwrap_or(0);
+
+    let mut addr = state.pc();
+
+    frames.iter()
+        .enumerate()
+        .map(|(i, frame)| {
+            let name = function_name(binary, frame.entry);
+
+            let sp = match frame.sp {
+                Safe::Valid(sp) => format!("{}{:08x}", "0x".yellow(), sp),
+                Safe::Uninitialised => "uninitialised".red().to_string(),
+            };
+
+            let mut line = format!(
+                " #{:<2} {}{:08x} in {}{}  {} = {}",
+                i,
+                "0x".yellow(),
+                addr,
+                name.yellow().bold(),
+                " ".repeat(name_width - name.len()),
+                "$sp".yellow(),
+                sp,
+            );
+
+            if let Some((file_tag, line_num)) = binary.line_numbers.get(&addr) {
+                let source_line = source_code.iter()
+                    .find(|(tag, _)| tag == file_tag)
+                    .and_then(|(_, file)| file.lines().nth(*line_num as usize - 1));
+
+                let location = if source_code.len() > 1 {
+                    format!("{}:{}", file_tag, line_num)
+                } else {
+                    format!("line {}", line_num)
+                };
+
+                line.push_str(&format!("  [{}]", location.bold()));
+
+                if let Some(source_line) = source_line {
+                    line.push_str(&format!("  {} {}", "#".bright_black(), source_line.trim().bright_black()));
+                }
+            }
+
+            addr = frame.call_site;
+
+            line
+        })
+        .collect()
+}
+
+/// The label a function was called at, or its address if it has no label.
+pub(crate) fn function_name(binary: &Binary, entry: u32) -> String {
+    binary.labels.iter()
+        .find(|(_, &addr)| addr == entry)
+        .map(|(label, _)| label_display_name(label).to_string())
+        .unwrap_or_else(|| format!("0x{:08x}", entry))
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum ErrorContext {
     Binary,
@@ -56,7 +137,26 @@ pub enum Error {
     IntegerOverflow,
     DivisionByZero,
 
-    SegmentationFault { addr: u32 }
+    SegmentationFault { addr: u32 },
+
+    OddFloatRegister { reg_num: u32 },
+    UnknownCop0Register { reg_num: u32 },
+
+    CalleeSavedClobbered { entry: u32, reg_num: u32, on_entry: i32, on_return: Option<i32> },
+
+    LimitExceeded { limit: Limit },
+}
+
+/// # A resource limit, and the value it was set to.
+///
+/// See [`mipsy_utils::Limits`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Limit {
+    Steps(u64),
+    Output(u64),
+    Heap(u32),
+    Stack(u32),
+    TimeMs(u64),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
@@ -65,6 +165,7 @@ pub enum Uninitialised {
     Half { addr: u32 },
     Word { addr: u32 },
     Register { reg_num: u32 },
+    FloatRegister { reg_num: u32 },
     Lo,
     Hi,
 }
@@ -73,6 +174,7 @@ pub enum Uninitialised {
 pub enum AlignmentRequirement {
     Half,
     Word,
+    Double,
 }
 
 impl Error {
@@ -110,21 +212,28 @@ impl Error {
                     }
 
                     Uninitialised::Register { reg_num } => {
-                        let name = Register::from_u32(*reg_num).unwrap().to_lower_str();
+                        let name = Register::from_u32(*reg_num).unwrap().to_lower_str().to_string();
                         let last_mod = get_last_mod(runtime, *reg_num);
 
                         (name, last_mod)
                     }
 
+                    Uninitialised::FloatRegister { reg_num } => {
+                        let name = format!("f{}", reg_num);
+                        let last_mod = get_last_fp_mod(runtime, *reg_num);
+
+                        (name, last_mod)
+                    }
+
                     Uninitialised::Lo => {
-                        let name = "lo";
+                        let name = "lo".to_string();
                         let last_mod = get_last_mod(runtime, WRITE_MARKER_LO);
 
                         (name, last_mod)
                     }
 
                     Uninitialised::Hi => {
-                        let name = "hi";
+                        let name = "hi".to_string();
                         let last_mod = get_last_mod(runtime, WRITE_MARKER_HI);
 
                         (name, last_mod)
@@ -243,6 +352,7 @@ impl Error {
 
                                 let rs =    (last_inst >> 21) & 0x1F;
                                 let rt =    (last_inst >> 16) & 0x1F;
+                                let rd =    (last_inst >> 11) & 0x1F;
 
                                 for read in runtime_meta.reads() {
                                     let mut index = 0;
@@ -250,12 +360,15 @@ impl Error {
                                     for argument in inst_sig.format() {
                                         if read.eq_argument_type(argument) {
                                             let value = match read {
-                                                ReadsRegisterType::Rs | ReadsRegisterType::OffRs => state.read_register_uninit(rs),
-                                                ReadsRegisterType::Rt | ReadsRegisterType::OffRt => state.read_register_uninit(rt),
+                                                ReadsRegisterType::Rs | ReadsRegisterType::OffRs => state.read_register_uninit(rs).into_option().map(|value| value as u32),
+                                                ReadsRegisterType::Rt | ReadsRegisterType::OffRt => state.read_register_uninit(rt).into_option().map(|value| value as u32),
+                                                ReadsRegisterType::Fs => state.read_fp_register_uninit(rd).into_option(),
+                                                ReadsRegisterType::Ft => state.read_fp_register_uninit(rt).into_option(),
                                             };
 
                                             let name = match read {
-                                                ReadsRegisterType::Rs | ReadsRegisterType::Rt => {
+                                                ReadsRegisterType::Rs | ReadsRegisterType::Rt |
+                                                ReadsRegisterType::Fs | ReadsRegisterType::Ft => {
                                                     format!("{}{}", "$".yellow(), last_inst_parts.arguments[index][1..].bold())
                                                 },
                                                 ReadsRegisterType::OffRs | ReadsRegisterType::OffRt => {
@@ -268,8 +381,8 @@ impl Error {
                                                 "|".red(),
                                                 name,
                                                 match value {
-                                                    Safe::Valid(value)  => format!("0x{:08x}", value),
-                                                    Safe::Uninitialised => String::from("uninitialised"),
+                                                    Some(value) => format!("0x{:08x}", value),
+                                                    None        => String::from("uninitialised"),
                                                 },
                                             ));
 
@@ -354,6 +467,21 @@ impl Error {
                             distance.to_string().bold()
                         ));
                     }
+                } else if runtime.timeline().discarded_states() != 0 {
+                    let retained = runtime.timeline().timeline_len() - runtime.timeline().history_start();
+
+                    error.push_str(&format!(
+                        "{} note: {}{} was not initialised in the last {} instructions\n",
+                        "|".red(),
+                        "$".yellow(),
+                        name.bold(),
+                        retained.to_string().bold(),
+                    ));
+                    error.push_str(&format!(
+                        "{} (older history was discarded - see the `{}` config option)\n",
+                        "|".red(),
+                        "history".bold(),
+                    ));
                 } else {
                     (error.push_str(&format!(
                         "{} note: {}{} was {} initialised\n",
@@ -451,8 +579,9 @@ impl Error {
                 error.push_str("\n");
 
                 let alignment_bytes = match alignment_requirement {
-                    AlignmentRequirement::Half => 2,
-                    AlignmentRequirement::Word => 4,
+                    AlignmentRequirement::Half   => 2,
+                    AlignmentRequirement::Word   => 4,
+                    AlignmentRequirement::Double => 8,
                 };
 
                 let argument = {
@@ -684,6 +813,212 @@ impl Error {
                 error
 
             }
+
+            Error::OddFloatRegister { reg_num } => {
+                let mut error = String::new();
+
+                error.push_str("invalid floating point register\n");
+
+                let state = runtime.timeline().state();
+                let inst = state.read_mem_word(state.pc()).unwrap();
+                let decompiled = decompile::decompile_inst_into_parts(binary, inst_set, inst, state.pc());
+
+                if let ErrorContext::Binary | ErrorContext::Interactive = context {
+                    error.push_str("\nthe instruction that failed was:\n");
+                    error.push_str(&inst_parts_to_string(
+                        &decompiled,
+                        &source_code,
+                        binary,
+                        false,
+                        false,
+                    ));
+                    error.push('\n');
+                }
+
+                error.push('\n');
+                error.push_str(&format!(
+                    "this happened because `{}` uses {}{} to hold a double,\nbut doubles are stored across an even-numbered register pair\n",
+                    decompiled.inst_name.as_deref().unwrap_or("this instruction").bold(),
+                    "$".yellow(),
+                    format!("f{}", reg_num).bold(),
+                ));
+
+                error
+            }
+
+            Error::UnknownCop0Register { reg_num } => {
+                let mut error = String::new();
+
+                error.push_str(&format!(
+                    "unknown coprocessor 0 register {}{}\n",
+                    "$".yellow(),
+                    reg_num.to_string().bold(),
+                ));
+
+                let state = runtime.timeline().state();
+                let inst = state.read_mem_word(state.pc()).unwrap();
+                let decompiled = decompile::decompile_inst_into_parts(binary, inst_set, inst, state.pc());
+
+                if let ErrorContext::Binary | ErrorContext::Interactive = context {
+                    error.push_str("\nthe instruction that failed was:\n");
+                    error.push_str(&inst_parts_to_string(
+                        &decompiled,
+                        &source_code,
+                        binary,
+                        false,
+                        false,
+                    ));
+                    error.push('\n');
+                }
+
+                error.push_str("\nthe coprocessor 0 registers are:\n");
+                for (num, name) in COP0_REGISTERS {
+                    error.push_str(&format!(" - {}{} ({})\n", "$".yellow(), num.to_string().bold(), name));
+                }
+
+                error
+            }
+
+            Error::CalleeSavedClobbered { entry, reg_num, on_entry, on_return } => {
+                let function = function_name(binary, *entry);
+                let name = Register::from_u32(*reg_num).unwrap().to_lower_str();
+
+                let mut error = String::new();
+
+                error.push_str(&format!(
+                    "`{}` returned without restoring {}{}\n",
+                    function.bold(),
+                    "$".yellow(),
+                    name.bold(),
+                ));
+
+                let state = runtime.timeline().state();
+                let inst = state.read_mem_word(state.pc()).unwrap();
+                let decompiled = decompile::decompile_inst_into_parts(binary, inst_set, inst, state.pc());
+
+                if let ErrorContext::Binary | ErrorContext::Interactive = context {
+                    error.push_str("\nthe instruction that failed was:\n");
+                    error.push_str(&inst_parts_to_string(
+                        &decompiled,
+                        &source_code,
+                        binary,
+                        false,
+                        false,
+                    ));
+                    error.push('\n');
+                }
+
+                let on_return = match on_return {
+                    Some(value) => format!("0x{:08x}", value),
+                    None        => String::from("uninitialised"),
+                };
+
+                error.push_str(&format!(
+                    "\nthis happened because {0}{1} was {2} when `{3}` was called,\n but is {4} now that it's returning\n",
+                    "$".yellow(),
+                    name.bold(),
+                    format!("0x{:08x}", on_entry).bold(),
+                    function.bold(),
+                    on_return.bold(),
+                ));
+
+                if let Some((last_index, last_mod)) = get_last_mod(runtime, *reg_num) {
+                    error.push_str(&format!(
+                        "{}\n{} the instruction that last changed {}{} was:\n",
+                        ">".red(),
+                        "|".red(),
+                        "$".yellow(),
+                        name.bold(),
+                    ));
+
+                    let last_inst = last_mod.read_mem_word(last_mod.pc() - 4).unwrap();
+                    let last_inst_parts = decompile_inst_into_parts(binary, inst_set, last_inst, last_mod.pc() - 4);
+
+                    error.push_str(&format!(
+                        "{} {}\n",
+                        "|".red(),
+                        inst_parts_to_string(&last_inst_parts, &source_code, binary, false, false),
+                    ));
+
+                    if let ErrorContext::Interactive = context {
+                        let distance = runtime.timeline().timeline_len() - last_index;
+                        error.push_str(&format!(
+                            "{}\n{} to get back there, use `{} {}`\n",
+                            ">".red(),
+                            "|".red(),
+                            "back".bold(),
+                            distance.to_string().bold()
+                        ));
+                    }
+                }
+
+                error.push('\n');
+
+                error
+            }
+
+            Error::LimitExceeded { limit } => {
+                let mut error = String::new();
+
+                let (message, location) = match limit {
+                    Limit::Steps(max)  => (format!("the program ran for more than {} instructions", max.to_string().bold()), "the program was stopped at"),
+                    Limit::Output(max) => (format!("the program printed more than {} bytes", max.to_string().bold()), "the instruction that failed was"),
+                    Limit::Heap(max)   => (format!("the heap can't grow larger than {} bytes", max.to_string().bold()), "the instruction that failed was"),
+                    Limit::Stack(max)  => (format!("the stack can't grow larger than {} bytes", max.to_string().bold()), "the instruction that failed was"),
+                    Limit::TimeMs(max) => {
+                        let seconds = (*max as f64 / 1000.0).to_string();
+                        let unit = if *max == 1000 { "second" } else { "seconds" };
+
+                        (format!("the program ran for longer than {} {}", seconds.bold(), unit), "the program was stopped at")
+                    }
+                };
+
+                error.push_str(&message);
+                error.push('\n');
+
+                let state = runtime.timeline().state();
+
+                if let ErrorContext::Binary | ErrorContext::Interactive = context {
+                    if let Ok(inst) = state.read_mem_word(state.pc()) {
+                        let decompiled = decompile::decompile_inst_into_parts(binary, inst_set, inst, state.pc());
+
+                        error.push_str(&format!("\n{}:\n", location));
+                        error.push_str(&inst_parts_to_string(
+                            &decompiled,
+                            &source_code,
+                            binary,
+                            false,
+                            false,
+                        ));
+                        error.push('\n');
+                    }
+                }
+
+                match limit {
+                    Limit::Heap(_) => {
+                        if let Ok(bytes) = state.read_register(Register::A0.to_u32()) {
+                            error.push_str(&format!(
+                                "\nthis happened because the heap was {} bytes, and `{}` asked for {} more\n",
+                                state.heap_size().to_string().bold(),
+                                "sbrk".bold(),
+                                bytes.to_string().bold(),
+                            ));
+                        }
+                    }
+                    Limit::Stack(max) => {
+                        error.push_str(&format!(
+                            "\nthis happened because it would have moved {}{} below `{}{}`\n",
+                            "$".yellow(),
+                            "sp".bold(),
+                            "0x".bold(),
+                            format!("{:08x}", STACK_PTR.saturating_sub(*max)).bold(),
+                        ));
+                    }
+                    _ => {}
+                }
+
+                error
+            }
         }
     }
 
@@ -719,8 +1054,9 @@ impl Error {
                     format!(
                         "you may have forgotten to multiply an index by {}{}",
                         match alignment_requirement {
-                            AlignmentRequirement::Half => 2,
-                            AlignmentRequirement::Word => 4,
+                            AlignmentRequirement::Half   => 2,
+                            AlignmentRequirement::Word   => 4,
+                            AlignmentRequirement::Double => 8,
                         },
                         match equiv_instruction {
                             Some(equiv_instruction) => format!(" (or use a `{}` instruction instead)", equiv_instruction.bold()),
@@ -797,6 +1133,65 @@ impl Error {
             Error::DivisionByZero => {
                 vec![]
             }
+            Error::OddFloatRegister { reg_num } => {
+                vec![format!(
+                    "try using {0}{1} instead - a double in {0}{1} also occupies {0}{2}",
+                    "$".yellow(),
+                    format!("f{}", reg_num - 1).bold(),
+                    format!("f{}", reg_num).bold(),
+                )]
+            }
+            Error::UnknownCop0Register { .. } => {
+                vec![]
+            }
+            Error::CalleeSavedClobbered { reg_num, on_entry, on_return, .. } => {
+                let register = Register::from_u32(*reg_num).unwrap();
+
+                match (register, on_return) {
+                    (Register::Sp, Some(on_return)) => {
+                        let difference = on_return.wrapping_sub(*on_entry);
+
+                        vec![format!(
+                            "{}{} is {} bytes {} than it should be - make sure everything pushed onto the stack is popped before returning",
+                            "$".yellow(),
+                            "sp".bold(),
+                            difference.unsigned_abs().to_string().bold(),
+                            if difference < 0 { "lower" } else { "higher" },
+                        )]
+                    }
+                    (Register::Sp, None) | (Register::Fp, _) | (Register::Gp, _) => {
+                        vec![format!(
+                            "{0}{1} must have the same value when a function returns as when it was called",
+                            "$".yellow(),
+                            register.to_lower_str().bold(),
+                        )]
+                    }
+                    _ => {
+                        vec![format!(
+                            "if a function changes an {0}{1} register, it must save it to the stack at the start\n     of the function, and restore it before returning - or use a {0}{2} register instead",
+                            "$".yellow(),
+                            "s".bold(),
+                            "t".bold(),
+                        )]
+                    }
+                }
+            }
+            Error::LimitExceeded { limit } => {
+                match limit {
+                    Limit::Steps(_) | Limit::TimeMs(_) => {
+                        vec![String::from("this is usually caused by an infinite loop - check the condition of each loop's branch")]
+                    }
+                    Limit::Output(_) => {
+                        vec![String::from("this is usually caused by an infinite loop that prints something each time around")]
+                    }
+                    Limit::Heap(_) => {
+                        vec![]
+                    }
+                    Limit::Stack(_) => {
+                        vec![String::from("this is usually caused by infinite recursion - make sure every recursive function has a base case it reaches")]
+                    }
+                }
+            }
             Error::SegmentationFault { addr } => {
                 let addr = *addr;
 
@@ -832,16 +1227,13 @@ impl Error {
 }
 
 fn get_last_mod(runtime: &Runtime, write_marker: u32) -> Option<(usize, &State)> {
-    println!();
-    for i in (0..runtime.timeline().timeline_len()).rev() {
-        let old_state = runtime.timeline().nth_state(i).unwrap();
-
-        if old_state.write_marker() & (1u64 << write_marker) != 0 {
-            return Some((i, old_state));
-        }
-    }
+    runtime.timeline().last_write(write_marker)
+        .map(|i| (i, runtime.timeline().nth_state(i).unwrap()))
+}
 
-    None
+fn get_last_fp_mod(runtime: &Runtime, reg_num: u32) -> Option<(usize, &State)> {
+    runtime.timeline().last_fp_write(reg_num)
+        .map(|i| (i, runtime.timeline().nth_state(i).unwrap()))
 }
 
 fn get_real_instruction_start<'inst_set>(state: &State, binary: &Binary, inst_set: &'inst_set InstSet, pseudo_address: u32) -> Option<Decompiled<'inst_set>> {
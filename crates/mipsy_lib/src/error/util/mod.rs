@@ -1,6 +1,7 @@
-use std::rc::Rc;
+use std::{path::MAIN_SEPARATOR, rc::Rc};
 
-use colored::Colorize;
+use colored::{Color, Colorize};
+use mipsy_utils::MipsyConfig;
 use crate::{ArgumentType, Binary, InstSet, KTEXT_BOT, decompile::{Decompiled, decompile_inst_into_parts}};
 
 // arg.to_string() will simply use the existing Display impl
@@ -9,7 +10,9 @@ use crate::{ArgumentType, Binary, InstSet, KTEXT_BOT, decompile::{Decompiled, de
 pub fn syntax_highlight_argument(arg: &ArgumentType) -> String {
     match arg {
         // register
-        ArgumentType::Rd | ArgumentType::Rs | ArgumentType::Rt => {
+        ArgumentType::Rd | ArgumentType::Rs | ArgumentType::Rt |
+        ArgumentType::Fd | ArgumentType::Fs | ArgumentType::Ft |
+        ArgumentType::Cd => {
             let register_dollar = "$".yellow();
             let argument = arg.to_string()[1..].bold();
 
@@ -48,6 +51,103 @@ pub fn tip_header() -> String {
     format!("{}{}", header, colon)
 }
 
+pub fn note_header() -> String {
+    let header = "note".bright_blue().bold();
+    let colon = ":".bold();
+
+    format!("{}{}", header, colon)
+}
+
+/// # A note about a whole line of source, such as where a macro is defined.
+pub fn note_source_line(config: &MipsyConfig, file: &str, file_tag: &str, line: u32, note: &str) -> String {
+    let source_line = file.lines()
+        .nth((line - 1) as usize)
+        .expect("invalid line position in compiler diagnostic");
+
+    let tab_size = mipsy_parser::file_tab_size(file, config.tab_size);
+    let source_line = mipsy_parser::tabs_to_spaces(source_line, tab_size);
+
+    let col = (source_line.len() - source_line.trim_start().len()) as u32 + 1;
+    let col_end = source_line.trim_end().len() as u32 + 1;
+
+    format!(
+        "{}{} {}\n",
+        highlight_source_line(config, file, file_tag, line, col, col_end, Color::BrightBlue),
+        note_header(),
+        note,
+    )
+}
+
+pub fn highlight_source_line(config: &MipsyConfig, file: &str, file_tag: &str, line: u32, col: u32, col_end: u32, colour: Color) -> String {
+    let source_line = file.lines()
+        .nth((line - 1) as usize)
+        .expect("invalid line position in compiler diagnostic");
+
+    let tab_size = mipsy_parser::file_tab_size(file, config.tab_size);
+
+    let updated_line = {
+        let mut updated_line = String::new();
+
+        for char in source_line.chars() {
+            if char != '\t' {
+                updated_line.push(char);
+                continue;
+            }
+
+            let spaces_to_insert = tab_size - (updated_line.len() as u32 % tab_size);
+            updated_line.push_str(&" ".repeat(spaces_to_insert as usize));
+        }
+
+        updated_line
+    };
+
+    // format of the diagnostic:
+
+    //   --> ./foo.s:1:2
+    //    |
+    // 22 | mips code here
+    //    |      ^^^^ error: some useless diagnosis
+    //
+
+    let line_num_str = line.to_string();
+    let line_num_str_colored = line_num_str.bright_blue().bold();
+    let line_num_width = line_num_str.len();
+    let line_num_blank = " ".repeat(line_num_width);
+    let arrow = "-->".bright_blue().bold();
+    let file_name = {
+        if file_tag.is_empty() {
+            String::new()
+        } else {
+            let dot_slash = if !file_tag.contains(MAIN_SEPARATOR) {
+                "./"
+            } else {
+                ""
+            };
+
+            let line_col = format!(":{}:{}", line, col);
+
+            format!("{}{}{}", dot_slash.bold(), file_tag.bold(), line_col.bold())
+        }
+    };
+    let bar = "|".bright_blue().bold();
+    let pre_highlight_space = " ".repeat((col - 1) as usize);
+    let highlight = "^".repeat((col_end - col) as usize).color(colour).bold();
+
+    // and this is where the magic happens...
+
+    let mut output = String::new();
+
+    if !file_name.is_empty() {
+        output.push_str(&format!("{}{} {}\n", line_num_blank, arrow, file_name));
+    }
+
+    output.push_str(&format!("{} {}\n", line_num_blank, bar));
+    output.push_str(&format!("{} {} {}\n", line_num_str_colored, bar, updated_line));
+    output.push_str(&format!("{} {} {}{} ", line_num_blank, bar, pre_highlight_space, highlight));
+
+    output
+}
+
 pub fn inst_to_string(inst: u32, addr: u32, source_code: &[(Rc<str>, Rc<str>)], binary: &Binary, iset: &InstSet, highlight_curr_inst: bool, show_labels: bool) -> String {
     let parts = decompile_inst_into_parts(binary, iset, inst, addr);
     inst_parts_to_string(&parts, source_code, binary, highlight_curr_inst, show_labels)
@@ -0,0 +1,353 @@
+use std::rc::Rc;
+use serde::{Serialize, Deserialize};
+
+use colored::{Color, Colorize};
+use mipsy_parser::{Attribute, MpMacroExpansion};
+use mipsy_utils::MipsyConfig;
+
+use crate::{CompilerError, compile::{KnownAttribute, lint_names}};
+use super::{compiler, util::{highlight_source_line, note_source_line, tip_header}};
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct CompilerWarning {
+    warning:  Warning,
+    file_tag: Rc<str>,
+    line:     u32,
+    col:      u32,
+    col_end:  u32,
+    /// The macro expansion the warning is in, if any.
+    expansion: Option<MpMacroExpansion>,
+}
+
+impl CompilerWarning {
+    pub fn new(warning: Warning, file_tag: Rc<str>, line: u32, col: u32, col_end: u32) -> Self {
+        Self {
+            warning,
+            file_tag,
+            line,
+            col,
+            col_end,
+            expansion: None,
+        }
+    }
+
+    /// Points the warning at the invocation of the macro whose expansion it's in.
+    pub(crate) fn in_expansion(self, expansion: MpMacroExpansion) -> Self {
+        Self {
+            col: expansion.col(),
+            col_end: expansion.col_end(),
+            expansion: Some(expansion),
+            ..self
+        }
+    }
+
+    pub fn warning(&self) -> &Warning {
+        &self.warning
+    }
+
+    pub fn file_tag(&self) -> Rc<str> {
+        self.file_tag.clone()
+    }
+
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    pub fn col(&self) -> u32 {
+        self.col
+    }
+
+    pub fn col_end(&self) -> u32 {
+        self.col_end
+    }
+
+    pub fn expansion(&self) -> Option<&MpMacroExpansion> {
+        self.expansion.as_ref()
+    }
+
+    /// Turns this warning into an error, for lints set to `deny`.
+    pub fn into_error(self) -> CompilerError {
+        let error = CompilerError::new(
+            compiler::Error::DeniedWarning { warning: self.warning },
+            self.file_tag,
+            self.line,
+            self.col,
+            self.col_end,
+        );
+
+        match self.expansion {
+            Some(expansion) => error.in_expansion(expansion),
+            None => error,
+        }
+    }
+
+    pub fn show_warning(&self, config: &MipsyConfig, file: Rc<str>) {
+        eprint!(
+            "{}",
+            highlight_source_line(config, &file, &self.file_tag, self.line, self.col, self.col_end, Color::BrightYellow)
+        );
+
+        eprintln!("{}", self.warning.message());
+
+        for tip in self.warning.tips() {
+            eprint!("{} {}", tip_header(), tip);
+        }
+
+        eprintln!("{} this warning can be disabled with `{}`", tip_header(), format!("#[allow({})]", self.warning.lint().name()).bold());
+
+        if let Some(expansion) = &self.expansion {
+            let note = format!("in this macro, `{}`, as it was expanded", expansion.name().bold());
+            eprint!("{}", note_source_line(config, &file, &self.file_tag, expansion.defined_at(), &note));
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub enum Warning {
+    UnusedLabel            { label: String },
+    SingleNativePseudo     { inst_name: String, native_name: String },
+    IndentedLabel          { label: String },
+    UnindentedInstruction  { inst_name: String },
+    UnindentedDirective,
+    NoBlankLineBeforeLabel { label: String },
+    UnknownAttribute       { name: String },
+    UnknownLint            { name: String },
+}
+
+impl Warning {
+    pub fn lint(&self) -> Lint {
+        match self {
+            Warning::UnusedLabel { .. } => Lint::UnusedLabel,
+            Warning::SingleNativePseudo { .. } => Lint::SingleNativePseudo,
+            Warning::IndentedLabel { .. }
+            | Warning::UnindentedInstruction { .. }
+            | Warning::UnindentedDirective => Lint::Indentation,
+            Warning::NoBlankLineBeforeLabel { .. } => Lint::BlankLineBeforeLabel,
+            Warning::UnknownAttribute { .. } => Lint::UnknownAttribute,
+            Warning::UnknownLint { .. } => Lint::UnknownLint,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Warning::UnusedLabel { label } => {
+                let message = "label is never used:".bright_yellow().bold();
+                let label = label.bold();
+
+                format!("{} {}", message, label)
+            }
+
+            Warning::SingleNativePseudo { inst_name, native_name } => {
+                let message = "pseudo-instruction compiles to a single native instruction:".bright_yellow().bold();
+                let inst_name = inst_name.bold();
+                let native_name = native_name.bold();
+
+                format!("{} {} is {}", message, inst_name, native_name)
+            }
+
+            Warning::IndentedLabel { label } => {
+                let message = "label is indented:".bright_yellow().bold();
+                let label = label.bold();
+
+                format!("{} {}", message, label)
+            }
+
+            Warning::UnindentedInstruction { inst_name } => {
+                let message = "instruction is not indented:".bright_yellow().bold();
+                let inst_name = inst_name.bold();
+
+                format!("{} {}", message, inst_name)
+            }
+
+            Warning::UnindentedDirective => {
+                "directive is not indented".bright_yellow().bold().to_string()
+            }
+
+            Warning::NoBlankLineBeforeLabel { label } => {
+                let message = "missing blank line before label:".bright_yellow().bold();
+                let label = label.bold();
+
+                format!("{} {}", message, label)
+            }
+
+            Warning::UnknownAttribute { name } => {
+                let message = "unknown attribute:".bright_yellow().bold();
+                let name = name.bold();
+
+                format!("{} {}", message, name)
+            }
+
+            Warning::UnknownLint { name } => {
+                let message = "unknown warning name:".bright_yellow().bold();
+                let name = name.bold();
+
+                format!("{} {}", message, name)
+            }
+        }
+    }
+
+    pub fn tips(&self) -> Vec<String> {
+        match self {
+            // numeric local labels can't start with an underscore
+            Warning::UnusedLabel { label } if label.chars().all(|char| char.is_ascii_digit()) => {
+                vec![]
+            }
+
+            Warning::UnusedLabel { label } => {
+                vec![
+                    format!("if this is intentional, prefix the label with an underscore: `{}`\n", format!("_{}", label).bold()),
+                ]
+            }
+
+            Warning::SingleNativePseudo { native_name, .. } => {
+                vec![
+                    format!("consider using the native instruction `{}` directly\n", native_name.bold()),
+                ]
+            }
+
+            Warning::IndentedLabel { .. } => {
+                vec![
+                    String::from("labels should start at the beginning of the line\n"),
+                ]
+            }
+
+            Warning::UnindentedInstruction { .. }
+            | Warning::UnindentedDirective => {
+                vec![
+                    String::from("indent instructions and directives so that labels stand out\n"),
+                ]
+            }
+
+            Warning::NoBlankLineBeforeLabel { .. } => {
+                vec![
+                    String::from("separating labelled blocks with a blank line makes them easier to read\n"),
+                ]
+            }
+
+            Warning::UnknownAttribute { name } => {
+                let known = KnownAttribute::ALL.iter()
+                    .map(KnownAttribute::name);
+
+                similar_name_tips(name, known)
+            }
+
+            Warning::UnknownLint { name } => {
+                let known = Lint::ALL.iter()
+                    .map(Lint::name)
+                    .chain(Lint::GROUPS.iter().copied());
+
+                similar_name_tips(name, known)
+            }
+        }
+    }
+}
+
+fn similar_name_tips<'a>(name: &str, known: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let name = name.to_ascii_lowercase();
+
+    known.filter(|known| strsim::jaro_winkler(known, &name) >= 0.8)
+        .map(|known| format!("did you mean `{}`?\n", known.bold()))
+        .collect()
+}
+
+/// # A category of compiler warning that can be toggled.
+///
+/// Each lint is enabled or disabled by default, which can be
+/// overridden by the `warnings` section of the config. That can
+/// in turn be overridden for a whole file with `#![allow(...)]`,
+/// `#![warn(...)]` or `#![deny(...)]`, or for a single item with
+/// the same attributes written as `#[...]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Lint {
+    UnusedLabel,
+    SingleNativePseudo,
+    Indentation,
+    BlankLineBeforeLabel,
+    UnknownAttribute,
+    UnknownLint,
+}
+
+/// # What happens when a lint is triggered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 6] = [
+        Lint::UnusedLabel,
+        Lint::SingleNativePseudo,
+        Lint::Indentation,
+        Lint::BlankLineBeforeLabel,
+        Lint::UnknownAttribute,
+        Lint::UnknownLint,
+    ];
+
+    /// Names that refer to several lints at once.
+    pub const GROUPS: [&'static str; 2] = ["unused", "warnings"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::UnusedLabel          => "unused_label",
+            Lint::SingleNativePseudo   => "single_native_pseudo",
+            Lint::Indentation          => "indentation",
+            Lint::BlankLineBeforeLabel => "blank_line_before_label",
+            Lint::UnknownAttribute     => "unknown_attribute",
+            Lint::UnknownLint          => "unknown_lint",
+        }
+    }
+
+    pub fn enabled_by_default(&self) -> bool {
+        match self {
+            Lint::UnusedLabel          => false,
+            Lint::SingleNativePseudo   => false,
+            Lint::Indentation          => false,
+            Lint::BlankLineBeforeLabel => false,
+            Lint::UnknownAttribute     => true,
+            Lint::UnknownLint          => true,
+        }
+    }
+
+    /// Whether `name` refers to this lint, either directly
+    /// or through a group (`unused`, or `warnings` for every lint).
+    pub fn matches(&self, name: &str) -> bool {
+        name == self.name()
+            || name == "warnings"
+            || (name == "unused" && *self == Lint::UnusedLabel)
+    }
+
+    /// Whether `name` is a lint or lint group.
+    pub fn is_known_name(name: &str) -> bool {
+        Self::GROUPS.contains(&name) || Self::ALL.iter().any(|lint| lint.name() == name)
+    }
+
+    /// The level of this lint for an item, given the attributes of
+    /// its file and of the item itself. Later attributes take priority.
+    pub fn level(&self, config: &MipsyConfig, file_attributes: &[Attribute], item_attributes: &[Attribute]) -> LintLevel {
+        let mut level = match config.warnings.get(self.name()).copied() {
+            Some(true)  => LintLevel::Warn,
+            Some(false) => LintLevel::Allow,
+            None if self.enabled_by_default() => LintLevel::Warn,
+            None => LintLevel::Allow,
+        };
+
+        for attribute in file_attributes.iter().chain(item_attributes) {
+            let attribute_level = match KnownAttribute::from_name(attribute.key()) {
+                Some(KnownAttribute::Allow) => LintLevel::Allow,
+                Some(KnownAttribute::Warn)  => LintLevel::Warn,
+                Some(KnownAttribute::Deny)  => LintLevel::Deny,
+                _ => continue,
+            };
+
+            let names = attribute.value().unwrap_or("");
+
+            if lint_names(names).any(|name| self.matches(name)) {
+                level = attribute_level;
+            }
+        }
+
+        level
+    }
+}
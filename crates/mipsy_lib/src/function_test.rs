@@ -0,0 +1,331 @@
+//! # Unit tests for a single function.
+//!
+//! Rather than running a whole program from `main`, a function test calls
+//! one function directly, with chosen arguments and memory, and checks
+//! what the function returns and what it leaves in memory.
+//!
+//! Tests are written in YAML:
+//!
+//! ```yaml
+//! function: sum_array
+//! tests:
+//!   - name: sums three numbers
+//!     args: [{ words: [1, 2, 3] }, 3]
+//!     expect:
+//!       v0: 6
+//!       memory:
+//!         - { arg: 0, words: [1, 2, 3] }
+//! ```
+//!
+//! Arguments are either integers, data (which is copied into the heap,
+//! and passed by address), or `{ label: name }` for the address of a label.
+//! Memory is located by `label`, by `addr`, or by `arg` - the address
+//! passed as that argument - and holds `words`, `halves`, `bytes` or
+//! a `string` (which is NUL-terminated).
+//!
+//! The function must also follow the calling convention - returning
+//! without restoring `$s0`-`$s7`, `$sp`, `$fp` or `$gp` fails the test.
+
+use std::fmt;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{Binary, HEAP_BOT, MipsyResult, Register, Runtime, STACK_PTR, State};
+
+/// # A set of tests for the functions of a program.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionTests {
+    /// The function each test calls, unless the test gives its own.
+    #[serde(default)]
+    pub function: Option<String>,
+    #[serde(default = "default_check_calling_convention")]
+    pub check_calling_convention: bool,
+    pub tests: Vec<FunctionTest>,
+}
+
+fn default_check_calling_convention() -> bool {
+    true
+}
+
+/// # A single call to a function, and what it should do.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionTest {
+    pub name: String,
+    #[serde(default)]
+    pub function: Option<String>,
+    #[serde(default)]
+    pub args: Vec<Argument>,
+    /// Memory to set before the function is called.
+    #[serde(default)]
+    pub memory: Vec<Memory>,
+    /// The function's standard input.
+    #[serde(default)]
+    pub stdin: String,
+    #[serde(default)]
+    pub expect: Expect,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Argument {
+    Value(i64),
+    Label { label: String },
+    Data(Data),
+}
+
+/// # What a function should have done once it returns.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Expect {
+    #[serde(default)]
+    pub v0: Option<i64>,
+    #[serde(default)]
+    pub v1: Option<i64>,
+    #[serde(default)]
+    pub memory: Vec<Memory>,
+    /// The function's standard output - which is up to
+    /// whatever runs the test to check.
+    #[serde(default)]
+    pub stdout: Option<String>,
+    /// The function's standard error, likewise.
+    #[serde(default)]
+    pub stderr: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Memory {
+    #[serde(flatten)]
+    pub location: Location,
+    #[serde(flatten)]
+    pub data: Data,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Location {
+    Label(String),
+    Addr(u32),
+    /// The address passed as the `n`th argument.
+    Arg(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Data {
+    Words(Vec<i64>),
+    Halves(Vec<i64>),
+    Bytes(Vec<i64>),
+    String(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FunctionTestError {
+    /// The test doesn't say which function to call.
+    NoFunction,
+    UnknownLabel { label: String },
+    /// `Location::Arg` refers to an argument that doesn't exist.
+    UnknownArgument { arg: usize },
+    /// Memory couldn't be written at `addr`.
+    BadAddress { addr: u32 },
+}
+
+impl fmt::Display for FunctionTestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FunctionTestError::NoFunction => write!(f, "no function to call"),
+            FunctionTestError::UnknownLabel { label } => write!(f, "label `{}` does not exist", label),
+            FunctionTestError::UnknownArgument { arg } => write!(f, "argument {} does not exist", arg),
+            FunctionTestError::BadAddress { addr } => write!(f, "cannot write to address 0x{:08x}", addr),
+        }
+    }
+}
+
+impl FunctionTests {
+    pub fn from_yaml(yaml: &str) -> Result<Self, serde_yaml::Error> {
+        serde_yaml::from_str(yaml)
+    }
+}
+
+impl FunctionTest {
+    /// # Builds a runtime that is about to call this test's function.
+    ///
+    /// Also returns the value of each argument, which for data
+    /// arguments is the address the data was copied to.
+    pub fn runtime(&self, tests: &FunctionTests, binary: &Binary) -> Result<(Runtime, Vec<i32>), FunctionTestError> {
+        let function = self.function.as_ref()
+            .or(tests.function.as_ref())
+            .ok_or(FunctionTestError::NoFunction)?;
+        let entry = label_addr(binary, function)?;
+
+        let mut runtime = Runtime::new(binary, &[]);
+        runtime.set_check_calling_convention(tests.check_calling_convention);
+
+        let mut args = vec![];
+        for arg in self.args.iter() {
+            let value = match arg {
+                Argument::Value(value) => *value as i32,
+                Argument::Label { label } => label_addr(binary, label)? as i32,
+                Argument::Data(data) => {
+                    runtime.allocate(&data.to_bytes())
+                        .map_err(|_| FunctionTestError::BadAddress { addr: HEAP_BOT })? as i32
+                }
+            };
+
+            args.push(value);
+        }
+
+        for memory in self.memory.iter() {
+            let addr = memory.location.addr(binary, &args)?;
+
+            let state = runtime.timeline_mut().state_mut();
+            for (i, byte) in memory.data.to_bytes().into_iter().enumerate() {
+                state.write_mem_byte(addr + i as u32, byte)
+                    .map_err(|_| FunctionTestError::BadAddress { addr: addr + i as u32 })?;
+            }
+        }
+
+        runtime.call(entry, &args)
+            .map_err(|_| FunctionTestError::BadAddress { addr: STACK_PTR })?;
+
+        Ok((runtime, args))
+    }
+
+    /// # Checks the state a function returned with against what was expected.
+    ///
+    /// `args` are the argument values returned by [`FunctionTest::runtime`].
+    /// Returns a description of each expectation that wasn't met.
+    /// Standard output isn't checked.
+    pub fn check(&self, binary: &Binary, state: &State, args: &[i32]) -> Vec<String> {
+        let mut failures = vec![];
+
+        for (reg, expected) in [(Register::V0, self.expect.v0), (Register::V1, self.expect.v1)] {
+            let expected = match expected {
+                Some(expected) => expected as i32,
+                None => continue,
+            };
+
+            match state.read_register_uninit(reg.to_u32()).into_option() {
+                Some(value) if value == expected => {}
+                Some(value) => failures.push(format!("expected ${} to be {}, but it was {}", reg.to_lower_str(), expected, value)),
+                None => failures.push(format!("expected ${} to be {}, but it was uninitialised", reg.to_lower_str(), expected)),
+            }
+        }
+
+        for memory in self.expect.memory.iter() {
+            let addr = match memory.location.addr(binary, args) {
+                Ok(addr) => addr,
+                Err(err) => {
+                    failures.push(err.to_string());
+                    continue;
+                }
+            };
+
+            if let Some(failure) = memory.check(state, addr) {
+                failures.push(failure);
+            }
+        }
+
+        failures
+    }
+}
+
+fn label_addr(binary: &Binary, label: &str) -> Result<u32, FunctionTestError> {
+    binary.labels.get(label)
+        .copied()
+        .ok_or_else(|| FunctionTestError::UnknownLabel { label: label.to_string() })
+}
+
+impl Location {
+    fn addr(&self, binary: &Binary, args: &[i32]) -> Result<u32, FunctionTestError> {
+        match self {
+            Location::Label(label) => label_addr(binary, label),
+            Location::Addr(addr) => Ok(*addr),
+            Location::Arg(arg) => args.get(*arg)
+                .map(|&addr| addr as u32)
+                .ok_or(FunctionTestError::UnknownArgument { arg: *arg }),
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Label(label) => write!(f, "`{}`", label),
+            Location::Addr(addr) => write!(f, "0x{:08x}", addr),
+            Location::Arg(arg) => write!(f, "argument {}", arg),
+        }
+    }
+}
+
+impl Data {
+    /// The size of each element, in bytes.
+    fn element_size(&self) -> u32 {
+        match self {
+            Data::Words(_) => 4,
+            Data::Halves(_) => 2,
+            Data::Bytes(_) | Data::String(_) => 1,
+        }
+    }
+
+    fn elements(&self) -> Vec<i64> {
+        match self {
+            Data::Words(values) | Data::Halves(values) | Data::Bytes(values) => values.clone(),
+            Data::String(string) => string.bytes()
+                .chain(std::iter::once(0))
+                .map(|byte| byte as i64)
+                .collect(),
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let size = self.element_size() as usize;
+
+        self.elements().into_iter()
+            .flat_map(|value| (value as u32).to_le_bytes().into_iter().take(size))
+            .collect()
+    }
+}
+
+impl Memory {
+    /// Describes how the memory at `addr` differs from this, if it does.
+    fn check(&self, state: &State, addr: u32) -> Option<String> {
+        let size = self.data.element_size();
+
+        for (i, expected) in self.data.elements().into_iter().enumerate() {
+            let element_addr = addr + i as u32 * size;
+
+            let value: MipsyResult<Option<u32>> = match size {
+                4 => state.read_mem_word_uninit(element_addr).map(|value| value.into_option()),
+                2 => state.read_mem_half_uninit(element_addr).map(|value| value.into_option().map(|value| value as u32)),
+                _ => state.read_mem_byte_uninit(element_addr).map(|value| value.into_option().map(|value| value as u32)),
+            };
+
+            let mask = if size == 4 { u32::MAX } else { (1 << (size * 8)) - 1 };
+            let expected = (expected as u32) & mask;
+
+            // words are shown signed, like registers are
+            let show = |value: u32| if size == 4 { (value as i32).to_string() } else { value.to_string() };
+
+            let element = match self.data {
+                Data::Words(_) => "word",
+                Data::Halves(_) => "half",
+                Data::Bytes(_) => "byte",
+                Data::String(_) => "character",
+            };
+
+            match value {
+                Ok(Some(value)) if value == expected => {}
+                Ok(Some(value)) => {
+                    return Some(format!("expected {} {} of {} to be {}, but it was {}", element, i, self.location, show(expected), show(value)));
+                }
+                Ok(None) => {
+                    return Some(format!("expected {} {} of {} to be {}, but it was uninitialised", element, i, self.location, show(expected)));
+                }
+                Err(_) => {
+                    return Some(format!("expected {} {} of {} to be {}, but 0x{:08x} is not a valid address", element, i, self.location, show(expected), element_addr));
+                }
+            }
+        }
+
+        None
+    }
+}
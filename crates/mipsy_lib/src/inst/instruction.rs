@@ -1,9 +1,9 @@
 use std::{collections::HashMap, fmt, str::FromStr};
 use serde::{Serialize, Deserialize};
 
-use crate::{Binary, TEXT_BOT, error::{MipsyInternalResult}};
-use super::register::Register;
-use mipsy_parser::{MpArgument, MpImmediate, MpImmediateBinaryOp, MpInstruction, MpNumber, MpOffsetOperator, MpRegister, MpRegisterIdentifier, parse_argument};
+use crate::{Binary, TEXT_BOT, error::{InternalError, MipsyInternalResult, compiler}};
+use super::register::{self, Register};
+use mipsy_parser::{MpArgument, MpImmediate, MpImmediateBinaryOp, MpInstruction, MpNumber, MpOffsetOperator, MpRegister, MpRegisterIdentifier, MpRelocation, parse_argument};
 
 #[derive(Debug, Clone)]
 pub struct InstSet {
@@ -105,6 +105,10 @@ pub enum ArgumentType {
     OffRt,
     F32,
     F64,
+    Fd,
+    Fs,
+    Ft,
+    Cd,
 
     // pseudo
     I32,
@@ -116,7 +120,7 @@ pub enum ArgumentType {
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum RuntimeSignature {
     R { opcode: u8, funct: u8, shamt: Option<u8>, rs: Option<u8>, rt: Option<u8>, rd: Option<u8> },
-    I { opcode: u8, rt: Option<u8> },
+    I { opcode: u8, rs: Option<u8>, rt: Option<u8> },
     J { opcode: u8 },
 }
 
@@ -143,6 +147,8 @@ pub enum ReadsRegisterType {
     Rt,
     OffRs,
     OffRt,
+    Fs,
+    Ft,
 }
 
 impl ReadsRegisterType {
@@ -152,6 +158,8 @@ impl ReadsRegisterType {
             (Self::Rt,    ArgumentType::Rt) => true,
             (Self::OffRs, ArgumentType::OffRs) => true,
             (Self::OffRt, ArgumentType::OffRt) => true,
+            (Self::Fs,    ArgumentType::Fs) => true,
+            (Self::Ft,    ArgumentType::Ft) => true,
             _ => false,
         }
     }   
@@ -322,9 +330,13 @@ impl InstSignature {
                     inst |= (rd as u32 & 0x1F) << 11;
                 }
             }
-            RuntimeSignature::I { opcode, rt } => {
+            RuntimeSignature::I { opcode, rs, rt } => {
                 inst |= (opcode as u32 & 0x3F) << 26;
 
+                if let Some(rs) = rs {
+                    inst |= (rs as u32 & 0x1F) << 21;
+                }
+
                 if let Some(rt) = rt {
                     inst |= (rt as u32 & 0x1F) << 16;
                 }
@@ -345,6 +357,18 @@ impl InstSignature {
                         }
                         _ => unreachable!(),
                     },
+                    ArgumentType::Fd | ArgumentType::Fs | ArgumentType::Ft => match arg {
+                        MpArgument::Register(MpRegister::Normal(reg)) => {
+                            reg.to_float_register()?
+                        }
+                        _ => unreachable!(),
+                    },
+                    ArgumentType::Cd => match arg {
+                        MpArgument::Register(MpRegister::Normal(reg)) => {
+                            reg.to_register()?.to_u32()
+                        }
+                        _ => unreachable!(),
+                    },
                     ArgumentType::Shamt => match arg {
                         MpArgument::Number(MpNumber::Immediate(MpImmediate::I16(num))) => {
                             (*num as u16 as u32) & 0x1F
@@ -367,11 +391,14 @@ impl InstSignature {
                                         // must be relative
                                         let addr = program.get_label(label)?;
 
-                                        let current_inst_addr = program.text.len() as u32 + TEXT_BOT;
+                                        let current_inst_addr = program.next_inst_addr();
 
                                         ((addr.wrapping_sub(current_inst_addr)) / 4) & 0xFFFF
                                     }
                                 }
+                                MpImmediate::Relocation(relocation, value) => {
+                                    relocation.apply(program.evaluate(&value.0)?) as u32
+                                }
                                 _ => unreachable!()
                             }
                             &MpNumber::Char(chr) => {
@@ -397,11 +424,14 @@ impl InstSignature {
                                         // must be relative
                                         let addr = program.get_label(label)?;
 
-                                        let current_inst_addr = program.text.len() as u32 + TEXT_BOT;
+                                        let current_inst_addr = program.next_inst_addr();
 
                                         ((addr.wrapping_sub(current_inst_addr)) / 4) & 0xFFFF
                                     }
                                 }
+                                MpImmediate::Relocation(relocation, value) => {
+                                    relocation.apply(program.evaluate(&value.0)?) as u32
+                                }
                                 _ => unreachable!()
                             }
                             &MpNumber::Char(chr) => {
@@ -429,18 +459,22 @@ impl InstSignature {
                     },
                     ArgumentType::OffRs | ArgumentType::OffRt => match arg {
                         MpArgument::Register(reg) => match reg {
-                            MpRegister::Offset(imm, reg) => match *imm {
-                                MpImmediate::I16(imm) => {
-                                    let register = reg.to_register()?.to_u32();
-                                    let imm = imm as u16 as u32;
+                            MpRegister::Offset(imm, reg) => {
+                                let imm = match imm {
+                                    &MpImmediate::I16(imm) => imm as u16 as u32,
+                                    MpImmediate::Relocation(relocation, value) => {
+                                        relocation.apply(program.evaluate(&value.0)?) as u32
+                                    }
+                                    _ => unreachable!(),
+                                };
 
-                                    (register << 16) | imm
-                                }
-                                _ => unreachable!(),
+                                let register = reg.to_register()?.to_u32();
+
+                                (register << 16) | imm
                             }
                             _ => unreachable!(),
                         }
-                        x => unreachable!(format!("{:?}", x)),
+                        x => unreachable!("{:?}", x),
                     },
                     ArgumentType::F32     => unimplemented!(),
                     ArgumentType::F64     => unimplemented!(),
@@ -454,6 +488,10 @@ impl InstSignature {
                 ArgumentType::Rs     => inst |= (val & 0x1F) << 21,
                 ArgumentType::Rt     => inst |= (val & 0x1F) << 16,
                 ArgumentType::Rd     => inst |= (val & 0x1F) << 11,
+                ArgumentType::Ft     => inst |= (val & 0x1F) << 16,
+                ArgumentType::Fs     => inst |= (val & 0x1F) << 11,
+                ArgumentType::Fd     => inst |= (val & 0x1F) << 6,
+                ArgumentType::Cd     => inst |= (val & 0x1F) << 11,
                 ArgumentType::Shamt  => inst |= (val & 0x1F) << 6,
                 ArgumentType::I16    => inst |=  val & 0xFFFF,
                 ArgumentType::U16    => inst |=  val & 0xFFFF,
@@ -506,6 +544,10 @@ impl fmt::Display for ArgumentType {
             ArgumentType::OffRt   => write!(f, "i16($Rt)"),
             ArgumentType::F32     => write!(f, "f32"),
             ArgumentType::F64     => write!(f, "f64"),
+            ArgumentType::Fd      => write!(f, "$Fd"),
+            ArgumentType::Fs      => write!(f, "$Fs"),
+            ArgumentType::Ft      => write!(f, "$Ft"),
+            ArgumentType::Cd      => write!(f, "$Cd"),
             ArgumentType::I32     => write!(f, "i32"),
             ArgumentType::U32     => write!(f, "u32"),
             ArgumentType::Off32Rs => write!(f, "i32($Rs)"),
@@ -519,14 +561,25 @@ impl ArgumentType {
         match arg {
             MpArgument::Register(register) => {
                 match register {
-                    MpRegister::Normal(_) => matches!(self, Self::Rd | Self::Rs | Self::Rt),
+                    MpRegister::Normal(ident) => {
+                        if ident.is_float_register() {
+                            matches!(self, Self::Fd | Self::Fs | Self::Ft)
+                        } else if let MpRegisterIdentifier::Numbered(_) = ident {
+                            // coprocessor 0 registers are only ever numbered
+                            matches!(self, Self::Rd | Self::Rs | Self::Rt | Self::Cd)
+                        } else {
+                            matches!(self, Self::Rd | Self::Rs | Self::Rt)
+                        }
+                    }
                     MpRegister::Offset(imm, _) => match imm {
-                        MpImmediate::I16(_) => matches!(self, Self::OffRs | Self::OffRt | Self::Off32Rs | Self::Off32Rt),
+                        MpImmediate::I16(_)
+                        | MpImmediate::Relocation(..) => matches!(self, Self::OffRs | Self::OffRt | Self::Off32Rs | Self::Off32Rt),
 
                         MpImmediate::U16(_)
                         | MpImmediate::U32(_)
                         | MpImmediate::I32(_)
-                        | MpImmediate::LabelReference(_) => matches!(self, Self::Off32Rs | Self::Off32Rt),
+                        | MpImmediate::LabelReference(_)
+                        | MpImmediate::Expression(_) => matches!(self, Self::Off32Rs | Self::Off32Rt),
                     }
                     MpRegister::BinaryOpOffset(..) => matches!(self, Self::Off32Rs | Self::Off32Rt),
                 }
@@ -559,6 +612,8 @@ impl ArgumentType {
                                     _ => false,
                                 }
                             }
+                            MpImmediate::Relocation(..) => matches!(self, Self::I16 | Self::U16 | Self::I32 | Self::U32 | Self::Off32Rs | Self::Off32Rt),
+                            MpImmediate::Expression(_) => matches!(self, Self::I32 | Self::U32 | Self::Off32Rs | Self::Off32Rt),
                         }
                     }
                     MpNumber::BinaryOpImmediate(_imm1, _op, _imm2) => {
@@ -573,7 +628,6 @@ impl ArgumentType {
                     MpNumber::Float64(_) => matches!(self, Self::F64),
                 }
             }
-            // MpArgument::LabelPlusConst(..)
         }
     }
 }
@@ -591,6 +645,10 @@ enum PseudoVariable {
     OffRt,
     F32,
     F64,
+    Fd,
+    Fs,
+    Ft,
+    Cd,
     Off,
 
     // pseudo
@@ -613,6 +671,10 @@ impl PseudoVariable {
             Self::OffRt => "offrt",
             Self::F32   => "f32",
             Self::F64   => "f64",
+            Self::Fd    => "fd",
+            Self::Fs    => "fs",
+            Self::Ft    => "ft",
+            Self::Cd    => "cd",
             Self::Off   => "off",
         
             // pseudo
@@ -635,6 +697,10 @@ impl PseudoVariable {
             ArgumentType::OffRt => Self::OffRt,
             ArgumentType::F32   => Self::F32,
             ArgumentType::F64   => Self::F64,
+            ArgumentType::Fd    => Self::Fd,
+            ArgumentType::Fs    => Self::Fs,
+            ArgumentType::Ft    => Self::Ft,
+            ArgumentType::Cd    => Self::Cd,
         
             // pseudo
             ArgumentType::I32 | ArgumentType::U32 | ArgumentType::Off32Rs | ArgumentType::Off32Rt => panic!("Bad arg type from mips.yaml"),
@@ -693,6 +759,19 @@ impl PseudoSignature {
                             ((addr & 0xFFFF) as u16, (addr >> 16) as u16)
                         }
                     }
+                    MpImmediate::Relocation(relocation, value) => {
+                        let value = relocation.apply(program.evaluate(&value.0)?);
+
+                        match relocation {
+                            MpRelocation::Hi => (value, 0),
+                            MpRelocation::Lo => (value, (value as i16 as i32 >> 16) as u16),
+                        }
+                    }
+                    MpImmediate::Expression(value) => {
+                        let value = program.evaluate(&value.0)?;
+
+                        ((value & 0xFFFF) as u16, (value >> 16) as u16)
+                    }
                 }
                 &MpNumber::Char(chr) => {
                     (chr as u16, 0_u16)
@@ -778,7 +857,8 @@ impl PseudoSignature {
             let last = i == args.len() - 1;
 
             match arg_type {
-                ArgumentType::Rd | ArgumentType::Rs | ArgumentType::Rt | ArgumentType::Shamt | ArgumentType::J => {
+                ArgumentType::Rd | ArgumentType::Rs | ArgumentType::Rt | ArgumentType::Shamt | ArgumentType::J |
+                ArgumentType::Fd | ArgumentType::Fs | ArgumentType::Ft | ArgumentType::Cd => {
                     self.new_variable(program, PseudoVariable::from_arg_type(arg_type), arg.clone(), &mut variables, &mut used, last)?;
                 }
                 ArgumentType::I16 => {
@@ -787,7 +867,7 @@ impl PseudoSignature {
                         MpArgument::Number(MpNumber::Immediate(MpImmediate::LabelReference(label))) => {
                             let addr = program.get_label(label)?;
 
-                            let current_inst_addr = (program.text.len() + (self.expand.len() - 1) * 4) as u32 + TEXT_BOT;
+                            let current_inst_addr = program.next_inst_addr() + (self.expand.len() - 1) as u32 * 4;
                             let imm = ((addr.wrapping_sub(current_inst_addr)) / 4) as i16;
 
                             MpArgument::Number(MpNumber::Immediate(MpImmediate::I16(imm)))
@@ -976,6 +1056,10 @@ impl Signature {
 
 pub(crate) trait ToRegister {
     fn to_register(&self) -> MipsyInternalResult<Register>;
+
+    fn to_float_register(&self) -> MipsyInternalResult<u32>;
+
+    fn is_float_register(&self) -> bool;
 }
 
 impl ToRegister for MpRegisterIdentifier {
@@ -987,4 +1071,24 @@ impl ToRegister for MpRegisterIdentifier {
             }
         )
     }
+
+    fn to_float_register(&self) -> MipsyInternalResult<u32> {
+        match self {
+            MpRegisterIdentifier::Named(name) => register::float_register_from_str(name),
+            MpRegisterIdentifier::Numbered(num) => Err(
+                InternalError::Compiler(
+                    compiler::Error::UnknownRegister {
+                        reg_name: num.to_string(),
+                    }
+                )
+            ),
+        }
+    }
+
+    fn is_float_register(&self) -> bool {
+        match self {
+            MpRegisterIdentifier::Named(name) => register::is_float_register_name(name),
+            MpRegisterIdentifier::Numbered(_) => false,
+        }
+    }
 }
@@ -2,7 +2,7 @@ use serde::{Serialize, Deserialize};
 use crate::error::{InternalError, MipsyInternalResult, compiler};
 use std::str::FromStr;
 
-#[derive(Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Register {
     Zero,
     At,
@@ -266,4 +266,55 @@ impl Register {
             Self::Ra   => "ra",
         }
     }
-}
\ No newline at end of file
+}
+pub const FLOAT_REGISTERS: u32 = 32;
+
+pub const COP0_BAD_VADDR: u32 = 8;
+pub const COP0_STATUS:    u32 = 12;
+pub const COP0_CAUSE:     u32 = 13;
+pub const COP0_EPC:       u32 = 14;
+
+/// The coprocessor 0 registers that mipsy has, and their names.
+pub const COP0_REGISTERS: [(u32, &str); 4] = [
+    (COP0_BAD_VADDR, "badvaddr"),
+    (COP0_STATUS,    "status"),
+    (COP0_CAUSE,     "cause"),
+    (COP0_EPC,       "epc"),
+];
+
+/// Whether `name` looks like a coprocessor 1 register (`f0` through `f31`),
+/// as opposed to one of the general purpose registers (e.g. `fp`).
+pub fn is_float_register_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    matches!(chars.next(), Some('f' | 'F'))
+        && !chars.as_str().is_empty()
+        && chars.all(|chr| chr.is_ascii_digit())
+}
+
+pub fn float_register_from_str(name: &str) -> MipsyInternalResult<u32> {
+    if is_float_register_name(name) {
+        if let Ok(num) = name[1..].parse::<u32>() {
+            if num < FLOAT_REGISTERS {
+                return Ok(num);
+            }
+        }
+
+        return Err(
+            InternalError::Compiler(
+                compiler::Error::NamedRegisterOutOfRange {
+                    reg_name: 'f',
+                    reg_index: name[1..].parse::<i32>().unwrap_or(i32::MAX),
+                }
+            )
+        );
+    }
+
+    Err(
+        InternalError::Compiler(
+            compiler::Error::UnknownRegister {
+                reg_name: name.to_string(),
+            }
+        )
+    )
+}
@@ -4,6 +4,9 @@ pub mod util;
 pub mod compile;
 pub mod decompile;
 pub mod runtime;
+pub mod function_test;
+pub mod elf;
+pub mod snapshot;
 
 use std::rc::Rc;
 
@@ -15,6 +18,7 @@ pub use error::{
     ParserError,
     CompilerError,
     RuntimeError,
+    CompilerWarning,
     runtime::Uninitialised,
 };
 pub use inst::instruction::{
@@ -23,7 +27,7 @@ pub use inst::instruction::{
 };
 pub use inst::register::Register;
 pub use compile::{Binary};
-use mipsy_parser::TaggedFile;
+use mipsy_parser::{ErrorKind, ErrorLocation, TaggedFile};
 use mipsy_utils::MipsyConfig;
 pub use runtime::{
     Runtime,
@@ -49,22 +53,39 @@ pub fn compile(iset: &InstSet, files: Vec<TaggedFile<'_, '_>>, config: &MipsyCon
 
 pub fn compile_with_kernel(iset: &InstSet, files: Vec<TaggedFile<'_, '_>>, kernel: &mut MpProgram, config: &MipsyConfig) -> MipsyResult<Binary> {
     let mut parsed = mipsy_parser::parse_mips(files, config.tab_size)
-        .map_err(|err| 
-            error::MipsyError::Parser(
-                ParserError::new(
-                    error::parser::Error::ParseFailure,
-                    err.file_name.unwrap_or_else(|| Rc::from("")),
-                    err.line,
-                    err.col as u32
-                )
-            )
-        )?;
+        .map_err(parser_error)?;
 
     let compiled = compile::compile_with_kernel(&mut parsed, kernel, config, iset)?;
 
     Ok(compiled)
 }
 
+/// # Adds the files that `files` include, with `.include`, to them.
+///
+/// See [`mipsy_parser::include_files`].
+pub fn include_files<F>(files: &mut Vec<(String, String)>, config: &MipsyConfig, read_file: F) -> MipsyResult<()>
+where
+    F: FnMut(&str) -> std::io::Result<String>,
+{
+    mipsy_parser::include_files(files, config.tab_size, read_file)
+        .map_err(parser_error)
+}
+
+fn parser_error(err: ErrorLocation) -> MipsyError {
+    MipsyError::Parser(
+        ParserError::new(
+            match err.kind {
+                Some(ErrorKind::Macro(error))   => error::parser::Error::InvalidMacro { error },
+                Some(ErrorKind::Include(error)) => error::parser::Error::InvalidInclude { error },
+                None => error::parser::Error::ParseFailure,
+            },
+            err.file_name.unwrap_or_else(|| Rc::from("")),
+            err.line,
+            err.col as u32
+        ).with_expansion(err.expansion.map(|expansion| *expansion))
+    )
+}
+
 pub use compile::compile1;
 
 pub fn decompile(iset: &InstSet, binary: &Binary) -> String {
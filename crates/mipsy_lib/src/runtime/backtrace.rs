@@ -0,0 +1,193 @@
+use std::rc::Rc;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{MipsyError, MipsyResult, Register, RuntimeError, Safe, error::runtime::Error};
+use super::{SPECIAL, State};
+
+/// The registers a function must restore before it returns.
+pub const CALLEE_SAVED: [Register; 11] = [
+    Register::S0, Register::S1, Register::S2, Register::S3,
+    Register::S4, Register::S5, Register::S6, Register::S7,
+    Register::Gp, Register::Sp, Register::Fp,
+];
+
+/// # A function that has been called, but hasn't yet returned.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    /// The address of the `jal` or `jalr` that called the function.
+    pub call_site: u32,
+    /// The address the function was called at.
+    pub entry: u32,
+    /// The value of `$sp` when the function was called.
+    pub sp: Safe<i32>,
+    /// The values of the [`CALLEE_SAVED`] registers when the function was called.
+    pub callee_saved: [Safe<i32>; CALLEE_SAVED.len()],
+}
+
+/// # The functions that have been called, but haven't yet returned.
+///
+/// A call stack is kept in every [`State`], so that it's correct no
+/// matter how far back the timeline is stepped, or how much of it
+/// has been discarded. Frames are shared between states, so cloning
+/// a call stack is cheap.
+///
+/// Calls are made with `jal` or `jalr`. A function returns with a
+/// `jr` to the instruction after its call site - any frames above it
+/// are assumed to have returned too.
+#[derive(Debug, Clone, Default)]
+pub struct CallStack {
+    top: Option<Rc<Node>>,
+}
+
+#[derive(Debug)]
+struct Node {
+    frame: Frame,
+    depth: usize,
+    parent: Option<Rc<Node>>,
+}
+
+impl CallStack {
+    /// The frames in the call stack, starting with the current function.
+    pub fn frames(&self) -> impl Iterator<Item = &Frame> {
+        std::iter::successors(self.top.as_deref(), |node| node.parent.as_deref())
+            .map(|node| &node.frame)
+    }
+
+    /// How many functions are currently being executed.
+    pub fn depth(&self) -> usize {
+        self.top.as_ref()
+            .map(|node| node.depth)
+            .unwrap_or(0)
+    }
+
+    /// A call stack of `frames`, from the outermost function in.
+    pub(super) fn from_frames(frames: impl IntoIterator<Item = Frame>) -> Self {
+        let mut call_stack = Self::default();
+
+        for frame in frames {
+            call_stack.push(frame);
+        }
+
+        call_stack
+    }
+
+    fn push(&mut self, frame: Frame) {
+        let depth = self.depth() + 1;
+        let parent = self.top.take();
+
+        self.top = Some(Rc::new(Node { frame, depth, parent }));
+    }
+
+    /// The frame of the function that returns to `return_addr`, if any.
+    pub fn returning_to(&self, return_addr: u32) -> Option<&Frame> {
+        self.frames()
+            .find(|frame| frame.call_site.wrapping_add(4) == return_addr)
+    }
+
+    /// Pops frames up to and including the one that returns to `return_addr`.
+    /// If no frame returns there, nothing is popped.
+    fn return_to(&mut self, return_addr: u32) {
+        let returned = std::iter::successors(self.top.as_ref(), |node| node.parent.as_ref())
+            .find(|node| node.frame.call_site.wrapping_add(4) == return_addr)
+            .map(|node| node.parent.clone());
+
+        if let Some(parent) = returned {
+            self.top = parent;
+        }
+    }
+}
+
+impl Drop for CallStack {
+    fn drop(&mut self) {
+        // drop unshared frames iteratively, so that
+        // deep recursion can't overflow the stack
+        let mut next = self.top.take();
+
+        while let Some(node) = next {
+            next = match Rc::try_unwrap(node) {
+                Ok(mut node) => node.parent.take(),
+                Err(_) => None,
+            };
+        }
+    }
+}
+
+impl State {
+    pub fn call_stack(&self) -> &CallStack {
+        &self.call_stack
+    }
+
+    /// Updates the call stack after `inst`, at `inst_addr`, was executed.
+    pub(super) fn update_call_stack(&mut self, inst: u32, inst_addr: u32) {
+        if is_call(inst) {
+            self.push_frame(inst_addr);
+        } else if is_jump_register(inst) {
+            self.call_stack.return_to(self.pc());
+        }
+    }
+
+    /// Records a call to the current `pc`, made from `call_site`.
+    pub(super) fn push_frame(&mut self, call_site: u32) {
+        let frame = Frame {
+            call_site,
+            entry: self.pc(),
+            sp: self.read_register_uninit(Register::Sp.to_u32()),
+            callee_saved: CALLEE_SAVED.map(|reg| self.read_register_uninit(reg.to_u32())),
+        };
+
+        self.call_stack.push(frame);
+    }
+
+    /// Checks that, if `inst` returned from a function, the function
+    /// restored each of the [`CALLEE_SAVED`] registers.
+    ///
+    /// Registers that were uninitialised when the function was called
+    /// aren't checked, as the function couldn't have saved them.
+    pub(super) fn check_calling_convention(&self, inst: u32) -> MipsyResult<()> {
+        if !is_jump_register(inst) {
+            return Ok(());
+        }
+
+        let frame = match self.call_stack.returning_to(self.pc()) {
+            Some(frame) => frame,
+            None => return Ok(()),
+        };
+
+        for (reg, &on_entry) in CALLEE_SAVED.iter().zip(frame.callee_saved.iter()) {
+            let on_entry = match on_entry {
+                Safe::Valid(value) => value,
+                Safe::Uninitialised => continue,
+            };
+
+            let on_return = self.read_register_uninit(reg.to_u32()).into_option();
+
+            if on_return != Some(on_entry) {
+                return Err(MipsyError::Runtime(RuntimeError::new(Error::CalleeSavedClobbered {
+                    entry: frame.entry,
+                    reg_num: reg.to_u32(),
+                    on_entry,
+                    on_return,
+                })));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether `inst` is a `jal` or `jalr`.
+fn is_call(inst: u32) -> bool {
+    let opcode = inst >> 26;
+    let funct  = inst & 0x3F;
+
+    opcode == 0x03 || (opcode == SPECIAL && funct == 0x09)
+}
+
+/// Whether `inst` is a `jr`.
+fn is_jump_register(inst: u32) -> bool {
+    let opcode = inst >> 26;
+    let funct  = inst & 0x3F;
+
+    opcode == SPECIAL && funct == 0x08
+}
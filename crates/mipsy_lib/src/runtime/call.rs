@@ -0,0 +1,75 @@
+use crate::{HEAP_BOT, MipsyResult, Register};
+use super::Runtime;
+
+/// # The address that a function called with [`Runtime::call`] returns to.
+///
+/// There's never an instruction here, so once the `pc` reaches
+/// this address, the function has returned.
+pub const CALL_RETURN_ADDR: u32 = 0xFFFF_FFFC;
+
+/// `$s0`-`$s7` are given the values `SAVED_REGISTER_BASE + n` before
+/// a call, so that a function that doesn't restore them can be caught.
+pub const SAVED_REGISTER_BASE: i32 = 0x5EED_0000;
+
+const SAVED_REGISTERS: [Register; 8] = [
+    Register::S0, Register::S1, Register::S2, Register::S3,
+    Register::S4, Register::S5, Register::S6, Register::S7,
+];
+
+impl Runtime {
+    /// # Sets up a call to the function at `entry`, as its caller would.
+    ///
+    /// The first four `args` are passed in `$a0`-`$a3`, and the rest on the
+    /// stack, after the 16 bytes reserved for the first four. The function
+    /// returns to [`CALL_RETURN_ADDR`] - see [`Runtime::call_returned`].
+    pub fn call(&mut self, entry: u32, args: &[i32]) -> MipsyResult<()> {
+        let state = self.timeline.state_mut();
+
+        let arg_registers = [Register::A0, Register::A1, Register::A2, Register::A3];
+        for (reg, &arg) in arg_registers.iter().zip(args) {
+            state.write_register(reg.to_u32(), arg);
+        }
+
+        let stack_args = args.get(4..).unwrap_or(&[]);
+
+        // the stack pointer stays 8-byte aligned
+        let frame_size = (16 + 4 * stack_args.len() as u32 + 7) & !7;
+        let sp = state.read_register(Register::Sp.to_u32())? as u32 - frame_size;
+        state.write_register(Register::Sp.to_u32(), sp as i32);
+
+        for (i, &arg) in stack_args.iter().enumerate() {
+            state.write_mem_word(sp + 16 + 4 * i as u32, arg as u32)?;
+        }
+
+        for (n, reg) in SAVED_REGISTERS.iter().enumerate() {
+            state.write_register(reg.to_u32(), SAVED_REGISTER_BASE + n as i32);
+        }
+
+        state.write_register(Register::Ra.to_u32(), CALL_RETURN_ADDR as i32);
+        state.set_pc(entry);
+        state.push_frame(CALL_RETURN_ADDR - 4);
+
+        Ok(())
+    }
+
+    /// Whether the function called with [`Runtime::call`] has returned.
+    pub fn call_returned(&self) -> bool {
+        self.timeline.state().pc() == CALL_RETURN_ADDR
+    }
+
+    /// # Copies `bytes` into newly allocated heap memory.
+    ///
+    /// The memory is word-aligned, and its address is returned.
+    pub fn allocate(&mut self, bytes: &[u8]) -> MipsyResult<u32> {
+        let state = self.timeline.state_mut();
+
+        let addr = HEAP_BOT + ((state.heap_size() + 3) & !3);
+        state.set_heap_size(addr - HEAP_BOT + bytes.len() as u32);
+
+        for (i, &byte) in bytes.iter().enumerate() {
+            state.write_mem_byte(addr + i as u32, byte)?;
+        }
+
+        Ok(addr)
+    }
+}
@@ -0,0 +1,288 @@
+use std::{collections::BTreeMap, rc::Rc};
+
+use colored::Colorize;
+
+use crate::{Binary, Safe, TEXT_BOT};
+use super::profile::{LineTable, Profile, is_branch};
+
+/// # How often a branch went each way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BranchCoverage {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+/// # How often a single instruction was executed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstCoverage {
+    pub addr: u32,
+    /// The source line the instruction was generated from, if any.
+    pub line: Option<(Rc<str>, u32)>,
+    pub executed: u64,
+    /// Only set for branch instructions.
+    pub branch: Option<BranchCoverage>,
+}
+
+/// # How often a single source line was executed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineCoverage {
+    pub file_tag: Rc<str>,
+    pub line_num: u32,
+    /// The most times any of the line's instructions was executed.
+    pub executed: u64,
+    /// The number of native instructions the line was compiled to -
+    /// more than one for most pseudo-instructions.
+    pub instructions: usize,
+    pub instructions_executed: usize,
+    /// Each branch instruction on the line, in address order.
+    pub branches: Vec<BranchCoverage>,
+}
+
+/// # Which of a program's instructions were executed, over one or more runs.
+///
+/// Only the user's own instructions are covered - the kernel isn't.
+/// Runs are added from the [`Profile`] of each runtime, so profiling
+/// must be enabled (see [`Runtime::set_profiling`](super::Runtime::set_profiling)).
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    /// Every instruction in the text segment, in address order.
+    instructions: Vec<InstCoverage>,
+}
+
+impl Coverage {
+    /// The coverage of `binary` before it has been run.
+    pub fn new(binary: &Binary) -> Self {
+        let line_table = LineTable::new(binary);
+
+        let instructions = binary.text_words()
+            .enumerate()
+            .map(|(i, inst)| {
+                let addr = TEXT_BOT + 4 * i as u32;
+
+                InstCoverage {
+                    addr,
+                    line: line_table.line(addr).cloned(),
+                    executed: 0,
+                    branch: match inst {
+                        Safe::Valid(inst) if is_branch(inst) => Some(BranchCoverage::default()),
+                        _ => None,
+                    },
+                }
+            })
+            .collect();
+
+        Self { instructions }
+    }
+
+    /// # Adds the instructions executed by a single run.
+    ///
+    /// The `profile` must be of the same binary this coverage was made from.
+    pub fn add(&mut self, profile: &Profile) {
+        for (&addr, counts) in profile.instructions() {
+            if addr < TEXT_BOT {
+                continue;
+            }
+
+            let inst = match self.instructions.get_mut(((addr - TEXT_BOT) / 4) as usize) {
+                Some(inst) => inst,
+                None => continue,
+            };
+
+            inst.executed += counts.executed;
+
+            if let Some(branch) = inst.branch.as_mut() {
+                branch.taken     += counts.branches_taken;
+                branch.not_taken += counts.executed - counts.branches_taken;
+            }
+        }
+    }
+
+    pub fn instructions(&self) -> &[InstCoverage] {
+        &self.instructions
+    }
+
+    /// # The coverage of each source line with instructions, in source order.
+    pub fn lines(&self) -> Vec<LineCoverage> {
+        let mut lines: BTreeMap<(Rc<str>, u32), LineCoverage> = BTreeMap::new();
+
+        for inst in self.instructions.iter() {
+            let (file_tag, line_num) = match &inst.line {
+                Some(line) => line.clone(),
+                None => continue,
+            };
+
+            let line = lines.entry((file_tag.clone(), line_num))
+                .or_insert_with(|| LineCoverage {
+                    file_tag,
+                    line_num,
+                    executed: 0,
+                    instructions: 0,
+                    instructions_executed: 0,
+                    branches: vec![],
+                });
+
+            line.executed = line.executed.max(inst.executed);
+            line.instructions += 1;
+            if inst.executed > 0 {
+                line.instructions_executed += 1;
+            }
+            line.branches.extend(inst.branch);
+        }
+
+        lines.into_values().collect()
+    }
+
+    /// # The coverage in the lcov tracefile format.
+    ///
+    /// Each branch instruction is a block of two branches - taken, then not taken.
+    pub fn lcov(&self) -> String {
+        let mut lcov = String::new();
+
+        let mut files: BTreeMap<Rc<str>, Vec<LineCoverage>> = BTreeMap::new();
+        for line in self.lines() {
+            files.entry(line.file_tag.clone()).or_default().push(line);
+        }
+
+        for (file_tag, lines) in files {
+            lcov.push_str("TN:\n");
+            lcov.push_str(&format!("SF:{}\n", file_tag));
+
+            let mut branches_found = 0;
+            let mut branches_hit = 0;
+
+            for line in lines.iter() {
+                for (block, branch) in line.branches.iter().enumerate() {
+                    for (n, count) in [branch.taken, branch.not_taken].into_iter().enumerate() {
+                        // lcov uses `-` for a branch whose line never ran
+                        let count = match line.executed {
+                            0 => String::from("-"),
+                            _ => count.to_string(),
+                        };
+
+                        lcov.push_str(&format!("BRDA:{},{},{},{}\n", line.line_num, block, n, count));
+                    }
+
+                    branches_found += 2;
+                    branches_hit += (branch.taken > 0) as usize + (branch.not_taken > 0) as usize;
+                }
+            }
+
+            if branches_found > 0 {
+                lcov.push_str(&format!("BRF:{}\n", branches_found));
+                lcov.push_str(&format!("BRH:{}\n", branches_hit));
+            }
+
+            for line in lines.iter() {
+                lcov.push_str(&format!("DA:{},{}\n", line.line_num, line.executed));
+            }
+
+            lcov.push_str(&format!("LF:{}\n", lines.len()));
+            lcov.push_str(&format!("LH:{}\n", lines.iter().filter(|line| line.executed > 0).count()));
+            lcov.push_str("end_of_record\n");
+        }
+
+        lcov
+    }
+
+    /// # The source code, annotated with how often each line executed.
+    ///
+    /// Lines that never executed are marked with `#####`, and lines without
+    /// any instructions with `-`. Lines that only partly executed, and
+    /// branches that only ever went one way, are pointed out.
+    pub fn annotate(&self, source_code: &[(Rc<str>, Rc<str>)]) -> String {
+        let mut annotated = String::new();
+
+        let lines = self.lines();
+
+        for (file_tag, file) in source_code.iter() {
+            let file_lines = lines.iter()
+                .filter(|line| line.file_tag == *file_tag)
+                .map(|line| (line.line_num, line))
+                .collect::<BTreeMap<_, _>>();
+
+            if file_lines.is_empty() {
+                continue;
+            }
+
+            if !annotated.is_empty() {
+                annotated.push('\n');
+            }
+
+            annotated.push_str(&format!("{}\n", file_tag.bold()));
+
+            for (line_num, source_line) in (1..).zip(file.lines()) {
+                let line = match file_lines.get(&line_num) {
+                    Some(line) => line,
+                    None => {
+                        annotated.push_str(&format!("{:>10}:{:>5}: {}\n", "-", line_num, source_line));
+                        continue;
+                    }
+                };
+
+                if line.executed == 0 {
+                    annotated.push_str(&format!(
+                        "{}:{:>5}: {}\n",
+                        format!("{:>10}", "#####").red().bold(),
+                        line_num,
+                        source_line,
+                    ));
+                    continue;
+                }
+
+                let mut notes = vec![];
+
+                if line.instructions_executed < line.instructions {
+                    notes.push(format!("{} of {} instructions executed", line.instructions_executed, line.instructions));
+                }
+
+                for branch in line.branches.iter() {
+                    if branch.taken == 0 {
+                        notes.push(String::from("branch never taken"));
+                    } else if branch.not_taken == 0 {
+                        notes.push(String::from("branch always taken"));
+                    }
+                }
+
+                let notes = notes.into_iter()
+                    .map(|note| format!("  [{}]", note).yellow().bold().to_string())
+                    .collect::<String>();
+
+                annotated.push_str(&format!("{:>10}:{:>5}: {}{}\n", line.executed, line_num, source_line, notes));
+            }
+        }
+
+        annotated.push_str(&format!("\n{}\n", self.summary()));
+
+        annotated
+    }
+
+    /// # A one-line summary of how many lines and branches were covered.
+    pub fn summary(&self) -> String {
+        let lines = self.lines();
+
+        let lines_hit = lines.iter().filter(|line| line.executed > 0).count();
+
+        let branches = lines.iter().flat_map(|line| line.branches.iter());
+        let branches_found = 2 * branches.clone().count();
+        let branches_hit = branches
+            .map(|branch| (branch.taken > 0) as usize + (branch.not_taken > 0) as usize)
+            .sum::<usize>();
+
+        format!(
+            "lines: {} of {} executed ({}), branches: {} of {} covered ({})",
+            lines_hit.to_string().bold(),
+            lines.len(),
+            percentage(lines_hit, lines.len()),
+            branches_hit.to_string().bold(),
+            branches_found,
+            percentage(branches_hit, branches_found),
+        )
+    }
+}
+
+fn percentage(hit: usize, found: usize) -> String {
+    match found {
+        0 => String::from("100.0%"),
+        _ => format!("{:.1}%", 100.0 * hit as f64 / found as f64),
+    }
+}
@@ -0,0 +1,745 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use crate::{MipsyError, Register, Safe};
+use super::{Runtime, RuntimeSyscallGuard, State};
+
+/// # Breakpoints and watchpoints for a [`Runtime`].
+///
+/// A debugger is kept alongside a runtime by the frontend, and
+/// passed to [`Runtime::run_until`], which runs the program until
+/// one of its breakpoints or watchpoints is hit.
+///
+/// Every breakpoint and watchpoint is given a unique id when it's
+/// inserted, which is never reused by the same debugger.
+#[derive(Debug, Default)]
+pub struct Debugger {
+    breakpoints: BTreeMap<u32, Breakpoint>,
+    watchpoints: BTreeMap<u32, Watchpoint>,
+    next_id: u32,
+    pending: Option<Snapshot>,
+    /// Where the program last stopped, whose breakpoint
+    /// isn't hit again when the program is resumed.
+    stopped_at: Option<u32>,
+}
+
+/// # A breakpoint on an instruction address.
+///
+/// Execution stops when the program is about to execute the
+/// instruction at `addr`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoint {
+    pub id: u32,
+    pub addr: u32,
+    pub enabled: bool,
+    /// Only stop if this condition holds.
+    pub condition: Option<Condition>,
+    /// How many hits to skip before stopping.
+    pub ignore_count: u32,
+    /// How many times the breakpoint has been hit, including ignored hits.
+    pub hits: u32,
+}
+
+/// # A watchpoint on a register, a range of memory, or a condition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub id: u32,
+    pub target: WatchTarget,
+    pub enabled: bool,
+    /// Only stop if this condition holds.
+    pub condition: Option<Condition>,
+    /// How many hits to skip before stopping.
+    pub ignore_count: u32,
+    /// How many times the watchpoint has been hit, including ignored hits.
+    pub hits: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WatchTarget {
+    /// Stops when the register changes value.
+    Register(Register),
+    /// Stops on accesses to `len` bytes of memory starting at `addr`.
+    Memory { addr: u32, len: u32, kind: WatchKind },
+    /// Stops when the condition goes from false to true.
+    Condition(Condition),
+}
+
+/// # Which memory accesses a watchpoint stops on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WatchKind {
+    /// Any change in value, whether by a store or a syscall.
+    Write,
+    /// A load from the range.
+    Read,
+    /// A load from or store to the range, or any change in value.
+    Access,
+}
+
+/// The value of a watchpoint's target at some point in time.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WatchValue {
+    Register(Safe<i32>),
+    Memory(Vec<Safe<u8>>),
+    Condition(bool),
+}
+
+/// # Why [`Runtime::run_until`] stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    Breakpoint {
+        id: u32,
+        addr: u32,
+    },
+    Watchpoint {
+        id: u32,
+        /// The address of the instruction that triggered the watchpoint.
+        inst_addr: u32,
+        old: WatchValue,
+        new: WatchValue,
+    },
+}
+
+/// # The result of [`Runtime::run_until`].
+pub enum StoppedRuntime {
+    /// The program made a syscall, which the frontend must resolve.
+    /// Once it has, [`Debugger::check_pending`] reports any breakpoints
+    /// or watchpoints the syscall triggered.
+    Guard(RuntimeSyscallGuard),
+    /// One or more breakpoints or watchpoints were hit.
+    Debugger(Runtime, Vec<StopReason>),
+    /// The maximum number of steps was reached.
+    StepLimit(Runtime),
+}
+
+/// # Where [`Runtime::rewind_until`] stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rewound {
+    /// At a state that one or more breakpoints or watchpoints stopped at.
+    Debugger(Vec<StopReason>),
+    /// At the start of the retained history, without hitting anything.
+    HistoryStart,
+}
+
+/// # A comparison between two values in the runtime state.
+///
+/// Conditions are written as `lhs op rhs`, where `op` is one of
+/// `==`, `!=`, `<`, `<=`, `>` or `>=`, and each side is a register
+/// (`$t0`, `$8`, `$pc`, `$hi`, `$lo`) or an integer (`5`, `-1`, `0x10`).
+/// Comparisons are signed, and a condition involving an uninitialised
+/// register is never true.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub lhs: Operand,
+    pub op: CmpOp,
+    pub rhs: Operand,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Register(Register),
+    Pc,
+    Hi,
+    Lo,
+    Immediate(i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConditionError {
+    /// There is no comparison operator.
+    MissingOperator,
+    /// One side of the comparison is neither a register nor an integer.
+    BadOperand { operand: String },
+}
+
+/// The state of everything being watched, just before an instruction is executed.
+#[derive(Debug)]
+struct Snapshot {
+    pc: u32,
+    access: Option<MemoryAccess>,
+    values: BTreeMap<u32, WatchValue>,
+}
+
+/// A load or store performed by an instruction.
+#[derive(Debug, Clone, Copy)]
+struct MemoryAccess {
+    addr: u32,
+    len: u32,
+    write: bool,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.breakpoints.values()
+    }
+
+    pub fn breakpoint(&self, addr: u32) -> Option<&Breakpoint> {
+        self.breakpoints.get(&addr)
+    }
+
+    pub fn breakpoint_mut(&mut self, addr: u32) -> Option<&mut Breakpoint> {
+        self.breakpoints.get_mut(&addr)
+    }
+
+    /// Inserts a breakpoint at `addr`, returning its id,
+    /// or `None` if there is already a breakpoint there.
+    pub fn insert_breakpoint(&mut self, addr: u32) -> Option<u32> {
+        if self.breakpoints.contains_key(&addr) {
+            return None;
+        }
+
+        let id = self.next_id();
+
+        self.breakpoints.insert(addr, Breakpoint {
+            id,
+            addr,
+            enabled: true,
+            condition: None,
+            ignore_count: 0,
+            hits: 0,
+        });
+
+        Some(id)
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u32) -> Option<Breakpoint> {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = &Watchpoint> {
+        self.watchpoints.values()
+    }
+
+    pub fn watchpoint(&self, id: u32) -> Option<&Watchpoint> {
+        self.watchpoints.get(&id)
+    }
+
+    pub fn watchpoint_mut(&mut self, id: u32) -> Option<&mut Watchpoint> {
+        self.watchpoints.get_mut(&id)
+    }
+
+    /// Inserts a watchpoint, returning its id.
+    pub fn insert_watchpoint(&mut self, target: WatchTarget) -> u32 {
+        let id = self.next_id();
+
+        self.watchpoints.insert(id, Watchpoint {
+            id,
+            target,
+            enabled: true,
+            condition: None,
+            ignore_count: 0,
+            hits: 0,
+        });
+
+        id
+    }
+
+    pub fn remove_watchpoint(&mut self, id: u32) -> Option<Watchpoint> {
+        self.watchpoints.remove(&id)
+    }
+
+    /// Removes every breakpoint and watchpoint.
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+        self.discard_pending();
+    }
+
+    /// Forgets the breakpoints and watchpoints triggered by the last syscall,
+    /// without checking them. The frontend must do this whenever it moves the
+    /// runtime to another state (eg. by resetting or rewinding it), as they
+    /// would otherwise be checked against that state on the next run.
+    ///
+    /// A breakpoint at the state it moved to is then hit before anything runs.
+    pub fn discard_pending(&mut self) {
+        self.pending = None;
+        self.stopped_at = None;
+    }
+
+    /// Checks the breakpoints and watchpoints triggered by the
+    /// last syscall, once the frontend has resolved its guard.
+    pub fn check_pending(&mut self, runtime: &Runtime) -> Vec<StopReason> {
+        match self.pending.take() {
+            Some(snapshot) => {
+                self.stopped_at = Some(runtime.timeline().state().pc());
+
+                self.check(&snapshot, runtime.timeline().state())
+            }
+            None => vec![],
+        }
+    }
+
+    fn next_id(&mut self) -> u32 {
+        self.next_id += 1;
+
+        self.next_id
+    }
+
+    fn has_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    fn snapshot(&self, state: &State) -> Snapshot {
+        let values = self.watchpoints.values()
+            .filter(|watchpoint| watchpoint.enabled)
+            .map(|watchpoint| (watchpoint.id, watchpoint.target.value(state)))
+            .collect();
+
+        Snapshot {
+            pc: state.pc(),
+            access: memory_access(state),
+            values,
+        }
+    }
+
+    /// Checks which breakpoints and watchpoints were hit going from
+    /// `snapshot` to `state`, counting their hits.
+    fn check(&mut self, snapshot: &Snapshot, state: &State) -> Vec<StopReason> {
+        self.triggered(snapshot, state)
+            .into_iter()
+            .filter(|reason| {
+                let (hits, ignore_count) = match *reason {
+                    StopReason::Breakpoint { addr, .. } => {
+                        let breakpoint = self.breakpoints.get_mut(&addr).expect("breakpoint was just triggered");
+
+                        (&mut breakpoint.hits, breakpoint.ignore_count)
+                    }
+                    StopReason::Watchpoint { id, .. } => {
+                        let watchpoint = self.watchpoints.get_mut(&id).expect("watchpoint was just triggered");
+
+                        (&mut watchpoint.hits, watchpoint.ignore_count)
+                    }
+                };
+
+                *hits += 1;
+
+                *hits > ignore_count
+            })
+            .collect()
+    }
+
+    /// Which breakpoints and watchpoints are triggered going from `snapshot` to `state`,
+    /// regardless of their hit counts.
+    fn triggered(&self, snapshot: &Snapshot, state: &State) -> Vec<StopReason> {
+        let mut reasons = vec![];
+
+        for watchpoint in self.watchpoints.values() {
+            if !watchpoint.enabled {
+                continue;
+            }
+
+            // inserted while a syscall was pending
+            let old = match snapshot.values.get(&watchpoint.id) {
+                Some(old) => old.clone(),
+                None => continue,
+            };
+
+            let new = watchpoint.target.value(state);
+
+            let triggered = match (&watchpoint.target, &old, &new) {
+                (WatchTarget::Condition(_), WatchValue::Condition(old), WatchValue::Condition(new)) => !old && *new,
+                (&WatchTarget::Memory { addr, len, kind }, _, _) => {
+                    let accessed = snapshot.access
+                        .filter(|access| access.addr < addr.wrapping_add(len) && addr < access.addr.wrapping_add(access.len));
+
+                    match kind {
+                        WatchKind::Write  => old != new,
+                        WatchKind::Read   => accessed.map(|access| !access.write).unwrap_or(false),
+                        WatchKind::Access => accessed.is_some() || old != new,
+                    }
+                }
+                _ => old != new,
+            };
+
+            if triggered && holds(&watchpoint.condition, state) {
+                reasons.push(StopReason::Watchpoint {
+                    id: watchpoint.id,
+                    inst_addr: snapshot.pc,
+                    old,
+                    new,
+                });
+            }
+        }
+
+        if let Some(breakpoint) = self.breakpoints.get(&state.pc()) {
+            if breakpoint.enabled && holds(&breakpoint.condition, state) {
+                reasons.push(StopReason::Breakpoint {
+                    id: breakpoint.id,
+                    addr: breakpoint.addr,
+                });
+            }
+        }
+
+        reasons
+    }
+}
+
+fn holds(condition: &Option<Condition>, state: &State) -> bool {
+    condition.as_ref()
+        .map(|condition| condition.evaluate(state))
+        .unwrap_or(true)
+}
+
+impl WatchTarget {
+    pub fn value(&self, state: &State) -> WatchValue {
+        match *self {
+            WatchTarget::Register(register) => {
+                WatchValue::Register(state.read_register_uninit(register.to_u32()))
+            }
+            WatchTarget::Memory { addr, len, .. } => {
+                let bytes = (0..len)
+                    .map(|offset| {
+                        state.read_mem_byte_uninit(addr.wrapping_add(offset))
+                            .unwrap_or(Safe::Uninitialised)
+                    })
+                    .collect();
+
+                WatchValue::Memory(bytes)
+            }
+            WatchTarget::Condition(condition) => {
+                WatchValue::Condition(condition.evaluate(state))
+            }
+        }
+    }
+}
+
+impl Condition {
+    pub fn evaluate(&self, state: &State) -> bool {
+        let (lhs, rhs) = match (self.lhs.value(state), self.rhs.value(state)) {
+            (Some(lhs), Some(rhs)) => (lhs, rhs),
+            _ => return false,
+        };
+
+        match self.op {
+            CmpOp::Eq => lhs == rhs,
+            CmpOp::Ne => lhs != rhs,
+            CmpOp::Lt => lhs <  rhs,
+            CmpOp::Le => lhs <= rhs,
+            CmpOp::Gt => lhs >  rhs,
+            CmpOp::Ge => lhs >= rhs,
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = ConditionError;
+
+    fn from_str(condition: &str) -> Result<Self, Self::Err> {
+        // two-character operators first, so that `<=` isn't read as `<`
+        const OPERATORS: [(&str, CmpOp); 6] = [
+            ("==", CmpOp::Eq),
+            ("!=", CmpOp::Ne),
+            ("<=", CmpOp::Le),
+            (">=", CmpOp::Ge),
+            ("<",  CmpOp::Lt),
+            (">",  CmpOp::Gt),
+        ];
+
+        let (lhs, op, rhs) = OPERATORS.iter()
+            .find_map(|&(symbol, op)| {
+                condition.split_once(symbol)
+                    .map(|(lhs, rhs)| (lhs, op, rhs))
+            })
+            .ok_or(ConditionError::MissingOperator)?;
+
+        Ok(Self {
+            lhs: lhs.parse()?,
+            op,
+            rhs: rhs.parse()?,
+        })
+    }
+}
+
+impl Operand {
+    fn value(&self, state: &State) -> Option<i32> {
+        match *self {
+            Operand::Register(register) => state.read_register(register.to_u32()).ok(),
+            Operand::Pc => Some(state.pc() as i32),
+            Operand::Hi => state.read_hi().ok(),
+            Operand::Lo => state.read_lo().ok(),
+            Operand::Immediate(value) => Some(value),
+        }
+    }
+}
+
+impl FromStr for Operand {
+    type Err = ConditionError;
+
+    fn from_str(operand: &str) -> Result<Self, Self::Err> {
+        let operand = operand.trim();
+
+        let bad_operand = || ConditionError::BadOperand { operand: operand.to_string() };
+
+        if let Some(name) = operand.strip_prefix('$') {
+            return match name.to_ascii_lowercase().as_str() {
+                "pc" => Ok(Operand::Pc),
+                "hi" => Ok(Operand::Hi),
+                "lo" => Ok(Operand::Lo),
+                _ => Register::from_str(name)
+                    .map(Operand::Register)
+                    .map_err(|_| bad_operand()),
+            };
+        }
+
+        let (negative, digits) = match operand.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, operand),
+        };
+
+        let value = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => digits.parse::<u32>(),
+        }.map_err(|_| bad_operand())? as i32;
+
+        Ok(Operand::Immediate(if negative { value.wrapping_neg() } else { value }))
+    }
+}
+
+/// The load or store that the next instruction will perform, if any.
+fn memory_access(state: &State) -> Option<MemoryAccess> {
+    let inst = state.read_mem_word(state.pc()).ok()?;
+
+    let opcode = inst >> 26;
+    let rs     = (inst >> 21) & 0x1F;
+    let imm    = (inst & 0xFFFF) as i16 as i32;
+
+    let (len, write) = match opcode {
+        // LB, LBU
+        0x20 | 0x24 => (1, false),
+        // LH, LHU
+        0x21 | 0x25 => (2, false),
+        // LW, LWC1
+        0x23 | 0x31 => (4, false),
+        // LDC1
+        0x35 => (8, false),
+        // SB
+        0x28 => (1, true),
+        // SH
+        0x29 => (2, true),
+        // SW, SWC1
+        0x2B | 0x39 => (4, true),
+        // SDC1
+        0x3D => (8, true),
+        _ => return None,
+    };
+
+    let addr = state.read_register(rs).ok()?.wrapping_add(imm) as u32;
+
+    Some(MemoryAccess { addr, len, write })
+}
+
+impl Runtime {
+    /// Runs the program until it makes a syscall, hits a breakpoint or
+    /// watchpoint in `debugger`, or has executed `max_steps` instructions.
+    ///
+    /// Breakpoints and watchpoints triggered by a syscall are reported by
+    /// [`Debugger::check_pending`] - if the frontend doesn't check them,
+    /// the next call to `run_until` reports them without stepping.
+    ///
+    /// A breakpoint on the first instruction is hit before it runs,
+    /// unless the program already stopped there.
+    pub fn run_until(mut self, debugger: &mut Debugger, max_steps: Option<u64>) -> Result<StoppedRuntime, (Runtime, MipsyError)> {
+        if debugger.has_pending() {
+            let reasons = debugger.check_pending(&self);
+
+            if !reasons.is_empty() {
+                return Ok(StoppedRuntime::Debugger(self, reasons));
+            }
+        }
+
+        let pc = self.timeline.state().pc();
+        if debugger.stopped_at.take() != Some(pc) {
+            // no watchpoint has a snapshot value, so only a breakpoint at `pc` can be hit
+            let snapshot = Snapshot { pc, access: None, values: BTreeMap::new() };
+            let reasons = debugger.check(&snapshot, self.timeline.state());
+
+            if !reasons.is_empty() {
+                debugger.stopped_at = Some(pc);
+
+                return Ok(StoppedRuntime::Debugger(self, reasons));
+            }
+        }
+
+        let mut steps = 0;
+
+        loop {
+            if max_steps.map(|max_steps| steps >= max_steps).unwrap_or(false) {
+                debugger.stopped_at = Some(self.timeline.state().pc());
+
+                return Ok(StoppedRuntime::StepLimit(self));
+            }
+
+            let snapshot = debugger.snapshot(self.timeline.state());
+
+            match self.step()? {
+                Ok(runtime) => {
+                    let reasons = debugger.check(&snapshot, runtime.timeline.state());
+
+                    if !reasons.is_empty() {
+                        debugger.stopped_at = Some(runtime.timeline.state().pc());
+
+                        return Ok(StoppedRuntime::Debugger(runtime, reasons));
+                    }
+
+                    self = runtime;
+                }
+                Err(guard) => {
+                    debugger.pending = Some(snapshot);
+
+                    return Ok(StoppedRuntime::Guard(guard));
+                }
+            }
+
+            steps += 1;
+        }
+    }
+}
+
+impl Runtime {
+    /// Steps backwards to the last state that a breakpoint or watchpoint in
+    /// `debugger` would have stopped at, had the program been run forwards.
+    /// Hit counts are neither checked nor updated, and anything pending
+    /// in `debugger` is discarded if the program steps back at all.
+    pub fn rewind_until(&mut self, debugger: &mut Debugger) -> Rewound {
+        let start = self.timeline.history_start();
+        let current = self.timeline.timeline_len() - 1;
+
+        // the step from state i - 1 to state i, excluding the current state
+        for i in (start + 1..current).rev() {
+            let before = self.timeline.nth_state(i - 1).unwrap();
+            let after  = self.timeline.nth_state(i).unwrap();
+
+            let reasons = debugger.triggered(&debugger.snapshot(before), after);
+
+            if !reasons.is_empty() {
+                self.timeline.rewind_to(i);
+                debugger.discard_pending();
+                debugger.stopped_at = Some(self.timeline.state().pc());
+
+                return Rewound::Debugger(reasons);
+            }
+        }
+
+        if start < current {
+            debugger.discard_pending();
+        }
+
+        self.timeline.rewind_to(start);
+
+        Rewound::HistoryStart
+    }
+
+    /// Steps backwards to the `jal` or `jalr` that called the current function,
+    /// returning false (without stepping back) if the call is not in the retained history.
+    pub fn rewind_to_call(&mut self) -> bool {
+        let start = self.timeline.history_start();
+        let current = self.timeline.timeline_len() - 1;
+
+        let depth = self.timeline.state().call_stack().depth();
+
+        if depth == 0 {
+            return false;
+        }
+
+        // the last state before the current function was called
+        let call = (start..current)
+            .rev()
+            .find(|&i| self.timeline.nth_state(i).unwrap().call_stack().depth() < depth);
+
+        match call {
+            Some(i) => {
+                self.timeline.rewind_to(i);
+
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{KTEXT_BOT, runtime::tests::binary};
+    use super::*;
+
+    #[test]
+    fn test_discard_pending() {
+        // ori $s0, $zero, 7
+        // ori $v0, $zero, 10
+        // syscall
+        let binary = binary(&[0x34100007, 0x3402000A, 0x0000000C]);
+        let mut debugger = Debugger::new();
+        let id = debugger.insert_watchpoint(WatchTarget::Register(Register::S0));
+
+        let set_s0 = StopReason::Watchpoint {
+            id,
+            inst_addr: KTEXT_BOT,
+            old: WatchValue::Register(Safe::Uninitialised),
+            new: WatchValue::Register(Safe::Valid(7)),
+        };
+
+        let Ok(StoppedRuntime::Debugger(runtime, reasons)) = Runtime::new(&binary, &[]).run_until(&mut debugger, None) else {
+            panic!("expected to stop at the watchpoint");
+        };
+        assert_eq!(reasons, vec![set_s0.clone()]);
+
+        let Ok(StoppedRuntime::Guard(RuntimeSyscallGuard::Exit(mut runtime))) = runtime.run_until(&mut debugger, None) else {
+            panic!("expected to stop at the exit syscall");
+        };
+
+        // the exit syscall's snapshot has $s0 = 7, which the reset
+        // state would otherwise be compared to
+        debugger.discard_pending();
+        runtime.reset();
+
+        let Ok(StoppedRuntime::Debugger(_, reasons)) = runtime.run_until(&mut debugger, None) else {
+            panic!("expected to stop at the watchpoint");
+        };
+        assert_eq!(reasons, vec![set_s0]);
+        assert_eq!(debugger.watchpoint(id).unwrap().hits, 2);
+    }
+
+    #[test]
+    fn test_breakpoint_at_entry() {
+        // ori $s0, $zero, 7
+        // ori $v0, $zero, 10
+        // syscall
+        let binary = binary(&[0x34100007, 0x3402000A, 0x0000000C]);
+        let mut debugger = Debugger::new();
+        let id = debugger.insert_breakpoint(KTEXT_BOT).unwrap();
+
+        let at_entry = StopReason::Breakpoint { id, addr: KTEXT_BOT };
+
+        let Ok(StoppedRuntime::Debugger(runtime, reasons)) = Runtime::new(&binary, &[]).run_until(&mut debugger, None) else {
+            panic!("expected to stop at the breakpoint");
+        };
+        assert_eq!(reasons, vec![at_entry.clone()]);
+        assert_eq!(runtime.timeline().state().pc(), KTEXT_BOT);
+
+        // resuming from the breakpoint doesn't hit it again
+        let Ok(StoppedRuntime::Guard(RuntimeSyscallGuard::Exit(mut runtime))) = runtime.run_until(&mut debugger, None) else {
+            panic!("expected to stop at the exit syscall");
+        };
+
+        debugger.discard_pending();
+        runtime.reset();
+
+        let Ok(StoppedRuntime::Debugger(_, reasons)) = runtime.run_until(&mut debugger, None) else {
+            panic!("expected to stop at the breakpoint");
+        };
+        assert_eq!(reasons, vec![at_entry]);
+        assert_eq!(debugger.breakpoint(KTEXT_BOT).unwrap().hits, 2);
+    }
+}
@@ -0,0 +1,168 @@
+//! # Exceptions, and the coprocessor 0 registers that describe them.
+//!
+//! With exceptions enabled (see [`Runtime::set_exceptions`]), the runtime
+//! errors that a MIPS processor raises as exceptions - bad addresses,
+//! overflow, unknown instructions - and the `break` and trap instructions
+//! jump to the handler at [`EXCEPTION_HANDLER`], if the program put one there
+//! with `.ktext 0x80000180`. The handler finds out what went wrong from the
+//! Cause, EPC and BadVAddr registers, and returns with `eret`.
+//!
+//! An exception raised while one is being handled isn't caught,
+//! and stops the program like any other runtime error.
+
+use serde::{Serialize, Deserialize};
+
+use crate::{KTEXT_BOT, MipsyError, Safe, error::runtime::Error, inst::register::{COP0_BAD_VADDR, COP0_CAUSE, COP0_EPC, COP0_STATUS}};
+use super::{PAGE_SIZE, Runtime, RuntimeSyscallGuard, SteppedRuntime};
+
+/// The address that every exception jumps to.
+pub const EXCEPTION_HANDLER: u32 = KTEXT_BOT + 0x180;
+
+/// The exception level bit of the Status register, set while an exception is handled.
+pub const STATUS_EXL: u32 = 1 << 1;
+
+/// # The registers of coprocessor 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cop0 {
+    /// The address that caused the last address error.
+    pub bad_vaddr: u32,
+    pub status: u32,
+    /// The code of the last exception, in bits 2 to 6.
+    pub cause: u32,
+    /// The address of the instruction that caused the last exception.
+    pub epc: u32,
+}
+
+impl Cop0 {
+    pub fn register(&self, reg_num: u32) -> Option<u32> {
+        match reg_num {
+            COP0_BAD_VADDR => Some(self.bad_vaddr),
+            COP0_STATUS    => Some(self.status),
+            COP0_CAUSE     => Some(self.cause),
+            COP0_EPC       => Some(self.epc),
+            _ => None,
+        }
+    }
+
+    pub fn register_mut(&mut self, reg_num: u32) -> Option<&mut u32> {
+        match reg_num {
+            COP0_BAD_VADDR => Some(&mut self.bad_vaddr),
+            COP0_STATUS    => Some(&mut self.status),
+            COP0_CAUSE     => Some(&mut self.cause),
+            COP0_EPC       => Some(&mut self.epc),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exception {
+    AddressLoad { addr: u32 },
+    AddressStore { addr: u32 },
+    Breakpoint,
+    ReservedInstruction,
+    Overflow,
+    Trap,
+}
+
+impl Exception {
+    /// The exception code, as it's put in the Cause register.
+    pub fn code(&self) -> u32 {
+        match self {
+            Exception::AddressLoad { .. }  => 4,
+            Exception::AddressStore { .. } => 5,
+            Exception::Breakpoint          => 9,
+            Exception::ReservedInstruction => 10,
+            Exception::Overflow            => 12,
+            Exception::Trap                => 13,
+        }
+    }
+
+    /// The exception that the instruction `inst` raises
+    /// instead of failing with `error`, if there is one.
+    fn from_error(error: &Error, inst: u32) -> Option<Self> {
+        match *error {
+            Error::SegmentationFault { addr }
+            | Error::UnalignedAccess { addr, .. } => {
+                // SB, SH, SWL, SW, SWR, SC, SWC1 and SDC1
+                if matches!(inst >> 26, 0x28..=0x2B | 0x2E | 0x38 | 0x39 | 0x3D) {
+                    Some(Exception::AddressStore { addr })
+                } else {
+                    Some(Exception::AddressLoad { addr })
+                }
+            }
+            Error::IntegerOverflow => Some(Exception::Overflow),
+            Error::UnknownInstruction { .. } => Some(Exception::ReservedInstruction),
+            _ => None,
+        }
+    }
+}
+
+impl Runtime {
+    /// Whether the errors and instructions that raise an exception
+    /// jump to the program's exception handler.
+    pub fn exceptions(&self) -> bool {
+        self.exceptions
+    }
+
+    pub fn set_exceptions(&mut self, exceptions: bool) {
+        self.exceptions = exceptions;
+    }
+
+    /// Whether an exception raised now would be handled: exceptions must be
+    /// enabled, the program must have a handler, and it can't already be in it.
+    pub(super) fn handles_exceptions(&self) -> bool {
+        let state = self.timeline.state();
+
+        // looked up directly, as user code can't read the kernel's memory
+        let offset = (EXCEPTION_HANDLER % PAGE_SIZE) as usize;
+        let has_handler = state.get_page(EXCEPTION_HANDLER)
+            .is_some_and(|page| page[offset..offset + 4].iter().all(|byte| matches!(byte, Safe::Valid(_))));
+
+        self.exceptions
+            && state.cop0.status & STATUS_EXL == 0
+            && has_handler
+    }
+
+    /// The exception that the instruction `inst` raises instead of
+    /// failing with `err`, if there is one and it would be handled.
+    pub(super) fn exception_for(&self, err: &MipsyError, inst: u32) -> Option<Exception> {
+        match err {
+            MipsyError::Runtime(err) if self.handles_exceptions() => Exception::from_error(err.error(), inst),
+            _ => None,
+        }
+    }
+
+    /// Raises an exception from the instruction at `epc` in the current state,
+    /// jumping to the exception handler.
+    pub(super) fn raise(&mut self, exception: Exception, epc: u32) {
+        let state = self.timeline.state_mut();
+
+        if let Exception::AddressLoad { addr } | Exception::AddressStore { addr } = exception {
+            state.cop0.bad_vaddr = addr;
+        }
+
+        state.cop0.epc = epc;
+        state.cop0.cause = exception.code() << 2;
+        state.cop0.status |= STATUS_EXL;
+        state.set_pc(EXCEPTION_HANDLER);
+    }
+
+    /// Raises the exception for a `break` or trap instruction at `pc`,
+    /// if it would be handled, or otherwise gives back its guard.
+    pub(super) fn raise_guard(guard: RuntimeSyscallGuard, pc: u32) -> SteppedRuntime {
+        match guard {
+            RuntimeSyscallGuard::Breakpoint(mut runtime) if runtime.handles_exceptions() => {
+                runtime.raise(Exception::Breakpoint, pc);
+
+                Ok(runtime)
+            }
+            RuntimeSyscallGuard::Trap(mut runtime) if runtime.handles_exceptions() => {
+                runtime.raise(Exception::Trap, pc);
+
+                Ok(runtime)
+            }
+            guard => Err(guard),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use mipsy_utils::Limits;
+
+use crate::{HEAP_BOT, MipsyError, MipsyResult, Register, RuntimeError, STACK_BOT, STACK_PTR, error::runtime::{Error, Limit}};
+use super::Runtime;
+
+/// How often, in instructions, the clock is checked against the time limit.
+/// Must be a power of two.
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// # The resources a runtime has used so far.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Usage {
+    pub(super) steps: u64,
+    pub(super) output: u64,
+    /// When the first instruction was run, by [`Usage::clock`].
+    started_ms: Option<u64>,
+    clock: Option<fn() -> u64>,
+}
+
+impl Usage {
+    pub(super) fn new() -> Self {
+        Self {
+            clock: default_clock(),
+            ..Default::default()
+        }
+    }
+
+    pub(super) fn reset(&mut self) {
+        self.steps = 0;
+        self.output = 0;
+        self.started_ms = None;
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn default_clock() -> Option<fn() -> u64> {
+    fn system_clock() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_millis() as u64)
+    }
+
+    Some(system_clock)
+}
+
+// std::time isn't available in the browser
+#[cfg(target_arch = "wasm32")]
+fn default_clock() -> Option<fn() -> u64> {
+    None
+}
+
+impl Runtime {
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// # Sets the resource limits of the program.
+    ///
+    /// Going over a limit is a [`Error::LimitExceeded`] runtime error.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// # Sets where the current time comes from, in milliseconds.
+    ///
+    /// Only the difference between two times is used, so the clock can
+    /// start from any point. Without a clock, the time limit is ignored -
+    /// which is the default on `wasm32`.
+    pub fn set_clock(&mut self, clock: fn() -> u64) {
+        self.usage.clock = Some(clock);
+    }
+
+    /// The number of instructions the program has run.
+    pub fn steps(&self) -> u64 {
+        self.usage.steps
+    }
+
+    /// Checks the instruction and time limits, before another instruction runs.
+    pub(super) fn check_step_limits(&mut self) -> MipsyResult<()> {
+        if let Some(max) = self.limits.max_steps {
+            if self.usage.steps >= max {
+                return Err(limit_exceeded(Limit::Steps(max)));
+            }
+        }
+
+        if let (Some(max), Some(clock)) = (self.limits.max_time_ms, self.usage.clock) {
+            if self.usage.steps & (CLOCK_INTERVAL - 1) == 0 {
+                let now = clock();
+                let started = *self.usage.started_ms.get_or_insert(now);
+
+                if now.saturating_sub(started) > max {
+                    return Err(limit_exceeded(Limit::TimeMs(max)));
+                }
+            }
+        }
+
+        self.usage.steps += 1;
+
+        Ok(())
+    }
+
+    /// Counts `bytes` towards the output limit.
+    pub(super) fn count_output(&mut self, bytes: usize) -> MipsyResult<()> {
+        self.usage.output += bytes as u64;
+
+        match self.limits.max_output {
+            Some(max) if self.usage.output > max => Err(limit_exceeded(Limit::Output(max))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that the heap can grow to `heap_size` bytes.
+    ///
+    /// Even without a limit, the heap can't grow into the stack.
+    pub(super) fn check_heap_size(&self, heap_size: u32) -> MipsyResult<()> {
+        let max = self.limits.max_heap
+            .map_or(STACK_BOT - HEAP_BOT, |max| max.min(STACK_BOT - HEAP_BOT));
+
+        if heap_size > max {
+            return Err(limit_exceeded(Limit::Heap(max)));
+        }
+
+        Ok(())
+    }
+
+    /// Checks the stack limit against the `$sp` of the state just produced.
+    pub(super) fn check_stack_size(mut self) -> Result<Runtime, (Runtime, MipsyError)> {
+        let max = match self.limits.max_stack {
+            Some(max) => max,
+            None => return Ok(self),
+        };
+
+        let sp = self.timeline.state().read_register_uninit(Register::Sp.to_u32()).into_option();
+
+        match sp {
+            Some(sp) if sp as u32 <= STACK_PTR && STACK_PTR - sp as u32 > max => {
+                self.timeline.pop_last_state();
+
+                Err((self, limit_exceeded(Limit::Stack(max))))
+            }
+            _ => Ok(self),
+        }
+    }
+}
+
+fn limit_exceeded(limit: Limit) -> MipsyError {
+    MipsyError::Runtime(RuntimeError::new(Error::LimitExceeded { limit }))
+}
@@ -1,11 +1,27 @@
 mod unsafe_cow;
 pub mod state;
+pub mod debugger;
+pub mod backtrace;
+pub mod call;
+mod limits;
+pub mod profile;
+pub mod coverage;
+pub mod exception;
+mod saved;
 
 pub use self::state::State;
-
-use std::collections::HashMap;
+pub use self::debugger::{Debugger, Rewound, StoppedRuntime, StopReason};
+pub use self::backtrace::{CALLEE_SAVED, CallStack, Frame};
+pub use self::call::CALL_RETURN_ADDR;
+pub use self::profile::{Counts, FunctionProfile, Profile};
+pub use self::coverage::{BranchCoverage, Coverage, InstCoverage, LineCoverage};
+pub use self::exception::{Cop0, EXCEPTION_HANDLER, Exception};
+
+use std::collections::{HashMap, HashSet};
+use mipsy_utils::Limits;
 use crate::{Binary, DATA_BOT, HEAP_BOT, KDATA_BOT, KTEXT_BOT, MipsyError, MipsyResult, Register, RuntimeError, STACK_PTR, Safe, TEXT_BOT, Uninitialised, error::runtime::{AlignmentRequirement, Error}, compile::GLOBAL_PTR};
 use self::state::Timeline;
+use self::limits::Usage;
 
 pub const NUL:  u8  = 0;
 pub const NULL: u32 = 0;
@@ -32,6 +48,8 @@ pub const SYS17_EXIT_STATUS: i32 = 17;
 pub const SPECIAL:  u32 = 0b000000;
 pub const SPECIAL2: u32 = 0b011100;
 pub const SPECIAL3: u32 = 0b011111;
+pub const COP0:     u32 = 0b010000;
+pub const COP1:     u32 = 0b010001;
 
 macro_rules! try_owned_self {
     ($self:ident, $res:expr) => {
@@ -44,6 +62,12 @@ macro_rules! try_owned_self {
 
 pub struct Runtime {
     timeline: Timeline,
+    no_uninit_check: HashSet<u32>,
+    check_calling_convention: bool,
+    limits: Limits,
+    usage: Usage,
+    profile: Option<Profile>,
+    exceptions: bool,
 }
 
 impl Runtime {
@@ -55,32 +79,113 @@ impl Runtime {
         &mut self.timeline
     }
 
+    /// # Resets the program to its initial state.
+    ///
+    /// Unlike [`Timeline::reset`], this also forgets the resources
+    /// the program has used so far, and restarts its profile.
+    pub fn reset(&mut self) {
+        self.timeline.reset();
+        self.usage.reset();
+
+        if self.profile.is_some() {
+            self.set_profiling(true);
+        }
+    }
+
+    pub fn check_calling_convention(&self) -> bool {
+        self.check_calling_convention
+    }
+
+    /// Whether returning from a function without restoring
+    /// its callee-saved registers is a runtime error.
+    pub fn set_check_calling_convention(&mut self, check_calling_convention: bool) {
+        self.check_calling_convention = check_calling_convention;
+    }
+
     pub fn step(mut self) -> Result<SteppedRuntime, (Runtime, MipsyError)> {
-        let state = self.timeline.push_next_state();
+        // uninitialised values that a #[no_uninit_check] instruction
+        // has tried to read, which it will instead read as zero
+        let mut zeroed: Vec<Uninitialised> = vec![];
+
+        try_owned_self!(self, self.check_step_limits());
 
-        let inst = match state.read_mem_word(state.pc()) {
-            Ok(inst) => inst,
-            Err(_) => {
-                let addr = state.pc();
+        loop {
+            let state = self.timeline.push_next_state();
 
-                return Err((self, MipsyError::Runtime(RuntimeError::new(Error::UnknownInstruction { addr }))));
+            for value in zeroed.iter() {
+                zero_uninitialised(state, value);
             }
-        };
 
-        state.set_pc(state.pc() + 4);
+            let pc = state.pc();
 
-        match self.execute_in_current_state(inst) {
-            Err((mut new_self, err)) => {
-                new_self.timeline.pop_last_state();
+            let inst = match state.read_mem_word(pc) {
+                Ok(inst) => inst,
+                Err(_) if self.handles_exceptions() => {
+                    self.raise(Exception::AddressLoad { addr: pc }, pc);
 
-                Err((new_self, err))
+                    return Ok(Ok(self));
+                }
+                Err(_) => {
+                    return Err((self, MipsyError::Runtime(RuntimeError::new(Error::UnknownInstruction { addr: pc }))));
+                }
+            };
+
+            self.timeline.state_mut().set_pc(pc + 4);
+
+            // an instruction retried with zeroed values has already been counted
+            if zeroed.is_empty() {
+                self.profile_inst(pc, inst);
+            }
+
+            match self.execute_in_current_state(inst) {
+                Err((mut new_self, err)) => {
+                    new_self.timeline.pop_last_state();
+
+                    if new_self.no_uninit_check.contains(&pc) {
+                        if let MipsyError::Runtime(ref runtime_error) = err {
+                            if let Error::Uninitialised { value } = runtime_error.error() {
+                                if !zeroed.contains(value) {
+                                    zeroed.push(value.clone());
+                                    self = new_self;
+
+                                    continue;
+                                }
+                            }
+                        }
+                    }
+
+                    if let Some(exception) = new_self.exception_for(&err, inst) {
+                        new_self.timeline.push_next_state();
+                        new_self.raise(exception, pc);
+
+                        return Ok(Ok(new_self));
+                    }
+
+                    new_self.profile_failed_step();
+
+                    return Err((new_self, err));
+                }
+                Ok(Ok(mut new_self)) => {
+                    new_self.profile_branch(pc, inst);
+
+                    let stepped = match new_self.check_stack_size() {
+                        Ok(new_self) => new_self.track_call(inst, pc),
+                        Err(err) => Err(err),
+                    };
+
+                    return stepped.map_err(|(mut new_self, err)| {
+                        new_self.profile_failed_step();
+
+                        (new_self, err)
+                    });
+                }
+                Ok(Err(guard)) => return Ok(Runtime::raise_guard(guard, pc)),
             }
-            ok => ok,
         }
     }
 
     pub fn exec_inst(mut self, opcode: u32) -> Result<SteppedRuntime, (Runtime, MipsyError)> {
-        self.timeline.push_next_state();
+        let pc = self.timeline.push_next_state().pc();
 
         match self.execute_in_current_state(opcode) {
             Err((mut new_self, err)) => {
@@ -88,10 +193,27 @@ impl Runtime {
 
                 Err((new_self, err))
             }
+            Ok(Ok(new_self)) => new_self.track_call(opcode, pc),
             ok => ok,
         }
     }
 
+    /// Updates the call stack of the state `inst` just produced,
+    /// first checking the calling convention if that's enabled.
+    fn track_call(mut self, inst: u32, inst_addr: u32) -> Result<SteppedRuntime, (Runtime, MipsyError)> {
+        if self.check_calling_convention {
+            if let Err(err) = self.timeline.state().check_calling_convention(inst) {
+                self.timeline.pop_last_state();
+
+                return Err((self, err));
+            }
+        }
+
+        self.timeline.state_mut().update_call_stack(inst, inst_addr);
+
+        Ok(Ok(self))
+    }
+
     pub fn next_inst(&self) -> MipsyResult<u32> {
         let state = self.timeline().state();
 
@@ -131,6 +253,18 @@ impl Runtime {
 
                 Ok(Ok(self))
             }
+            COP0 => {
+                // Coprocessor 0 (exceptions)
+                try_owned_self!(self, self.execute_cop0(rs, rt, rd, funct));
+
+                Ok(Ok(self))
+            }
+            COP1 => {
+                // Coprocessor 1 (FPU)
+                try_owned_self!(self, self.execute_cop1(rs, rt, rd, shamt, funct, imm));
+
+                Ok(Ok(self))
+            }
             _ => {
                 // I-Type
                 self.execute_i(opcode, rs, rt, imm)
@@ -138,8 +272,6 @@ impl Runtime {
         }
     }
 
-    // remove when floating point syscalls are finished
-    #[allow(unreachable_code)]
     fn syscall(mut self) -> Result<RuntimeSyscallGuard, (Runtime, MipsyError)> {
         let syscall = try_owned_self!(self, self.timeline.state().read_register(Register::V0.to_u32()));
     
@@ -147,6 +279,7 @@ impl Runtime {
             match syscall {
                 SYS1_PRINT_INT => {
                     let value = try_owned_self!(self, self.timeline.state().read_register(Register::A0.to_u32()));
+                    try_owned_self!(self, self.count_output(value.to_string().len()));
 
                     RuntimeSyscallGuard::PrintInt(
                         PrintIntArgs {
@@ -155,22 +288,33 @@ impl Runtime {
                         self
                     )
                 }
-                SYS2_PRINT_FLOAT => RuntimeSyscallGuard::PrintFloat(
-                    PrintFloatArgs {
-                        value: todo!(),
-                    },
-                    self
-                ),
-                SYS3_PRINT_DOUBLE => RuntimeSyscallGuard::PrintDouble(
-                    PrintDoubleArgs {
-                        value: todo!(),
-                    },
-                    self
-                ),
+                SYS2_PRINT_FLOAT => {
+                    let value = try_owned_self!(self, self.timeline.state().read_fp_single(12));
+                    try_owned_self!(self, self.count_output(value.to_string().len()));
+
+                    RuntimeSyscallGuard::PrintFloat(
+                        PrintFloatArgs {
+                            value,
+                        },
+                        self
+                    )
+                }
+                SYS3_PRINT_DOUBLE => {
+                    let value = try_owned_self!(self, self.timeline.state().read_fp_double(12));
+                    try_owned_self!(self, self.count_output(value.to_string().len()));
+
+                    RuntimeSyscallGuard::PrintDouble(
+                        PrintDoubleArgs {
+                            value,
+                        },
+                        self
+                    )
+                }
                 SYS4_PRINT_STRING => {
                     let value = try_owned_self!(self, self.timeline.state().read_mem_string(
                         try_owned_self!(self, self.timeline.state().read_register(Register::A0.to_u32())) as _
                     ));
+                    try_owned_self!(self, self.count_output(value.len()));
 
                     RuntimeSyscallGuard::PrintString(
                         PrintStringArgs {
@@ -186,10 +330,17 @@ impl Runtime {
                     })
                 ),
                 SYS6_READ_FLOAT => RuntimeSyscallGuard::ReadFloat(
-                    todo!()
+                    Box::new(move |value| {
+                        self.timeline.state_mut().write_fp_single(0, value);
+                        self
+                    })
                 ),
                 SYS7_READ_DOUBLE => RuntimeSyscallGuard::ReadDouble(
-                    todo!()
+                    Box::new(move |value| {
+                        // $f0 is even, so this can't fail
+                        self.timeline.state_mut().write_fp_double(0, value).unwrap();
+                        self
+                    })
                 ),
                 SYS8_READ_STRING => {
                     let buf = try_owned_self!(self, self.timeline.state().read_register(Register::A0.to_u32())) as u32;
@@ -223,6 +374,10 @@ impl Runtime {
                     let bytes = try_owned_self!(self, self.timeline.state().read_register(Register::A0.to_u32()));
                     let heap_size = self.timeline.state().heap_size();
 
+                    if bytes > 0 {
+                        try_owned_self!(self, self.check_heap_size(heap_size.saturating_add(bytes as _)));
+                    }
+
                     self.timeline.state_mut().write_register(Register::V0.to_u32(), (HEAP_BOT + heap_size) as _);
 
                     if bytes > 0 {
@@ -241,12 +396,17 @@ impl Runtime {
                 SYS10_EXIT => RuntimeSyscallGuard::Exit(
                     self
                 ),
-                SYS11_PRINT_CHAR => RuntimeSyscallGuard::PrintChar(
-                    PrintCharArgs {
-                        value: try_owned_self!(self, self.timeline.state().read_register(Register::A0.to_u32())) as _,
-                    },
-                    self
-                ),
+                SYS11_PRINT_CHAR => {
+                    let value = try_owned_self!(self, self.timeline.state().read_register(Register::A0.to_u32())) as _;
+                    try_owned_self!(self, self.count_output(1));
+
+                    RuntimeSyscallGuard::PrintChar(
+                        PrintCharArgs {
+                            value,
+                        },
+                        self
+                    )
+                }
                 SYS12_READ_CHAR => RuntimeSyscallGuard::ReadChar(
                     Box::new(move |value| {
                         self.timeline.state_mut().write_register(Register::V0.to_u32(), value as _);
@@ -293,11 +453,16 @@ impl Runtime {
                     let fd  = try_owned_self!(self, self.timeline.state().read_register(Register::A0.to_u32())) as _;
                     let buf = try_owned_self!(self, self.timeline.state().read_register(Register::A1.to_u32())) as _;
                     let len = try_owned_self!(self, self.timeline.state().read_register(Register::A2.to_u32())) as _;
+                    let buf = try_owned_self!(self, self.timeline.state().read_mem_bytes(buf, len));
+
+                    if fd == 1 || fd == 2 {
+                        try_owned_self!(self, self.count_output(buf.len()));
+                    }
 
                     RuntimeSyscallGuard::Write(
                         WriteArgs {
                             fd,
-                            buf: try_owned_self!(self, self.timeline.state().read_mem_bytes(buf, len)),
+                            buf,
                         },
                         Box::new(move |written| {
                             self.timeline.state_mut().write_register(Register::V0.to_u32(), written as _);
@@ -964,8 +1129,16 @@ impl Runtime {
             // Unused
             0x30 => {},
             
-            // LWC1 $Rt, Im($Rs)
-            0x31 => { todo!() },
+            // LWC1 $Ft, Im($Rs)
+            0x31 => {
+                let addr = state.read_register(rs)?.wrapping_add(imm_sign_extend) as _;
+
+                if addr % 4 != 0 {
+                    return Err(MipsyError::Runtime(RuntimeError::new(Error::UnalignedAccess { addr, alignment_requirement: AlignmentRequirement::Word })));
+                }
+
+                state.write_fp_register_uninit(rt, state.read_mem_word_uninit(addr)?);
+            },
             
             // Unused
             0x32 => {},
@@ -976,8 +1149,21 @@ impl Runtime {
             // Unused
             0x34 => {},
             
-            // Unused
-            0x35 => {},
+            // LDC1 $Ft, Im($Rs)
+            0x35 => {
+                let addr = state.read_register(rs)?.wrapping_add(imm_sign_extend) as _;
+
+                if addr % 8 != 0 {
+                    return Err(MipsyError::Runtime(RuntimeError::new(Error::UnalignedAccess { addr, alignment_requirement: AlignmentRequirement::Double })));
+                }
+
+                let value = match (state.read_mem_word_uninit(addr)?, state.read_mem_word_uninit(addr + 4)?) {
+                    (Safe::Valid(lower), Safe::Valid(upper)) => Safe::Valid(((upper as u64) << 32) | lower as u64),
+                    _ => Safe::Uninitialised,
+                };
+
+                state.write_fp_double_uninit(rt, value)?;
+            },
             
             // Unused
             0x36 => {},
@@ -988,8 +1174,16 @@ impl Runtime {
             // Unused
             0x38 => {},
             
-            // SWC1 $Rt, Im($Rs)
-            0x39 => { todo!() },
+            // SWC1 $Ft, Im($Rs)
+            0x39 => {
+                let addr = state.read_register(rs)?.wrapping_add(imm_sign_extend) as _;
+
+                if addr % 4 != 0 {
+                    return Err(MipsyError::Runtime(RuntimeError::new(Error::UnalignedAccess { addr, alignment_requirement: AlignmentRequirement::Word })));
+                }
+
+                state.write_mem_word_uninit(addr, state.read_fp_register_uninit(rt))?;
+            },
             
             // Unused
             0x3A => {},
@@ -1000,8 +1194,22 @@ impl Runtime {
             // Unused
             0x3C => {},
             
-            // Unused
-            0x3D => {},
+            // SDC1 $Ft, Im($Rs)
+            0x3D => {
+                let addr = state.read_register(rs)?.wrapping_add(imm_sign_extend) as _;
+
+                if addr % 8 != 0 {
+                    return Err(MipsyError::Runtime(RuntimeError::new(Error::UnalignedAccess { addr, alignment_requirement: AlignmentRequirement::Double })));
+                }
+
+                let (lower, upper) = match state.read_fp_double_uninit(rt)? {
+                    Safe::Valid(value)  => (Safe::Valid(value as u32), Safe::Valid((value >> 32) as u32)),
+                    Safe::Uninitialised => (Safe::Uninitialised, Safe::Uninitialised),
+                };
+
+                state.write_mem_word_uninit(addr,     lower)?;
+                state.write_mem_word_uninit(addr + 4, upper)?;
+            },
             
             // Unused
             0x3E => {},
@@ -1016,6 +1224,153 @@ impl Runtime {
         Ok(())
     }
 
+    fn execute_cop0(&mut self, rs: u32, rt: u32, rd: u32, funct: u32) -> MipsyResult<()> {
+        let state = self.timeline.state_mut();
+
+        match (rs, funct) {
+            // MFC0 $Rt, $Cd
+            (0x00, 0x00) => { state.write_register(rt, state.read_cop0_register(rd)? as _); },
+
+            // MTC0 $Rt, $Cd
+            (0x04, 0x00) => { state.write_cop0_register(rd, state.read_register(rt)? as _)?; },
+
+            // ERET
+            (0x10, 0x18) => {
+                state.cop0.status &= !exception::STATUS_EXL;
+                state.set_pc(state.cop0.epc);
+            }
+
+            _ => return Err(unknown_instruction(state)),
+        }
+
+        Ok(())
+    }
+
+    fn execute_cop1(&mut self, fmt: u32, ft: u32, fs: u32, fd: u32, funct: u32, imm: i16) -> MipsyResult<()> {
+        let state = self.timeline.state_mut();
+
+        match fmt {
+            // MFC1 $Rt, $Fs
+            0x00 => { state.write_register_uninit(ft, state.read_fp_register_uninit(fs).extend_sign()); },
+
+            // MTC1 $Rt, $Fs
+            0x04 => { state.write_fp_register_uninit(fs, state.read_register_uninit(ft).truncate()); },
+
+            // BC1F / BC1T Im
+            0x08 => {
+                let cc = ft >> 2;
+                let branch_on_true = ft & 1 != 0;
+
+                if state.read_fp_condition_flag(cc) == branch_on_true {
+                    state.branch(imm);
+                }
+            }
+
+            // Single precision
+            0x10 => match funct {
+                // ADD.S $Fd, $Fs, $Ft
+                0x00 => { state.write_fp_single(fd, state.read_fp_single(fs)? + state.read_fp_single(ft)?); },
+
+                // SUB.S $Fd, $Fs, $Ft
+                0x01 => { state.write_fp_single(fd, state.read_fp_single(fs)? - state.read_fp_single(ft)?); },
+
+                // MUL.S $Fd, $Fs, $Ft
+                0x02 => { state.write_fp_single(fd, state.read_fp_single(fs)? * state.read_fp_single(ft)?); },
+
+                // DIV.S $Fd, $Fs, $Ft
+                0x03 => { state.write_fp_single(fd, state.read_fp_single(fs)? / state.read_fp_single(ft)?); },
+
+                // SQRT.S $Fd, $Fs
+                0x04 => { state.write_fp_single(fd, state.read_fp_single(fs)?.sqrt()); },
+
+                // ABS.S $Fd, $Fs
+                0x05 => { state.write_fp_single(fd, state.read_fp_single(fs)?.abs()); },
+
+                // MOV.S $Fd, $Fs
+                0x06 => { state.write_fp_register_uninit(fd, state.read_fp_register_uninit(fs)); },
+
+                // NEG.S $Fd, $Fs
+                0x07 => { state.write_fp_single(fd, -state.read_fp_single(fs)?); },
+
+                // CVT.D.S $Fd, $Fs
+                0x21 => { state.write_fp_double(fd, state.read_fp_single(fs)? as f64)?; },
+
+                // CVT.W.S $Fd, $Fs
+                0x24 => { state.write_fp_register(fd, float_to_word(state.read_fp_single(fs)? as f64) as u32); },
+
+                // C.EQ.S $Fs, $Ft
+                0x32 => { state.write_fp_condition_flag(fd >> 2, state.read_fp_single(fs)? == state.read_fp_single(ft)?); },
+
+                // C.LT.S $Fs, $Ft
+                0x3C => { state.write_fp_condition_flag(fd >> 2, state.read_fp_single(fs)? <  state.read_fp_single(ft)?); },
+
+                // C.LE.S $Fs, $Ft
+                0x3E => { state.write_fp_condition_flag(fd >> 2, state.read_fp_single(fs)? <= state.read_fp_single(ft)?); },
+
+                _ => return Err(unknown_instruction(state)),
+            },
+
+            // Double precision
+            0x11 => match funct {
+                // ADD.D $Fd, $Fs, $Ft
+                0x00 => { state.write_fp_double(fd, state.read_fp_double(fs)? + state.read_fp_double(ft)?)?; },
+
+                // SUB.D $Fd, $Fs, $Ft
+                0x01 => { state.write_fp_double(fd, state.read_fp_double(fs)? - state.read_fp_double(ft)?)?; },
+
+                // MUL.D $Fd, $Fs, $Ft
+                0x02 => { state.write_fp_double(fd, state.read_fp_double(fs)? * state.read_fp_double(ft)?)?; },
+
+                // DIV.D $Fd, $Fs, $Ft
+                0x03 => { state.write_fp_double(fd, state.read_fp_double(fs)? / state.read_fp_double(ft)?)?; },
+
+                // SQRT.D $Fd, $Fs
+                0x04 => { state.write_fp_double(fd, state.read_fp_double(fs)?.sqrt())?; },
+
+                // ABS.D $Fd, $Fs
+                0x05 => { state.write_fp_double(fd, state.read_fp_double(fs)?.abs())?; },
+
+                // MOV.D $Fd, $Fs
+                0x06 => { state.write_fp_double_uninit(fd, state.read_fp_double_uninit(fs)?)?; },
+
+                // NEG.D $Fd, $Fs
+                0x07 => { state.write_fp_double(fd, -state.read_fp_double(fs)?)?; },
+
+                // CVT.S.D $Fd, $Fs
+                0x20 => { state.write_fp_single(fd, state.read_fp_double(fs)? as f32); },
+
+                // CVT.W.D $Fd, $Fs
+                0x24 => { state.write_fp_register(fd, float_to_word(state.read_fp_double(fs)?) as u32); },
+
+                // C.EQ.D $Fs, $Ft
+                0x32 => { state.write_fp_condition_flag(fd >> 2, state.read_fp_double(fs)? == state.read_fp_double(ft)?); },
+
+                // C.LT.D $Fs, $Ft
+                0x3C => { state.write_fp_condition_flag(fd >> 2, state.read_fp_double(fs)? <  state.read_fp_double(ft)?); },
+
+                // C.LE.D $Fs, $Ft
+                0x3E => { state.write_fp_condition_flag(fd >> 2, state.read_fp_double(fs)? <= state.read_fp_double(ft)?); },
+
+                _ => return Err(unknown_instruction(state)),
+            },
+
+            // Word (fixed point)
+            0x14 => match funct {
+                // CVT.S.W $Fd, $Fs
+                0x20 => { state.write_fp_single(fd, state.read_fp_register(fs)? as i32 as f32); },
+
+                // CVT.D.W $Fd, $Fs
+                0x21 => { state.write_fp_double(fd, state.read_fp_register(fs)? as i32 as f64)?; },
+
+                _ => return Err(unknown_instruction(state)),
+            },
+
+            _ => return Err(unknown_instruction(state)),
+        }
+
+        Ok(())
+    }
+
     fn execute_j(&mut self, opcode: u32, target: u32) {
         let state = self.timeline.state_mut();
 
@@ -1370,6 +1725,11 @@ impl Runtime {
                 write_marker: 0,
                 hi: Default::default(),
                 lo: Default::default(),
+                fp_registers: Default::default(),
+                fp_write_marker: 0,
+                fp_condition_flags: 0,
+                call_stack: CallStack::default(),
+                cop0: Cop0::default(),
             };
 
         let mut text_addr = TEXT_BOT;
@@ -1413,6 +1773,12 @@ impl Runtime {
 
         Self {
             timeline: Timeline::new(initial_state),
+            no_uninit_check: program.no_uninit_check.clone(),
+            check_calling_convention: false,
+            limits: Limits::default(),
+            usage: Usage::new(),
+            profile: None,
+            exceptions: false,
         }
     }
 
@@ -1468,6 +1834,45 @@ impl Runtime {
     }
 }
 
+/// Initialises an uninitialised value to zero, for instructions that
+/// are marked `#[no_uninit_check]`.
+fn zero_uninitialised(state: &mut State, value: &Uninitialised) {
+    let (addr, len) = match *value {
+        Uninitialised::Register { reg_num } => return state.write_register(reg_num, 0),
+        Uninitialised::FloatRegister { reg_num } => return state.write_fp_register(reg_num, 0),
+        Uninitialised::Hi => return state.write_hi(0),
+        Uninitialised::Lo => return state.write_lo(0),
+        Uninitialised::Byte { addr } => (addr, 1),
+        Uninitialised::Half { addr } => (addr, 2),
+        Uninitialised::Word { addr } => (addr, 4),
+    };
+
+    for addr in addr..addr + len {
+        if let Ok(Safe::Uninitialised) = state.read_mem_byte_uninit(addr) {
+            // can't fail, as reading the same address didn't segfault
+            state.write_mem_byte(addr, 0).unwrap();
+        }
+    }
+}
+
+fn unknown_instruction(state: &State) -> MipsyError {
+    MipsyError::Runtime(RuntimeError::new(Error::UnknownInstruction { addr: state.pc().wrapping_sub(4) }))
+}
+
+/// Converts to a word the way the FPU does with its default
+/// (round to nearest) rounding mode: ties go to the even value,
+/// and NaN or out of range values become the invalid-operation
+/// result `0x7FFFFFFF`.
+fn float_to_word(value: f64) -> i32 {
+    let rounded = value.round_ties_even();
+
+    if rounded.is_nan() || rounded < i32::MIN as f64 || rounded > i32::MAX as f64 {
+        i32::MAX
+    } else {
+        rounded as i32
+    }
+}
+
 fn checked_add(x: i32, y: i32) -> MipsyResult<i32> {
     match x.checked_add(y) {
         Some(z) => Ok(z),
@@ -1481,3 +1886,243 @@ fn checked_sub(x: i32, y: i32) -> MipsyResult<i32> {
         None => Err(MipsyError::Runtime(RuntimeError::new(Error::IntegerOverflow))),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use linked_hash_map::LinkedHashMap;
+
+    use super::*;
+
+    /// A binary that runs `insts` from the start of the kernel text.
+    pub(super) fn binary(insts: &[u32]) -> Binary {
+        Binary {
+            text: vec![],
+            data: vec![],
+            ktext: insts.iter().flat_map(|inst| inst.to_le_bytes()).map(Safe::Valid).collect(),
+            kdata: vec![],
+            labels: LinkedHashMap::new(),
+            scopes: LinkedHashMap::new(),
+            constants: HashMap::new(),
+            globals: vec![],
+            line_numbers: HashMap::new(),
+            warnings: vec![],
+            expected_exit_code: None,
+            no_uninit_check: HashSet::new(),
+            scope: None,
+            in_ktext: false,
+        }
+    }
+
+    /// Runs the first `steps` instructions of `insts`.
+    fn run_steps(insts: &[u32], steps: usize) -> Runtime {
+        let mut runtime = Runtime::new(&binary(insts), &[]);
+
+        for _ in 0..steps {
+            runtime = match runtime.step() {
+                Ok(Ok(runtime)) => runtime,
+                _ => panic!("expected every instruction to run"),
+            };
+        }
+
+        runtime
+    }
+
+    fn run(insts: &[u32]) -> Runtime {
+        run_steps(insts, insts.len())
+    }
+
+    /// Runs `insts` up to the syscall they end with.
+    fn syscall_guard(insts: &[u32]) -> RuntimeSyscallGuard {
+        assert_eq!(insts.last(), Some(&SYSCALL));
+
+        match run_steps(insts, insts.len() - 1).step() {
+            Ok(Err(guard)) => guard,
+            _ => panic!("expected a syscall"),
+        }
+    }
+
+    const SYSCALL: u32 = 0x0000000C;
+
+    fn lui(rt: Register, imm: u16) -> u32 {
+        0x3C000000 | rt.to_u32() << 16 | imm as u32
+    }
+
+    pub(super) fn ori(rt: Register, imm: u16) -> u32 {
+        0x34000000 | rt.to_u32() << 16 | imm as u32
+    }
+
+    fn mtc1(rt: Register, fs: u32) -> u32 {
+        0x44800000 | rt.to_u32() << 16 | fs << 11
+    }
+
+    fn mfc1(rt: Register, fs: u32) -> u32 {
+        0x44000000 | rt.to_u32() << 16 | fs << 11
+    }
+
+    fn cop1(fmt: u32, funct: u32, fd: u32, fs: u32, ft: u32) -> u32 {
+        0x44000000 | fmt << 21 | ft << 16 | fs << 11 | fd << 6 | funct
+    }
+
+    fn bc1(on_true: bool, offset: i16) -> u32 {
+        0x45000000 | (on_true as u32) << 16 | offset as u16 as u32
+    }
+
+    const S: u32 = 0x10;
+    const D: u32 = 0x11;
+    const W: u32 = 0x14;
+
+    /// Loads the single-precision `value`, which must have an empty low half, into `$f{fs}`.
+    fn li_s(fs: u32, value: f32) -> [u32; 2] {
+        let bits = value.to_bits();
+        assert_eq!(bits & 0xFFFF, 0);
+
+        [lui(Register::T9, (bits >> 16) as u16), mtc1(Register::T9, fs)]
+    }
+
+    #[test]
+    fn test_fpu_arithmetic() {
+        let insts = [
+            &li_s(2, 1.5)[..],
+            &li_s(4, 2.25),
+            &[
+                cop1(S, 0x00, 6,  2,  4),  // add.s   $f6,  $f2,  $f4
+                cop1(S, 0x01, 8,  2,  4),  // sub.s   $f8,  $f2,  $f4
+                cop1(S, 0x02, 10, 2,  4),  // mul.s   $f10, $f2,  $f4
+                cop1(S, 0x03, 12, 4,  2),  // div.s   $f12, $f4,  $f2
+                cop1(S, 0x07, 14, 2,  0),  // neg.s   $f14, $f2
+                cop1(S, 0x21, 16, 6,  0),  // cvt.d.s $f16, $f6
+                ori(Register::T0, 7),      // ori     $t0,  $zero, 7
+                mtc1(Register::T0, 18),    // mtc1    $t0,  $f18
+                cop1(W, 0x21, 20, 18, 0),  // cvt.d.w $f20, $f18
+                cop1(D, 0x00, 22, 16, 20), // add.d   $f22, $f16, $f20
+                cop1(D, 0x02, 24, 22, 20), // mul.d   $f24, $f22, $f20
+                cop1(D, 0x20, 26, 22, 0),  // cvt.s.d $f26, $f22
+                cop1(D, 0x24, 28, 22, 0),  // cvt.w.d $f28, $f22
+                mfc1(Register::T1, 28),    // mfc1    $t1,  $f28
+                cop1(W, 0x20, 30, 18, 0),  // cvt.s.w $f30, $f18
+            ],
+        ].concat();
+
+        let runtime = run(&insts);
+        let state = runtime.timeline().state();
+
+        assert_eq!(state.read_fp_single(6).unwrap(), 3.75);
+        assert_eq!(state.read_fp_single(8).unwrap(), -0.75);
+        assert_eq!(state.read_fp_single(10).unwrap(), 3.375);
+        assert_eq!(state.read_fp_single(12).unwrap(), 1.5);
+        assert_eq!(state.read_fp_single(14).unwrap(), -1.5);
+        assert_eq!(state.read_fp_double(16).unwrap(), 3.75);
+        assert_eq!(state.read_fp_double(20).unwrap(), 7.0);
+        assert_eq!(state.read_fp_double(22).unwrap(), 10.75);
+        assert_eq!(state.read_fp_double(24).unwrap(), 75.25);
+        assert_eq!(state.read_fp_single(26).unwrap(), 10.75);
+        assert_eq!(state.read_register(Register::T1.to_u32()).unwrap(), 11);
+        assert_eq!(state.read_fp_single(30).unwrap(), 7.0);
+    }
+
+    #[test]
+    fn test_fpu_convert_rounding() {
+        let insts = [
+            &li_s(2, 2.5)[..],
+            &li_s(4, -3.5),
+            &li_s(6, f32::NAN),
+            &[
+                cop1(S, 0x24, 8,  2, 0), // cvt.w.s $f8,  $f2
+                cop1(S, 0x24, 10, 4, 0), // cvt.w.s $f10, $f4
+                cop1(S, 0x24, 12, 6, 0), // cvt.w.s $f12, $f6
+            ],
+        ].concat();
+
+        let runtime = run(&insts);
+        let state = runtime.timeline().state();
+
+        // ties go to the even value, and NaN is the invalid-operation result
+        assert_eq!(state.read_fp_register(8).unwrap() as i32, 2);
+        assert_eq!(state.read_fp_register(10).unwrap() as i32, -4);
+        assert_eq!(state.read_fp_register(12).unwrap() as i32, i32::MAX);
+    }
+
+    #[test]
+    fn test_fpu_compare_and_branch() {
+        let insts = [
+            &li_s(2, 1.5)[..],
+            &li_s(4, 2.25),
+            &[
+                cop1(S, 0x3C, 0, 2, 4),   // c.lt.s $f2, $f4
+                bc1(true, 2),             // bc1t   skip_s0
+                ori(Register::S0, 1),
+                                          // skip_s0:
+                cop1(S, 0x32, 0, 2, 4),   // c.eq.s $f2, $f4
+                bc1(false, 2),            // bc1f   skip_s1
+                ori(Register::S1, 1),
+                                          // skip_s1:
+                cop1(S, 0x21, 6, 2, 0),   // cvt.d.s $f6, $f2
+                cop1(S, 0x21, 8, 4, 0),   // cvt.d.s $f8, $f4
+                cop1(D, 0x3E, 0, 8, 6),   // c.le.d $f8, $f6
+                bc1(true, 2),             // bc1t   skip_s2
+                ori(Register::S2, 1),
+                                          // skip_s2:
+            ],
+        ].concat();
+
+        let mut runtime = Runtime::new(&binary(&insts), &[]);
+        while runtime.timeline().state().pc() < KTEXT_BOT + 4 * insts.len() as u32 {
+            runtime = match runtime.step() {
+                Ok(Ok(runtime)) => runtime,
+                _ => panic!("expected every instruction to run"),
+            };
+        }
+
+        let state = runtime.timeline().state();
+
+        assert!(state.read_register_uninit(Register::S0.to_u32()).as_option().is_none());
+        assert!(state.read_register_uninit(Register::S1.to_u32()).as_option().is_none());
+        assert_eq!(state.read_register(Register::S2.to_u32()).unwrap(), 1);
+        assert!(!state.read_fp_condition_flag(0));
+    }
+
+    #[test]
+    fn test_fpu_syscalls() {
+        let RuntimeSyscallGuard::PrintFloat(args, _) = syscall_guard(&[
+            &li_s(12, 1.5)[..],
+            &[ori(Register::V0, SYS2_PRINT_FLOAT as u16), SYSCALL],
+        ].concat()) else {
+            panic!("expected print_float");
+        };
+        assert_eq!(args.value, 1.5);
+
+        let RuntimeSyscallGuard::PrintDouble(args, _) = syscall_guard(&[
+            &li_s(2, -2.25)[..],
+            &[
+                cop1(S, 0x21, 12, 2, 0), // cvt.d.s $f12, $f2
+                ori(Register::V0, SYS3_PRINT_DOUBLE as u16),
+                SYSCALL,
+            ],
+        ].concat()) else {
+            panic!("expected print_double");
+        };
+        assert_eq!(args.value, -2.25);
+
+        let RuntimeSyscallGuard::ReadFloat(guard) = syscall_guard(&[ori(Register::V0, SYS6_READ_FLOAT as u16), SYSCALL]) else {
+            panic!("expected read_float");
+        };
+        assert_eq!(guard(0.5).timeline().state().read_fp_single(0).unwrap(), 0.5);
+
+        let RuntimeSyscallGuard::ReadDouble(guard) = syscall_guard(&[ori(Register::V0, SYS7_READ_DOUBLE as u16), SYSCALL]) else {
+            panic!("expected read_double");
+        };
+        assert_eq!(guard(0.1).timeline().state().read_fp_double(0).unwrap(), 0.1);
+    }
+
+    #[test]
+    fn test_odd_float_register() {
+        let setup = [&li_s(2, 1.5)[..], &li_s(3, 1.5)].concat();
+        // add.d $f4, $f3, $f2
+        let insts = [&setup[..], &[cop1(D, 0x00, 4, 3, 2)]].concat();
+
+        let Err((_, MipsyError::Runtime(error))) = run_steps(&insts, setup.len()).step() else {
+            panic!("expected an error");
+        };
+        assert_eq!(error.error(), &Error::OddFloatRegister { reg_num: 3 });
+    }
+}
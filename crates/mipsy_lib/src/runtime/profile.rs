@@ -0,0 +1,523 @@
+use std::{cmp::Reverse, collections::{BTreeMap, HashMap, VecDeque}, rc::Rc};
+
+use colored::Colorize;
+
+use crate::{Binary, KTEXT_BOT, error::runtime::function_name};
+use super::{COP1, Runtime, SPECIAL};
+
+/// # How many of each kind of instruction were executed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub executed: u64,
+    pub loads: u64,
+    pub stores: u64,
+    pub branches: u64,
+    pub branches_taken: u64,
+    pub syscalls: u64,
+}
+
+impl Counts {
+    fn add(&mut self, other: &Counts) {
+        self.executed       += other.executed;
+        self.loads          += other.loads;
+        self.stores         += other.stores;
+        self.branches       += other.branches;
+        self.branches_taken += other.branches_taken;
+        self.syscalls       += other.syscalls;
+    }
+
+    fn sub(&mut self, other: &Counts) {
+        self.executed       -= other.executed;
+        self.loads          -= other.loads;
+        self.stores         -= other.stores;
+        self.branches       -= other.branches;
+        self.branches_taken -= other.branches_taken;
+        self.syscalls       -= other.syscalls;
+    }
+}
+
+/// # The instructions executed by a single function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionProfile {
+    /// The address the function was called at.
+    pub entry: u32,
+    /// Instructions executed in the function itself.
+    pub exclusive: Counts,
+    /// Instructions executed in the function, or in
+    /// any function it called, while it was running.
+    pub inclusive: u64,
+}
+
+/// # Counts of the instructions a program has executed.
+///
+/// Instructions are counted by address, and each is attributed to the
+/// function on top of the call stack (see [`CallStack`](super::CallStack))
+/// when it was executed. Instructions executed before any function was
+/// called are attributed to the label they started at - usually `_start`.
+///
+/// When the program steps backwards, the instructions it steps back
+/// over are no longer counted.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    instructions: HashMap<u32, Counts>,
+    /// Exclusive counts, by function entry.
+    exclusive: HashMap<u32, Counts>,
+    /// Inclusive counts of the functions that have returned, by function entry.
+    inclusive: HashMap<u32, u64>,
+    /// The functions currently being executed, with
+    /// how many instructions had executed when each was called.
+    stack: Vec<(u32, u64)>,
+    /// Where execution began, outside of any function.
+    root: Option<u32>,
+    total: Counts,
+    /// The steps that can still be stepped back over, oldest first.
+    steps: VecDeque<ProfiledStep>,
+}
+
+/// # What a single step added to a [`Profile`].
+#[derive(Debug, Clone)]
+struct ProfiledStep {
+    /// The position in the timeline of the state the step produced.
+    position: usize,
+    addr: u32,
+    function: u32,
+    counts: Counts,
+    /// Whether the step was the first, outside of any function.
+    root: bool,
+    /// The functions that had returned by the step, innermost first,
+    /// with when each was called and what it added to its inclusive count.
+    returned: Vec<(u32, u64, u64)>,
+    /// How many functions had been called by the step.
+    called: usize,
+}
+
+impl Profile {
+    pub fn total(&self) -> Counts {
+        self.total
+    }
+
+    /// The counts of each instruction executed, by address.
+    pub fn instructions(&self) -> &HashMap<u32, Counts> {
+        &self.instructions
+    }
+
+    /// # The counts of each source line executed, in source order.
+    ///
+    /// Instructions generated from a pseudo-instruction are counted
+    /// towards its line. Instructions without a line, such as those in
+    /// the kernel, aren't included.
+    pub fn lines(&self, binary: &Binary) -> Vec<((Rc<str>, u32), Counts)> {
+        let line_table = LineTable::new(binary);
+
+        let mut lines: BTreeMap<(Rc<str>, u32), Counts> = BTreeMap::new();
+
+        for (&addr, counts) in self.instructions.iter() {
+            if let Some(line) = line_table.line(addr) {
+                lines.entry(line.clone())
+                    .or_default()
+                    .add(counts);
+            }
+        }
+
+        lines.into_iter().collect()
+    }
+
+    /// # The counts of each function executed, most inclusive first.
+    pub fn functions(&self) -> Vec<FunctionProfile> {
+        let mut functions = self.exclusive.iter()
+            .map(|(&entry, &exclusive)| FunctionProfile {
+                entry,
+                exclusive,
+                inclusive: self.inclusive_count(entry),
+            })
+            .collect::<Vec<_>>();
+
+        functions.sort_by(|a, b| b.inclusive.cmp(&a.inclusive).then(a.entry.cmp(&b.entry)));
+
+        functions
+    }
+
+    fn inclusive_count(&self, entry: u32) -> u64 {
+        if self.root == Some(entry) {
+            return self.total.executed;
+        }
+
+        let returned = self.inclusive.get(&entry).copied().unwrap_or(0);
+
+        // only the outermost call of a recursive function counts
+        let running = self.stack.iter()
+            .find(|&&(running, _)| running == entry)
+            .map(|&(_, start)| self.total.executed - start)
+            .unwrap_or(0);
+
+        returned + running
+    }
+
+    /// # A text report of the profile.
+    ///
+    /// Shows the totals, every function, and the `max_lines` lines
+    /// that executed the most instructions.
+    pub fn report(&self, binary: &Binary, source_code: &[(Rc<str>, Rc<str>)], max_lines: usize) -> String {
+        let mut report = String::new();
+
+        let total = self.total;
+        report.push_str(&format!("{} instructions executed\n", total.executed.to_string().bold()));
+        report.push_str(&format!(" - loads:    {}\n", total.loads.to_string().bold()));
+        report.push_str(&format!(" - stores:   {}\n", total.stores.to_string().bold()));
+        report.push_str(&format!(" - branches: {} ({} taken)\n", total.branches.to_string().bold(), total.branches_taken.to_string().bold()));
+        report.push_str(&format!(" - syscalls: {}\n", total.syscalls.to_string().bold()));
+
+        report.push_str(&format!("\n{:>10}  {:>10}  {}\n", "inclusive".bold(), "exclusive".bold(), "function".bold()));
+        for function in self.functions() {
+            report.push_str(&format!(
+                "{:>10}  {:>10}  {}\n",
+                function.inclusive,
+                function.exclusive.executed,
+                function_name(binary, function.entry).yellow().bold(),
+            ));
+        }
+
+        let mut lines = self.lines(binary);
+        lines.sort_by_key(|(_, counts)| Reverse(counts.executed));
+        lines.truncate(max_lines);
+
+        if lines.is_empty() {
+            return report;
+        }
+
+        report.push_str(&format!(
+            "\n{:>10}  {:>6}  {:>6}  {:>6}  {:>8}  {}\n",
+            "executed".bold(),
+            "loads".bold(),
+            "stores".bold(),
+            "taken".bold(),
+            "syscalls".bold(),
+            "line".bold(),
+        ));
+
+        for ((file_tag, line_num), counts) in lines {
+            let location = if source_code.len() > 1 {
+                format!("{}:{}", file_tag, line_num)
+            } else {
+                format!("line {}", line_num)
+            };
+
+            let source_line = source_code.iter()
+                .find(|(tag, _)| *tag == file_tag)
+                .and_then(|(_, file)| file.lines().nth(line_num as usize - 1))
+                .map(|line| format!("  {} {}", "#".bright_black(), line.trim().bright_black()))
+                .unwrap_or_default();
+
+            report.push_str(&format!(
+                "{:>10}  {:>6}  {:>6}  {:>6}  {:>8}  [{}]{}\n",
+                counts.executed,
+                counts.loads,
+                counts.stores,
+                counts.branches_taken,
+                counts.syscalls,
+                location.bold(),
+                source_line,
+            ));
+        }
+
+        report
+    }
+
+    /// Keeps the functions being executed in line with a call stack `depth` deep,
+    /// whose top `depth` functions (from the top down) are given by `entries`.
+    /// Returns the functions that returned, and how many were called.
+    fn sync_stack(&mut self, depth: usize, entries: impl Iterator<Item = u32>) -> (Vec<(u32, u64, u64)>, usize) {
+        let mut returned = vec![];
+
+        while self.stack.len() > depth {
+            let (entry, start) = self.stack.pop().unwrap();
+
+            // a recursive call is already counted by the outermost call
+            let inclusive = match self.stack.iter().all(|&(running, _)| running != entry) {
+                true  => self.total.executed - start,
+                false => 0,
+            };
+            *self.inclusive.entry(entry).or_default() += inclusive;
+
+            returned.push((entry, start, inclusive));
+        }
+
+        let mut called = vec![];
+        if self.stack.len() < depth {
+            called = entries.take(depth - self.stack.len()).collect::<Vec<_>>();
+            called.reverse();
+
+            let start = self.total.executed;
+            self.stack.extend(called.iter().map(|&entry| (entry, start)));
+        }
+
+        (returned, called.len())
+    }
+
+    /// Puts the functions being executed back the way they were before `step`.
+    fn unsync_stack(&mut self, step: &ProfiledStep) {
+        self.stack.truncate(self.stack.len() - step.called);
+
+        for &(entry, start, inclusive) in step.returned.iter().rev() {
+            let count = self.inclusive.get_mut(&entry).expect("returned function was counted");
+            *count -= inclusive;
+            if *count == 0 {
+                self.inclusive.remove(&entry);
+            }
+
+            self.stack.push((entry, start));
+        }
+    }
+
+    /// Takes the steps that produced states after `position` back out.
+    fn rewind_to(&mut self, position: usize) {
+        while self.steps.back().is_some_and(|step| step.position > position) {
+            let step = self.steps.pop_back().unwrap();
+
+            remove_counts(&mut self.instructions, step.addr, &step.counts);
+            remove_counts(&mut self.exclusive, step.function, &step.counts);
+            self.total.sub(&step.counts);
+
+            if step.root {
+                self.root = None;
+            }
+
+            self.unsync_stack(&step);
+        }
+    }
+
+    /// Forgets the steps that produced states up to `position`,
+    /// which can no longer be stepped back over.
+    fn forget_to(&mut self, position: usize) {
+        while self.steps.front().is_some_and(|step| step.position <= position) {
+            self.steps.pop_front();
+        }
+    }
+
+    fn record(&mut self, addr: u32, inst: u32, function: u32) -> Counts {
+        let mut counts = Counts { executed: 1, ..Default::default() };
+
+        if is_load(inst) {
+            counts.loads = 1;
+        } else if is_store(inst) {
+            counts.stores = 1;
+        } else if is_branch(inst) {
+            counts.branches = 1;
+        } else if is_syscall(inst) {
+            counts.syscalls = 1;
+        }
+
+        self.instructions.entry(addr).or_default().add(&counts);
+        self.exclusive.entry(function).or_default().add(&counts);
+        self.total.add(&counts);
+
+        counts
+    }
+
+    fn record_taken(&mut self, addr: u32, function: u32) {
+        let taken = Counts { branches_taken: 1, ..Default::default() };
+
+        self.instructions.entry(addr).or_default().add(&taken);
+        self.exclusive.entry(function).or_default().add(&taken);
+        self.total.add(&taken);
+
+        if let Some(step) = self.steps.back_mut() {
+            step.counts.add(&taken);
+        }
+    }
+}
+
+fn remove_counts(counts: &mut HashMap<u32, Counts>, key: u32, removed: &Counts) {
+    let entry = counts.get_mut(&key).expect("step was counted");
+    entry.sub(removed);
+
+    if *entry == Counts::default() {
+        counts.remove(&key);
+    }
+}
+
+/// # Finds the source line that each instruction was generated from.
+pub(super) struct LineTable<'a> {
+    line_numbers: BTreeMap<u32, &'a (Rc<str>, u32)>,
+}
+
+impl<'a> LineTable<'a> {
+    pub(super) fn new(binary: &'a Binary) -> Self {
+        Self {
+            line_numbers: binary.line_numbers.iter()
+                .map(|(&addr, line)| (addr, line))
+                .collect(),
+        }
+    }
+
+    pub(super) fn line(&self, addr: u32) -> Option<&'a (Rc<str>, u32)> {
+        // the rest of a pseudo-instruction follows the address of its first instruction
+        self.line_numbers.range(..=addr)
+            .next_back()
+            .filter(|&(&line_addr, _)| (line_addr >= KTEXT_BOT) == (addr >= KTEXT_BOT))
+            .map(|(_, &line)| line)
+    }
+}
+
+impl Runtime {
+    pub fn profile(&mut self) -> Option<&Profile> {
+        let position = self.timeline_position();
+
+        let profile = self.profile.as_mut()?;
+        profile.rewind_to(position);
+
+        Some(profile)
+    }
+
+    /// # Whether to count the instructions the program executes.
+    ///
+    /// Enabling profiling starts a new [`Profile`], and
+    /// disabling it discards the current one.
+    pub fn set_profiling(&mut self, profiling: bool) {
+        self.profile = match profiling {
+            true  => Some(Profile::default()),
+            false => None,
+        };
+    }
+
+    /// Counts `inst`, at `addr`, which is about to be executed.
+    pub(super) fn profile_inst(&mut self, addr: u32, inst: u32) {
+        let position = self.timeline_position();
+        let oldest = self.timeline.discarded_states() + self.timeline.history_start();
+
+        let profile = match self.profile.as_mut() {
+            Some(profile) => profile,
+            None => return,
+        };
+
+        // anything stepped back over since the last step
+        profile.rewind_to(position - 1);
+        profile.forget_to(oldest);
+
+        let call_stack = self.timeline.state().call_stack();
+        let (returned, called) = profile.sync_stack(call_stack.depth(), call_stack.frames().map(|frame| frame.entry));
+
+        let root = profile.stack.is_empty() && profile.root.is_none();
+        let function = match profile.stack.last() {
+            Some(&(entry, _)) => entry,
+            None => *profile.root.get_or_insert(addr),
+        };
+
+        let counts = profile.record(addr, inst, function);
+
+        profile.steps.push_back(ProfiledStep { position, addr, function, counts, root, returned, called });
+    }
+
+    /// Counts whether `inst`, at `addr`, branched, now that it's been executed.
+    pub(super) fn profile_branch(&mut self, addr: u32, inst: u32) {
+        let pc = self.timeline.state().pc();
+
+        let profile = match self.profile.as_mut() {
+            Some(profile) => profile,
+            None => return,
+        };
+
+        if is_branch(inst) && pc != addr.wrapping_add(4) {
+            let function = profile.stack.last()
+                .map(|&(entry, _)| entry)
+                .or(profile.root)
+                .unwrap_or(addr);
+
+            profile.record_taken(addr, function);
+        }
+    }
+
+    /// Keeps the counts of a step that failed, and so never produced a state
+    /// to step back from, but leaves its calls and returns to the next step.
+    pub(super) fn profile_failed_step(&mut self) {
+        let position = self.timeline_position();
+
+        let profile = match self.profile.as_mut() {
+            Some(profile) => profile,
+            None => return,
+        };
+
+        if profile.steps.back().is_some_and(|step| step.position > position) {
+            let step = profile.steps.pop_back().unwrap();
+            profile.unsync_stack(&step);
+        }
+    }
+
+    /// The position of the current state in the timeline,
+    /// counting the states that have been discarded.
+    fn timeline_position(&self) -> usize {
+        self.timeline.discarded_states() + self.timeline.timeline_len() - 1
+    }
+}
+
+fn is_load(inst: u32) -> bool {
+    // LB, LH, LWL, LW, LBU, LHU, LWR, LWC1, LDC1
+    matches!(inst >> 26, 0x20..=0x26 | 0x31 | 0x35)
+}
+
+fn is_store(inst: u32) -> bool {
+    // SB, SH, SWL, SW, SWR, SWC1, SDC1
+    matches!(inst >> 26, 0x28..=0x2B | 0x2E | 0x39 | 0x3D)
+}
+
+pub(crate) fn is_branch(inst: u32) -> bool {
+    let opcode = inst >> 26;
+    let rs     = (inst >> 21) & 0x1F;
+    let rt     = (inst >> 16) & 0x1F;
+
+    match opcode {
+        // BLTZ, BGEZ, BLTZAL, BGEZAL
+        0x01 => matches!(rt, 0x00 | 0x01 | 0x10 | 0x11),
+        // BEQ, BNE, BLEZ, BGTZ
+        0x04..=0x07 => true,
+        // BC1F, BC1T
+        COP1 => rs == 0x08,
+        _ => false,
+    }
+}
+
+fn is_syscall(inst: u32) -> bool {
+    inst >> 26 == SPECIAL && inst & 0x3F == 0x0C
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Register;
+    use super::*;
+    use super::super::tests::{binary, ori};
+
+    fn step(runtime: Runtime) -> Runtime {
+        match runtime.step() {
+            Ok(Ok(runtime)) => runtime,
+            _ => panic!("expected the instruction to run"),
+        }
+    }
+
+    #[test]
+    fn test_rewind_uncounts_steps() {
+        let insts = [ori(Register::T0, 1), ori(Register::T1, 2), ori(Register::T2, 3)];
+
+        let mut runtime = Runtime::new(&binary(&insts), &[]);
+        runtime.set_profiling(true);
+
+        for _ in 0..insts.len() {
+            runtime = step(runtime);
+        }
+        assert_eq!(runtime.profile().unwrap().total().executed, 3);
+
+        runtime.timeline_mut().pop_last_state();
+        runtime.timeline_mut().pop_last_state();
+
+        let profile = runtime.profile().unwrap();
+        assert_eq!(profile.total().executed, 1);
+        assert!(profile.instructions().contains_key(&KTEXT_BOT));
+        assert!(!profile.instructions().contains_key(&(KTEXT_BOT + 4)));
+
+        // stepping forward again counts the step once
+        runtime = step(runtime);
+
+        let profile = runtime.profile().unwrap();
+        assert_eq!(profile.total().executed, 2);
+        assert_eq!(profile.instructions()[&(KTEXT_BOT + 4)].executed, 1);
+    }
+}
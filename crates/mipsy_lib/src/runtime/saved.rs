@@ -0,0 +1,148 @@
+use std::collections::{BTreeMap, HashSet};
+
+use mipsy_utils::Limits;
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error};
+
+use crate::Safe;
+use super::{PAGE_SIZE, Runtime, State, Timeline, backtrace::{CallStack, Frame}, exception::Cop0, limits::Usage, unsafe_cow::UnsafeCow};
+
+/// # How a [`State`] is saved.
+///
+/// Pages are copied out of the timeline they're shared with, and the
+/// call stack is listed from the outermost function in. Write markers
+/// aren't kept, as they only describe the step that made the state.
+#[derive(Serialize, Deserialize)]
+struct SavedState {
+    pc: u32,
+    registers: [Safe<i32>; 32],
+    hi: Safe<i32>,
+    lo: Safe<i32>,
+    heap_size: u32,
+    fp_registers: [Safe<u32>; 32],
+    fp_condition_flags: u8,
+    cop0: Cop0,
+    /// By base address.
+    pages: BTreeMap<u32, SavedPage>,
+    call_stack: Vec<Frame>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedPage(#[serde(with = "crate::util::safe_bytes")] Vec<Safe<u8>>);
+
+impl Serialize for State {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let pages = self.pages.keys()
+            .map(|&base_addr| (base_addr, SavedPage(self.get_page(base_addr).expect("page exists").to_vec())))
+            .collect();
+
+        let mut call_stack = self.call_stack.frames().cloned().collect::<Vec<_>>();
+        call_stack.reverse();
+
+        SavedState {
+            pc: self.pc,
+            registers: self.registers,
+            hi: self.hi,
+            lo: self.lo,
+            heap_size: self.heap_size,
+            fp_registers: self.fp_registers,
+            fp_condition_flags: self.fp_condition_flags,
+            cop0: self.cop0,
+            pages,
+            call_stack,
+        }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for State {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let saved = SavedState::deserialize(deserializer)?;
+
+        let mut pages = std::collections::HashMap::new();
+        for (base_addr, SavedPage(page)) in saved.pages {
+            if base_addr % PAGE_SIZE != 0 || page.len() != PAGE_SIZE as usize {
+                return Err(D::Error::custom(format!("invalid page at 0x{:08x}", base_addr)));
+            }
+
+            pages.insert(base_addr, UnsafeCow::new_boxed(page.into_boxed_slice()));
+        }
+
+        Ok(State {
+            pages,
+            pc: saved.pc,
+            registers: saved.registers,
+            write_marker: 0,
+            hi: saved.hi,
+            lo: saved.lo,
+            heap_size: saved.heap_size,
+            fp_registers: saved.fp_registers,
+            fp_write_marker: 0,
+            fp_condition_flags: saved.fp_condition_flags,
+            call_stack: CallStack::from_frames(saved.call_stack),
+            cop0: saved.cop0,
+        })
+    }
+}
+
+/// # How a [`Runtime`] is saved.
+///
+/// Only the current state is kept, not the timeline before it -
+/// apart from the state the program started in, if it has moved on.
+#[derive(Serialize, Deserialize)]
+struct SavedRuntime<S> {
+    initial: Option<S>,
+    state: S,
+    no_uninit_check: HashSet<u32>,
+    check_calling_convention: bool,
+    exceptions: bool,
+    limits: Limits,
+    steps: u64,
+    output: u64,
+}
+
+/// A runtime is saved as its current state, so once restored, it can't
+/// step back any further - but resetting it still returns to the start.
+/// Its history policy, clock and profile aren't kept.
+impl Serialize for Runtime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let initial = match self.timeline.timeline_len() {
+            1 => None,
+            _ => self.timeline.nth_state(0),
+        };
+
+        SavedRuntime {
+            initial,
+            state: self.timeline.state(),
+            no_uninit_check: self.no_uninit_check.clone(),
+            check_calling_convention: self.check_calling_convention,
+            exceptions: self.exceptions,
+            limits: self.limits,
+            steps: self.usage.steps,
+            output: self.usage.output,
+        }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Runtime {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let saved = SavedRuntime::<State>::deserialize(deserializer)?;
+
+        let mut usage = Usage::new();
+        usage.steps = saved.steps;
+        usage.output = saved.output;
+
+        let timeline = match saved.initial {
+            Some(initial) => Timeline::restored(initial, saved.state),
+            None => Timeline::new(saved.state),
+        };
+
+        Ok(Runtime {
+            timeline,
+            no_uninit_check: saved.no_uninit_check,
+            check_calling_convention: saved.check_calling_convention,
+            exceptions: saved.exceptions,
+            limits: saved.limits,
+            usage,
+            profile: None,
+        })
+    }
+}
@@ -1,10 +1,12 @@
 use std::collections::{HashMap, VecDeque};
 
+use mipsy_utils::HistoryPolicy;
+
 use crate::{MipsyResult, Safe, Uninitialised, TEXT_BOT, compile::TEXT_TOP, GLOBAL_BOT, HEAP_BOT, STACK_BOT, STACK_TOP, KTEXT_BOT, MipsyError, error::runtime::{RuntimeError, self}};
-use super::{PAGE_SIZE, SafeToUninitResult, unsafe_cow::UnsafeCow};
+use super::{PAGE_SIZE, SafeToUninitResult, backtrace::CallStack, exception::Cop0, unsafe_cow::UnsafeCow};
 
 pub const WRITE_MARKER_LO: u32 = 32;
-pub const WRITE_MARKER_HI: u32 = 32;
+pub const WRITE_MARKER_HI: u32 = 33;
 
 /// A timeline of states
 ///
@@ -15,8 +17,15 @@ pub const WRITE_MARKER_HI: u32 = 32;
 /// state `a` will live for at least as long as state `b`.
 ///
 /// This follows the standard lifetime subtyping rules in Rust, i.e. `'a: 'b`.
+///
+/// The first (seed) state is always kept, so that the timeline can be reset.
+/// Subsequent states are kept according to the timeline's [`HistoryPolicy`] -
+/// once states are discarded, the seed state is no longer contiguous with the
+/// rest of the timeline, so it can no longer be stepped back to.
 pub struct Timeline {
     timeline: VecDeque<State>,
+    history_policy: HistoryPolicy,
+    discarded: usize,
 }
 
 impl Drop for Timeline {
@@ -35,9 +44,43 @@ impl Timeline {
 
         Self {
             timeline,
+            history_policy: HistoryPolicy::default(),
+            discarded: 0,
         }
     }
 
+    /// A timeline that has reached `current` from `seed`, but
+    /// has discarded every state in between.
+    pub(super) fn restored(seed: State, current: State) -> Self {
+        let mut timeline = Self::new(seed);
+        timeline.timeline.push_back(current);
+        timeline.discarded = 1;
+
+        timeline
+    }
+
+    pub fn history_policy(&self) -> HistoryPolicy {
+        self.history_policy
+    }
+
+    pub fn set_history_policy(&mut self, history_policy: HistoryPolicy) {
+        self.history_policy = history_policy;
+        self.discard_old_states();
+    }
+
+    /// The number of states that have been discarded
+    /// due to the history policy, since the last reset.
+    pub fn discarded_states(&self) -> usize {
+        self.discarded
+    }
+
+    /// The index of the oldest state that is contiguous
+    /// with the current state, i.e. the oldest state that
+    /// can be reached by stepping backwards.
+    pub fn history_start(&self) -> usize {
+        if self.discarded == 0 { 0 } else { 1 }
+    }
+
     pub fn state(&self) -> &State {
         self.timeline.back().expect("timeline cannot be empty")
     }
@@ -50,6 +93,8 @@ impl Timeline {
         while self.timeline.len() > 1 {
             self.timeline.pop_back();
         }
+
+        self.discarded = 0;
     }
 
     pub fn timeline_len(&self) -> usize {
@@ -60,7 +105,40 @@ impl Timeline {
         self.timeline.get(n)
     }
 
+    /// The index of the last state in which the register with the given
+    /// write marker (its number, or [`WRITE_MARKER_HI`] / [`WRITE_MARKER_LO`])
+    /// was written, if it was written within the retained history.
+    pub fn last_write(&self, write_marker: u32) -> Option<usize> {
+        (self.history_start()..self.timeline.len())
+            .rev()
+            .find(|&i| self.timeline[i].write_marker() & (1u64 << write_marker) != 0)
+    }
+
+    /// The index of the last state in which the given floating
+    /// point register was written, if it was written within
+    /// the retained history.
+    pub fn last_fp_write(&self, reg_num: u32) -> Option<usize> {
+        (self.history_start()..self.timeline.len())
+            .rev()
+            .find(|&i| self.timeline[i].fp_write_marker() & (1u32 << reg_num) != 0)
+    }
+
+    /// Steps backwards until the `n`th state is the current state,
+    /// or as far back as the retained history allows.
+    /// Returns the number of states stepped back.
+    pub fn rewind_to(&mut self, n: usize) -> usize {
+        let mut rewound = 0;
+
+        while self.timeline.len() > n + 1 && self.pop_last_state() {
+            rewound += 1;
+        }
+
+        rewound
+    }
+
     pub fn push_next_state(&mut self) -> &mut State {
+        self.discard_old_states();
+
         let last_state = self.timeline.back().expect("timelint cannot be empty");
         let next_state = last_state.clone();
 
@@ -70,7 +148,7 @@ impl Timeline {
     }
 
     pub fn pop_last_state(&mut self) -> bool {
-        if self.timeline.len() > 1 {
+        if self.timeline.len() > self.history_start() + 1 {
             self.timeline.pop_back();
             
             true
@@ -78,6 +156,42 @@ impl Timeline {
             false
         }
     }
+
+    fn discard_old_states(&mut self) {
+        let max_depth = match self.history_policy {
+            HistoryPolicy::Unbounded => return,
+            HistoryPolicy::MaxDepth(depth) => depth,
+            HistoryPolicy::Disabled => 0,
+        };
+
+        // the seed state and the current state are always kept
+        while self.timeline.len() > 2 && self.timeline.len() - 1 > max_depth {
+            self.discard_oldest_state();
+        }
+    }
+
+    fn discard_oldest_state(&mut self) {
+        let mut oldest = self.timeline.remove(1).expect("timeline has at least 3 states");
+        let next = self.timeline.get_mut(1).expect("timeline has at least 2 states");
+
+        // Any pages owned by the discarded state may still be borrowed by the states after it.
+        // Those borrows all point at the same allocation (they were copied from `next`),
+        // so moving the owned page into `next` keeps them valid, and `next` is
+        // now the oldest of them, maintaining the timeline invariant.
+        for (addr, page) in oldest.pages.drain() {
+            if let UnsafeCow::Owned(owned) = page {
+                if let Some(next_page) = next.pages.get_mut(&addr) {
+                    if let UnsafeCow::Borrowed(borrowed) = next_page {
+                        if std::ptr::addr_eq(*borrowed, owned.as_ref() as *const [Safe<u8>]) {
+                            *next_page = UnsafeCow::Owned(owned);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.discarded += 1;
+    }
 }
 
 pub struct State {
@@ -88,6 +202,11 @@ pub struct State {
     pub(super) hi: Safe<i32>,
     pub(super) lo: Safe<i32>,
     pub(super) heap_size: u32,
+    pub(super) fp_registers: [Safe<u32>; 32],
+    pub(super) fp_write_marker: u32,
+    pub(super) fp_condition_flags: u8,
+    pub(super) call_stack: CallStack,
+    pub(super) cop0: Cop0,
 }
 
 impl State {
@@ -170,6 +289,131 @@ impl State {
         self.write_marker |= 1u64 << WRITE_MARKER_LO;
     }
 
+    pub fn fp_write_marker(&self) -> u32 {
+        self.fp_write_marker
+    }
+
+    pub fn fp_registers(&self) -> &[Safe<u32>] {
+        &self.fp_registers
+    }
+
+    pub fn read_fp_register(&self, reg_num: u32) -> MipsyResult<u32> {
+        self.fp_registers[reg_num as usize]
+            .to_result(Uninitialised::FloatRegister { reg_num })
+    }
+
+    pub fn read_fp_register_uninit(&self, reg_num: u32) -> Safe<u32> {
+        self.fp_registers[reg_num as usize]
+    }
+
+    pub fn write_fp_register(&mut self, reg_num: u32, value: u32) {
+        self.write_fp_register_uninit(reg_num, Safe::Valid(value));
+    }
+
+    pub fn write_fp_register_uninit(&mut self, reg_num: u32, value: Safe<u32>) {
+        assert!(reg_num < 32);
+
+        self.fp_registers[reg_num as usize] = value;
+        self.fp_write_marker |= 1u32 << reg_num;
+    }
+
+    pub fn read_fp_single(&self, reg_num: u32) -> MipsyResult<f32> {
+        Ok(f32::from_bits(self.read_fp_register(reg_num)?))
+    }
+
+    pub fn write_fp_single(&mut self, reg_num: u32, value: f32) {
+        self.write_fp_register(reg_num, value.to_bits());
+    }
+
+    /// Reads the double stored across the even/odd register pair
+    /// `$f{reg_num}` (lower word) and `$f{reg_num + 1}` (upper word).
+    pub fn read_fp_double(&self, reg_num: u32) -> MipsyResult<f64> {
+        Self::check_fp_double_register(reg_num)?;
+
+        let lower = self.read_fp_register(reg_num)?     as u64;
+        let upper = self.read_fp_register(reg_num + 1)? as u64;
+
+        Ok(f64::from_bits((upper << 32) | lower))
+    }
+
+    pub fn read_fp_double_uninit(&self, reg_num: u32) -> MipsyResult<Safe<u64>> {
+        Self::check_fp_double_register(reg_num)?;
+
+        Ok(
+            match (self.read_fp_register_uninit(reg_num), self.read_fp_register_uninit(reg_num + 1)) {
+                (Safe::Valid(lower), Safe::Valid(upper)) => Safe::Valid(((upper as u64) << 32) | lower as u64),
+                _ => Safe::Uninitialised,
+            }
+        )
+    }
+
+    pub fn write_fp_double(&mut self, reg_num: u32, value: f64) -> MipsyResult<()> {
+        self.write_fp_double_uninit(reg_num, Safe::Valid(value.to_bits()))
+    }
+
+    pub fn write_fp_double_uninit(&mut self, reg_num: u32, value: Safe<u64>) -> MipsyResult<()> {
+        Self::check_fp_double_register(reg_num)?;
+
+        match value {
+            Safe::Valid(value) => {
+                self.write_fp_register(reg_num,     value as u32);
+                self.write_fp_register(reg_num + 1, (value >> 32) as u32);
+            }
+            Safe::Uninitialised => {
+                self.write_fp_register_uninit(reg_num,     Safe::Uninitialised);
+                self.write_fp_register_uninit(reg_num + 1, Safe::Uninitialised);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_fp_double_register(reg_num: u32) -> MipsyResult<()> {
+        if reg_num & 1 != 0 {
+            return Err(MipsyError::Runtime(RuntimeError::new(runtime::Error::OddFloatRegister { reg_num })));
+        }
+
+        Ok(())
+    }
+
+    pub fn cop0(&self) -> &Cop0 {
+        &self.cop0
+    }
+
+    pub fn read_cop0_register(&self, reg_num: u32) -> MipsyResult<u32> {
+        self.cop0.register(reg_num)
+            .ok_or_else(|| MipsyError::Runtime(RuntimeError::new(runtime::Error::UnknownCop0Register { reg_num })))
+    }
+
+    pub fn write_cop0_register(&mut self, reg_num: u32, value: u32) -> MipsyResult<()> {
+        let register = self.cop0.register_mut(reg_num)
+            .ok_or_else(|| MipsyError::Runtime(RuntimeError::new(runtime::Error::UnknownCop0Register { reg_num })))?;
+
+        *register = value;
+
+        Ok(())
+    }
+
+    pub fn fp_condition_flags(&self) -> u8 {
+        self.fp_condition_flags
+    }
+
+    pub fn read_fp_condition_flag(&self, cc: u32) -> bool {
+        assert!(cc < 8);
+
+        self.fp_condition_flags & (1u8 << cc) != 0
+    }
+
+    pub fn write_fp_condition_flag(&mut self, cc: u32, value: bool) {
+        assert!(cc < 8);
+
+        if value {
+            self.fp_condition_flags |= 1u8 << cc;
+        } else {
+            self.fp_condition_flags &= !(1u8 << cc);
+        }
+    }
+
     #[must_use]
     pub fn check_segfault(&self, address: u32) -> MipsyResult<()> {
         let segfault = match address {
@@ -464,6 +708,49 @@ impl Clone for State {
             hi: self.hi.clone(),
             lo: self.lo.clone(),
             heap_size: self.heap_size,
+            fp_registers: self.fp_registers,
+            fp_write_marker: 0,
+            fp_condition_flags: self.fp_condition_flags,
+            call_stack: self.call_stack.clone(),
+            cop0: self.cop0,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::runtime::{Runtime, tests::binary};
+    use super::*;
+
+    #[test]
+    fn test_discard_oldest_state_keeps_pages() {
+        let addr = STACK_TOP - 3;
+
+        let mut runtime = Runtime::new(&binary(&[]), &[]);
+        let timeline = runtime.timeline_mut();
+        timeline.set_history_policy(HistoryPolicy::MaxDepth(2));
+
+        // the page is owned by the first state after the seed,
+        // and borrowed by each state after that
+        timeline.push_next_state().write_mem_word(addr, 0x1234_5678).unwrap();
+        for _ in 0..4 {
+            timeline.push_next_state();
+        }
+
+        assert_eq!(timeline.discarded_states(), 2);
+        assert_eq!(timeline.timeline_len(), 4);
+
+        let base_addr = State::addr_to_page_base_addr(addr);
+        assert!(matches!(timeline.nth_state(1).unwrap().pages.get(&base_addr), Some(UnsafeCow::Owned(_))));
+
+        // the page was handed on as its owner was discarded,
+        // so every remaining state can still read it
+        for n in 1..timeline.timeline_len() {
+            assert_eq!(timeline.nth_state(n).unwrap().read_mem_word(addr).unwrap(), 0x1234_5678);
+        }
+
+        // and it outlives them being popped, in the order they were pushed
+        timeline.rewind_to(1);
+        assert_eq!(timeline.state().read_mem_word(addr).unwrap(), 0x1234_5678);
+    }
+}
@@ -0,0 +1,116 @@
+//! # Saving compiled programs, and paused runtimes, to files.
+//!
+//! A saved [`Binary`] can be run without its source code, and a
+//! [`Snapshot`] holds everything needed to pick a program back up
+//! exactly where it was paused - on another machine, if need be.
+//!
+//! Both are encoded with bincode, after a header of what the file holds,
+//! and which version of the format it was saved with.
+
+use std::fmt;
+
+use bincode::Options;
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+
+use crate::{Binary, Runtime};
+
+const BINARY_MAGIC: [u8; 8] = *b"mipsybin";
+const SNAPSHOT_MAGIC: [u8; 8] = *b"mipsysnp";
+const HEADER_SIZE: usize = 12;
+
+/// Bumped whenever the layout of anything saved changes.
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaveError {
+    /// The file doesn't hold what was being loaded.
+    WrongKind { expected: &'static str },
+    /// The file was saved with a different version of the format.
+    UnsupportedVersion { version: u32 },
+    Corrupt { reason: String },
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::WrongKind { expected } => write!(f, "not a saved {}", expected),
+            SaveError::UnsupportedVersion { version } => write!(f, "saved by an incompatible version of mipsy (format version {}, expected {})", version, FORMAT_VERSION),
+            SaveError::Corrupt { reason } => write!(f, "file is corrupt: {}", reason),
+        }
+    }
+}
+
+/// # A paused program.
+///
+/// Along with the runtime itself, this holds the binary it's running, and
+/// the files that were compiled - so that the program can be debugged
+/// further, with its source code, by someone that doesn't have it.
+#[derive(Deserialize)]
+pub struct Snapshot {
+    pub binary: Binary,
+    /// The name and contents of each file the binary was compiled from.
+    pub files: Vec<(String, String)>,
+    /// Only the runtime's current and initial states are kept,
+    /// so it can't step back from where it was saved - but it can be reset.
+    pub runtime: Runtime,
+}
+
+/// A [`Snapshot`], by reference - which saves identically.
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    binary: &'a Binary,
+    files: &'a [(String, String)],
+    runtime: &'a Runtime,
+}
+
+impl Snapshot {
+    pub fn save(binary: &Binary, files: &[(String, String)], runtime: &Runtime) -> Vec<u8> {
+        encode(SNAPSHOT_MAGIC, &SnapshotRef { binary, files, runtime })
+    }
+
+    pub fn load(bytes: &[u8]) -> Result<Snapshot, SaveError> {
+        decode(SNAPSHOT_MAGIC, "snapshot", bytes)
+    }
+}
+
+impl Binary {
+    /// # Saves the binary, to be loaded again with [`Binary::load`].
+    pub fn save(&self) -> Vec<u8> {
+        encode(BINARY_MAGIC, self)
+    }
+
+    pub fn load(bytes: &[u8]) -> Result<Binary, SaveError> {
+        decode(BINARY_MAGIC, "binary", bytes)
+    }
+}
+
+/// Whether `bytes` look like a binary saved by [`Binary::save`].
+pub fn is_saved_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(&BINARY_MAGIC)
+}
+
+fn encode<T: Serialize>(magic: [u8; 8], value: &T) -> Vec<u8> {
+    let mut bytes = magic.to_vec();
+    bytes.extend(FORMAT_VERSION.to_le_bytes());
+
+    bincode::options()
+        .serialize_into(&mut bytes, value)
+        .expect("cannot fail to serialise into memory");
+
+    bytes
+}
+
+fn decode<T: DeserializeOwned>(magic: [u8; 8], expected: &'static str, bytes: &[u8]) -> Result<T, SaveError> {
+    if bytes.len() < HEADER_SIZE || bytes[..8] != magic {
+        return Err(SaveError::WrongKind { expected });
+    }
+
+    let version = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
+    if version != FORMAT_VERSION {
+        return Err(SaveError::UnsupportedVersion { version });
+    }
+
+    bincode::options()
+        .deserialize(&bytes[HEADER_SIZE..])
+        .map_err(|err| SaveError::Corrupt { reason: err.to_string() })
+}
@@ -59,6 +59,51 @@ impl<T> Default for Safe<T> {
     }
 }
 
+/// # Serialises bytes that may be uninitialised.
+///
+/// Rather than tagging every byte, the bytes are kept
+/// alongside a bitmap of which of them are initialised.
+pub(crate) mod safe_bytes {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Safe;
+
+    #[derive(Serialize, Deserialize)]
+    struct SafeBytes {
+        bytes: Vec<u8>,
+        initialised: Vec<u8>,
+    }
+
+    pub(crate) fn serialize<S: Serializer>(bytes: &[Safe<u8>], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut initialised = vec![0; bytes.len().div_ceil(8)];
+
+        for (i, byte) in bytes.iter().enumerate() {
+            if byte.as_option().is_some() {
+                initialised[i / 8] |= 1 << (i % 8);
+            }
+        }
+
+        SafeBytes {
+            bytes: bytes.iter().map(|byte| byte.into_option().unwrap_or(0)).collect(),
+            initialised,
+        }.serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Safe<u8>>, D::Error> {
+        let SafeBytes { bytes, initialised } = SafeBytes::deserialize(deserializer)?;
+
+        Ok(
+            bytes.into_iter()
+                .enumerate()
+                .map(|(i, byte)| match initialised.get(i / 8) {
+                    Some(bits) if bits & (1 << (i % 8)) != 0 => Safe::Valid(byte),
+                    _ => Safe::Uninitialised,
+                })
+                .collect()
+        )
+    }
+}
+
 pub trait TruncImm {
     fn trunc_imm(&self) -> Self;
 }
@@ -12,6 +12,8 @@ use nom::{
     sequence::tuple
 };
 
+use nom_locate::position;
+
 use crate::{
     Span,
     misc::parse_ident
@@ -21,6 +23,9 @@ use crate::{
 pub struct Attribute {
     key: String,
     value: Option<String>,
+    line: u32,
+    col: u32,
+    col_end: u32,
 }
 
 impl Attribute {
@@ -31,6 +36,18 @@ impl Attribute {
     pub fn value(&self) -> Option<&str> {
         self.value.as_deref()
     }
+
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    pub fn col(&self) -> u32 {
+        self.col
+    }
+
+    pub fn col_end(&self) -> u32 {
+        self.col_end
+    }
 }
 
 fn parse_attribute<'a>(attribute_header: &'static str) -> impl FnMut(Span<'a>) -> IResult<Span<'a>, Attribute> {
@@ -38,6 +55,7 @@ fn parse_attribute<'a>(attribute_header: &'static str) -> impl FnMut(Span<'a>) -
         map(
             tuple((
                 multispace0,
+                position,
                 tag(attribute_header),
                 multispace0,
                 parse_ident,
@@ -53,9 +71,11 @@ fn parse_attribute<'a>(attribute_header: &'static str) -> impl FnMut(Span<'a>) -
                 ),
                 multispace0,
                 tag("]"),
+                position,
             )),
             |(
                 _,
+                pos_start,
                 _,
                 _,
                 key,
@@ -63,23 +83,22 @@ fn parse_attribute<'a>(attribute_header: &'static str) -> impl FnMut(Span<'a>) -
                 value,
                 _,
                 _,
+                pos_end,
             )| {
-                if let Some((_, _, value, _, _)) = value {
-                    let text = String::from_utf8_lossy(
+                let value = value.map(|(_, _, value, _, _)| {
+                    String::from_utf8_lossy(
                         &value.iter()
                             .copied()
                             .collect::<Vec<_>>()
-                    ).to_string();
-    
-                    Attribute {
-                        key,
-                        value: Some(text),
-                    }
-                } else {
-                    Attribute {
-                        key,
-                        value: None,
-                    }
+                    ).to_string()
+                });
+
+                Attribute {
+                    key,
+                    value,
+                    line: pos_start.location_line(),
+                    col: pos_start.get_column() as u32,
+                    col_end: pos_end.get_column() as u32,
                 }
             }
         )(i)
@@ -93,3 +112,32 @@ pub fn parse_outer_attribute(i: Span<'_>) -> IResult<Span<'_>, Attribute> {
 pub fn parse_inner_attribute(i: Span<'_>) -> IResult<Span<'_>, Attribute> {
     parse_attribute("#[")(i)   
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::misc::{span, unspan};
+
+    #[test]
+    fn test_parse_inner_attribute() {
+        let (leftover, attr) = unspan(parse_inner_attribute(span("#[allow(unused)]\nfoo:")).unwrap());
+
+        assert_eq!(leftover, "\nfoo:");
+        assert_eq!(attr.key(), "allow");
+        assert_eq!(attr.value(), Some("unused"));
+        assert_eq!((attr.line(), attr.col(), attr.col_end()), (1, 1, 17));
+
+        let (_, attr) = unspan(parse_inner_attribute(span("  #[no_uninit_check]")).unwrap());
+
+        assert_eq!(attr.key(), "no_uninit_check");
+        assert_eq!(attr.value(), None);
+        assert_eq!((attr.col(), attr.col_end()), (3, 21));
+    }
+
+    #[test]
+    fn test_file_tab_size() {
+        assert_eq!(crate::file_tab_size("#![tabsize(4)]\n\tnop\n", 8), 4);
+        assert_eq!(crate::file_tab_size("\tnop\n", 8), 8);
+        assert_eq!(crate::file_tab_size("#![tabsize(zero)]\n\tnop\n", 8), 8);
+    }
+}
@@ -11,7 +11,8 @@ use nom_locate::position;
 use serde::{Serialize, Deserialize};
 
 use crate::Span;
-use crate::misc::{comment_multispace0, parse_ident};
+use crate::label::parse_local_label_reference;
+use crate::misc::{comment_multispace0, parse_ident, parse_label_name};
 use crate::number::{parse_u32, parse_char};
 use crate::parser::Position;
 
@@ -74,25 +75,91 @@ pub enum MpConstValue {
 
 impl Display for MpConstValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let left  = |x: &MpConstValueLoc| operand(self, x, false);
+        let right = |y: &MpConstValueLoc| operand(self, y, true);
+
         match self {
             MpConstValue::Value(x)   => write!(f, "{}", x),
             MpConstValue::Const(x)   => write!(f, "{}", x),
-            MpConstValue::Minus(x)   => write!(f, "-{}", x.0),
-            MpConstValue::Mult(x, y) => write!(f, "{} * {}", x.0, y.0),
-            MpConstValue::Sum(x, y)  => write!(f, "{} + {}", x.0, y.0),
-            MpConstValue::Sub(x, y)  => write!(f, "{} - {}", x.0, y.0),
-            MpConstValue::Div(x, y)  => write!(f, "{} / {}", x.0, y.0),
-            MpConstValue::Mod(x, y)  => write!(f, "{} % {}", x.0, y.0),
-            MpConstValue::And(x, y)  => write!(f, "{} & {}", x.0, y.0),
-            MpConstValue::Or (x, y)  => write!(f, "{} | {}", x.0, y.0),
-            MpConstValue::Xor(x, y)  => write!(f, "{} ^ {}", x.0, y.0),
-            MpConstValue::Neg(x)     => write!(f, "~{}", x.0),
-            MpConstValue::Shl(x, y)  => write!(f, "{} << {}", x.0, y.0),
-            MpConstValue::Shr(x, y)  => write!(f, "{} >> {}", x.0, y.0),
+            MpConstValue::Minus(x)   => write!(f, "-{}", left(x)),
+            MpConstValue::Mult(x, y) => write!(f, "{} * {}", left(x), right(y)),
+            MpConstValue::Sum(x, y)  => write!(f, "{} + {}", left(x), right(y)),
+            MpConstValue::Sub(x, y)  => write!(f, "{} - {}", left(x), right(y)),
+            MpConstValue::Div(x, y)  => write!(f, "{} / {}", left(x), right(y)),
+            MpConstValue::Mod(x, y)  => write!(f, "{} % {}", left(x), right(y)),
+            MpConstValue::And(x, y)  => write!(f, "{} & {}", left(x), right(y)),
+            MpConstValue::Or (x, y)  => write!(f, "{} | {}", left(x), right(y)),
+            MpConstValue::Xor(x, y)  => write!(f, "{} ^ {}", left(x), right(y)),
+            MpConstValue::Neg(x)     => write!(f, "~{}", left(x)),
+            MpConstValue::Shl(x, y)  => write!(f, "{} << {}", left(x), right(y)),
+            MpConstValue::Shr(x, y)  => write!(f, "{} >> {}", left(x), right(y)),
+        }
+    }
+}
+
+impl MpConstValue {
+    /// How tightly the value's operator binds, from `|` up.
+    fn precedence(&self) -> u8 {
+        match self {
+            MpConstValue::Or(..)   => 1,
+            MpConstValue::Xor(..)  => 2,
+            MpConstValue::And(..)  => 3,
+            MpConstValue::Shl(..)
+            | MpConstValue::Shr(..) => 4,
+            MpConstValue::Sum(..)
+            | MpConstValue::Sub(..) => 5,
+            MpConstValue::Mult(..)
+            | MpConstValue::Div(..)
+            | MpConstValue::Mod(..) => 6,
+            MpConstValue::Value(_)
+            | MpConstValue::Const(_)
+            | MpConstValue::Minus(_)
+            | MpConstValue::Neg(_)  => 7,
+        }
+    }
+}
+
+impl MpConstValue {
+    /// Calls `f` with each name the value uses, so it can be changed.
+    pub(crate) fn for_each_name_mut<F: FnMut(&mut String)>(&mut self, f: &mut F) {
+        match self {
+            MpConstValue::Value(_) => {}
+            MpConstValue::Const(name) => f(name),
+            MpConstValue::Minus(value)
+            | MpConstValue::Neg(value) => value.0.for_each_name_mut(f),
+            MpConstValue::Mult(v1, v2)
+            | MpConstValue::Sum (v1, v2)
+            | MpConstValue::Sub (v1, v2)
+            | MpConstValue::Div (v1, v2)
+            | MpConstValue::Mod (v1, v2)
+            | MpConstValue::And (v1, v2)
+            | MpConstValue::Or  (v1, v2)
+            | MpConstValue::Xor (v1, v2)
+            | MpConstValue::Shl (v1, v2)
+            | MpConstValue::Shr (v1, v2) => {
+                v1.0.for_each_name_mut(f);
+                v2.0.for_each_name_mut(f);
+            }
         }
     }
 }
 
+/// Formats an operand of `parent`, with brackets where it
+/// would otherwise be parsed back as a different expression.
+fn operand(parent: &MpConstValue, value: &MpConstValueLoc, right: bool) -> String {
+    let needs_brackets = match right {
+        // operators are left-associative
+        true  => value.0.precedence() <= parent.precedence(),
+        false => value.0.precedence() <  parent.precedence(),
+    };
+
+    if needs_brackets {
+        format!("({})", value.0)
+    } else {
+        value.0.to_string()
+    }
+}
+
 pub fn parse_constant(i: Span<'_>) -> IResult<Span<'_>, MpConst> {
     map(
         tuple((
@@ -355,6 +422,14 @@ pub fn parse_neg_value(i: Span<'_>) -> IResult<Span<'_>, MpConstValueLoc> {
 
 pub fn parse_value(i: Span<'_>) -> IResult<Span<'_>, MpConstValueLoc> {
     alt((
+        map(
+            tuple((
+                position,
+                parse_local_label_reference,
+                position,
+            )),
+            |(pos_start, value, pos_end)| MpConstValueLoc(MpConstValue::Const(value), Position::from_positions(pos_start, pos_end)),
+        ),
         map(
             tuple((
                 position,
@@ -374,7 +449,7 @@ pub fn parse_value(i: Span<'_>) -> IResult<Span<'_>, MpConstValueLoc> {
         map(
             tuple((
                 position,
-                parse_ident,
+                parse_label_name,
                 position,
             )),
             |(pos_start, value, pos_end)| MpConstValueLoc(MpConstValue::Const(value), Position::from_positions(pos_start, pos_end)),
@@ -2,6 +2,7 @@ use std::fmt::Display;
 
 use crate::{Span, constant::{MpConstValueLoc, parse_constant_value}, misc::{
         parse_ident,
+        parse_label_name,
         parse_escaped_char,
         comment_multispace0,
         comment_multispace1,
@@ -9,8 +10,10 @@ use crate::{Span, constant::{MpConstValueLoc, parse_constant_value}, misc::{
 use nom_locate::position;
 use serde::{Serialize, Deserialize};
 use nom::{IResult, branch::alt, bytes::complete::{
+        is_not,
         tag,
-    }, character::complete::{char, space0}, combinator::{map, opt}, multi::{
+        take_while1,
+    }, character::complete::{char, space0, space1}, combinator::{map, opt, verify}, multi::{
         many_till,
         separated_list1
     }, sequence::tuple};
@@ -21,7 +24,9 @@ pub type MpDirectiveLoc = (MpDirective, Position);
 pub enum MpDirective {
     Text,
     Data,
-    KText,
+    /// Switches to the kernel text section - at an address
+    /// in it, if one is given (eg. `.ktext 0x80000180`).
+    KText(Option<MpConstValueLoc>),
     KData,
     Ascii (String),
     Asciiz(String),
@@ -33,6 +38,45 @@ pub enum MpDirective {
     Align(MpConstValueLoc),
     Space(MpConstValueLoc),
     Globl(String),
+    /// A file to include in the program, by its path
+    /// relative to the file including it.
+    Include(String),
+    /// Switches to a section, by its name - eg. `.rodata`.
+    Section(String),
+    /// Switches back to the section before the current one.
+    Previous,
+    /// Switches to the read-only data section.
+    RData,
+    /// Sets an assembler option, such as `noreorder`.
+    Set(String),
+    /// Reserves space for a label, by its size
+    /// and (optionally) its alignment, in bytes.
+    Comm(String, MpConstValueLoc, Option<MpConstValueLoc>),
+    /// Aligns to a power of two, like `.align`.
+    P2Align(MpConstValueLoc),
+    /// A directive that only describes the program to other tools,
+    /// such as `.type` or `.frame`, by its name.
+    Annotation(String),
+}
+
+/// The directives that are only annotations, as far as mipsy is concerned.
+///
+/// `.cpload` and `.cprestore` set up and save `$gp` for position independent
+/// code, but mipsy sets `$gp` itself, and has no global offset table for it.
+const ANNOTATIONS: &[&str] = &[
+    "file", "type", "size", "ent", "end", "frame", "mask", "fmask",
+    "ident", "nan", "module", "local", "gnu_attribute",
+    "abicalls", "option", "cpload", "cprestore",
+];
+
+impl MpDirective {
+    /// Whether this is one of the directives GNU `as` has, which
+    /// are only accepted in GNU compatibility mode.
+    pub fn is_gnu(&self) -> bool {
+        use MpDirective::*;
+
+        matches!(self, Section(_) | Previous | RData | Set(_) | Comm(..) | P2Align(_) | Annotation(_))
+    }
 }
 
 impl Display for MpDirective {
@@ -50,9 +94,17 @@ impl Display for MpDirective {
             Align(_)  => "align",
             Space(_)  => "space",
             Globl(_)  => "globl",
+            Include(_) => "include",
+            Section(_) => "section",
+            Previous  => "previous",
+            RData     => "rdata",
+            Set(_)    => "set",
+            Comm(..)  => "comm",
+            P2Align(_) => "p2align",
+            Annotation(name) => name,
             Text      => "text",
             Data      => "data",
-            KText     => "ktext",
+            KText(_)  => "ktext",
             KData     => "kdata",
         })
     }
@@ -77,6 +129,16 @@ pub fn parse_directive(i: Span<'_>) -> IResult<Span<'_>, MpDirectiveLoc> {
                 parse_space,
                 parse_align,
                 parse_globl,
+                parse_include,
+                alt((
+                    parse_section,
+                    parse_previous,
+                    parse_rdata,
+                    parse_set,
+                    parse_comm,
+                    parse_p2align,
+                    parse_annotation,
+                )),
             )),
             position,
         )),
@@ -105,10 +167,19 @@ fn parse_data(i: Span<'_>) -> IResult<Span<'_>, MpDirective> {
 fn parse_ktext(i: Span<'_>) -> IResult<Span<'_>, MpDirective> {
     let (
         remaining_data,
-        ..
-    ) = tag(".ktext")(i)?;
+        (
+            _,
+            address,
+        )
+    ) = tuple((
+        tag(".ktext"),
+        opt(map(
+            tuple((space1, parse_constant_value)),
+            |(_, address)| address,
+        )),
+    ))(i)?;
 
-    Ok((remaining_data, MpDirective::KText))
+    Ok((remaining_data, MpDirective::KText(address)))
 }
 
 fn parse_kdata(i: Span<'_>) -> IResult<Span<'_>, MpDirective> {
@@ -299,10 +370,143 @@ fn parse_globl(i: Span<'_>) -> IResult<Span<'_>, MpDirective> {
     Ok((remaining_data, MpDirective::Globl(ident)))
 }
 
+fn parse_include(i: Span<'_>) -> IResult<Span<'_>, MpDirective> {
+    map(
+        parse_ascii_type(".include"),
+        MpDirective::Include
+    )(i)
+}
+
+fn parse_section(i: Span<'_>) -> IResult<Span<'_>, MpDirective> {
+    let (
+        remaining_data,
+        (
+            _,
+            _,
+            name,
+            _,
+        )
+    ) = tuple((
+        tag(".section"),
+        space1,
+        take_while1(|chr: u8| chr.is_ascii_alphanumeric() || b"._-$".contains(&chr)),
+        // the section's flags and type don't matter to mipsy
+        skip_line,
+    ))(i)?;
+
+    Ok((remaining_data, MpDirective::Section(String::from_utf8_lossy(name.fragment()).to_string())))
+}
+
+fn parse_previous(i: Span<'_>) -> IResult<Span<'_>, MpDirective> {
+    let (
+        remaining_data,
+        ..
+    ) = tag(".previous")(i)?;
+
+    Ok((remaining_data, MpDirective::Previous))
+}
+
+fn parse_rdata(i: Span<'_>) -> IResult<Span<'_>, MpDirective> {
+    let (
+        remaining_data,
+        ..
+    ) = tag(".rdata")(i)?;
+
+    Ok((remaining_data, MpDirective::RData))
+}
+
+fn parse_set(i: Span<'_>) -> IResult<Span<'_>, MpDirective> {
+    let (
+        remaining_data,
+        (
+            _,
+            _,
+            option,
+            _,
+        )
+    ) = tuple((
+        tag(".set"),
+        space1,
+        parse_ident,
+        skip_line,
+    ))(i)?;
+
+    Ok((remaining_data, MpDirective::Set(option)))
+}
+
+fn parse_comm(i: Span<'_>) -> IResult<Span<'_>, MpDirective> {
+    let (
+        remaining_data,
+        (
+            _,
+            _,
+            label,
+            _,
+            _,
+            _,
+            size,
+            alignment,
+        )
+    ) = tuple((
+        tag(".comm"),
+        space1,
+        parse_label_name,
+        space0,
+        char(','),
+        space0,
+        parse_constant_value,
+        opt(
+            map(
+                tuple((
+                    space0,
+                    char(','),
+                    space0,
+                    parse_constant_value,
+                )),
+                |(_, _, _, alignment)| alignment,
+            )
+        ),
+    ))(i)?;
+
+    Ok((remaining_data, MpDirective::Comm(label, size, alignment)))
+}
+
+fn parse_p2align(i: Span<'_>) -> IResult<Span<'_>, MpDirective> {
+    map(
+        parse_u32_type(".p2align"),
+        MpDirective::P2Align,
+    )(i)
+}
+
+fn parse_annotation(i: Span<'_>) -> IResult<Span<'_>, MpDirective> {
+    let (
+        remaining_data,
+        (
+            _,
+            name,
+            _,
+        )
+    ) = tuple((
+        char('.'),
+        verify(parse_ident, |name: &str| ANNOTATIONS.contains(&name)),
+        skip_line,
+    ))(i)?;
+
+    Ok((remaining_data, MpDirective::Annotation(name)))
+}
+
+/// Skips the rest of a line, which is ignored.
+fn skip_line(i: Span<'_>) -> IResult<Span<'_>, ()> {
+    map(
+        opt(is_not("\n")),
+        |_| (),
+    )(i)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::misc::{span, unspan};
+    use crate::{MpConstValue, misc::{span, unspan}};
 
     #[test]
     fn test_ascii() {
@@ -322,4 +526,69 @@ mod test {
             )
         );
     }
+
+    #[test]
+    fn test_ktext() {
+        assert_eq!(
+            unspan(parse_directive(span(".ktext")).unwrap()).1.0,
+            MpDirective::KText(None)
+        );
+
+        assert!(matches!(
+            unspan(parse_directive(span(".ktext 0x80000180")).unwrap()).1.0,
+            MpDirective::KText(Some(MpConstValueLoc(MpConstValue::Value(0x80000180), _)))
+        ));
+    }
+
+    #[test]
+    fn test_include() {
+        assert_eq!(
+            unspan(parse_include(span(".include \"lib/helpers.s\"")).unwrap()),
+            (
+                "".to_string(),
+                MpDirective::Include("lib/helpers.s".to_string())
+            )
+        );
+
+        assert!(parse_include(span(".include helpers.s")).is_err());
+    }
+
+    #[test]
+    fn test_gnu() {
+        assert_eq!(
+            unspan(parse_directive(span(".section .rodata.str1.4,\"aMS\",@progbits,1")).unwrap()).1.0,
+            MpDirective::Section(".rodata.str1.4".to_string())
+        );
+
+        assert_eq!(
+            unspan(parse_directive(span(".set\tnoreorder")).unwrap()).1.0,
+            MpDirective::Set("noreorder".to_string())
+        );
+
+        assert_eq!(
+            unspan(parse_annotation(span(".frame $fp,24,$31\t\t# vars= 0\n.mask 0xc0000000,-4")).unwrap()),
+            (
+                "\n.mask 0xc0000000,-4".to_string(),
+                MpDirective::Annotation("frame".to_string()),
+            )
+        );
+
+        assert!(matches!(
+            unspan(parse_directive(span(".comm buffer,40,4")).unwrap()).1.0,
+            MpDirective::Comm(label, _, Some(_)) if label == "buffer"
+        ));
+
+        assert_eq!(
+            unspan(parse_directive(span(".abicalls")).unwrap()).1.0,
+            MpDirective::Annotation("abicalls".to_string())
+        );
+
+        assert_eq!(
+            unspan(parse_directive(span(".option\tpic0")).unwrap()).1.0,
+            MpDirective::Annotation("option".to_string())
+        );
+
+        assert!(matches!(unspan(parse_directive(span(".rdata")).unwrap()).1.0, MpDirective::RData));
+        assert!(parse_directive(span(".endif")).is_err());
+    }
 }
@@ -0,0 +1,194 @@
+use std::{collections::HashSet, io, path::{Component, Path, PathBuf}, rc::Rc};
+
+use serde::{Serialize, Deserialize};
+
+use crate::{ErrorKind, ErrorLocation, MpDirective, Span, directive::parse_directive, file_tab_size, tabs_to_spaces};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum IncludeError {
+    CannotRead { path: String, reason: String },
+    /// A file that includes the file including it.
+    Cycle      { path: String },
+    /// A file that was included, but wasn't given to the parser.
+    NotLoaded  { path: String },
+}
+
+/// # Adds the files that `files` include, with `.include`, to them.
+///
+/// Each included file is read with `read_file`, by its path relative to
+/// the file that includes it, and is added just after that file - unless
+/// it has already been added. If including fails, `files` still holds
+/// every file that was read, so that the error can be shown.
+pub fn include_files<F>(files: &mut Vec<(String, String)>, default_tab_size: u32, mut read_file: F) -> Result<(), ErrorLocation>
+where
+    F: FnMut(&str) -> io::Result<String>,
+{
+    let roots = std::mem::take(files);
+
+    let mut loaded = roots.iter()
+        .map(|(tag, _)| normalise(Path::new(tag)))
+        .collect::<HashSet<_>>();
+
+    let mut result = Ok(());
+
+    for root in roots {
+        if result.is_ok() {
+            result = include_into(files, root, &mut loaded, &mut vec![], default_tab_size, &mut read_file);
+        } else {
+            files.push(root);
+        }
+    }
+
+    result
+}
+
+/// The tag of a file included by `including_file`, with `path`.
+pub(crate) fn include_path(including_file: &str, path: &str) -> String {
+    let directory = Path::new(including_file).parent().unwrap_or_else(|| Path::new(""));
+
+    normalise(&directory.join(path))
+}
+
+/// Adds `file` to `files`, followed by the files it includes.
+///
+/// `including` is the files whose includes are being added, from the outermost in.
+fn include_into<F>(files: &mut Vec<(String, String)>, file: (String, String), loaded: &mut HashSet<String>, including: &mut Vec<String>, default_tab_size: u32, read_file: &mut F) -> Result<(), ErrorLocation>
+where
+    F: FnMut(&str) -> io::Result<String>,
+{
+    let tag = file.0.clone();
+    let includes = find_includes(&file.1, default_tab_size);
+
+    files.push(file);
+    including.push(normalise(Path::new(&tag)));
+
+    for (path, line, col) in includes {
+        let path = include_path(&tag, &path);
+
+        let error = |error| ErrorLocation {
+            file_name: Some(Rc::from(&*tag)),
+            line,
+            col,
+            kind: Some(ErrorKind::Include(error)),
+            expansion: None,
+        };
+
+        if including.contains(&path) {
+            return Err(error(IncludeError::Cycle { path }));
+        }
+
+        if !loaded.insert(path.clone()) {
+            continue;
+        }
+
+        let contents = read_file(&path)
+            .map_err(|err| error(IncludeError::CannotRead { path: path.clone(), reason: err.to_string() }))?;
+
+        include_into(files, (path, contents), loaded, including, default_tab_size, read_file)?;
+    }
+
+    including.pop();
+
+    Ok(())
+}
+
+/// The path, line and column of each `.include` in a file.
+fn find_includes(contents: &str, default_tab_size: u32) -> Vec<(String, u32, usize)> {
+    let contents = tabs_to_spaces(contents, file_tab_size(contents, default_tab_size));
+
+    contents.split('\n')
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let start = line.len() - line.trim_start().len();
+
+            if !line[start..].starts_with(".include") {
+                return None;
+            }
+
+            match parse_directive(Span::new(&line.as_bytes()[start..])) {
+                Ok((_, (MpDirective::Include(path), _))) => Some((path, index as u32 + 1, start + 1)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Removes any `.` and `..` from a path that can be, so that
+/// each file is given the same tag however it's included.
+pub(crate) fn normalise(path: &Path) -> String {
+    let mut components: Vec<Component<'_>> = vec![];
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(components.last(), Some(Component::Normal(_))) => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+
+    components.iter()
+        .collect::<PathBuf>()
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_from<'a>(files: &'a [(&'a str, &'a str)]) -> impl FnMut(&str) -> io::Result<String> + 'a {
+        move |path| files.iter()
+            .find(|(name, _)| *name == path)
+            .map(|(_, contents)| contents.to_string())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "not found"))
+    }
+
+    fn tags(files: &[(String, String)]) -> Vec<&str> {
+        files.iter()
+            .map(|(tag, _)| &tag[..])
+            .collect()
+    }
+
+    #[test]
+    fn test_include_path() {
+        assert_eq!(include_path("main.s", "helpers.s"), "helpers.s");
+        assert_eq!(include_path("src/main.s", "./lib/helpers.s"), "src/lib/helpers.s");
+        assert_eq!(include_path("src/main.s", "../helpers.s"), "helpers.s");
+        assert_eq!(include_path("../main.s", "../helpers.s"), "../../helpers.s");
+    }
+
+    #[test]
+    fn test_include_files() {
+        let mut files = vec![("src/main.s".to_string(), "    .include \"lib/a.s\"\n    .include \"b.s\"\n".to_string())];
+
+        let library = [
+            ("src/lib/a.s", "# a\n\t.include \"../b.s\"\n"),
+            ("src/b.s", "# b\n"),
+        ];
+
+        include_files(&mut files, 8, read_from(&library)).unwrap();
+
+        assert_eq!(tags(&files), vec!["src/main.s", "src/lib/a.s", "src/b.s"]);
+    }
+
+    #[test]
+    fn test_include_errors() {
+        let mut files = vec![("main.s".to_string(), "\n  .include \"a.s\"\n".to_string())];
+        let library = [("a.s", ".include \"main.s\"")];
+
+        let error = include_files(&mut files, 8, read_from(&library)).unwrap_err();
+
+        assert_eq!((error.file_name.as_deref(), error.line, error.col), (Some("a.s"), 1, 1));
+        assert_eq!(error.kind, Some(ErrorKind::Include(IncludeError::Cycle { path: "main.s".to_string() })));
+        assert_eq!(tags(&files), vec!["main.s", "a.s"]);
+
+        let mut files = vec![("main.s".to_string(), "\n  .include \"a.s\"\n".to_string())];
+
+        let error = include_files(&mut files, 8, read_from(&[])).unwrap_err();
+
+        assert_eq!((error.file_name.as_deref(), error.line, error.col), (Some("main.s"), 2, 3));
+        assert!(matches!(error.kind, Some(ErrorKind::Include(IncludeError::CannotRead { path, .. })) if path == "a.s"));
+    }
+}
@@ -26,8 +26,6 @@ pub struct MpInstruction {
 pub enum MpArgument {
     Register(MpRegister),
     Number(MpNumber),
-    // LabelPlusConst(String, MpNumber),
-    // LabelPlusOffReg(String, MpImmediate, MpRegisterIdentifier),
 }
 
 impl MpInstruction {
@@ -61,7 +59,6 @@ impl fmt::Display for MpArgument {
         match self {
             Self::Register(reg)                    => write!(f, "{}", reg),
             Self::Number(num)                      => write!(f, "{}", num),
-            // Self::LabelPlusConst(label, the_const) => write!(f, "{} + {}", label, the_const),
         }
     }
 }
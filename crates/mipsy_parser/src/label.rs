@@ -1,17 +1,35 @@
+use std::collections::HashMap;
+
 use crate::{
     Span,
-    misc::parse_ident,
+    MpArgument,
+    MpDirective,
+    MpImmediate,
+    MpItem,
+    MpNumber,
+    MpRegister,
+    misc::{IDENT_CONTD_CHARS, parse_label_name},
+    parser::MpAttributedItem,
 };
 use nom::{
     IResult,
+    branch::alt,
     character::complete::{
+        digit1,
+        one_of,
         space0,
         char,
     },
+    combinator::{map, not},
     sequence::tuple,
 };
 use nom_locate::position;
 
+/// Separates the number of a numeric local label from which of its
+/// definitions it is, once it's been resolved - as GNU `as` does,
+/// so that it can't be mistaken for any label in the source.
+const LOCAL_LABEL_SEPARATOR: char = '\u{2}';
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct MpLabel {
     label:   String,
@@ -33,6 +51,19 @@ impl MpLabel {
     }
 }
 
+/// The number of a numeric local label (eg. `1` for `1:`),
+/// by its resolved name, or `None` if it's any other label.
+pub fn local_label_number(label: &str) -> Option<&str> {
+    label.split_once(LOCAL_LABEL_SEPARATOR)
+        .map(|(number, _)| number)
+}
+
+/// The name a label is shown by, which for
+/// a numeric local label is just its number.
+pub fn label_display_name(label: &str) -> &str {
+    local_label_number(label).unwrap_or(label)
+}
+
 pub fn parse_label(i: Span<'_>) -> IResult<Span<'_>, MpLabel> {
     let (
         remaining_data,
@@ -45,7 +76,10 @@ pub fn parse_label(i: Span<'_>) -> IResult<Span<'_>, MpLabel> {
         )
     ) = tuple((
             position,
-            parse_ident,
+            alt((
+                parse_label_name,
+                map(digit1, |number: Span<'_>| String::from_utf8_lossy(number.fragment()).to_string()),
+            )),
             space0,
             char(':'),
             position,
@@ -56,3 +90,190 @@ pub fn parse_label(i: Span<'_>) -> IResult<Span<'_>, MpLabel> {
 
     Ok((remaining_data, MpLabel { label, col, col_end }))
 }
+
+/// A reference to a numeric local label, such as `1b` for the
+/// closest `1:` before it, or `1f` for the closest after it.
+///
+/// Once resolved, its name is parsed too, as instructions are
+/// parsed again after their pseudo-instructions are expanded.
+pub fn parse_local_label_reference(i: Span<'_>) -> IResult<Span<'_>, String> {
+    let (
+        remaining_data,
+        (
+            number,
+            suffix,
+        )
+    ) = tuple((
+        digit1,
+        alt((
+            map(
+                tuple((one_of("bf"), not(one_of(IDENT_CONTD_CHARS)))),
+                |(direction, _)| direction.to_string(),
+            ),
+            map(
+                tuple((char(LOCAL_LABEL_SEPARATOR), digit1)),
+                |(separator, definition): (char, Span<'_>)| format!("{}{}", separator, String::from_utf8_lossy(definition.fragment())),
+            ),
+        )),
+    ))(i)?;
+
+    Ok((remaining_data, format!("{}{}", String::from_utf8_lossy(number.fragment()), suffix)))
+}
+
+/// # Gives each numeric local label in a file a name of its own.
+///
+/// A numeric label can be defined any number of times, so each definition
+/// is named by its number and how many definitions of it came before, and
+/// each `1b` or `1f` is renamed to match the definition it refers to. One
+/// that refers to no definition keeps its name, to be reported as missing.
+pub(crate) fn resolve_local_labels(items: &mut [MpAttributedItem]) {
+    let mut totals = HashMap::<String, usize>::new();
+
+    for item in items.iter() {
+        if let MpItem::Label(label) = &item.item {
+            if is_number(&label.label) {
+                *totals.entry(label.label.clone()).or_default() += 1;
+            }
+        }
+    }
+
+    if totals.is_empty() {
+        return;
+    }
+
+    let mut defined = HashMap::<String, usize>::new();
+
+    for item in items.iter_mut() {
+        let mut resolve = |name: &mut String| resolve_reference(name, &defined, &totals);
+
+        match &mut item.item {
+            MpItem::Label(label) if is_number(&label.label) => {
+                let count = defined.entry(label.label.clone()).or_default();
+                *count += 1;
+
+                label.label = local_label_name(&label.label, *count);
+            }
+            MpItem::Instruction(instruction) => {
+                for (argument, _, _) in instruction.arguments.iter_mut() {
+                    let imms = match argument {
+                        MpArgument::Number(MpNumber::Immediate(imm))
+                        | MpArgument::Register(MpRegister::Offset(imm, _)) => vec![imm],
+                        MpArgument::Number(MpNumber::BinaryOpImmediate(i1, _, i2))
+                        | MpArgument::Register(MpRegister::BinaryOpOffset(i1, _, i2, _)) => vec![i1, i2],
+                        _ => continue,
+                    };
+
+                    for imm in imms {
+                        match imm {
+                            MpImmediate::LabelReference(label) => resolve(label),
+                            MpImmediate::Relocation(_, value)
+                            | MpImmediate::Expression(value) => value.0.for_each_name_mut(&mut resolve),
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            MpItem::Directive((MpDirective::Byte(values) | MpDirective::Half(values) | MpDirective::Word(values), _)) => {
+                for (value, _) in values {
+                    value.0.for_each_name_mut(&mut resolve);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn resolve_reference(name: &mut String, defined: &HashMap<String, usize>, totals: &HashMap<String, usize>) {
+    let (number, backwards) = match name.strip_suffix('b') {
+        Some(number) => (number, true),
+        None => match name.strip_suffix('f') {
+            Some(number) => (number, false),
+            None => return,
+        },
+    };
+
+    if !is_number(number) {
+        return;
+    }
+
+    let count = defined.get(number).copied().unwrap_or(0);
+    let total = totals.get(number).copied().unwrap_or(0);
+
+    let definition = match backwards {
+        true  => count,
+        false => count + 1,
+    };
+
+    if definition >= 1 && definition <= total {
+        *name = local_label_name(number, definition);
+    }
+}
+
+fn local_label_name(number: &str, definition: usize) -> String {
+    format!("{}{}{}", number, LOCAL_LABEL_SEPARATOR, definition)
+}
+
+fn is_number(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|char| char.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MpConstValue, parse_mips, TaggedFile};
+    use crate::misc::{span, unspan};
+
+    #[test]
+    fn test_parse_local_labels() {
+        assert_eq!(unspan(parse_label(span("1:")).unwrap()).1.label(), "1");
+        assert_eq!(unspan(parse_local_label_reference(span("10b")).unwrap()), ("".to_string(), "10b".to_string()));
+        assert_eq!(unspan(parse_local_label_reference(span("1f, $t0")).unwrap()), (", $t0".to_string(), "1f".to_string()));
+        assert!(parse_local_label_reference(span("0b101")).is_err());
+        assert!(parse_local_label_reference(span("1")).is_err());
+
+        let resolved = local_label_name("1", 2);
+        assert_eq!(unspan(parse_local_label_reference(span(&resolved)).unwrap()), ("".to_string(), resolved));
+    }
+
+    #[test]
+    fn test_resolve_local_labels() {
+        let program = parse_mips(vec![TaggedFile::new(None, "
+1:  b   1f
+    b   1b
+1:  b   1b
+    b   2f
+    .data
+    .word 1b + 4
+")], 8).unwrap();
+
+        let labels = program.items().iter()
+            .filter_map(|item| match item.item() {
+                MpItem::Label(label) => Some(label.label()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(labels, vec![local_label_name("1", 1), local_label_name("1", 2)]);
+        assert_eq!(labels.iter().map(|label| label_display_name(label)).collect::<Vec<_>>(), vec!["1", "1"]);
+
+        let references = program.items().iter()
+            .filter_map(|item| match item.item() {
+                MpItem::Instruction(instruction) => match &instruction.arguments()[0].0 {
+                    MpArgument::Number(MpNumber::Immediate(MpImmediate::LabelReference(label))) => Some(label.clone()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        // there's no `2:`, so `2f` is left to be reported as missing
+        assert_eq!(references, vec![local_label_name("1", 2), local_label_name("1", 1), local_label_name("1", 2), "2f".to_string()]);
+
+        match program.items().last().unwrap().item() {
+            MpItem::Directive((MpDirective::Word(values), _)) => {
+                assert!(matches!(&(values[0].0).0, MpConstValue::Sum(label, _) if label.0 == MpConstValue::Const(local_label_name("1", 2))));
+            }
+            item => panic!("expected a .word, not {:?}", item),
+        }
+    }
+}
@@ -16,14 +16,29 @@ pub use instruction::{
     MpArgument,
 };
 pub use directive::MpDirective;
+pub use label::{
+    local_label_number,
+    label_display_name,
+};
+pub use attribute::Attribute;
+pub use include::{
+    IncludeError,
+    include_files,
+};
+pub use macros::{
+    MacroError,
+    MpMacroExpansion,
+};
 pub use misc::{
     ErrorLocation,
+    ErrorKind,
     tabs_to_spaces,
 };
 pub use number::{
     MpNumber,
     MpImmediate,
     MpImmediateBinaryOp,
+    MpRelocation,
 };
 pub use register::{
     MpRegister,
@@ -37,7 +52,7 @@ pub use constant::{
 };
 
 
-pub use parser::parse_mips;
+pub use parser::{parse_mips, file_tab_size};
 
 pub fn parse_instruction<T>(input: T, tab_size: u32) -> Result<MpInstruction, ErrorLocation>
 where
@@ -68,7 +83,9 @@ pub mod parser;
 mod attribute;
 mod directive;
 mod instruction;
+mod include;
 mod label;
+mod macros;
 mod misc;
 mod number;
 mod register;
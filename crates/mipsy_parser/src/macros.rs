@@ -0,0 +1,758 @@
+//! # Assembler macros.
+//!
+//! Macros are expanded as each file is parsed. Both MARS-style macros:
+//!
+//! ```text
+//! .macro print_int (%x)
+//!     li      $a0, %x
+//!     li      $v0, 1
+//!     syscall
+//! .end_macro
+//! ```
+//!
+//! and GNU-style macros (`.macro print_int x` ... `.endm`, which refer to
+//! their parameters as `\x`) are supported, and may be invoked either as
+//! `print_int(42)` or `print_int 42`. Labels defined in a macro are renamed
+//! in each expansion, so that a macro can be used more than once.
+
+use std::{collections::HashMap, rc::Rc};
+
+use serde::{Serialize, Deserialize};
+
+use crate::{ErrorKind, ErrorLocation, Span, misc::{IDENT_CONTD_CHARS, IDENT_FIRST_CHAR, parse_result}, parser::{MpProgram, parse_mips_bytes}};
+
+/// # Where a macro was expanded.
+///
+/// Every item a macro expands to is given the line it was invoked on.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct MpMacroExpansion {
+    name: String,
+    /// The line of the macro's `.macro` directive.
+    defined_at: u32,
+    line: u32,
+    col: u32,
+    col_end: u32,
+}
+
+impl MpMacroExpansion {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn defined_at(&self) -> u32 {
+        self.defined_at
+    }
+
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    pub fn col(&self) -> u32 {
+        self.col
+    }
+
+    pub fn col_end(&self) -> u32 {
+        self.col_end
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum MacroError {
+    /// A `.macro` without a name.
+    MissingName,
+    InvalidParameter { param: String },
+    /// A `.macro` without an `.end_macro`.
+    Unterminated     { name: String },
+    /// An `.end_macro` without a `.macro`.
+    UnmatchedEnd,
+    NestedDefinition { name: String },
+    /// A macro defined twice, with the same number of parameters.
+    Redefined        { name: String, defined_at: u32 },
+    WrongArguments   { name: String, given: usize, expected: usize, defined_at: u32 },
+    /// A macro that would expand into itself, forever.
+    Recursive        { name: String },
+}
+
+struct Macro {
+    name: String,
+    /// Each parameter's name, and its default value.
+    params: Vec<(String, Option<String>)>,
+    line: u32,
+    col: u32,
+    body: Vec<String>,
+}
+
+struct Invocation<'a> {
+    name: &'a str,
+    args: Vec<&'a str>,
+    /// Where the macro's name starts.
+    start: usize,
+    end: usize,
+}
+
+struct Expander {
+    file_name: Option<Rc<str>>,
+    /// By name - a macro can be defined once for each number of parameters.
+    macros: HashMap<String, Vec<Macro>>,
+    /// How many expansions there have been, to give each unique labels.
+    expansions: usize,
+}
+
+/// # Parses a file, expanding any macros it defines.
+///
+/// The file must have already had its tabs expanded.
+pub(crate) fn parse_file(file_string: &str, file_name: Option<Rc<str>>) -> Result<MpProgram, ErrorLocation> {
+    let mut expander = Expander {
+        file_name,
+        macros: HashMap::new(),
+        expansions: 0,
+    };
+
+    let source = expander.collect_definitions(file_string)?;
+
+    expander.parse(&source, &mut vec![])
+}
+
+impl Expander {
+    /// Takes out each macro definition, leaving a blank line for each of their lines.
+    fn collect_definitions(&mut self, source: &str) -> Result<String, ErrorLocation> {
+        let mut lines = vec![];
+        let mut defining: Option<Macro> = None;
+
+        for (index, line) in source.split('\n').enumerate() {
+            let line_number = index as u32 + 1;
+            let (word, col) = leading_word(line);
+
+            match (word, &mut defining) {
+                (".macro", Some(defining)) => {
+                    return Err(self.error(MacroError::NestedDefinition { name: defining.name.clone() }, line_number, col));
+                }
+                (".macro", None) => {
+                    defining = Some(self.parse_definition(line, line_number, col)?);
+                }
+                (".end_macro" | ".endm", None) => {
+                    return Err(self.error(MacroError::UnmatchedEnd, line_number, col));
+                }
+                (".end_macro" | ".endm", Some(_)) => {
+                    self.define(defining.take().unwrap())?;
+                }
+                (_, Some(defining)) => {
+                    defining.body.push(line.to_string());
+                }
+                (_, None) => {
+                    lines.push(line);
+                    continue;
+                }
+            }
+
+            lines.push("");
+        }
+
+        if let Some(defining) = defining {
+            return Err(self.error(MacroError::Unterminated { name: defining.name }, defining.line, defining.col as usize));
+        }
+
+        Ok(lines.join("\n"))
+    }
+
+    fn parse_definition(&self, line: &str, line_number: u32, col: usize) -> Result<Macro, ErrorLocation> {
+        let header = strip_comment(&line[col - 1 + ".macro".len()..]).trim();
+
+        let name_end = ident_end(header, 0);
+        if name_end == 0 {
+            return Err(self.error(MacroError::MissingName, line_number, col));
+        }
+
+        let mut params_text = header[name_end..].trim();
+        if let Some(text) = params_text.strip_prefix('(').and_then(|text| text.strip_suffix(')')) {
+            params_text = text;
+        }
+
+        let params_text = params_text.split(if params_text.contains(',') { ',' } else { ' ' })
+            .map(str::trim)
+            .filter(|param| !param.is_empty());
+
+        let mut params = vec![];
+        for param_text in params_text {
+            let param = param_text.trim_start_matches(['%', '\\']);
+            let (param, default) = match param.split_once('=') {
+                Some((param, default)) => (param.trim(), Some(default.trim().to_string())),
+                None => (param, None),
+            };
+
+            if param.is_empty() || ident_end(param, 0) != param.len() {
+                return Err(self.error(MacroError::InvalidParameter { param: param_text.to_string() }, line_number, col));
+            }
+
+            params.push((param.to_string(), default));
+        }
+
+        Ok(Macro {
+            name: header[..name_end].to_string(),
+            params,
+            line: line_number,
+            col: col as u32,
+            body: vec![],
+        })
+    }
+
+    fn define(&mut self, mac: Macro) -> Result<(), ErrorLocation> {
+        let overloads = self.macros.entry(mac.name.clone()).or_default();
+
+        if let Some(existing) = overloads.iter().find(|existing| existing.params.len() == mac.params.len()) {
+            let error = MacroError::Redefined { name: mac.name.clone(), defined_at: existing.line };
+            return Err(self.error(error, mac.line, mac.col as usize));
+        }
+
+        overloads.push(mac);
+
+        Ok(())
+    }
+
+    /// Parses `source`, expanding each macro invoked in it.
+    ///
+    /// `expanding` is the macros being expanded, from the outermost in.
+    fn parse(&mut self, source: &str, expanding: &mut Vec<String>) -> Result<MpProgram, ErrorLocation> {
+        let mut lines = source.split('\n').collect::<Vec<_>>();
+        let mut expansions = vec![];
+
+        for (index, line) in lines.iter_mut().enumerate() {
+            if self.macros.is_empty() {
+                break;
+            }
+
+            let invocation = match find_invocation(line, &self.macros) {
+                Some(invocation) => invocation,
+                None => continue,
+            };
+
+            let line_number = index as u32 + 1;
+            let col = invocation.start + 1;
+
+            let overloads = &self.macros[invocation.name];
+            let mac = match overloads.iter().find(|mac| mac.accepts(&invocation.args)) {
+                Some(mac) => mac,
+                None => {
+                    let error = MacroError::WrongArguments {
+                        name: invocation.name.to_string(),
+                        given: invocation.args.len(),
+                        expected: overloads[0].params.len(),
+                        defined_at: overloads[0].line,
+                    };
+
+                    return Err(self.error(error, line_number, col));
+                }
+            };
+
+            if expanding.iter().any(|name| name == invocation.name) {
+                return Err(self.error(MacroError::Recursive { name: invocation.name.to_string() }, line_number, col));
+            }
+
+            self.expansions += 1;
+
+            // the body is parsed at the lines it was defined on, so errors in it point there
+            let body = "\n".repeat(mac.line as usize) + &mac.expand(&invocation.args, self.expansions).join("\n");
+
+            let expansion = MpMacroExpansion {
+                name: mac.name.clone(),
+                defined_at: mac.line,
+                line: line_number,
+                col: col as u32,
+                col_end: invocation.end as u32 + 1,
+            };
+
+            expansions.push((expansion, body));
+
+            // any labels before the invocation stay where they are
+            *line = &line[..invocation.start];
+        }
+
+        let source = lines.join("\n");
+        let mut program = parse_result(Span::new(source.as_bytes()), self.file_name.clone(), parse_mips_bytes(self.file_name.clone()))?;
+
+        if expansions.is_empty() {
+            return Ok(program);
+        }
+
+        let mut items = vec![];
+        let mut unexpanded = program.items.into_iter().peekable();
+
+        for (expansion, body) in expansions {
+            while let Some(item) = unexpanded.next_if(|item| item.line_number <= expansion.line) {
+                items.push(item);
+            }
+
+            expanding.push(expansion.name.clone());
+            let expanded = self.parse(&body, expanding)
+                .map_err(|mut error| {
+                    error.expansion.get_or_insert_with(|| Box::new(expansion.clone()));
+                    error
+                })?;
+            expanding.pop();
+
+            let expansion = Rc::new(expansion);
+
+            // an expansion within this one is attributed to this one
+            items.extend(
+                expanded.items.into_iter()
+                    .map(|mut item| {
+                        item.line_number = expansion.line;
+                        item.expanded_from = Some(expansion.clone());
+                        item
+                    })
+            );
+        }
+
+        items.extend(unexpanded);
+        program.items = items;
+
+        Ok(program)
+    }
+
+    fn error(&self, error: MacroError, line: u32, col: usize) -> ErrorLocation {
+        ErrorLocation {
+            file_name: self.file_name.clone(),
+            line,
+            col,
+            kind: Some(ErrorKind::Macro(error)),
+            expansion: None,
+        }
+    }
+}
+
+impl Macro {
+    fn accepts(&self, args: &[&str]) -> bool {
+        args.len() <= self.params.len()
+            && self.params[args.len()..].iter().all(|(_, default)| default.is_some())
+    }
+
+    /// The lines of the macro's body, with its parameters substituted,
+    /// and its labels renamed to be unique to expansion `id`.
+    fn expand(&self, args: &[&str], id: usize) -> Vec<String> {
+        let args = self.params.iter()
+            .enumerate()
+            .map(|(index, (_, default))| match args.get(index) {
+                Some(&arg) if !arg.is_empty() => arg,
+                _ => default.as_deref().unwrap_or(""),
+            })
+            .collect::<Vec<_>>();
+
+        let labels = self.body.iter()
+            .filter_map(|line| defined_label(line))
+            .collect::<Vec<_>>();
+
+        self.body.iter()
+            .map(|line| self.substitute(line, &args, &labels, id))
+            .collect()
+    }
+
+    fn substitute(&self, line: &str, args: &[&str], labels: &[&str], id: usize) -> String {
+        let param_index = |name: &str| self.params.iter().position(|(param, _)| param == name);
+
+        let mut output = String::new();
+        let mut quote = None;
+        let mut i = 0;
+
+        while i < line.len() {
+            let rest = &line[i..];
+            let char = rest.chars().next().unwrap();
+
+            if let Some(quote_char) = quote {
+                if char == '\\' && rest.len() > 1 {
+                    let escaped_len = 1 + rest[1..].chars().next().unwrap().len_utf8();
+                    output.push_str(&rest[..escaped_len]);
+                    i += escaped_len;
+                    continue;
+                }
+
+                if char == quote_char {
+                    quote = None;
+                }
+
+                output.push(char);
+                i += char.len_utf8();
+                continue;
+            }
+
+            match char {
+                '"' | '\'' => {
+                    quote = Some(char);
+                    output.push(char);
+                    i += 1;
+                }
+                '#' => {
+                    output.push_str(rest);
+                    break;
+                }
+                '%' | '\\' => {
+                    let end = ident_end(line, i + 1);
+
+                    if let Some(index) = param_index(&line[i + 1..end]) {
+                        output.push_str(args[index]);
+                        i = end;
+                    } else if char == '\\' && rest.starts_with("\\@") {
+                        output.push_str(&id.to_string());
+                        i += 2;
+                    } else if char == '\\' && rest.starts_with("\\()") {
+                        i += 3;
+                    } else {
+                        output.push(char);
+                        i += 1;
+                    }
+                }
+                _ if IDENT_FIRST_CHAR.contains(char) && !follows_word(line, i) => {
+                    let end = ident_end(line, i);
+                    let ident = &line[i..end];
+
+                    output.push_str(ident);
+                    if labels.contains(&ident) {
+                        output.push_str(&format!("_M{}", id));
+                    }
+
+                    i = end;
+                }
+                _ => {
+                    output.push(char);
+                    i += char.len_utf8();
+                }
+            }
+        }
+
+        output
+    }
+}
+
+/// The macro invoked by a line, if any.
+///
+/// The invocation may follow any number of labels, and its arguments
+/// may be surrounded by parentheses.
+fn find_invocation<'a>(line: &'a str, macros: &HashMap<String, Vec<Macro>>) -> Option<Invocation<'a>> {
+    let mut start = skip_spaces(line, 0);
+
+    loop {
+        let end = ident_end(line, start);
+        if end == start {
+            return None;
+        }
+
+        let after = skip_spaces(line, end);
+
+        if line[after..].starts_with(':') {
+            start = skip_spaces(line, after + 1);
+            continue;
+        }
+
+        let name = &line[start..end];
+        if !macros.contains_key(name) {
+            return None;
+        }
+
+        let rest = &line[after..];
+        let (args, end) = match rest.chars().next() {
+            None | Some('#') => ("", end),
+            Some('(') => {
+                let close = closing_paren(rest)?;
+
+                let trailing = rest[close + 1..].trim_start();
+                if !trailing.is_empty() && !trailing.starts_with('#') {
+                    return None;
+                }
+
+                (&rest[1..close], after + close + 1)
+            }
+            Some('=') => return None,
+            Some(_) if after > end => {
+                let args = strip_comment(rest).trim_end();
+                (args, after + args.len())
+            }
+            Some(_) => return None,
+        };
+
+        return Some(Invocation {
+            name,
+            args: split_args(args),
+            start,
+            end,
+        });
+    }
+}
+
+/// The first word on a line, and its column.
+fn leading_word(line: &str) -> (&str, usize) {
+    let start = skip_spaces(line, 0);
+    let end = line[start..].find(|char: char| char.is_whitespace() || char == '#' || char == '(')
+        .map_or(line.len(), |len| start + len);
+
+    (&line[start..end], start + 1)
+}
+
+/// The label defined at the start of a line, if any.
+fn defined_label(line: &str) -> Option<&str> {
+    let start = skip_spaces(line, 0);
+    let end = ident_end(line, start);
+
+    if end > start && line[skip_spaces(line, end)..].starts_with(':') {
+        Some(&line[start..end])
+    } else {
+        None
+    }
+}
+
+/// Splits arguments on the commas that aren't in parentheses or quotes.
+fn split_args(args: &str) -> Vec<&str> {
+    if args.trim().is_empty() {
+        return vec![];
+    }
+
+    let mut split = vec![];
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    let mut start = 0;
+
+    for (i, char) in args.char_indices() {
+        match (quote, char) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(quote_char), _) if char == quote_char => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(char),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                split.push(args[start..i].trim());
+                start = i + 1;
+            }
+            (None, _) => {}
+        }
+    }
+
+    split.push(args[start..].trim());
+
+    split
+}
+
+/// The index of the parenthesis that closes the one `text` starts with.
+fn closing_paren(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (i, char) in text.char_indices() {
+        match (quote, char) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(quote_char), _) if char == quote_char => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(char),
+            (None, '(') => depth += 1,
+            (None, ')') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            (None, _) => {}
+        }
+    }
+
+    None
+}
+
+fn strip_comment(text: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (i, char) in text.char_indices() {
+        match (quote, char) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(quote_char), _) if char == quote_char => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(char),
+            (None, '#') => return &text[..i],
+            (None, _) => {}
+        }
+    }
+
+    text
+}
+
+fn skip_spaces(line: &str, start: usize) -> usize {
+    line[start..].find(|char: char| !char.is_whitespace())
+        .map_or(line.len(), |len| start + len)
+}
+
+/// The end of the identifier starting at `start` - or `start`, if there isn't one.
+fn ident_end(line: &str, start: usize) -> usize {
+    match line[start..].chars().next() {
+        Some(char) if IDENT_FIRST_CHAR.contains(char) => {
+            line[start..].find(|char| !IDENT_CONTD_CHARS.contains(char))
+                .map_or(line.len(), |len| start + len)
+        }
+        _ => start,
+    }
+}
+
+/// Whether the character at `i` continues a word, register or parameter.
+fn follows_word(line: &str, i: usize) -> bool {
+    line[..i].chars()
+        .next_back()
+        .is_some_and(|char| IDENT_CONTD_CHARS.contains(char) || "$%\\".contains(char))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MpItem, TaggedFile, parse_mips};
+
+    fn parse(source: &str) -> Result<MpProgram, ErrorLocation> {
+        parse_mips(vec![TaggedFile::new(None, source)], 8)
+    }
+
+    fn macro_error(source: &str) -> (Option<MacroError>, u32) {
+        let error = parse(source).unwrap_err();
+
+        let macro_error = match error.kind {
+            Some(ErrorKind::Macro(macro_error)) => Some(macro_error),
+            _ => None,
+        };
+
+        (macro_error, error.line)
+    }
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(split_args(""), Vec::<&str>::new());
+        assert_eq!(split_args("$t0"), vec!["$t0"]);
+        assert_eq!(split_args(" $t0 , 4($sp) "), vec!["$t0", "4($sp)"]);
+        assert_eq!(split_args("\"a, b\", ','"), vec!["\"a, b\"", "','"]);
+    }
+
+    #[test]
+    fn test_find_invocation() {
+        let mut macros = HashMap::new();
+        macros.insert("print".to_string(), vec![]);
+
+        let invocation = find_invocation("loop: print($t0, 4) # comment", &macros).unwrap();
+        assert_eq!((invocation.name, invocation.args, invocation.start, invocation.end), ("print", vec!["$t0", "4"], 6, 19));
+
+        let invocation = find_invocation("    print $t0, 4", &macros).unwrap();
+        assert_eq!((invocation.args, invocation.start, invocation.end), (vec!["$t0", "4"], 4, 16));
+
+        assert!(find_invocation("print", &macros).is_some());
+        assert!(find_invocation("print = 5", &macros).is_none());
+        assert!(find_invocation("printf $t0", &macros).is_none());
+        assert!(find_invocation("print: li $t0, 1", &macros).is_none());
+    }
+
+    #[test]
+    fn test_substitute() {
+        let mac = Macro {
+            name: "m".to_string(),
+            params: vec![("x".to_string(), None), ("y".to_string(), Some("1".to_string()))],
+            line: 1,
+            col: 1,
+            body: vec![
+                "loop: addi %x, %x, \\y".to_string(),
+                "    bnez %x, loop  # loop %x".to_string(),
+                "    la $a0, s\\@".to_string(),
+                "s\\@: .asciiz \"%x loop\"".to_string(),
+            ],
+        };
+
+        assert_eq!(
+            mac.expand(&["$t0"], 3),
+            vec![
+                "loop_M3: addi $t0, $t0, 1",
+                "    bnez $t0, loop_M3  # loop %x",
+                "    la $a0, s3",
+                "s3: .asciiz \"%x loop\"",
+            ],
+        );
+    }
+
+    #[test]
+    fn test_parse_macros() {
+        let program = parse("\
+.macro print_int (%x)
+    li $a0, %x
+    li $v0, 1
+    syscall
+.end_macro
+
+.macro exit code=0
+    li $a0, \\code
+    li $v0, 17
+    syscall
+.endm
+
+main:
+    print_int(42)
+end: exit
+").unwrap();
+
+        let items = program.items().iter()
+            .map(|item| (item.line_number(), item.expanded_from().map(|expansion| (expansion.name(), expansion.defined_at(), expansion.col(), expansion.col_end()))))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            items,
+            vec![
+                (13, None),
+                (14, Some(("print_int", 1, 5, 18))),
+                (14, Some(("print_int", 1, 5, 18))),
+                (14, Some(("print_int", 1, 5, 18))),
+                (15, None),
+                (15, Some(("exit", 7, 6, 10))),
+                (15, Some(("exit", 7, 6, 10))),
+                (15, Some(("exit", 7, 6, 10))),
+            ],
+        );
+
+        assert!(matches!(program.items()[4].item(), MpItem::Label(label) if label.label() == "end"));
+    }
+
+    #[test]
+    fn test_macro_labels() {
+        let program = parse("\
+.macro spin
+spin: b spin
+.end_macro
+    spin
+    spin
+").unwrap();
+
+        let labels = program.items().iter()
+            .filter_map(|item| match item.item() {
+                MpItem::Label(label) => Some(label.label()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(labels, vec!["spin_M1", "spin_M2"]);
+    }
+
+    #[test]
+    fn test_macro_errors() {
+        assert_eq!(macro_error(".macro m\n"), (Some(MacroError::Unterminated { name: "m".into() }), 1));
+        assert_eq!(macro_error("\n.end_macro\n"), (Some(MacroError::UnmatchedEnd), 2));
+        assert_eq!(macro_error(".macro\n.end_macro\n"), (Some(MacroError::MissingName), 1));
+        assert_eq!(macro_error(".macro m\n.macro n\n"), (Some(MacroError::NestedDefinition { name: "m".into() }), 2));
+        assert_eq!(macro_error(".macro m(%a)\n.end_macro\n.macro m(%b)\n.end_macro\n"), (Some(MacroError::Redefined { name: "m".into(), defined_at: 1 }), 3));
+        assert_eq!(
+            macro_error(".macro m(%a)\n.end_macro\n    m(1, 2)\n"),
+            (Some(MacroError::WrongArguments { name: "m".into(), given: 2, expected: 1, defined_at: 1 }), 3),
+        );
+        assert_eq!(macro_error(".macro m\n    n\n.end_macro\n.macro n\n    m\n.end_macro\n    m\n"), (Some(MacroError::Recursive { name: "m".into() }), 5));
+    }
+
+    #[test]
+    fn test_macro_parse_error() {
+        let error = parse(".macro m(%x)\n    li %x, 1\n    not an instruction!\n.end_macro\n\n    m($t0)\n").unwrap_err();
+
+        assert_eq!(error.line, 3);
+        assert_eq!(error.kind, None);
+        assert_eq!(error.expansion.map(|expansion| (expansion.line(), expansion.col())), Some((6, 5)));
+    }
+}
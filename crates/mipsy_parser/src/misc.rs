@@ -1,13 +1,13 @@
 use std::rc::Rc;
 
-use crate::Span;
-use nom::{IResult, branch::alt, bytes::complete::{is_a, tag}, character::complete::{
+use crate::{Span, include::IncludeError, macros::{MacroError, MpMacroExpansion}};
+use nom::{IResult, branch::alt, bytes::complete::{is_a, tag, take_while_m_n}, character::complete::{
         anychar,
         char,
         multispace1,
         none_of,
         one_of
-    }, combinator::{map, not, opt}, multi::{
+    }, combinator::{map, map_res, not, opt}, multi::{
         many0,
         many1
     }, sequence::{
@@ -20,6 +20,16 @@ pub struct ErrorLocation {
     pub file_name: Option<Rc<str>>,
     pub line: u32,
     pub col:  usize,
+    /// Why parsing failed, if it wasn't simply invalid syntax.
+    pub kind: Option<ErrorKind>,
+    /// The expansion the error is in, if it's in the body of a macro.
+    pub expansion: Option<Box<MpMacroExpansion>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    Macro(MacroError),
+    Include(IncludeError),
 }
 
 pub(crate) fn parse_result<'a, T, P>(i: Span<'a>, file_name: Option<Rc<str>>, mut parser: P) -> Result<T, ErrorLocation> 
@@ -47,6 +57,8 @@ fn leftover_tokens_strip_multispace<'a>(i: Span<'a>, file_name: Option<Rc<str>>)
                 file_name,
                 line: leftover.location_line(),
                 col:  leftover.get_column(),
+                kind: None,
+                expansion: None,
             }
         }
         Err(err) => {
@@ -56,8 +68,8 @@ fn leftover_tokens_strip_multispace<'a>(i: Span<'a>, file_name: Option<Rc<str>>)
     }
 }
 
-const IDENT_FIRST_CHAR:  &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz_";
-const IDENT_CONTD_CHARS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz_0123456789.";
+pub(crate) const IDENT_FIRST_CHAR:  &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz_";
+pub(crate) const IDENT_CONTD_CHARS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz_0123456789.";
 
 pub fn escape_char(char: char) -> String {
     match char {
@@ -74,6 +86,14 @@ pub fn escape_char(char: char) -> String {
 
 pub fn parse_escaped_char(i: Span<'_>) -> IResult<Span<'_>, char> {
     alt((
+        // octal escapes, such as `\012`, as GNU tools write them
+        map_res(
+            tuple((
+                char('\\'),
+                take_while_m_n(1, 3, |chr: u8| (b'0'..=b'7').contains(&chr)),
+            )),
+            |(_, digits): (_, Span<'_>)| u8::from_str_radix(&String::from_utf8_lossy(digits.fragment()), 8).map(char::from)
+        ),
         map(
             tuple((
                 char('\\'),
@@ -118,6 +138,21 @@ pub fn parse_ident(i: Span<'_>) -> IResult<Span<'_>, String> {
     Ok((remaining_data, ident))
 }
 
+/// The name of a label - an identifier, which can also start with a `$`,
+/// like the local labels GNU tools generate (eg. `$L2`).
+pub fn parse_label_name(i: Span<'_>) -> IResult<Span<'_>, String> {
+    alt((
+        parse_ident,
+        map(
+            tuple((
+                char('$'),
+                parse_ident,
+            )),
+            |(_, ident)| format!("${}", ident),
+        ),
+    ))(i)
+}
+
 pub fn parse_any1(i: Span<'_>) -> IResult<Span<'_>, u8> {
     map(
         anychar,
@@ -1,7 +1,9 @@
 use std::fmt;
 
 use crate::{
-    misc::{escape_char, parse_escaped_char, parse_ident},
+    constant::{parse_constant_value, MpConstValueLoc},
+    label::parse_local_label_reference,
+    misc::{escape_char, parse_escaped_char, parse_label_name},
     Span,
 };
 use nom::{IResult, branch::alt, bytes::complete::{is_a, tag}, character::complete::{char, digit1, hex_digit1, oct_digit1, one_of, space0}, combinator::{map, map_res, opt}, number::complete::{double, float}, sequence::tuple};
@@ -23,6 +25,22 @@ pub enum MpImmediate {
     I32(i32),
     U32(u32),
     LabelReference(String),
+    /// Part of the value of an expression, such as `%hi(label)`.
+    Relocation(MpRelocation, MpConstValueLoc),
+    /// An expression of labels and constants, such as `array + 4 * N`.
+    Expression(MpConstValueLoc),
+}
+
+/// # An operator that takes part of an address.
+///
+/// A `%hi` and `%lo` of the same label can be combined, as with
+/// `lui` and then `addiu`, to build the label's address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum MpRelocation {
+    /// The upper 16 bits, adjusted for the sign of the lower 16.
+    Hi,
+    /// The lower 16 bits.
+    Lo,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
@@ -51,6 +69,30 @@ impl fmt::Display for MpImmediate {
             Self::I32(i) => write!(f, "{}", i),
             Self::U32(i) => write!(f, "{}", i),
             Self::LabelReference(label) => write!(f, "{}", label),
+            Self::Relocation(relocation, value) => write!(f, "{}({})", relocation, value.0),
+            Self::Expression(value) => write!(f, "{}", value.0),
+        }
+    }
+}
+
+impl fmt::Display for MpRelocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Hi => write!(f, "%hi"),
+            Self::Lo => write!(f, "%lo"),
+        }
+    }
+}
+
+impl MpRelocation {
+    /// The part of `address` this takes, as a 16-bit immediate.
+    ///
+    /// `%lo` is sign-extended when it's used, so `%hi` is rounded up
+    /// whenever the lower half would be negative, to make up for it.
+    pub fn apply(&self, address: u32) -> u16 {
+        match self {
+            Self::Hi => (address.wrapping_add(0x8000) >> 16) as u16,
+            Self::Lo => address as u16,
         }
     }
 }
@@ -65,13 +107,24 @@ impl fmt::Display for MpImmediateBinaryOp {
 }
 
 pub fn parse_number(i: Span<'_>) -> IResult<Span<'_>, MpNumber> {
-    alt((
+    let number = alt((
         parse_binary_op_immedaite,
         map(parse_immediate, MpNumber::Immediate),
         map(parse_f32, MpNumber::Float32),
         map(parse_f64, MpNumber::Float64),
         map(parse_char, MpNumber::Char),
-    ))(i)
+    ))(i);
+
+    // an expression is only used where it's longer than the number,
+    // so that eg. `-1` and `label + 4` are still parsed as they were
+    match (number, parse_expression(i)) {
+        (Ok((number_data, _)), Ok((remaining_data, expression)))
+            if remaining_data.location_offset() > number_data.location_offset() => {
+            Ok((remaining_data, MpNumber::Immediate(expression)))
+        }
+        (Err(_), Ok((remaining_data, expression))) => Ok((remaining_data, MpNumber::Immediate(expression))),
+        (number, _) => number,
+    }
 }
 
 pub fn parse_binary_op_immedaite(i: Span<'_>) -> IResult<Span<'_>, MpNumber> {
@@ -104,6 +157,8 @@ pub fn parse_binary_op_immedaite(i: Span<'_>) -> IResult<Span<'_>, MpNumber> {
 
 pub fn parse_immediate(i: Span<'_>) -> IResult<Span<'_>, MpImmediate> {
     alt((
+        parse_relocation,
+        map(parse_local_label_reference, MpImmediate::LabelReference),
         map(parse_i16, MpImmediate::I16),
         map(parse_u16, MpImmediate::U16),
         map(parse_i32, MpImmediate::I32),
@@ -210,7 +265,39 @@ pub fn parse_u32(i: Span<'_>) -> IResult<Span<'_>, u32> {
 }
 
 pub fn parse_labelref(i: Span<'_>) -> IResult<Span<'_>, String> {
-    parse_ident(i)
+    parse_label_name(i)
+}
+
+pub fn parse_expression(i: Span<'_>) -> IResult<Span<'_>, MpImmediate> {
+    map(parse_constant_value, MpImmediate::Expression)(i)
+}
+
+pub fn parse_relocation(i: Span<'_>) -> IResult<Span<'_>, MpImmediate> {
+    let (
+        remaining_data,
+        (
+            relocation,
+            _,
+            _,
+            _,
+            value,
+            _,
+            _,
+        )
+    ) = tuple((
+        alt((
+            map(tag("%hi"), |_| MpRelocation::Hi),
+            map(tag("%lo"), |_| MpRelocation::Lo),
+        )),
+        space0,
+        char('('),
+        space0,
+        parse_constant_value,
+        space0,
+        char(')'),
+    ))(i)?;
+
+    Ok((remaining_data, MpImmediate::Relocation(relocation, value)))
 }
 
 pub fn parse_f32(i: Span<'_>) -> IResult<Span<'_>, f32> {
@@ -249,6 +336,7 @@ fn get_sign(neg: Option<char>) -> &'static str {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::constant::MpConstValue;
     use crate::misc::{span, unspan};
 
     #[test]
@@ -281,6 +369,43 @@ mod tests {
                 ("".to_string(), escaped)
             );
         }
+
+        assert_eq!(unspan(parse_char(span("'\\012'")).unwrap()), ("".to_string(), '\n'));
+        assert_eq!(unspan(parse_char(span("'\\101'")).unwrap()), ("".to_string(), 'A'));
+    }
+
+    #[test]
+    fn relocation() {
+        let (rest, hi) = unspan(parse_immediate(span("%hi($LC0)")).unwrap());
+        assert_eq!(rest, "");
+        assert!(matches!(&hi, MpImmediate::Relocation(MpRelocation::Hi, MpConstValueLoc(MpConstValue::Const(label), _)) if label == "$LC0"));
+
+        let (rest, lo) = unspan(parse_immediate(span("%lo( array + 4*2 )")).unwrap());
+        assert_eq!(rest, "");
+        assert!(matches!(lo, MpImmediate::Relocation(MpRelocation::Lo, MpConstValueLoc(MpConstValue::Sum(..), _))));
+        assert_eq!(lo.to_string(), "%lo(array + 4 * 2)");
+
+        assert_eq!(MpRelocation::Hi.apply(0x1001_8004), 0x1002);
+        assert_eq!(MpRelocation::Lo.apply(0x1001_8004), 0x8004);
+        assert_eq!(MpRelocation::Hi.apply(0x1001_0004), 0x1001);
+    }
+
+    #[test]
+    fn expression() {
+        assert_eq!(unspan(parse_number(span("-1")).unwrap()), ("".to_string(), MpNumber::Immediate(MpImmediate::I16(-1))));
+        assert_eq!(
+            unspan(parse_number(span("label + 4")).unwrap()),
+            ("".to_string(), MpNumber::BinaryOpImmediate(MpImmediate::LabelReference("label".to_string()), MpImmediateBinaryOp::Plus, MpImmediate::I16(4)))
+        );
+
+        let (rest, number) = unspan(parse_number(span("arr + 4*N, $t0")).unwrap());
+        assert_eq!(rest, ", $t0");
+        assert!(matches!(number, MpNumber::Immediate(MpImmediate::Expression(MpConstValueLoc(MpConstValue::Sum(..), _)))));
+        assert_eq!(number.to_string(), "arr + 4 * N");
+
+        let (rest, number) = unspan(parse_number(span("-(N - 1) << 2")).unwrap());
+        assert_eq!(rest, "");
+        assert_eq!(number.to_string(), "-(N - 1) << 2");
     }
 }
 
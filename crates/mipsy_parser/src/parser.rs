@@ -1,9 +1,9 @@
-use std::rc::Rc;
+use std::{collections::HashSet, path::Path, rc::Rc};
 
-use crate::{ErrorLocation, Span, attribute::{Attribute, parse_inner_attribute, parse_outer_attribute}, constant::{MpConst, parse_constant}, directive::{MpDirective, MpDirectiveLoc, parse_directive}, instruction::{
+use crate::{ErrorKind, ErrorLocation, IncludeError, Span, attribute::{Attribute, parse_inner_attribute, parse_outer_attribute}, constant::{MpConst, parse_constant}, directive::{MpDirective, MpDirectiveLoc, parse_directive}, instruction::{
         MpInstruction,
         parse_instruction,
-    }, label::{MpLabel, parse_label}, misc::{comment_multispace0, comment_multispace1, parse_result}};
+    }, include::{include_path, normalise}, label::{MpLabel, parse_label, resolve_local_labels}, macros::MpMacroExpansion, misc::{comment_multispace0, comment_multispace1}};
 use nom::{AsBytes, IResult, branch::alt, combinator::map, multi::many0, sequence::tuple};
 use nom_locate::{LocatedSpan, position};
 use serde::{Serialize, Deserialize};
@@ -25,7 +25,7 @@ pub struct Position {
 #[derive(Debug, Clone, PartialEq)]
 pub struct MpProgram {
     pub(crate) items: Vec<MpAttributedItem>,
-    pub(crate) file_attributes: Vec<Attribute>,
+    pub(crate) file_attributes: Vec<(Option<Rc<str>>, Vec<Attribute>)>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -34,6 +34,8 @@ pub struct MpAttributedItem {
     pub(crate) attributes: Vec<Attribute>,
     pub(crate) file_tag: Option<Rc<str>>,
     pub(crate) line_number: u32,
+    /// The macro expansion the item came from, if any.
+    pub(crate) expanded_from: Option<Rc<MpMacroExpansion>>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -100,6 +102,7 @@ impl MpAttributedItem {
             attributes,
             file_tag,
             line_number,
+            expanded_from: None,
         }
     }
 
@@ -122,13 +125,17 @@ impl MpAttributedItem {
     pub fn line_number(&self) -> u32 {
         self.line_number
     }
+
+    pub fn expanded_from(&self) -> Option<&MpMacroExpansion> {
+        self.expanded_from.as_deref()
+    }
 }
 
 impl MpProgram {
     pub fn new(items: Vec<MpAttributedItem>, file_attributes: Vec<Attribute>) -> Self {
         Self {
             items,
-            file_attributes,
+            file_attributes: vec![(None, file_attributes)],
         }
     }
 
@@ -140,6 +147,19 @@ impl MpProgram {
         &mut self.items
     }
 
+    /// The `#![...]` attributes of every file in the program, by file tag.
+    pub fn file_attributes(&self) -> &[(Option<Rc<str>>, Vec<Attribute>)] {
+        &self.file_attributes
+    }
+
+    /// The `#![...]` attributes of the file with the given tag.
+    pub fn attributes_of_file(&self, file_tag: Option<&str>) -> &[Attribute] {
+        self.file_attributes.iter()
+            .find(|(tag, _)| tag.as_deref() == file_tag)
+            .map(|(_, attributes)| &attributes[..])
+            .unwrap_or(&[])
+    }
+
     fn merge(&mut self, mut other: MpProgram) {
         if !self.items.is_empty() {
             self.items.push(MpAttributedItem {
@@ -147,10 +167,12 @@ impl MpProgram {
                 attributes: vec![],
                 file_tag: None,
                 line_number: 0,
+                expanded_from: None,
             });
         }
 
         self.items.append(&mut other.items);
+        self.file_attributes.append(&mut other.file_attributes);
     }
 }
 
@@ -198,6 +220,7 @@ pub fn parse_mips_bytes<'a>(file_name: Option<Rc<str>>) -> impl FnMut(Span<'a>)
                             attributes: attrs,
                             file_tag: file_name.clone(),
                             line_number: line,
+                            expanded_from: None,
                         }),
                     ),
                     map(comment_multispace1, |_| None),
@@ -213,7 +236,7 @@ pub fn parse_mips_bytes<'a>(file_name: Option<Rc<str>>) -> impl FnMut(Span<'a>)
             remaining_input,
             MpProgram {
                 items,
-                file_attributes: attrs,
+                file_attributes: vec![(file_name.clone(), attrs)],
             },
         ))
     }
@@ -237,41 +260,64 @@ pub fn parse_outer_attributes(i: Span<'_>) -> IResult<Span<'_>, Vec<Attribute>>
     )(i)
 }
 
+/// The tab size of a file - given by its `#![tabsize(...)]`
+/// attribute, or `default_tab_size` if it doesn't have a valid one.
+pub fn file_tab_size(input: &str, default_tab_size: u32) -> u32 {
+    let initial_file_string = crate::misc::tabs_to_spaces(input, default_tab_size);
+    let initial_span = Span::new(initial_file_string.as_bytes());
+
+    let (_remaining_input, outer_attrs) = parse_outer_attributes(initial_span)
+        .expect("Initial outer attributes parser should never fail");
+
+    outer_attrs.iter()
+        .rev()
+        .filter(|attr| attr.key().eq_ignore_ascii_case("tabsize"))
+        .filter_map(|attr| attr.value()?.trim().parse().ok())
+        .find(|&tab_size| tab_size > 0)
+        .unwrap_or(default_tab_size)
+}
+
 pub fn parse_mips(files: Vec<TaggedFile<'_, '_>>, default_tab_size: u32) -> Result<MpProgram, ErrorLocation> {
     let mut program = MpProgram {
         items: vec![],
         file_attributes: vec![],
     };
 
+    let loaded = files.iter()
+        .filter_map(|file| file.tag)
+        .map(|tag| normalise(Path::new(tag)))
+        .collect::<HashSet<_>>();
+
     for file in files {
         let file_name = file.tag;
         let input = file.file_contents;
 
         let file_name = file_name.map(Rc::from);
 
-        let initial_file_string = crate::misc::tabs_to_spaces(input, default_tab_size);
-        let initial_span = Span::new(initial_file_string.as_bytes());
+        let actual_tabsize = file_tab_size(input, default_tab_size);
 
-        let (_remaining_input, outer_attrs) = parse_outer_attributes(initial_span)
-            .expect("Initial outer attributes parser should never fail");
-
-        let mut actual_tabsize = default_tab_size;
+        let file_string = crate::misc::tabs_to_spaces(input, actual_tabsize);
 
-        for attr in outer_attrs {
-            // TODO(zkol): Not a fan of this random hardcoding here
-            if attr.key().to_ascii_lowercase() == "tabsize" {
-                // TODO(zkol): This error handling needs to get wrapped up
-                // with the rest somehow...
-                actual_tabsize = attr.value().expect("Tabsize attribute requires a value")
-                    .parse().expect("Tabsize attribute value should be numeric");
+        let mut result = crate::macros::parse_file(&file_string, file_name.clone())?;
+        resolve_local_labels(&mut result.items);
+
+        // included files are added by `include_files`, before parsing
+        for item in result.items.iter() {
+            if let MpItem::Directive((MpDirective::Include(path), position)) = &item.item {
+                let path = include_path(file_name.as_deref().unwrap_or(""), path);
+
+                if !loaded.contains(&path) {
+                    return Err(ErrorLocation {
+                        file_name,
+                        line: item.line_number,
+                        col: position.col() as usize,
+                        kind: Some(ErrorKind::Include(IncludeError::NotLoaded { path })),
+                        expansion: None,
+                    });
+                }
             }
         }
 
-        let file_string = crate::misc::tabs_to_spaces(input, actual_tabsize);
-        let span = Span::new(file_string.as_bytes());
-
-        let result = parse_result(span, file_name.clone(), parse_mips_bytes(file_name))?;
-
         program.merge(result);
     }
 
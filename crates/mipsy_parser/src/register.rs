@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 use std::fmt;
 
 use crate::{
-    number::{parse_immediate, MpImmediate},
+    number::{parse_expression, parse_immediate, MpImmediate},
     Span,
 };
 use nom::{IResult, branch::alt, character::complete::{alphanumeric1, char, digit1, one_of, space0}, combinator::{map, opt}, sequence::tuple};
@@ -65,7 +65,7 @@ impl fmt::Display for MpOffsetOperator {
 }
 
 pub fn parse_register(i: Span<'_>) -> IResult<Span<'_>, MpRegister> {
-    alt((parse_normal_register, parse_offset_register, parse_offset_binary_op_register))(i)
+    alt((parse_normal_register, parse_offset_register, parse_offset_binary_op_register, parse_offset_expression_register))(i)
 }
 
 pub fn parse_normal_register(i: Span<'_>) -> IResult<Span<'_>, MpRegister> {
@@ -130,3 +130,20 @@ pub fn parse_offset_binary_op_register(i: Span<'_>) -> IResult<Span<'_>, MpRegis
         MpRegister::BinaryOpOffset(i1, op, i2, reg.get_identifier().clone()),
     ))
 }
+
+pub fn parse_offset_expression_register(i: Span<'_>) -> IResult<Span<'_>, MpRegister> {
+    let (remaining_data, (imm, _, _, _, reg, ..)) = tuple((
+        parse_expression,
+        space0,
+        char('('),
+        space0,
+        parse_normal_register,
+        space0,
+        char(')'),
+    ))(i)?;
+
+    Ok((
+        remaining_data,
+        MpRegister::Offset(imm, reg.get_identifier().clone()),
+    ))
+}
@@ -1,4 +1,4 @@
-use std::{fs::{self, File}, io::{Read, Write}, path::PathBuf, time::SystemTime};
+use std::{collections::BTreeMap, fs::{self, File}, io::{Read, Write}, path::PathBuf, time::SystemTime};
 use serde::{Serialize, Deserialize};
 
 const MIPSY_DIR: &str = "mipsy";
@@ -11,6 +11,76 @@ const CONFIG_NAME: &str = "config.yaml";
 pub struct MipsyConfig {
     pub tab_size: u32,
     pub spim: bool,
+    /// How many states to keep for stepping backwards -
+    /// `unbounded`, `disabled` or `max_depth: <n>`.
+    /// Defaults to the most recent 100,000.
+    #[serde(default)]
+    pub history: HistoryPolicy,
+    /// Compiler warnings to enable (`true`) or disable (`false`),
+    /// by name - eg. `unused_label: true`.
+    /// Warnings not listed here use their default.
+    #[serde(default)]
+    pub warnings: BTreeMap<String, bool>,
+    /// Whether returning from a function without restoring
+    /// `$s0`-`$s7`, `$sp`, `$fp` or `$gp` is a runtime error.
+    #[serde(default)]
+    pub check_calling_convention: bool,
+    #[serde(default)]
+    pub limits: Limits,
+    /// Whether to accept the assembly GNU tools (such as `gcc -S`)
+    /// write: their directives, like `.section` and `.set noreorder`,
+    /// and their `$`-prefixed local labels.
+    #[serde(default)]
+    pub gnu: bool,
+    /// Whether runtime errors such as overflow or bad addresses, and
+    /// the `break` and trap instructions, jump to the program's
+    /// exception handler at `0x80000180` (if it has one).
+    #[serde(default)]
+    pub exceptions: bool,
+}
+
+/// # How much execution history a runtime keeps.
+/// 
+/// Every step of a program records the state it produced,
+/// which is what allows stepping backwards. Long-running
+/// programs can record a great deal of states, so this
+/// bounds how many are kept around.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryPolicy {
+    /// Keep every state, for the entire run.
+    Unbounded,
+    /// Keep (at least) the most recent `n` states,
+    /// discarding older states as new ones are recorded.
+    MaxDepth(usize),
+    /// Keep no history - stepping backwards is disabled.
+    Disabled,
+}
+
+impl Default for HistoryPolicy {
+    fn default() -> Self {
+        Self::MaxDepth(100_000)
+    }
+}
+
+/// # Limits on the resources a program can use.
+/// 
+/// A program that goes over any of these limits is stopped
+/// with a runtime error. A limit of `None` is never reached.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    /// The most instructions the program can run.
+    pub max_steps: Option<u64>,
+    /// The most bytes the program can print to
+    /// standard output (or standard error).
+    pub max_output: Option<u64>,
+    /// The most bytes the program can grow the heap to with `sbrk`.
+    pub max_heap: Option<u32>,
+    /// The most bytes the stack can grow to, below its initial `$sp`.
+    pub max_stack: Option<u32>,
+    /// The longest the program can run for, in milliseconds.
+    pub max_time_ms: Option<u64>,
 }
 
 /// # Errors arising from reading the mipsy configuration.
@@ -56,6 +126,12 @@ impl Default for MipsyConfig {
         Self {
             tab_size: 8,
             spim: false,
+            history: HistoryPolicy::default(),
+            warnings: BTreeMap::new(),
+            check_calling_convention: false,
+            limits: Limits::default(),
+            gnu: false,
+            exceptions: false,
         }
     }
 }
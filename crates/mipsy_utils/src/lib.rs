@@ -6,6 +6,8 @@ mod config;
 pub use config::{
     MipsyConfig,
     MipsyConfigError,
+    HistoryPolicy,
+    Limits,
     read_config,
     config_path,
 };
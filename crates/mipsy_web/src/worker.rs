@@ -2,7 +2,7 @@ use crate::pages::main::state::MipsState;
 use log::{error, info};
 use mipsy_lib::{runtime::RuntimeSyscallGuard, Binary, InstSet, MipsyError, Runtime, Safe};
 use mipsy_parser::TaggedFile;
-use mipsy_utils::MipsyConfig;
+use mipsy_utils::{HistoryPolicy, Limits, MipsyConfig};
 use serde::{Deserialize, Serialize};
 use yew_agent::{Agent, AgentLink, HandlerId, Public};
 use gloo_console::log;
@@ -141,6 +141,12 @@ impl Agent for Worker {
                 let config = MipsyConfig {
                     tab_size: 8,
                     spim: false,
+                    history: HistoryPolicy::default(),
+                    warnings: Default::default(),
+                    check_calling_convention: false,
+                    limits: Limits::default(),
+                    gnu: false,
+                    exceptions: false,
                 };
                 let compiled = mipsy_lib::compile(
                     &self.inst_set,
@@ -155,7 +161,7 @@ impl Agent for Worker {
                             decompiled,
                             file: Some(f)
                         });
-                        let runtime = mipsy_lib::runtime(&binary, &[]);
+                        let runtime = new_runtime(&binary);
                         self.binary = Some(binary);
                         self.runtime = Some(RuntimeState::Running(runtime));
 
@@ -177,7 +183,7 @@ impl Agent for Worker {
                 if let Some(runtime_state) = &mut self.runtime {
                     match runtime_state {
                         RuntimeState::Running(runtime) => {
-                            runtime.timeline_mut().reset();
+                            runtime.reset();
                             mips_state.stdout.drain(..);
                             mips_state.mipsy_stdout.drain(..);
                             mips_state.exit_status = None;
@@ -192,7 +198,7 @@ impl Agent for Worker {
                             if let Some(binary) = &self.binary {
                                 let decompiled = mipsy_lib::decompile(&self.inst_set, &binary);
                                 let response = Self::Output::DecompiledCode(DecompiledResponse {decompiled, file: None});
-                                let runtime = mipsy_lib::runtime(&binary, &[]);
+                                let runtime = new_runtime(&binary);
                                 self.runtime = Some(RuntimeState::Running(runtime));
                                 self.link.respond(id, response)
                             }
@@ -202,7 +208,7 @@ impl Agent for Worker {
                     if let Some(binary) = &self.binary {
                         let decompiled = mipsy_lib::decompile(&self.inst_set, &binary);
                         let response = Self::Output::DecompiledCode(DecompiledResponse {decompiled, file: None});
-                        let runtime = mipsy_lib::runtime(&binary, &[]);
+                        let runtime = new_runtime(&binary);
                         self.runtime = Some(RuntimeState::Running(runtime));
                         self.link.respond(id, response)
                     }
@@ -593,6 +599,20 @@ impl Agent for Worker {
     }
 }
 
+/// Programs that run for longer than this are almost certainly stuck,
+/// and would otherwise keep the worker busy forever.
+const MAX_STEPS: u64 = 10_000_000;
+
+fn new_runtime(binary: &Binary) -> Runtime {
+    let mut runtime = mipsy_lib::runtime(binary, &[]);
+    runtime.set_limits(Limits {
+        max_steps: Some(MAX_STEPS),
+        ..Limits::default()
+    });
+
+    runtime
+}
+
 impl Worker {
     fn upload_syscall_value<T>(
         &mut self,